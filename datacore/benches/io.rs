@@ -30,6 +30,16 @@ async fn hypercore_append(mut core: MemoryCore, blocks: u64) {
     }
 }
 
+fn init_appended(blocks: u64) -> MemoryCore {
+    let mut core = init();
+    block_on(async {
+        for i in 0..blocks {
+            core.append(&i.to_be_bytes(), None).await.unwrap();
+        }
+    });
+    core
+}
+
 pub fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("append 1000 blocks", |b| {
         b.to_async(AsyncStdExecutor).iter(|| {
@@ -39,5 +49,16 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, criterion_benchmark);
+pub fn verify_benchmark(c: &mut Criterion) {
+    let mut core = init_appended(10_000);
+    c.bench_function("verify 10000 blocks", |b| {
+        b.iter(|| block_on(core.verify()).unwrap())
+    });
+    #[cfg(feature = "parallel")]
+    c.bench_function("verify_parallel 10000 blocks", |b| {
+        b.iter(|| block_on(core.verify_parallel()).unwrap())
+    });
+}
+
+criterion_group!(benches, criterion_benchmark, verify_benchmark);
 criterion_main!(benches);
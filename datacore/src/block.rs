@@ -3,7 +3,12 @@ use std::mem::size_of;
 use std::io::{Cursor, Read};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
-pub use ed25519_dalek::{Signature, SIGNATURE_LENGTH};
+pub use ed25519_dalek::Signature;
+use crate::keys::PublicKey;
+use crate::scheme::Verifier;
+
+/// Length of a [Signature], of the default `Ed25519` scheme.
+pub const SIGNATURE_LENGTH: usize = <PublicKey as Verifier>::SIGNATURE_LENGTH;
 
 /// [BlockSignature] holds [Signature]s - `data` and `tree` - for a [Block].
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -31,6 +36,20 @@ impl BlockSignature {
     pub fn tree(&self) -> Signature {
         self.tree
     }
+
+    /// Sentinel [BlockSignature] of zeros, stored for unsigned blocks.
+    #[inline]
+    pub(crate) fn unsigned() -> Self {
+        let zero = Signature::from_bytes(&[0u8; SIGNATURE_LENGTH])
+            .expect("zero signature is well formed");
+        Self::new(zero, zero)
+    }
+
+    /// Check if this is the [BlockSignature::unsigned] sentinel.
+    #[inline]
+    pub(crate) fn is_unsigned(&self) -> bool {
+        *self == Self::unsigned()
+    }
 }
 
 /// [Block] describes a block of data in `Core`.
@@ -59,6 +78,9 @@ impl Block {
     }
 
     /// Serialize [Block].
+    ///
+    /// The layout is fixed across platforms: the offset and the length
+    /// little endian, then the data and the tree signatures.
     #[inline]
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
         let mut data = Vec::with_capacity(BLOCK_LENGTH);
@@ -126,6 +148,19 @@ mod tests {
         Ok(())
     }
     #[test]
+    pub fn to_bytes_golden() -> Result<()> {
+        let data = Signature::from_bytes(&[2u8; SIGNATURE_LENGTH])?;
+        let tree = Signature::from_bytes(&[7u8; SIGNATURE_LENGTH])?;
+        let signature = BlockSignature::new(data, tree);
+        let block = Block::new(0x0102030405060708, 0x0a0b0c0d, signature);
+        let expected = format!("{}{}{}{}",
+            "0807060504030201", "0d0c0b0a",
+            "02".repeat(SIGNATURE_LENGTH), "07".repeat(SIGNATURE_LENGTH));
+        assert_eq!(hex::encode(block.to_bytes()?), expected);
+        assert_eq!(Block::from_bytes(&hex::decode(expected)?)?, block);
+        Ok(())
+    }
+    #[test]
     pub fn from_bytes_fails_on_incomplete_input() -> Result<()> {
         let data = Signature::from_bytes(&[2u8; SIGNATURE_LENGTH])?;
         let tree = Signature::from_bytes(&[7u8; SIGNATURE_LENGTH])?;
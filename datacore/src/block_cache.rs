@@ -0,0 +1,73 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::BlockSignature;
+
+/// Bounded cache of recently appended or read blocks,
+/// evicting the oldest inserted block first.
+#[derive(Debug, Default)]
+pub(crate) struct BlockCache {
+    capacity: usize,
+    blocks: HashMap<u32, (Vec<u8>, BlockSignature)>,
+    order: VecDeque<u32>,
+}
+
+impl BlockCache {
+    /// Create a [BlockCache] keeping up to `capacity` blocks,
+    /// `0` disables the cache.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Get a cached block.
+    pub(crate) fn get(&self, index: u32) -> Option<&(Vec<u8>, BlockSignature)> {
+        self.blocks.get(&index)
+    }
+
+    /// Cache a block, evicting the oldest if full.
+    pub(crate) fn insert(
+        &mut self, index: u32, data: &[u8], signature: &BlockSignature)
+    {
+        if self.capacity == 0 || self.blocks.contains_key(&index) {
+            return
+        }
+        if self.blocks.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.blocks.remove(&oldest);
+            }
+        }
+        self.blocks.insert(index, (data.to_vec(), signature.clone()));
+        self.order.push_back(index);
+    }
+
+    /// Drop every cached block.
+    pub(crate) fn clear(&mut self) {
+        self.blocks.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_oldest() {
+        let signature = BlockSignature::unsigned();
+        let mut cache = BlockCache::new(2);
+        cache.insert(0, b"a", &signature);
+        cache.insert(1, b"b", &signature);
+        cache.insert(0, b"a", &signature);
+        cache.insert(2, b"c", &signature);
+        assert!(cache.get(0).is_none());
+        assert_eq!(cache.get(1).unwrap().0, b"b");
+        assert_eq!(cache.get(2).unwrap().0, b"c");
+
+        let mut cache = BlockCache::new(0);
+        cache.insert(0, b"a", &signature);
+        assert!(cache.get(0).is_none());
+    }
+}
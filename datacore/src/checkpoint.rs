@@ -0,0 +1,94 @@
+use anyhow::Result;
+use std::mem::size_of;
+use std::io::{Cursor, Read};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::hash::{Hash, HASH_SIZE};
+use crate::{PublicKey, Signature, SIGNATURE_LENGTH, verify};
+
+/// [Checkpoint] is a compact, verifiable pointer to the state of a `Core`,
+/// see [Core::checkpoint].
+///
+/// The `tree_signature` proves the writer signed `root_hash`.
+/// `length` and `byte_length` are only described by the `root_hash`,
+/// not covered by the signature directly.
+///
+/// [Core::checkpoint]: crate::Core::checkpoint
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Checkpoint {
+    /// Number of blocks.
+    pub length: u32,
+    /// Total length of the block data, uncompressed.
+    pub byte_length: u64,
+    /// Hash of the merkle tree roots.
+    pub root_hash: Hash,
+    /// Writer's signature of the `root_hash`.
+    pub tree_signature: Signature,
+}
+
+pub const CHECKPOINT_LENGTH: usize =
+    size_of::<u32>() + size_of::<u64>() + HASH_SIZE + SIGNATURE_LENGTH;
+
+impl Checkpoint {
+    /// Serialize [Checkpoint].
+    #[inline]
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(CHECKPOINT_LENGTH);
+
+        data.write_u32::<LittleEndian>(self.length)?;
+        data.write_u64::<LittleEndian>(self.byte_length)?;
+        data.extend_from_slice(self.root_hash.as_bytes());
+        data.extend_from_slice(&self.tree_signature.to_bytes());
+
+        Ok(data)
+    }
+    /// Deserialize [Checkpoint].
+    #[inline]
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut rdr = Cursor::new(data);
+        let length = rdr.read_u32::<LittleEndian>()?;
+        let byte_length = rdr.read_u64::<LittleEndian>()?;
+
+        let mut root_hash = [0u8; HASH_SIZE];
+        rdr.read_exact(&mut root_hash)?;
+        let mut tree_signature = [0u8; SIGNATURE_LENGTH];
+        rdr.read_exact(&mut tree_signature)?;
+
+        Ok(Self {
+            length,
+            byte_length,
+            root_hash: Hash::from_bytes(&root_hash)?,
+            tree_signature: Signature::from_bytes(&tree_signature)?,
+        })
+    }
+}
+
+/// Verify the `tree_signature` of a [Checkpoint] over its `root_hash`.
+pub fn verify_checkpoint(
+    public_key: &PublicKey,
+    checkpoint: &Checkpoint,
+    ) -> Result<()>
+{
+    verify(public_key, checkpoint.root_hash.as_bytes(),
+           &checkpoint.tree_signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn to_bytes_from_bytes() -> Result<()> {
+        let checkpoint = Checkpoint {
+            length: 3,
+            byte_length: 42,
+            root_hash: Hash::from_leaf(b"hello"),
+            tree_signature: Signature::from_bytes(&[7u8; SIGNATURE_LENGTH])?,
+        };
+        let bytes = checkpoint.to_bytes()?;
+        assert_eq!(bytes.len(), CHECKPOINT_LENGTH);
+        assert_eq!(Checkpoint::from_bytes(&bytes)?, checkpoint);
+        assert!(Checkpoint::from_bytes(&bytes[1..]).is_err());
+        Ok(())
+    }
+}
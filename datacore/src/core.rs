@@ -1,18 +1,33 @@
 //! Main `Core` abstraction.
 //! Exposes an append-only, single-writer, secure log structure.
 
-use anyhow::{Result, ensure, bail};
+use anyhow::{Result, ensure, bail, anyhow};
 use std::error::Error;
 use std::fmt::Debug;
+use std::future::Future;
+use std::io::{Cursor, Read};
+use std::mem::size_of;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ed25519_dalek::PUBLIC_KEY_LENGTH;
 use futures_lite::future::zip;
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use event_listener::Event;
 
 use crate::store_data::StoreData;
+use crate::verify_cursor::VerifyCursor;
 use crate::store_blocks::StoreBlocks;
 use crate::store_state::StoreState;
-use crate::merkle::{Merkle, NodeTrait};
+use crate::store_index::StoreIndex;
+use crate::block_cache::BlockCache;
+use crate::store_single::Partition;
+use crate::store_framed::Framed;
+use crate::checkpoint::Checkpoint;
+use crate::proof::{self, Proof};
+use crate::merkle_tree_stream::flat_tree;
+use crate::merkle::{Merkle, Node, NodeTrait};
 use crate::{
-    Block, BlockSignature, Hash, RandomAccess,
-    PublicKey, SecretKey, sign, verify,
+    Block, BlockSignature, Signature, Hash, RandomAccess,
+    PublicKey, SecretKey, SIGNATURE_LENGTH, sign, verify,
 };
 
 /// Maximum number of blocks of data in a `Core`.
@@ -20,6 +35,71 @@ pub const MAX_CORE_LENGTH: usize = u32::MAX as usize;
 /// Maximum size of a single block of data in a `Core`.
 pub const MAX_BLOCK_SIZE: usize = u32::MAX as usize;
 
+/// Number of blocks read before verifying their signatures in parallel,
+/// see [Core::verify_parallel].
+#[cfg(feature = "parallel")]
+pub const VERIFY_BATCH: u32 = 4096;
+
+/// Magic bytes at the start of a [Core::export].
+const EXPORT_MAGIC: &[u8; 8] = b"DCEXPORT";
+/// Version of the [Core::export] format.
+const EXPORT_VERSION: u8 = 1;
+/// Length of the [Core::export] header.
+const EXPORT_HEADER_LENGTH: usize =
+    EXPORT_MAGIC.len() + 1 + PUBLIC_KEY_LENGTH + size_of::<u32>();
+
+/// Callback invoked after each append, see [Core::on_append].
+pub type AppendHook = Box<dyn FnMut(u32, &[u8]) + Send>;
+
+/// Optional [AppendHook], opaque to [Debug].
+#[derive(Default)]
+struct OnAppend(Option<AppendHook>);
+
+impl Debug for OnAppend {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "OnAppend({})", self.0.is_some())
+    }
+}
+
+/// Options for a [Core].
+#[derive(Debug, Clone, Default)]
+pub struct CoreOptions {
+    /// Keep the merkle state in memory on [Core::append] and only persist it
+    /// on [Core::flush], which must be called before the `Core` is dropped.
+    ///
+    /// Without a flush the persisted state is stale: the `Core`
+    /// reopens at the length of the last flush, and the merkle state must be
+    /// rebuilt from the blocks store to recover later blocks.
+    pub lazy_state: bool,
+    /// Compress block data at rest.
+    /// Signatures are still over the uncompressed data.
+    ///
+    /// A `Core` must always be opened with the same setting.
+    pub compress: bool,
+    /// Largest block accepted by [Core::append], in bytes,
+    /// or `None` for [MAX_BLOCK_SIZE].
+    pub max_block_size: Option<usize>,
+    /// Number of recently appended or read blocks kept in memory
+    /// for [Core::try_get], `0` to disable.
+    pub cache_size: usize,
+    /// Verify the data signature of every block read by [Core::get],
+    /// catching stores tampered with or corrupted after the append.
+    ///
+    /// The tree signatures are only checked by [Core::verify].
+    pub verify_on_read: bool,
+}
+
+/// Result of [Core::try_get].
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryGet {
+    /// The block is cached.
+    Hit((Vec<u8>, BlockSignature)),
+    /// The block exists but is not cached, read it with [Core::get].
+    Miss,
+    /// There is no block at the index.
+    OutOfRange,
+}
+
 /// Core is an append-only, single-writer, secure log structure.
 ///
 /// To read an entry from a `Core` you only need to know its [PublicKey],
@@ -45,6 +125,7 @@ where
     data: StoreData<D>,
     blocks: StoreBlocks<B>,
     state: StoreState<S>,
+    index: Option<StoreIndex<S>>,
 
     merkle: Merkle,
     public_key: PublicKey,
@@ -52,6 +133,13 @@ where
 
     length: u32,
     byte_length: u64,
+
+    options: CoreOptions,
+    state_dirty: bool,
+    unsigned: bool,
+    on_append: OnAppend,
+    appended: Event,
+    cache: BlockCache,
 }
 
 impl<D, B, S> Core<D, B, S>
@@ -61,6 +149,9 @@ where
     S: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
 {
     /// Create a new instance with a custom storage backend.
+    ///
+    /// Fails if `secret_key` does not belong to `public_key`,
+    /// like all the other constructors.
     pub async fn new(
         data: D,
         blocks: B,
@@ -69,29 +160,113 @@ where
         secret_key: Option<SecretKey>
         ) -> Result<Self>
     {
-        let data = StoreData::new(data);
-        let mut blocks = StoreBlocks::new(blocks);
-        let mut state = StoreState::new(state);
+        Self::new_with_options(
+            data, blocks, state, public_key, secret_key,
+            CoreOptions::default())
+            .await
+    }
+
+    /// Create a new instance with a custom storage backend and [CoreOptions].
+    pub async fn new_with_options(
+        data: D,
+        blocks: B,
+        state: S,
+        public_key: PublicKey,
+        secret_key: Option<SecretKey>,
+        options: CoreOptions,
+        ) -> Result<Self>
+    {
+        Self::open(
+            data, blocks, state, None, public_key, secret_key, options)
+            .await
+    }
+
+    /// Create a new instance with a custom storage backend, [CoreOptions]
+    /// and a leaf [Hash] to block index map kept in `index`,
+    /// which enables [Core::index_of].
+    ///
+    /// The index costs extra storage and a few writes on every
+    /// [Core::append]. It is built from the existing blocks if missing.
+    pub async fn new_with_index(
+        data: D,
+        blocks: B,
+        state: S,
+        index: S,
+        public_key: PublicKey,
+        secret_key: Option<SecretKey>,
+        options: CoreOptions,
+        ) -> Result<Self>
+    {
+        Self::open(
+            data, blocks, state, Some(index), public_key, secret_key, options)
+            .await
+    }
+
+    async fn open(
+        data: D,
+        blocks: B,
+        state: S,
+        index: Option<S>,
+        public_key: PublicKey,
+        secret_key: Option<SecretKey>,
+        options: CoreOptions,
+        ) -> Result<Self>
+    {
+        if let Some(secret_key) = &secret_key {
+            ensure!(PublicKey::from(secret_key) == public_key,
+                "SecretKey does not match the PublicKey.");
+        }
+
+        let mut data = match options.compress {
+            true => StoreData::new_compressed(data),
+            false => StoreData::new(data),
+        };
+        let mut blocks = StoreBlocks::open(blocks).await?;
+        let mut state = StoreState::open(state).await?;
 
         let merkle = state.read().await?;
         let length = merkle.blocks() as u32;
-        let byte_length = match length {
-            0 => 0,
+        let (byte_length, unsigned) = match length {
+            0 => (0, false),
             n => {
                 let block = blocks.read(n - 1).await?;
-                block.offset() as u64 + block.length() as u64
+                (block.offset() as u64 + block.length() as u64,
+                 block.signature().is_unsigned())
+            },
+        };
+        data.set_len(Some(byte_length));
+
+        let index = match index {
+            None => None,
+            Some(index) => {
+                let mut index = StoreIndex::open(index).await?;
+                index.truncate(length).await?;
+                for i in index.len()..length {
+                    let block = blocks.read(i).await?;
+                    let block_data = data.read(&block).await?;
+                    index.insert(&Hash::from_leaf(&block_data), i).await?;
+                }
+                Some(index)
             },
         };
 
+        let cache = BlockCache::new(options.cache_size);
         Ok(Self {
             data,
             blocks,
             state,
+            index,
             merkle,
             public_key,
             secret_key,
             length,
             byte_length,
+            options,
+            state_dirty: false,
+            unsigned,
+            on_append: OnAppend::default(),
+            appended: Event::new(),
+            cache,
         })
     }
 
@@ -105,6 +280,20 @@ where
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+    /// Get the number of bytes of data in the `Core`, as stored:
+    /// compressed data counts its compressed length.
+    #[inline]
+    pub fn byte_len(&self) -> u64 {
+        self.byte_length
+    }
+    /// Get the offset of the data of block at `index` in the data store,
+    /// `None` if out of bounds.
+    pub async fn block_offset(&mut self, index: u32) -> Result<Option<u64>> {
+        if index >= self.len() {
+            return Ok(None)
+        }
+        Ok(Some(self.blocks.read(index).await?.offset()))
+    }
     /// Access the [PublicKey].
     pub fn public_key(&self) -> &PublicKey {
         &self.public_key
@@ -114,10 +303,28 @@ where
         &self.secret_key
     }
 
+    /// Check if the `Core` is unsigned, see [Core::append_unsigned].
+    ///
+    /// An unsigned `Core` cannot be verified or replicated.
+    #[inline]
+    pub fn is_unsigned(&self) -> bool {
+        self.unsigned
+    }
+
+    /// Check if the `Core` has an index, see [Core::index_of].
+    #[inline]
+    pub fn has_index(&self) -> bool {
+        self.index.is_some()
+    }
+
     /// Append data into the `Core`.
     ///
-    /// If `signature` is supplied, the caller is responsible for verifying its
-    /// integrity and consistency with the `data`.
+    /// A supplied `signature` is verified against the `data` and the merkle
+    /// tree. A block not extending the tree, or conflicting with a block
+    /// left over at this index from lost state, is rejected as a fork.
+    ///
+    /// Empty `data` is a valid block: it is signed and counted like any
+    /// other, and [Core::get] returns it as an empty `Vec`.
     #[inline]
     pub async fn append(
         &mut self,
@@ -125,19 +332,33 @@ where
         signature: Option<BlockSignature>,
         ) -> Result<()>
     {
-        let index = self.len();
+        self.append_with_hash(data, Hash::from_leaf(data), signature).await
+    }
+
+    /// Append data into the `Core` with its precomputed leaf hash,
+    /// see [Hash::from_leaf], saving a pass over the `data`.
+    ///
+    /// A supplied `signature` is verified against `data_hash`, same as
+    /// [Core::append]. Without a `signature`, `data_hash` is trusted
+    /// and signed as is: a wrong hash makes the `Core` unverifiable.
+    pub async fn append_with_hash(
+        &mut self,
+        data: &[u8],
+        data_hash: Hash,
+        signature: Option<BlockSignature>,
+        ) -> Result<()>
+    {
+        ensure!(!self.unsigned, "Core is unsigned, cannot append signed data.");
         let data_length = data.len();
-        ensure!(data_length <= MAX_BLOCK_SIZE);
+        self.check_block_size(data_length)?;
 
         // get or try to create the `signature`
         let signature = match signature {
             Some(signature) => {
-                let data_hash = Hash::from_leaf(data);
-                verify(&self.public_key, &data_hash, &signature.data())?;
+                self.check_fork(&signature).await?;
                 let mut merkle = self.merkle.clone();
-                merkle.next(data_hash, data_length as u64);
-                verify(&self.public_key,
-                       &hash_merkle(&merkle), &signature.tree())?;
+                append_verified(&self.public_key, &mut merkle,
+                    data_hash.clone(), data_length as u64, &signature)?;
                 self.merkle = merkle;
                 signature
             },
@@ -146,29 +367,612 @@ where
                     Some(secret) => secret,
                     None => bail!("No SecretKey for Core, cannot append."),
                 };
-                let data_hash = Hash::from_leaf(data);
                 let data_sign = sign(&self.public_key, &secret, &data_hash);
-                self.merkle.next(data_hash, data_length as u64);
-                let tree_sign = sign(&self.public_key,
-                                     &secret, &hash_merkle(&self.merkle));
+                let root_hash = self.merkle
+                    .append_and_root(data_hash.clone(), data_length as u64);
+                let tree_sign = sign(&self.public_key, &secret, &root_hash);
                 BlockSignature::new(data_sign, tree_sign)
             },
         };
 
+        self.write_block(data, data_hash, signature).await
+    }
+
+    /// Append data into the `Core` without signing it,
+    /// skipping the cost of signatures for trusted, local use.
+    ///
+    /// **This makes the whole `Core` unsigned, permanently.**
+    /// An unsigned `Core` can never be verified or replicated,
+    /// does not hand out [BlockSignature]s (read it with [Core::get_data]),
+    /// and refuses further signed appends.
+    #[cfg(feature = "unsigned")]
+    pub async fn append_unsigned(&mut self, data: &[u8]) -> Result<()> {
+        ensure!(self.secret_key.is_some(),
+            "No SecretKey for Core, cannot append.");
+        self.check_block_size(data.len())?;
+        let data_hash = Hash::from_leaf(data);
+        let merkle = self.merkle.clone();
+        self.merkle.next(data_hash.clone(), data.len() as u64);
+        let written = self.write_block(
+            data, data_hash, BlockSignature::unsigned()).await;
+        if let Err(err) = written {
+            self.merkle = merkle;
+            return Err(err)
+        }
+        self.unsigned = true;
+        Ok(())
+    }
+
+    /// Fail if a block conflicting with `signature` is already stored
+    /// at the next index, left over from lost state.
+    async fn check_fork(&mut self, signature: &BlockSignature) -> Result<()> {
+        let index = self.len();
+        if let Ok(block) = self.blocks.read(index).await {
+            ensure!(block.signature() == *signature,
+                "Fork detected at block {}, it conflicts with the stored one.",
+                index);
+        }
+        Ok(())
+    }
+
+    /// Limit the size of appended blocks to `limit` bytes,
+    /// see [CoreOptions::max_block_size].
+    pub fn with_max_block_size(mut self, limit: usize) -> Self {
+        self.options.max_block_size = Some(limit);
+        self
+    }
+
+    /// Verify blocks read by [Core::get],
+    /// see [CoreOptions::verify_on_read].
+    pub fn with_verify_on_read(mut self, verify_on_read: bool) -> Self {
+        self.options.verify_on_read = verify_on_read;
+        self
+    }
+
+    fn check_block_size(&self, length: usize) -> Result<()> {
+        let limit = self.options.max_block_size
+            .unwrap_or(MAX_BLOCK_SIZE)
+            .min(MAX_BLOCK_SIZE);
+        ensure!(length <= limit,
+            "Block of {} bytes is above the {} bytes limit.", length, limit);
+        Ok(())
+    }
+
+    /// Store the next block, after its leaf has been added to the merkle
+    /// state.
+    async fn write_block(
+        &mut self,
+        data: &[u8],
+        data_hash: Hash,
+        signature: BlockSignature,
+        ) -> Result<()>
+    {
+        let index = self.len();
+        let stored = self.data.encode(data)?;
+        ensure!(stored.len() <= MAX_BLOCK_SIZE);
         let block = Block::new(
-            self.byte_length, data_length as u32, signature);
+            self.byte_length, stored.len() as u32, signature);
 
         let (d, b) = zip(
-            self.data.write(&block, &data),
+            self.data.write(&block, &stored),
             self.blocks.write(index, &block))
             .await; d?; b?;
-        self.state.write(&self.merkle).await?;
-        self.byte_length += data_length as u64;
+        if self.options.lazy_state {
+            self.state_dirty = true;
+        } else {
+            self.state.write(&self.merkle).await?;
+        }
+        if let Some(store) = &mut self.index {
+            store.insert(&data_hash, index).await?;
+        }
+        self.flush_stores(!self.options.lazy_state).await?;
+        self.byte_length += block.length() as u64;
         self.length += 1;
+        if !block.signature().is_unsigned() {
+            self.cache.insert(index, data, &block.signature());
+        }
+
+        if let Some(hook) = &mut self.on_append.0 {
+            hook(index, data);
+        }
+        self.appended.notify(usize::MAX);
+        Ok(())
+    }
+
+    /// Wait for the next append.
+    ///
+    /// The returned future does not borrow the `Core`,
+    /// so a `Core` behind a lock can be released while waiting:
+    /// get the future while holding the lock, then await it without.
+    pub fn appended(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.appended.listen()
+    }
+
+    /// Register a `hook` called after every successful append
+    /// with the index and data of the new block,
+    /// e.g. to maintain a secondary index.
+    /// Replaces any earlier hook.
+    pub fn on_append(&mut self, hook: AppendHook) {
+        self.on_append.0 = Some(hook);
+    }
+
+    /// Persist the merkle state, if deferred by [CoreOptions::lazy_state].
+    ///
+    /// Must be called before dropping a lazy `Core`,
+    /// deferred state is not persisted on drop.
+    pub async fn flush(&mut self) -> Result<()> {
+        if self.state_dirty {
+            self.state.write(&self.merkle).await?;
+            self.state.flush().await?;
+            self.state_dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Discard the blocks from `length` on, rolling the `Core` back.
+    ///
+    /// The merkle state is rebuilt from the retained blocks,
+    /// reading all of their data, without verifying it.
+    /// The discarded blocks and data are deleted from the stores
+    /// before the state is persisted, so an interrupted truncation
+    /// is recovered by [Core::rebuild_state].
+    pub async fn truncate(&mut self, length: u32) -> Result<()> {
+        ensure!(length <= self.len(),
+            "Cannot truncate Core of length {} to {}.", self.len(), length);
+        if length == self.len() {
+            return Ok(())
+        }
 
+        let mut merkle = Merkle::new();
+        let mut byte_length = 0;
+        for index in 0..length {
+            let block = self.blocks.read(index).await?;
+            let data = self.data.read(&block).await?;
+            merkle.next(Hash::from_leaf(&data), data.len() as u64);
+            byte_length += block.length() as u64;
+        }
+
+        let (d, b) = zip(
+            self.data.truncate(byte_length),
+            self.blocks.truncate(length))
+            .await; d?; b?;
+        if let Some(store) = &mut self.index {
+            store.truncate(length).await?;
+        }
+        self.flush_stores(false).await?;
+        self.state.write(&merkle).await?;
+        self.state.flush().await?;
+        self.state_dirty = false;
+        self.merkle = merkle;
+        self.length = length;
+        self.byte_length = byte_length;
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// Flush the stores written by an append, the state store only
+    /// if `state` was written too.
+    async fn flush_stores(&mut self, state: bool) -> Result<()> {
+        let (d, b) = zip(self.data.flush(), self.blocks.flush()).await;
+        d?; b?;
+        if state {
+            self.state.flush().await?;
+        }
+        if let Some(store) = &mut self.index {
+            store.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Verify every block against its signatures,
+    /// and the merkle state against the blocks.
+    pub async fn verify(&mut self) -> Result<()> {
+        self.verify_from(VerifyCursor::new(), self.len()).await?;
         Ok(())
     }
 
+    /// Verify up to `count` blocks from `cursor`, like [Core::verify],
+    /// and return the advanced [VerifyCursor].
+    ///
+    /// Verifies a large `Core` in bounded slices, resumable after
+    /// a restart from a persisted cursor. Once the cursor reaches
+    /// the end of the `Core`, the merkle state is checked too;
+    /// the `Core` is fully verified when the returned cursor index
+    /// equals [Core::len].
+    pub async fn verify_from(&mut self, cursor: VerifyCursor, count: u32)
+        -> Result<VerifyCursor>
+    {
+        ensure!(!self.unsigned, "Core is unsigned, cannot verify.");
+        ensure!(cursor.index() <= self.len(),
+            "VerifyCursor is past the end of the Core.");
+        let mut cursor = cursor;
+        let end = std::cmp::min(
+            cursor.index().saturating_add(count), self.len());
+        for index in cursor.index()..end {
+            let block = self.blocks.read(index).await?;
+            let offset = cursor.byte_length();
+            let length = self.verify_block(
+                index, &block, offset, cursor.merkle_mut()).await?;
+            cursor.advance(length);
+        }
+        if cursor.index() == self.len() {
+            ensure!(cursor.merkle().roots() == self.merkle.roots(),
+                "Merkle state does not match the blocks.");
+        }
+        Ok(cursor)
+    }
+
+    /// [Core::verify] with the signature checks spread over
+    /// the rayon thread pool.
+    ///
+    /// Blocks are read and added to the merkle tree sequentially,
+    /// in batches of [VERIFY_BATCH]; the signatures of each batch are
+    /// then verified in parallel, blocking the calling task.
+    /// Fails on the first invalid block, like [Core::verify].
+    #[cfg(feature = "parallel")]
+    pub async fn verify_parallel(&mut self) -> Result<()> {
+        use rayon::prelude::*;
+
+        ensure!(!self.unsigned, "Core is unsigned, cannot verify.");
+        let mut merkle = Merkle::new();
+        let mut byte_length = 0;
+        let mut start = 0;
+        while start < self.len() {
+            let end = std::cmp::min(
+                start.saturating_add(VERIFY_BATCH), self.len());
+            let mut batch = Vec::with_capacity((end - start) as usize);
+            let blocks = self.blocks.read_range(start, end - start).await?;
+            for (index, block) in (start..end).zip(blocks) {
+                ensure!(block.offset() == byte_length,
+                    "Block {} has offset {}, expected {}.",
+                    index, block.offset(), byte_length);
+                let data = self.data.read(&block).await?;
+                byte_length += block.length() as u64;
+                let data_hash = Hash::from_leaf(&data);
+                let root_hash = merkle
+                    .append_and_root(data_hash.clone(), data.len() as u64);
+                batch.push((data_hash, root_hash, block.signature()));
+            }
+
+            let public_key = &self.public_key;
+            let invalid = batch.par_iter()
+                .enumerate()
+                .map(|(i, (data_hash, root_hash, signature))| {
+                    if verify(public_key, data_hash, &signature.data())
+                        .is_err()
+                    {
+                        return Some((i, "data"))
+                    }
+                    if verify(public_key, root_hash, &signature.tree())
+                        .is_err()
+                    {
+                        return Some((i, "tree"))
+                    }
+                    None
+                })
+                .find_first(Option::is_some)
+                .flatten();
+            if let Some((i, kind)) = invalid {
+                bail!("Block {} has invalid {} signature.",
+                    start + i as u32, kind);
+            }
+            start = end;
+        }
+        ensure!(merkle.roots() == self.merkle.roots(),
+            "Merkle state does not match the blocks.");
+        Ok(())
+    }
+
+    /// Serialize the whole `Core` into a single blob,
+    /// to be restored with [Core::import].
+    ///
+    /// The blob starts with the `DCEXPORT` magic, a version byte,
+    /// the [PublicKey] and the number of blocks as `u32`, followed by
+    /// each block as its data length as `u32`, the data,
+    /// and the data and tree [Signature]s; all little endian.
+    ///
+    /// Holds the whole `Core` in memory, see [Core::export_to].
+    ///
+    /// [Signature]: crate::Signature
+    pub async fn export(&mut self) -> Result<Vec<u8>> {
+        let mut blob = Vec::with_capacity(
+            EXPORT_HEADER_LENGTH + self.byte_length as usize);
+        self.export_to(&mut blob).await?;
+        Ok(blob)
+    }
+
+    /// Stream the format of [Core::export] into `writer`, block by block,
+    /// holding a single block in memory.
+    pub async fn export_to<W>(&mut self, mut writer: W) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        ensure!(!self.unsigned, "Core is unsigned, has no signatures.");
+        let mut header = Vec::with_capacity(EXPORT_HEADER_LENGTH);
+        header.extend_from_slice(EXPORT_MAGIC);
+        header.push(EXPORT_VERSION);
+        header.extend_from_slice(self.public_key.as_bytes());
+        header.write_u32::<LittleEndian>(self.length)?;
+        writer.write_all(&header).await?;
+        for index in 0..self.length {
+            let (data, signature) = self.get(index).await?
+                .ok_or_else(|| anyhow!("Block {} is missing.", index))?;
+            writer.write_all(&(data.len() as u32).to_le_bytes()).await?;
+            writer.write_all(&data).await?;
+            writer.write_all(&signature.data().to_bytes()).await?;
+            writer.write_all(&signature.tree().to_bytes()).await?;
+        }
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Create a new instance from a blob of [Core::export],
+    /// verifying every block.
+    ///
+    /// The stores must be empty. The `Core` has no [SecretKey],
+    /// reopen it with one to append.
+    pub async fn import(data: D, blocks: B, state: S, blob: &[u8])
+        -> Result<Self>
+    {
+        Self::import_from(data, blocks, state, blob).await
+    }
+
+    /// Create a new instance from the format of [Core::export]
+    /// streamed from `reader`, like [Core::import],
+    /// holding a single block in memory.
+    pub async fn import_from<R>(data: D, blocks: B, state: S, mut reader: R)
+        -> Result<Self>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut header = [0u8; EXPORT_HEADER_LENGTH];
+        reader.read_exact(&mut header).await?;
+        let mut rdr = Cursor::new(&header[..]);
+        let mut magic = [0u8; EXPORT_MAGIC.len()];
+        rdr.read_exact(&mut magic)?;
+        ensure!(&magic == EXPORT_MAGIC, "Not a Core export, magic mismatch.");
+        let version = rdr.read_u8()?;
+        ensure!(version == EXPORT_VERSION,
+            "Unknown Core export version {}.", version);
+        let mut public_key = [0u8; PUBLIC_KEY_LENGTH];
+        rdr.read_exact(&mut public_key)?;
+        let public_key = PublicKey::from_bytes(&public_key)?;
+        let length = rdr.read_u32::<LittleEndian>()?;
+
+        // Persist the state once, at the end.
+        let options = CoreOptions {
+            lazy_state: true,
+            ..CoreOptions::default()
+        };
+        let mut core = Self::new_with_options(
+            data, blocks, state, public_key, None, options)
+            .await?;
+        ensure!(core.is_empty(), "Cannot import into a non-empty Core.");
+        for _ in 0..length {
+            let mut data_length = [0u8; size_of::<u32>()];
+            reader.read_exact(&mut data_length).await?;
+            let data_length = u32::from_le_bytes(data_length) as usize;
+            // grow with the data read, a bogus length must not allocate
+            let mut data = vec![];
+            (&mut reader).take(data_length as u64)
+                .read_to_end(&mut data).await?;
+            ensure!(data.len() == data_length, "Core export is truncated.");
+            let mut data_signature = [0u8; SIGNATURE_LENGTH];
+            reader.read_exact(&mut data_signature).await?;
+            let mut tree_signature = [0u8; SIGNATURE_LENGTH];
+            reader.read_exact(&mut tree_signature).await?;
+            let signature = BlockSignature::new(
+                Signature::from_bytes(&data_signature)?,
+                Signature::from_bytes(&tree_signature)?);
+            core.append(&data, Some(signature)).await?;
+        }
+        ensure!(reader.read(&mut [0u8; 1]).await? == 0,
+            "Trailing bytes after the Core export.");
+        core.flush().await?;
+        core.options.lazy_state = false;
+        Ok(core)
+    }
+
+    /// Export the current signed state of the `Core` as a [Checkpoint],
+    /// to be verified later with [verify_checkpoint].
+    ///
+    /// [verify_checkpoint]: crate::verify_checkpoint
+    pub async fn checkpoint(&mut self) -> Result<Checkpoint> {
+        ensure!(!self.unsigned, "Core is unsigned, has no signatures.");
+        let index = match self.len() {
+            0 => bail!("Core is empty, has no signed state."),
+            len => len - 1,
+        };
+        let block = self.blocks.read(index).await?;
+        let byte_length = self.merkle.roots().iter()
+            .map(|root| root.len())
+            .sum();
+        Ok(Checkpoint {
+            length: self.len(),
+            byte_length,
+            root_hash: self.merkle.root_hash(),
+            tree_signature: block.signature().tree(),
+        })
+    }
+
+    /// Build a [Proof] of inclusion of the block at `index`,
+    /// to be verified with [verify_proof] without the rest of the `Core`.
+    ///
+    /// Hashes the data of every block under the same merkle root,
+    /// up to the whole `Core` when its length is a power of two,
+    /// reading one block at a time.
+    ///
+    /// [verify_proof]: crate::verify_proof
+    pub async fn proof(&mut self, index: u32) -> Result<Option<Proof>> {
+        ensure!(!self.unsigned, "Core is unsigned, has no signatures.");
+        let length = self.len();
+        if index >= length {
+            return Ok(None)
+        }
+        let leaf = 2 * index as u64;
+        let root = self.merkle.roots().iter()
+            .map(|root| root.index())
+            .find(|&root| {
+                let (start, end) = flat_tree::spans(root);
+                start <= leaf && leaf <= end
+            })
+            .ok_or_else(|| anyhow!("Block {} is under no root.", index))?;
+        let (start, end) = flat_tree::spans(root);
+        let (start, end) = ((start / 2) as u32, (end / 2) as u32 + 1);
+
+        // siblings on the path from the leaf to the root, lowest first
+        let mut siblings = vec![];
+        let mut node = leaf;
+        while node != root {
+            siblings.push(flat_tree::sibling(node));
+            node = flat_tree::parent(node);
+        }
+        let mut nodes: Vec<Option<Node>> = vec![None; siblings.len()];
+
+        // fold the leaves into a stack of complete subtrees,
+        // at most one per level of the tree
+        let mut stack: Vec<Node> = vec![];
+        for i in start..end {
+            let block = self.blocks.read(i).await?;
+            let data = self.data.read(&block).await?;
+            let mut node = Node::new(
+                2 * i as u64, Hash::from_leaf(&data), data.len() as u64);
+            loop {
+                if let Some(position) = siblings.iter()
+                    .position(|&sibling| sibling == node.index())
+                {
+                    nodes[position] = Some(node.clone());
+                }
+                let left = match stack.last() {
+                    Some(left) if flat_tree::parent(left.index())
+                        == flat_tree::parent(node.index()) => left,
+                    _ => break,
+                };
+                node = proof::parent(left, &node)
+                    .ok_or_else(|| anyhow!("Merkle nodes are not siblings."))?;
+                stack.pop();
+            }
+            stack.push(node);
+        }
+        let nodes = nodes.into_iter()
+            .collect::<Option<Vec<Node>>>()
+            .ok_or_else(|| anyhow!("Merkle siblings of block {} missing.", index))?;
+
+        let block = self.blocks.read(length - 1).await?;
+        Ok(Some(Proof {
+            index,
+            nodes,
+            roots: self.merkle.roots().clone(),
+            tree_signature: block.signature().tree(),
+        }))
+    }
+
+    /// Rebuild the merkle state from the blocks and data stores, verifying
+    /// every block, and persist it.
+    ///
+    /// Recovers the longest valid prefix of the `Core`: stops at the end of
+    /// the blocks store, at a block referencing data missing from the data
+    /// store, or at the first block failing verification.
+    /// Fails without changes on an unsigned block, see [Core::append_unsigned],
+    /// or if reading the stores fails.
+    pub async fn rebuild_state(&mut self) -> Result<()> {
+        ensure!(!self.unsigned, "Core is unsigned, cannot verify.");
+        let count = self.blocks.count().await?;
+        // The data length was derived from the possibly lost state.
+        let data_length = self.data.store_len().await?;
+        self.data.set_len(Some(data_length));
+        let rebuilt = self.rebuild_merkle(count, data_length).await;
+        let (merkle, length, byte_length) = match rebuilt {
+            Ok(rebuilt) => rebuilt,
+            Err(err) => {
+                self.data.set_len(Some(self.byte_length));
+                return Err(err)
+            },
+        };
+
+        self.state.write(&merkle).await?;
+        self.state.flush().await?;
+        self.state_dirty = false;
+        self.merkle = merkle;
+        self.length = length;
+        self.byte_length = byte_length;
+        self.data.set_len(Some(byte_length));
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// Verify the first `count` blocks, see [Core::rebuild_state].
+    /// Returns the merkle state, length and byte length
+    /// of the valid prefix.
+    async fn rebuild_merkle(&mut self, count: u32, data_length: u64)
+        -> Result<(Merkle, u32, u64)>
+    {
+        let mut merkle = Merkle::new();
+        let mut length = 0;
+        let mut byte_length = 0;
+        while length < count && (length as usize) < MAX_CORE_LENGTH {
+            let block = self.blocks.read(length).await?;
+            // The persisted state may be lost, do not rely on self.unsigned.
+            ensure!(!block.signature().is_unsigned(),
+                "Block {} is unsigned, cannot verify.", length);
+            let end = block.offset().checked_add(block.length() as u64);
+            if block.offset() != byte_length
+                || end.is_none_or(|end| end > data_length)
+            {
+                break
+            }
+            let data = self.data.read(&block).await?;
+            let mut next = merkle.clone();
+            match self.check_block(length, &block, &data, byte_length, &mut next) {
+                Ok(data_length) => byte_length += data_length as u64,
+                Err(_) => break,
+            };
+            merkle = next;
+            length += 1;
+        }
+        Ok((merkle, length, byte_length))
+    }
+
+    /// Verify a [Block] at `index`, expected at `offset` in the data store,
+    /// and add it to the `merkle`. Returns the length of its data.
+    async fn verify_block(
+        &mut self,
+        index: u32,
+        block: &Block,
+        offset: u64,
+        merkle: &mut Merkle,
+        ) -> Result<u32>
+    {
+        let data = self.data.read(block).await?;
+        self.check_block(index, block, &data, offset, merkle)
+    }
+
+    /// Verify a [Block] at `index` with its `data`, expected at `offset`
+    /// in the data store, and add it to the `merkle`.
+    /// Returns the length of its data.
+    fn check_block(
+        &self,
+        index: u32,
+        block: &Block,
+        data: &[u8],
+        offset: u64,
+        merkle: &mut Merkle,
+        ) -> Result<u32>
+    {
+        ensure!(block.offset() == offset,
+            "Block {} has offset {}, expected {}.",
+            index, block.offset(), offset);
+        let signature = block.signature();
+
+        let data_hash = Hash::from_leaf(data);
+        verify(&self.public_key, &data_hash, &signature.data())
+            .map_err(|_| anyhow!("Block {} has invalid data signature.", index))?;
+        let root_hash = merkle.append_and_root(data_hash, data.len() as u64);
+        verify(&self.public_key, &root_hash, &signature.tree())
+            .map_err(|_| anyhow!("Block {} has invalid tree signature.", index))?;
+        Ok(block.length())
+    }
+
     /// Get the block of data at the tip of the feed.
     /// This will be the most recently appended block.
     #[inline]
@@ -181,31 +985,201 @@ where
         }
     }
     /// Retrieve data for a block at index.
+    ///
+    /// Fails for an unsigned `Core`, see [Core::get_data].
     #[inline]
     pub async fn get(&mut self, index: u32)
         -> Result<Option<(Vec<u8>, BlockSignature)>>
     {
+        ensure!(!self.unsigned,
+            "Core is unsigned, has no signatures; use get_data.");
+        ensure!((index as usize) < MAX_CORE_LENGTH);
+        let length = self.len();
+        if index >= length {
+            return Ok(None)
+        }
+        let block = self.blocks.read(index).await?;
+        let data = self.data.read(&block).await?;
+        let signature = block.signature();
+        if self.options.verify_on_read {
+            verify(&self.public_key, &Hash::from_leaf(&data),
+                   &signature.data())
+                .map_err(|_| anyhow!("Block {} failed verification.", index))?;
+        }
+        self.cache.insert(index, &data, &signature);
+        Ok(Some((data, signature)))
+    }
+    /// Retrieve data and [BlockSignature]s for the blocks `start..end`,
+    /// in a single read from each of the blocks and data stores.
+    pub async fn get_range(&mut self, start: u32, end: u32)
+        -> Result<Vec<(Vec<u8>, BlockSignature)>>
+    {
+        ensure!(!self.unsigned,
+            "Core is unsigned, has no signatures; use get_data.");
+        ensure!(start <= end && end <= self.len(),
+            "Range {}..{} out of bounds for Core of length {}.",
+            start, end, self.len());
+
+        let blocks = self.blocks.read_range(start, end - start).await?;
+        let data = self.data.read_range(&blocks).await?;
+        let mut result = Vec::with_capacity(blocks.len());
+        for (i, (block, data)) in blocks.iter().zip(data).enumerate() {
+            let signature = block.signature();
+            if self.options.verify_on_read {
+                verify(&self.public_key, &Hash::from_leaf(&data),
+                       &signature.data())
+                    .map_err(|_| anyhow!("Block {} failed verification.",
+                                         start as usize + i))?;
+            }
+            result.push((data, signature));
+        }
+        Ok(result)
+    }
+    /// Retrieve data for a block at index without awaiting storage,
+    /// if it is in the cache, see [CoreOptions::cache_size].
+    ///
+    /// On [TryGet::Miss] fall back to [Core::get], for example in a spawned
+    /// task. Always misses for an unsigned `Core`.
+    #[inline]
+    pub fn try_get(&self, index: u32) -> TryGet {
+        if index >= self.len() {
+            return TryGet::OutOfRange
+        }
+        match self.cache.get(index) {
+            Some(block) => TryGet::Hit(block.clone()),
+            None => TryGet::Miss,
+        }
+    }
+    /// Retrieve data for a block at index, without its signature.
+    #[inline]
+    pub async fn get_data(&mut self, index: u32) -> Result<Option<Vec<u8>>> {
         ensure!((index as usize) < MAX_CORE_LENGTH);
+        if index >= self.len() {
+            return Ok(None)
+        }
+        let block = self.blocks.read(index).await?;
+        Ok(Some(self.data.read(&block).await?))
+    }
+
+    /// Find the index of the first block with data hashing to the leaf
+    /// `hash`, see [Hash::from_leaf].
+    ///
+    /// Requires an index, see [Core::new_with_index].
+    pub async fn index_of(&mut self, hash: &Hash) -> Result<Option<u32>> {
+        let index = match &mut self.index {
+            Some(index) => index,
+            None => bail!("Core has no index, open it with new_with_index."),
+        };
+        index.get(hash).await
+    }
+
+    /// Retrieve [BlockSignature]s for `count` blocks starting at `start`,
+    /// without reading their data.
+    pub async fn signatures(&mut self, start: u32, count: u32)
+        -> Result<Vec<BlockSignature>>
+    {
+        ensure!(!self.unsigned, "Core is unsigned, has no signatures.");
+        let end = start as u64 + count as u64;
+        ensure!(end <= self.len() as u64,
+            "Range {}..{} out of bounds for Core of length {}.",
+            start, end, self.len());
+
+        let blocks = self.blocks.read_range(start, count).await?;
+        Ok(blocks.iter().map(|block| block.signature()).collect())
+    }
+
+    /// Retrieve the messages signed for the block at `index`:
+    /// the leaf [Hash] of its data, signed as the data [Signature],
+    /// and the [Merkle::root_hash] after it, signed as the tree [Signature].
+    ///
+    /// Anything but the last block recomputes the merkle root
+    /// from the data of all the blocks up to `index`.
+    ///
+    /// [Signature]: crate::Signature
+    pub async fn signed_messages(&mut self, index: u32)
+        -> Result<Option<(Hash, Hash)>>
+    {
         let length = self.len();
         if index >= length {
             return Ok(None)
         }
+        if index == length - 1 {
+            let block = self.blocks.read(index).await?;
+            let data = self.data.read(&block).await?;
+            return Ok(Some((Hash::from_leaf(&data), self.merkle.root_hash())))
+        }
+
+        let mut merkle = Merkle::new();
+        for i in 0..index {
+            let block = self.blocks.read(i).await?;
+            let data = self.data.read(&block).await?;
+            merkle.next(Hash::from_leaf(&data), data.len() as u64);
+        }
         let block = self.blocks.read(index).await?;
         let data = self.data.read(&block).await?;
-        Ok(Some((data, block.signature())))
+        let data_hash = Hash::from_leaf(&data);
+        let root_hash = merkle
+            .append_and_root(data_hash.clone(), data.len() as u64);
+        Ok(Some((data_hash, root_hash)))
+    }
+}
+
+impl<T> Core<Partition<T>, Partition<T>, Partition<T>>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    /// Create a new instance keeping the data, blocks and state
+    /// in a single `store`, see [Partition] for the layout.
+    pub async fn new_single(
+        store: T,
+        public_key: PublicKey,
+        secret_key: Option<SecretKey>,
+        ) -> Result<Self>
+    {
+        let (data, blocks, state) = Partition::split(store).await?;
+        Self::new(data, blocks, state, public_key, secret_key).await
+    }
+}
+
+impl<T> Core<Framed<T>, Framed<T>, Framed<T>>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    /// Create a new instance keeping every block framed with its data
+    /// in a single `store`, without a blocks store,
+    /// see [Framed] for the layout and its tradeoffs.
+    pub async fn new_framed(
+        store: T,
+        public_key: PublicKey,
+        secret_key: Option<SecretKey>,
+        ) -> Result<Self>
+    {
+        let (data, blocks, state) = Framed::split(store).await?;
+        Self::new(data, blocks, state, public_key, secret_key).await
     }
 }
 
-#[inline]
-fn hash_merkle(merkle: &Merkle) -> Hash {
-    let roots = merkle.roots();
-    let hashes = roots.iter()
-        .map(|root| root.hash())
-        .collect::<Vec<&Hash>>();
-    let lengths = roots.iter()
-        .map(|root| root.len())
-        .collect::<Vec<u64>>();
-    Hash::from_roots(&hashes, &lengths)
+/// Add a block with leaf `data_hash` to `merkle`, verifying
+/// the `signature` of its data and of the new root hash.
+///
+/// A block with valid data signature but not signed on top of `merkle`
+/// is reported as a fork.
+/// `merkle` is left with the block added even if verification fails,
+/// pass a clone to keep the original.
+pub fn append_verified(
+    public_key: &PublicKey,
+    merkle: &mut Merkle,
+    data_hash: Hash,
+    length: u64,
+    signature: &BlockSignature,
+    ) -> Result<()>
+{
+    verify(public_key, &data_hash, &signature.data())?;
+    let root_hash = merkle.append_and_root(data_hash, length);
+    // The writer signed the data, but not on top of this tree.
+    verify(public_key, &root_hash, &signature.tree())
+        .map_err(|_| anyhow!("Fork detected, the block does not extend \
+                              the merkle tree."))
 }
 
 #[cfg(test)]
@@ -0,0 +1,132 @@
+use anyhow::{anyhow, Result};
+use std::error::Error;
+use std::fmt::Debug;
+
+use crate::{Core, CoreOptions, PublicKey, RandomAccess, SecretKey};
+
+/// Builder for a [Core], naming each of its stores and keys.
+///
+/// ```rust
+/// # use random_access_memory::RandomAccessMemory;
+/// # use datacore::{CoreBuilder, generate_keypair};
+/// # fn main() -> anyhow::Result<()> {
+/// # async_std::task::block_on(async {
+/// let keypair = generate_keypair();
+/// let mut core = CoreBuilder::new()
+///     .data(RandomAccessMemory::new(1024))
+///     .blocks(RandomAccessMemory::new(1024))
+///     .state(RandomAccessMemory::new(1024))
+///     .public_key(keypair.public)
+///     .secret_key(keypair.secret)
+///     .build()
+///     .await?;
+/// core.append(b"hello", None).await?;
+/// # Ok(())
+/// # })
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CoreBuilder<D, B, S> {
+    data: Option<D>,
+    blocks: Option<B>,
+    state: Option<S>,
+    index: Option<S>,
+    public_key: Option<PublicKey>,
+    secret_key: Option<SecretKey>,
+    options: CoreOptions,
+}
+
+impl<D, B, S> Default for CoreBuilder<D, B, S> {
+    fn default() -> Self {
+        Self {
+            data: None,
+            blocks: None,
+            state: None,
+            index: None,
+            public_key: None,
+            secret_key: None,
+            options: CoreOptions::default(),
+        }
+    }
+}
+
+impl<D, B, S> CoreBuilder<D, B, S>
+where
+    D: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+    B: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+    S: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    /// Create an empty [CoreBuilder].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the block data store, required.
+    pub fn data(mut self, data: D) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// Set the blocks store, required.
+    pub fn blocks(mut self, blocks: B) -> Self {
+        self.blocks = Some(blocks);
+        self
+    }
+
+    /// Set the merkle state store, required.
+    pub fn state(mut self, state: S) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Set the store of the leaf [Hash] to block index map,
+    /// see [Core::new_with_index].
+    ///
+    /// [Hash]: crate::Hash
+    pub fn index(mut self, index: S) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Set the [PublicKey], required.
+    pub fn public_key(mut self, public_key: PublicKey) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+
+    /// Set the [SecretKey], to append to the [Core].
+    pub fn secret_key(mut self, secret_key: SecretKey) -> Self {
+        self.secret_key = Some(secret_key);
+        self
+    }
+
+    /// Set the [CoreOptions].
+    pub fn options(mut self, options: CoreOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Open the [Core].
+    ///
+    /// Fails if a required store or the [PublicKey] is missing.
+    pub async fn build(self) -> Result<Core<D, B, S>> {
+        let data = self.data
+            .ok_or_else(|| anyhow!("Missing data store."))?;
+        let blocks = self.blocks
+            .ok_or_else(|| anyhow!("Missing blocks store."))?;
+        let state = self.state
+            .ok_or_else(|| anyhow!("Missing state store."))?;
+        let public_key = self.public_key
+            .ok_or_else(|| anyhow!("Missing public key."))?;
+        match self.index {
+            None => Core::new_with_options(
+                data, blocks, state, public_key, self.secret_key,
+                self.options)
+                .await,
+            Some(index) => Core::new_with_index(
+                data, blocks, state, index, public_key, self.secret_key,
+                self.options)
+                .await,
+        }
+    }
+}
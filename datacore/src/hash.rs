@@ -79,6 +79,49 @@ impl Hash {
     }
 }
 
+/// Incremental [Hash::from_leaf], for data too large to hold in memory.
+///
+/// The leaf hash is prefixed with the data length,
+/// so the total `length` must be known up front.
+#[derive(Debug, Clone)]
+pub struct HashLeafBuilder {
+    hasher: Hasher,
+    length: u64,
+    written: u64,
+}
+
+impl HashLeafBuilder {
+    /// Create a new [HashLeafBuilder] for `length` bytes of data.
+    #[inline]
+    pub fn new(length: u64) -> Self {
+        let mut hasher = Hasher::new();
+        hasher.update(&LEAF_TYPE);
+        hasher.update(&u64_to_bytes(length));
+        Self {
+            hasher,
+            length,
+            written: 0,
+        }
+    }
+
+    /// Hash the next `chunk` of data.
+    #[inline]
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.written += chunk.len() as u64;
+    }
+
+    /// Finish the leaf `Hash`.
+    /// Fails if the data hashed does not match the `length`.
+    #[inline]
+    pub fn finalize(self) -> Result<Hash> {
+        ensure!(self.written == self.length,
+            "Hashed {} bytes, expected {}.", self.written, self.length);
+        let hash = self.hasher.finalize().into();
+        Ok(Hash { hash })
+    }
+}
+
 impl Deref for Hash {
     type Target = [u8];
 
@@ -108,6 +151,21 @@ mod tests {
         assert_eq!(hash.as_bytes(), &hex_bytes(hex)[..]);
     }
 
+    #[test]
+    fn leaf_hash_builder() -> Result<()> {
+        let data = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let mut builder = HashLeafBuilder::new(data.len() as u64);
+        for chunk in data.chunks(3) {
+            builder.update(chunk);
+        }
+        assert_eq!(builder.finalize()?, Hash::from_leaf(&data));
+
+        let mut builder = HashLeafBuilder::new(data.len() as u64);
+        builder.update(&data[1..]);
+        assert!(builder.finalize().is_err());
+        Ok(())
+    }
+
     #[test]
     fn leaf_hash() {
         check_hash(
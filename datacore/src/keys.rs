@@ -1,10 +1,12 @@
 //! Generate a `Keypair`, sign and verify messages with `Keypair`.
-//! Uses `Ed25519` cryptography.
+//! Uses `Ed25519` cryptography, see [Verifier] for the scheme.
 
-use anyhow::{Result, ensure};
+use anyhow::Result;
 use rand::rngs::{OsRng, StdRng};
 use rand::SeedableRng;
-use ed25519_dalek::{ExpandedSecretKey, Verifier};
+use ed25519_dalek::ExpandedSecretKey;
+
+use crate::scheme::Verifier;
 
 pub use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature};
 
@@ -26,8 +28,7 @@ pub fn verify(
     signature: &Signature,
     ) -> Result<()>
 {
-    ensure!(public.verify(msg, signature).is_ok(), "Signature invalid.");
-    Ok(())
+    public.verify(msg, signature)
 }
 
 #[cfg(test)]
@@ -42,16 +42,47 @@
 //! # })
 //! # }
 //! ```
+//!
+//! ## Storage format
+//! The blocks and state stores start with a header, a magic and the
+//! format version, written when the store is created. Opening a store
+//! of an unknown version fails instead of misreading the data.
+//!
+//! Stores written before the header was introduced have none, they are
+//! detected by its absence and keep being read and written in their
+//! original layout, so existing cores open unchanged with no migration.
+//! To move a `Core` to the current layout, replicate it into a new one.
+//!
+//! ## Signature schemes
+//! Blocks are signed through the [Signer] and [Verifier] traits,
+//! implemented for the default `Ed25519` scheme by [Keypair] and
+//! [PublicKey]. A `Core` is currently always `Ed25519`.
+//!
+//! The stores hold signatures of [Verifier::SIGNATURE_LENGTH] bytes,
+//! so existing cores keep working unchanged but cannot switch schemes
+//! in place. Migrate to another scheme by appending the data of the old
+//! `Core` into a new one, signed by the new [Signer]; its [PublicKey]
+//! and signatures differ, so peers must follow the new `Core`.
 
 mod block;
 mod store_data;
 mod store_blocks;
 mod store_state;
+mod store_header;
+mod store_index;
+mod store_single;
+mod store_framed;
 mod merkle_tree_stream;
 mod keys;
+mod scheme;
 mod hash;
 mod merkle;
+mod checkpoint;
+mod proof;
+mod verify_cursor;
+mod block_cache;
 mod core;
+mod core_builder;
 
 pub use random_access_storage::RandomAccess;
 pub use block::{Signature, BlockSignature, Block, SIGNATURE_LENGTH};
@@ -59,6 +90,18 @@ pub use keys::{
     Keypair, PublicKey, SecretKey,
     generate_keypair, sign, verify
 };
-pub use hash::Hash;
+pub use scheme::{Signer, Verifier};
+pub use hash::{Hash, HashLeafBuilder};
+pub use store_single::{Partition, PAGE_SIZE};
+pub use store_framed::Framed;
+pub use checkpoint::{Checkpoint, verify_checkpoint};
+pub use proof::{Proof, verify_proof};
+pub use verify_cursor::VerifyCursor;
 pub use merkle::{Merkle, Node, NodeTrait};
-pub use self::core::{Core, MAX_CORE_LENGTH, MAX_BLOCK_SIZE};
+pub use self::core::{
+    Core, CoreOptions, TryGet, AppendHook, append_verified,
+    MAX_CORE_LENGTH, MAX_BLOCK_SIZE,
+};
+#[cfg(feature = "parallel")]
+pub use self::core::VERIFY_BATCH;
+pub use core_builder::CoreBuilder;
@@ -1,10 +1,12 @@
 use anyhow::{Result, ensure};
+use std::collections::HashMap;
 use std::mem::size_of;
 use std::io::{Cursor, Read};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
-use crate::merkle_tree_stream::{HashMethods, MerkleTreeStream};
+use crate::merkle_tree_stream::{HashMethods, MerkleTreeStream, flat_tree};
 use crate::hash::{Hash, HASH_SIZE};
+use crate::proof::parent;
 
 pub use crate::merkle_tree_stream::Node as NodeTrait;
 
@@ -36,6 +38,9 @@ impl Node {
     }
 
     /// Serialize [Node].
+    ///
+    /// The layout is fixed across platforms: the index and the length
+    /// little endian, then the hash.
     #[inline]
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
         let mut data = Vec::with_capacity(NODE_SIZE);
@@ -95,6 +100,7 @@ impl HashMethods for H {
 #[derive(Debug, Clone)]
 pub struct Merkle {
     stream: MerkleTreeStream<H>,
+    nodes: Option<HashMap<u64, Node>>,
 }
 
 impl Merkle {
@@ -109,13 +115,31 @@ impl Merkle {
     pub fn from_roots(roots: Vec<Node>) -> Self {
         Self {
             stream: MerkleTreeStream::new(H, roots),
+            nodes: None,
+        }
+    }
+
+    /// Create a new [Merkle] keeping every [Node] of the tree,
+    /// to build proofs with [Merkle::proof].
+    ///
+    /// Keeps two nodes per block in memory.
+    #[inline]
+    pub fn with_nodes() -> Self {
+        Self {
+            stream: MerkleTreeStream::new(H, vec![]),
+            nodes: Some(HashMap::new()),
         }
     }
 
     /// Access the next item.
     #[inline]
     pub fn next(&mut self, data: Hash, length: u64) {
-        self.stream.next(data, length);
+        match &mut self.nodes {
+            Some(nodes) => self.stream.next_with(data, length, |node| {
+                nodes.insert(node.index, node.clone());
+            }),
+            None => self.stream.next(data, length),
+        }
     }
 
     /// Get the roots vector.
@@ -137,6 +161,74 @@ impl Merkle {
     pub fn blocks(&self) -> u64 {
         self.stream.blocks()
     }
+
+    /// Get the `Hash` of the roots, signed as the tree signature
+    /// of a block.
+    #[inline]
+    pub fn root_hash(&self) -> Hash {
+        let roots = self.stream.roots();
+        let hashes = roots.iter()
+            .map(|root| root.hash())
+            .collect::<Vec<&Hash>>();
+        let lengths = roots.iter()
+            .map(|root| root.len())
+            .collect::<Vec<u64>>();
+        Hash::from_roots(&hashes, &lengths)
+    }
+
+    /// Get the audit path of the block at `block_index`: the siblings
+    /// on the path from its leaf to the root above it, lowest first.
+    ///
+    /// `None` if the block is out of range, or the [Merkle]
+    /// was not created with [Merkle::with_nodes].
+    pub fn proof(&self, block_index: u64) -> Option<Vec<Node>> {
+        let nodes = self.nodes.as_ref()?;
+        if block_index >= self.blocks() {
+            return None
+        }
+        let leaf = 2 * block_index;
+        let root = self.roots().iter()
+            .find(|root| {
+                let (start, end) = flat_tree::spans(root.index);
+                start <= leaf && leaf <= end
+            })?;
+        let mut index = leaf;
+        let mut path = vec![];
+        while index != root.index {
+            path.push(nodes.get(&flat_tree::sibling(index))?.clone());
+            index = flat_tree::parent(index);
+        }
+        Some(path)
+    }
+
+    /// Verify that the leaf `hash` of a block of `length` bytes at
+    /// `block_index` is under the root [Node] with `root_hash`,
+    /// given its audit path, see [Merkle::proof].
+    pub fn verify(
+        root_hash: &Hash,
+        block_index: u64,
+        leaf_hash: &Hash,
+        length: u64,
+        path: &[Node],
+        ) -> bool
+    {
+        let mut node = Node::new(2 * block_index, leaf_hash.clone(), length);
+        for sibling in path {
+            node = match parent(&node, sibling) {
+                Some(node) => node,
+                None => return false,
+            };
+        }
+        node.hash == *root_hash
+    }
+
+    /// Add the leaf `hash` of a block of `length` bytes,
+    /// returning the new [Merkle::root_hash].
+    #[inline]
+    pub fn append_and_root(&mut self, hash: Hash, length: u64) -> Hash {
+        self.next(hash, length);
+        self.root_hash()
+    }
 }
 
 #[cfg(test)]
@@ -157,6 +249,18 @@ mod tests {
         assert_eq!(node2, *node);
     }
 
+    #[test]
+    fn node_golden() -> Result<()> {
+        let hash = Hash::from_bytes(&[9u8; HASH_SIZE])?;
+        let node = Node::new(5, hash, 0x0102);
+        let expected = format!("{}{}{}",
+            "0500000000000000", "0201000000000000",
+            "09".repeat(HASH_SIZE));
+        assert_eq!(hex::encode(node.to_bytes()?), expected);
+        assert_eq!(Node::from_bytes(&hex::decode(expected)?)?, node);
+        Ok(())
+    }
+
     #[test]
     fn next() {
         let mut merkle = Merkle::new();
@@ -176,6 +280,55 @@ mod tests {
         assert_eq!(merkle.blocks(), 2);
     }
 
+    #[test]
+    fn append_and_root() {
+        let mut merkle = Merkle::new();
+        let mut expected = Merkle::new();
+        for data in ["a", "bb", "ccc"] {
+            let hash = Hash::from_leaf(data.as_bytes());
+            expected.next(hash.clone(), data.len() as u64);
+            let root = merkle.append_and_root(hash, data.len() as u64);
+            assert_eq!(root, expected.root_hash());
+        }
+        assert_eq!(merkle.blocks(), 3);
+        assert_ne!(merkle.root_hash(), Merkle::new().root_hash());
+    }
+
+    #[test]
+    fn proof_verify() {
+        let data = ["a", "bb", "ccc", "dddd", "eeeee"];
+        let mut merkle = Merkle::with_nodes();
+        for block in data {
+            merkle.next(Hash::from_leaf(block.as_bytes()), block.len() as u64);
+        }
+        assert_eq!(merkle.roots().len(), 2);
+        assert!(merkle.proof(5).is_none());
+        assert!(Merkle::new().proof(0).is_none());
+
+        for (i, block) in data.iter().enumerate() {
+            let i = i as u64;
+            let path = merkle.proof(i).unwrap();
+            let root = merkle.roots().iter()
+                .find(|root| flat_tree::right_span(root.index()) >= 2 * i)
+                .unwrap();
+            let leaf = Hash::from_leaf(block.as_bytes());
+            let length = block.len() as u64;
+            assert!(Merkle::verify(root.hash(), i, &leaf, length, &path));
+            let oops = Hash::from_leaf(b"oops");
+            assert!(!Merkle::verify(root.hash(), i, &oops, length, &path));
+
+            if let Some(sibling) = path.first() {
+                let mut corrupted = path.clone();
+                corrupted[0] = Node::new(
+                    sibling.index(), oops.clone(), sibling.len());
+                assert!(!Merkle::verify(
+                    root.hash(), i, &leaf, length, &corrupted));
+            }
+        }
+        assert_eq!(merkle.proof(2).unwrap().len(), 2);
+        assert!(merkle.proof(4).unwrap().is_empty());
+    }
+
     #[test]
     fn roots_full() {
         let mut merkle = Merkle::new();
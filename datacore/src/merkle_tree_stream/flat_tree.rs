@@ -81,6 +81,13 @@ pub fn parent(i: u64) -> u64 {
     index(depth + 1, offset(i) >> 1)
 }
 
+/// Returns the sibling of a node, the other child of its parent.
+#[inline]
+pub fn sibling(i: u64) -> u64 {
+    let depth = self::depth(i);
+    index(depth, offset(i) ^ 1)
+}
+
 /// Returns only the left child of a node.
 #[inline]
 pub fn left_child(i: u64) -> Option<u64> {
@@ -207,6 +214,12 @@ mod tests {
         assert_eq!(parent(3), 7);
         assert_eq!(parent(4), 5);
 
+        assert_eq!(sibling(0), 2);
+        assert_eq!(sibling(2), 0);
+        assert_eq!(sibling(1), 5);
+        assert_eq!(sibling(5), 1);
+        assert_eq!(sibling(11), 3);
+
         assert_eq!(left_child(0), None);
         assert_eq!(left_child(1), Some(0));
         assert_eq!(left_child(3), Some(1));
@@ -1,4 +1,4 @@
-mod flat_tree;
+pub(crate) mod flat_tree;
 
 /// Functions that need to be implemented for `MerkleTreeStream`.
 pub trait HashMethods {
@@ -86,10 +86,19 @@ impl<H: HashMethods> MerkleTreeStream<H> {
     /// Pass a string buffer through the flat-tree hash functions.
     #[inline]
     pub fn next(&mut self, hash: H::Hash, length: u64) {
+        self.next_with(hash, length, |_| {});
+    }
+
+    /// Like [MerkleTreeStream::next], calling `on_node` with every node
+    /// created: the leaf, then its new parents bottom up.
+    pub fn next_with<F>(&mut self, hash: H::Hash, length: u64, mut on_node: F)
+        where F: FnMut(&H::Node)
+    {
         let index: u64 = 2 * self.blocks;
         self.blocks += 1;
 
         let node = H::Node::new(index, hash, length);
+        on_node(&node);
         self.roots.push(node);
 
         while self.roots.len() > 1 {
@@ -112,6 +121,7 @@ impl<H: HashMethods> MerkleTreeStream<H> {
             for _ in 0..2 {
                 self.roots.pop();
             }
+            on_node(&leaf);
             self.roots.push(leaf);
         }
     }
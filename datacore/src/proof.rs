@@ -0,0 +1,78 @@
+use anyhow::{Result, anyhow, ensure};
+
+use crate::hash::Hash;
+use crate::merkle::{Node, NodeTrait};
+use crate::merkle_tree_stream::flat_tree;
+use crate::{PublicKey, Signature, verify};
+
+/// [Proof] of inclusion of a block in a `Core`, see [Core::proof].
+///
+/// Verify it with [verify_proof] against the data of the block,
+/// without the rest of the `Core`.
+///
+/// [Core::proof]: crate::Core::proof
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Proof {
+    /// Index of the proven block.
+    pub index: u32,
+    /// Siblings on the path from the block to its root, lowest first.
+    pub nodes: Vec<Node>,
+    /// Roots of the merkle tree, one of them above the block.
+    pub roots: Vec<Node>,
+    /// Writer's tree signature of the hash of the `roots`,
+    /// the tree signature of the last block of the `Core`.
+    pub tree_signature: Signature,
+}
+
+/// Verify that `data` is the block at `index` of the `Core` of
+/// `public_key`, as described by the [Proof].
+///
+/// Recomputes the root above the block from its siblings, checks it is
+/// one of the `roots`, and that the writer signed the hash of the `roots`.
+/// Returns `false` if any of it does not match,
+/// fails on a malformed [Proof].
+pub fn verify_proof(
+    public_key: &PublicKey,
+    index: u32,
+    data: &[u8],
+    proof: &Proof,
+    ) -> Result<bool>
+{
+    ensure!(proof.index == index,
+        "Proof is for block {}, not {}.", proof.index, index);
+    let mut node = Node::new(
+        2 * index as u64, Hash::from_leaf(data), data.len() as u64);
+    for sibling in &proof.nodes {
+        node = parent(&node, sibling)
+            .ok_or_else(|| anyhow!("Proof nodes are not siblings."))?;
+    }
+    if !proof.roots.contains(&node) {
+        return Ok(false)
+    }
+
+    let hashes = proof.roots.iter()
+        .map(|root| root.hash())
+        .collect::<Vec<&Hash>>();
+    let lengths = proof.roots.iter()
+        .map(|root| root.len())
+        .collect::<Vec<u64>>();
+    let root_hash = Hash::from_roots(&hashes, &lengths);
+    Ok(verify(public_key, &root_hash, &proof.tree_signature).is_ok())
+}
+
+/// The parent [Node] of two siblings, `None` if they are not siblings.
+pub(crate) fn parent(node: &Node, sibling: &Node) -> Option<Node> {
+    let index = flat_tree::parent(node.index());
+    if node.index() == sibling.index()
+        || index != flat_tree::parent(sibling.index())
+    {
+        return None
+    }
+    let (left, right) = match node.index() < sibling.index() {
+        true => (node, sibling),
+        false => (sibling, node),
+    };
+    let length = left.len() + right.len();
+    let hash = Hash::from_hashes(left.hash(), right.hash(), length);
+    Some(Node::new(index, hash, length))
+}
@@ -0,0 +1,67 @@
+//! [Signer] and [Verifier] abstract the signature scheme of a `Core`.
+//! `Ed25519` is the only implementation, for [Keypair] and [PublicKey].
+//!
+//! The stores and the wire format hold signatures of a fixed length,
+//! [Verifier::SIGNATURE_LENGTH], so a `Core` cannot change its scheme
+//! in place, see the crate documentation for migrating.
+
+use anyhow::{Result, ensure};
+use crate::keys::{Keypair, PublicKey, Signature, sign};
+
+/// Sign messages, the writer side of a signature scheme.
+pub trait Signer {
+    /// Signature produced by the scheme.
+    type Signature;
+
+    /// Sign a byte slice.
+    fn sign(&self, msg: &[u8]) -> Result<Self::Signature>;
+}
+
+/// Verify signed messages, the reader side of a signature scheme.
+pub trait Verifier {
+    /// Signature checked by the scheme.
+    type Signature;
+    /// Length of a serialized [Verifier::Signature], in bytes.
+    const SIGNATURE_LENGTH: usize;
+
+    /// Verify a `signature` of a byte slice.
+    fn verify(&self, msg: &[u8], signature: &Self::Signature) -> Result<()>;
+}
+
+impl Signer for Keypair {
+    type Signature = Signature;
+
+    #[inline]
+    fn sign(&self, msg: &[u8]) -> Result<Signature> {
+        Ok(sign(&self.public, &self.secret, msg))
+    }
+}
+
+impl Verifier for PublicKey {
+    type Signature = Signature;
+    const SIGNATURE_LENGTH: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+    #[inline]
+    fn verify(&self, msg: &[u8], signature: &Signature) -> Result<()> {
+        ensure!(
+            ed25519_dalek::Verifier::verify(self, msg, signature).is_ok(),
+            "Signature invalid.");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::generate_keypair;
+
+    #[test]
+    fn sign_verify() -> Result<()> {
+        let keypair = generate_keypair();
+        let signature = Signer::sign(&keypair, b"hello")?;
+        assert!(keypair.public.verify(b"hello", &signature).is_ok());
+        assert!(keypair.public.verify(b"oops", &signature).is_err());
+        assert_eq!(signature.to_bytes().len(), PublicKey::SIGNATURE_LENGTH);
+        Ok(())
+    }
+}
@@ -4,23 +4,36 @@ use std::fmt::Debug;
 
 use random_access_storage::RandomAccess;
 use crate::block::{Block, BLOCK_LENGTH};
+use crate::store_header::{self, BLOCKS_MAGIC};
 
 /// Save data to a desired storage backend.
+///
+/// Stored after a header, see [store_header::open],
+/// as consecutive serialized `Block`s.
 #[derive(Debug)]
 pub struct StoreBlocks<T>
 where
     T: Debug,
 {
     store: T,
+    /// Offset of the first `Block`, `0` in a legacy store without header.
+    start: u64,
 }
 impl<T> StoreBlocks<T>
 where
     T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
 {
-    /// Create a new [StoreBlocks] from [RandomAccess] interface.
+    /// Open a [StoreBlocks] from [RandomAccess] interface,
+    /// writing the header if the store is empty.
+    pub async fn open(mut store: T) -> Result<Self> {
+        let start = store_header::open(&mut store, BLOCKS_MAGIC).await?;
+        Ok(Self { store, start })
+    }
+
+    /// Offset of the `Block` at `index` in the store.
     #[inline]
-    pub fn new(store: T) -> Self {
-        Self { store }
+    fn offset(&self, index: u32) -> u64 {
+        self.start + index as u64 * BLOCK_LENGTH as u64
     }
 
     /// Write a `Block`.
@@ -31,7 +44,7 @@ where
         block: &Block,
         ) -> Result<()>
     {
-        let offset: u64 = (index as u64) * (BLOCK_LENGTH as u64);
+        let offset = self.offset(index);
         let data = block.to_bytes()?;
         ensure!(data.len() == BLOCK_LENGTH as usize);
 
@@ -40,6 +53,12 @@ where
             .await.map_err(|e| anyhow!(e))
     }
 
+    /// Flush written data to the store, see [RandomAccess::flush].
+    #[inline]
+    pub async fn flush(&mut self) -> Result<()> {
+        self.store.flush().await.map_err(|e| anyhow!(e))
+    }
+
     /// Read a `Block`.
     #[inline]
     pub async fn read(
@@ -47,14 +66,58 @@ where
         index: u32,
         ) -> Result<Block>
     {
-        let offset: u64 = (index as u64) * (BLOCK_LENGTH as u64);
-        ensure!(offset + BLOCK_LENGTH as u64 <= u64::MAX);
+        let offset = self.offset(index);
 
         let data = self.store
             .read(offset, BLOCK_LENGTH as u64)
             .await.map_err(|e| anyhow!(e))?;
         Block::from_bytes(&data)
     }
+
+    /// Number of complete `Block`s in the store.
+    #[inline]
+    pub async fn count(&mut self) -> Result<u32> {
+        let len = self.store.len().await.map_err(|e| anyhow!(e))?;
+        let count = len.saturating_sub(self.start) / BLOCK_LENGTH as u64;
+        Ok(count.min(u32::MAX as u64) as u32)
+    }
+
+    /// Delete the `Block`s from `length` on.
+    #[inline]
+    pub async fn truncate(&mut self, length: u32) -> Result<()> {
+        let offset = self.offset(length);
+        let len = self.store.len().await.map_err(|e| anyhow!(e))?;
+        if len > offset {
+            self.store
+                .delete(offset, len - offset)
+                .await.map_err(|e| anyhow!(e))?;
+        }
+        Ok(())
+    }
+
+    /// Read `count` consecutive `Block`s starting at `start`,
+    /// in a single read from the store.
+    #[inline]
+    pub async fn read_range(
+        &mut self,
+        start: u32,
+        count: u32,
+        ) -> Result<Vec<Block>>
+    {
+        if count == 0 {
+            return Ok(vec![]);
+        }
+        let offset = self.offset(start);
+        let length: u64 = (count as u64) * (BLOCK_LENGTH as u64);
+
+        let data = self.store
+            .read(offset, length)
+            .await.map_err(|e| anyhow!(e))?;
+        ensure!(data.len() as u64 == length);
+        data.chunks(BLOCK_LENGTH)
+            .map(Block::from_bytes)
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -71,13 +134,13 @@ mod tests {
 
     #[test]
     pub async fn init() -> Result<()> {
-        StoreBlocks::new(ram());
+        StoreBlocks::open(ram()).await?;
         Ok(())
     }
 
     #[test]
     pub async fn write_read() -> Result<()> {
-        let mut store = StoreBlocks::new(ram());
+        let mut store = StoreBlocks::open(ram()).await?;
         let data = Signature::from_bytes(&[2u8; SIGNATURE_LENGTH])?;
         let tree = Signature::from_bytes(&[7u8; SIGNATURE_LENGTH])?;
         let signature = BlockSignature::new(data, tree);
@@ -87,4 +150,43 @@ mod tests {
         assert_eq!(block, block2);
         Ok(())
     }
+
+    #[test]
+    pub async fn read_range() -> Result<()> {
+        let mut store = StoreBlocks::open(ram()).await?;
+        for i in 0..5u8 {
+            let data = Signature::from_bytes(&[i; SIGNATURE_LENGTH])?;
+            let tree = Signature::from_bytes(&[i + 1; SIGNATURE_LENGTH])?;
+            let signature = BlockSignature::new(data, tree);
+            let block = Block::new(i as u64 * 8, 8, signature);
+            store.write(i as u32, &block).await?;
+        }
+        let mut blocks = vec![];
+        for i in 1..4 {
+            blocks.push(store.read(i).await?);
+        }
+        assert_eq!(store.read_range(1, 3).await?, blocks);
+        assert!(store.read_range(0, 0).await?.is_empty());
+        assert!(store.read_range(3, 3).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub async fn open_legacy() -> Result<()> {
+        let data = Signature::from_bytes(&[2u8; SIGNATURE_LENGTH])?;
+        let tree = Signature::from_bytes(&[7u8; SIGNATURE_LENGTH])?;
+        let signature = BlockSignature::new(data, tree);
+        let block = Block::new(0, 8, signature);
+        let mut legacy = ram();
+        legacy.write(0, &block.to_bytes()?).await.map_err(|e| anyhow!(e))?;
+
+        let mut store = StoreBlocks::open(legacy).await?;
+        assert_eq!(store.count().await?, 1);
+        assert_eq!(store.read(0).await?, block);
+        store.write(1, &block).await?;
+        assert_eq!(store.count().await?, 2);
+        assert_eq!(store.store.len().await.map_err(|e| anyhow!(e))?,
+            2 * BLOCK_LENGTH as u64);
+        Ok(())
+    }
 }
@@ -1,4 +1,5 @@
 use anyhow::{anyhow, ensure, Result};
+use std::borrow::Cow;
 use std::error::Error;
 use std::fmt::Debug;
 
@@ -12,6 +13,8 @@ where
     T: Debug,
 {
     store: T,
+    compressed: bool,
+    len: Option<u64>,
 }
 impl<T> StoreData<T>
 where
@@ -20,10 +23,36 @@ where
     /// Create a new [StoreData] from [RandomAccess] interface.
     #[inline]
     pub fn new(store: T) -> Self {
-        Self { store }
+        Self { store, compressed: false, len: None }
+    }
+    /// Create a new [StoreData] from [RandomAccess] interface,
+    /// compressing data at rest.
+    #[inline]
+    pub fn new_compressed(store: T) -> Self {
+        Self { store, compressed: true, len: None }
+    }
+
+    /// Set the length of the data in use in the store,
+    /// reads of a `Block` beyond it fail, see [StoreData::read].
+    /// `None` when unknown, reads are then not checked.
+    ///
+    /// Grows with every [StoreData::write].
+    #[inline]
+    pub fn set_len(&mut self, len: Option<u64>) {
+        self.len = len;
+    }
+
+    /// Encode data as stored, the `Block` describes the encoded data.
+    #[inline]
+    pub fn encode<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if !self.compressed {
+            return Ok(Cow::Borrowed(data))
+        }
+        let compressed = snap::raw::Encoder::new().compress_vec(data)?;
+        Ok(Cow::Owned(compressed))
     }
 
-    /// Write data for a `Block`.
+    /// Write encoded data for a `Block`, see [StoreData::encode].
     #[inline]
     pub async fn write(
         &mut self,
@@ -36,10 +65,43 @@ where
 
         self.store
             .write(offset as u64, &data)
-            .await.map_err(|e| anyhow!(e))
+            .await.map_err(|e| anyhow!(e))?;
+        if let Some(len) = &mut self.len {
+            *len = (*len).max(offset + length as u64);
+        }
+        Ok(())
+    }
+
+    /// Flush written data to the store, see [RandomAccess::flush].
+    #[inline]
+    pub async fn flush(&mut self) -> Result<()> {
+        self.store.flush().await.map_err(|e| anyhow!(e))
+    }
+
+    /// Length of the store, including data not in use,
+    /// see [StoreData::set_len].
+    #[inline]
+    pub async fn store_len(&mut self) -> Result<u64> {
+        self.store.len().await.map_err(|e| anyhow!(e))
+    }
+
+    /// Delete the data from `offset` on, see [StoreData::set_len].
+    #[inline]
+    pub async fn truncate(&mut self, offset: u64) -> Result<()> {
+        let len = self.store.len().await.map_err(|e| anyhow!(e))?;
+        if len > offset {
+            self.store
+                .delete(offset, len - offset)
+                .await.map_err(|e| anyhow!(e))?;
+        }
+        self.len = Some(offset);
+        Ok(())
     }
 
     /// Read data for a `Block`.
+    ///
+    /// Fails if the `Block` references data beyond the length of the store,
+    /// see [StoreData::set_len].
     #[inline]
     pub async fn read(
         &mut self,
@@ -47,10 +109,59 @@ where
         ) -> Result<Vec<u8>>
     {
         let (offset, length) = verify_span(block_to_span(&node))?;
+        if let Some(len) = self.len {
+            ensure!(offset + length as u64 <= len,
+                "Block references data beyond store bounds ({}..{} > {}), \
+                core corrupt.", offset, offset + length as u64, len);
+        }
 
-        self.store
+        let data = self.store
             .read(offset, length as u64)
-            .await.map_err(|e| anyhow!(e))
+            .await.map_err(|e| anyhow!(e))?;
+        if !self.compressed {
+            return Ok(data)
+        }
+        let data = snap::raw::Decoder::new().decompress_vec(&data)?;
+        Ok(data)
+    }
+
+    /// Read data for contiguous `Block`s, in a single read from the store.
+    #[inline]
+    pub async fn read_range(
+        &mut self,
+        blocks: &[Block],
+        ) -> Result<Vec<Vec<u8>>>
+    {
+        let (first, last) = match (blocks.first(), blocks.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Ok(vec![]),
+        };
+        let start = first.offset();
+        let (offset, length) = verify_span(block_to_span(last))?;
+        let end = offset + length as u64;
+        if let Some(len) = self.len {
+            ensure!(end <= len,
+                "Block references data beyond store bounds ({}..{} > {}), \
+                core corrupt.", start, end, len);
+        }
+
+        let data = self.store
+            .read(start, end - start)
+            .await.map_err(|e| anyhow!(e))?;
+        let mut position = 0;
+        let mut result = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            ensure!(block.offset() == start + position as u64,
+                "Blocks are not contiguous.");
+            let stored = &data[position..position + block.length() as usize];
+            position += block.length() as usize;
+            if self.compressed {
+                result.push(snap::raw::Decoder::new().decompress_vec(stored)?);
+            } else {
+                result.push(stored.to_vec());
+            }
+        }
+        Ok(result)
     }
 }
 
@@ -96,4 +207,61 @@ mod tests {
         assert_eq!(msg, msg2);
         Ok(())
     }
+
+    #[test]
+    pub async fn write_read_compressed() -> Result<()> {
+        let mut store = StoreData::new_compressed(ram());
+        let data = Signature::from_bytes(&[2u8; SIGNATURE_LENGTH])?;
+        let tree = Signature::from_bytes(&[7u8; SIGNATURE_LENGTH])?;
+        let signature = BlockSignature::new(data, tree);
+        let msg = "hello hello hello hello hello".as_bytes();
+        let encoded = store.encode(msg)?;
+        assert!(encoded.len() < msg.len());
+        let block = Block::new(1, encoded.len() as u32, signature);
+        store.write(&block, &encoded).await?;
+        let msg2 = store.read(&block).await?;
+        assert_eq!(msg, msg2);
+        Ok(())
+    }
+
+    #[test]
+    pub async fn read_range_compressed() -> Result<()> {
+        let mut store = StoreData::new_compressed(ram());
+        let data = Signature::from_bytes(&[2u8; SIGNATURE_LENGTH])?;
+        let tree = Signature::from_bytes(&[7u8; SIGNATURE_LENGTH])?;
+        let signature = BlockSignature::new(data, tree);
+        let msgs = [&b"hello hello hello hello"[..], b"", b"world world world"];
+        let mut blocks = vec![];
+        let mut offset = 0;
+        for msg in msgs {
+            let encoded = store.encode(msg)?;
+            let block = Block::new(
+                offset, encoded.len() as u32, signature.clone());
+            store.write(&block, &encoded).await?;
+            offset += encoded.len() as u64;
+            blocks.push(block);
+        }
+        assert_eq!(store.read_range(&blocks).await?, msgs);
+        assert_eq!(store.read_range(&blocks[1..]).await?, &msgs[1..]);
+        assert!(store.read_range(&[]).await?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    pub async fn read_beyond_len() -> Result<()> {
+        let mut store = StoreData::new(ram());
+        store.set_len(Some(0));
+        let data = Signature::from_bytes(&[2u8; SIGNATURE_LENGTH])?;
+        let tree = Signature::from_bytes(&[7u8; SIGNATURE_LENGTH])?;
+        let signature = BlockSignature::new(data, tree);
+        let msg = "hello world".as_bytes();
+        let block = Block::new(0, msg.len() as u32, signature.clone());
+        store.write(&block, msg).await?;
+        assert_eq!(store.read(&block).await?, msg);
+
+        let tampered = Block::new(4, msg.len() as u32, signature);
+        let err = store.read(&tampered).await.unwrap_err();
+        assert!(err.to_string().contains("beyond store bounds"));
+        Ok(())
+    }
 }
@@ -0,0 +1,414 @@
+use anyhow::{anyhow, Result};
+use std::error::Error;
+use std::fmt::Debug;
+use std::mem::size_of;
+use std::sync::Arc;
+use async_lock::Mutex;
+
+use random_access_storage::RandomAccess;
+use crate::block::{Block, BLOCK_LENGTH, SIGNATURE_LENGTH};
+use crate::store_single::{HEADER_SIZE, STATE_SIZE};
+use crate::store_header::{self, BLOCKS_MAGIC, HEADER_LENGTH};
+use crate::{BlockSignature, Signature};
+
+/// Magic bytes at the start of a framed store, see [Framed].
+const MAGIC: &[u8; 8] = b"DCFRAMED";
+/// Version of the framed store layout.
+const VERSION: u8 = 1;
+/// Length of a frame header, the data length and both signatures.
+const FRAME_HEADER: u64 = (size_of::<u32>() + 2 * SIGNATURE_LENGTH) as u64;
+/// Offset of the first frame.
+const FRAMES_START: u64 = HEADER_SIZE + STATE_SIZE;
+
+/// View of a single [RandomAccess] store keeping every block framed
+/// with its data, used by [Core::new_framed] for all three of its stores.
+///
+/// The store is laid out as:
+/// - `[0, 64)`: header, the `DCFRAMED` magic and a version byte.
+/// - `[64, 4160)`: the state region.
+/// - `[4160, ..)`: a frame per block, back to back:
+///   the data length as `u32` little endian, the data and tree
+///   signatures, then the data.
+///
+/// There is no separate blocks store: the blocks view serves its header
+/// and the blocks from the frames, the offsets of the frames are
+/// recovered by scanning all of them when the store is opened, and kept
+/// in memory. This suits write-once cores read sequentially, as opening
+/// costs a read per block and the memory grows with the length of the
+/// `Core`. For random access to large cores prefer [Partition] or
+/// separate stores.
+///
+/// [Core::new_framed]: crate::Core::new_framed
+/// [Partition]: crate::Partition
+#[derive(Debug)]
+pub struct Framed<T>
+where
+    T: Debug,
+{
+    frames: Arc<Mutex<Frames<T>>>,
+    view: View,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum View {
+    Data,
+    Blocks,
+    State,
+}
+
+/// Store shared by the [Framed] views, with the scanned frames.
+#[derive(Debug)]
+struct Frames<T>
+where
+    T: Debug,
+{
+    store: T,
+    frames: Vec<Frame>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    /// Offset of the frame header in the store.
+    position: u64,
+    /// Logical offset of the data, as seen by the data view.
+    offset: u64,
+    length: u32,
+}
+
+impl Frame {
+    fn data_position(&self) -> u64 {
+        self.position + FRAME_HEADER
+    }
+    fn data_end(&self) -> u64 {
+        self.offset + self.length as u64
+    }
+}
+
+impl<T> Framed<T>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    /// Split `store` into the data, blocks and state [Framed] views,
+    /// writing a header if `store` is empty and scanning the frames
+    /// otherwise.
+    pub(crate) async fn split(mut store: T) -> Result<(Self, Self, Self)> {
+        let frames = match store.read(0, HEADER_SIZE).await {
+            Ok(header) => {
+                check_header(&header)?;
+                scan(&mut store).await
+            },
+            Err(_) => {
+                store.write(0, &new_header()).await.map_err(|e| anyhow!(e))?;
+                vec![]
+            },
+        };
+
+        let frames = Arc::new(Mutex::new(Frames { store, frames }));
+        let view = |view| Self { frames: Arc::clone(&frames), view };
+        Ok((
+            view(View::Data),
+            view(View::Blocks),
+            view(View::State),
+        ))
+    }
+}
+
+/// Scan the frames of `store`, stopping at the first incomplete one.
+async fn scan<T>(store: &mut T) -> Vec<Frame>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    let mut frames = vec![];
+    let mut position = FRAMES_START;
+    let mut offset = 0;
+    while let Ok(header) = store.read(position, size_of::<u32>() as u64).await {
+        let mut length = [0u8; size_of::<u32>()];
+        length.copy_from_slice(&header);
+        let frame = Frame {
+            position,
+            offset,
+            length: u32::from_le_bytes(length),
+        };
+        // the frame must be complete, the last byte of its data included
+        let end = frame.data_position() + frame.length as u64;
+        if store.read(end - 1, 1).await.is_err() {
+            break
+        }
+        frames.push(frame);
+        position = end;
+        offset = frame.data_end();
+    }
+    frames
+}
+
+impl<T> Frames<T>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    /// Offset of the next frame header.
+    fn end(&self) -> u64 {
+        match self.frames.last() {
+            Some(frame) => frame.data_position() + frame.length as u64,
+            None => FRAMES_START,
+        }
+    }
+
+    /// Physical position of the data at the logical `offset`.
+    ///
+    /// Data past the last frame belongs to the next one,
+    /// its header written after the data by the blocks view.
+    fn data_position(&self, offset: u64)
+        -> Result<u64, Box<dyn Error + Send + Sync>>
+    {
+        let data_end = self.frames.last().map_or(0, Frame::data_end);
+        if offset >= data_end {
+            return Ok(self.end() + FRAME_HEADER + (offset - data_end))
+        }
+        // the last frame at `offset`, frames before it may be empty
+        let index = self.frames.partition_point(|f| f.offset <= offset);
+        match index.checked_sub(1).map(|i| self.frames[i]) {
+            Some(frame) => Ok(frame.data_position() + (offset - frame.offset)),
+            None => Err("Framed data offset not in a frame".into()),
+        }
+    }
+
+    /// Read `length` bytes of data at the logical `offset`,
+    /// split across the frames it spans.
+    async fn read_data(&mut self, offset: u64, length: u64)
+        -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>
+    {
+        let data_end = self.frames.last().map_or(0, Frame::data_end);
+        let mut data = Vec::with_capacity(length as usize);
+        let mut offset = offset;
+        let end = offset + length;
+        while offset < end {
+            let position = self.data_position(offset)?;
+            // data past the last frame is contiguous
+            let chunk = match offset < data_end {
+                true => {
+                    let index = self.frames
+                        .partition_point(|f| f.offset <= offset) - 1;
+                    (self.frames[index].data_end() - offset).min(end - offset)
+                },
+                false => end - offset,
+            };
+            data.extend_from_slice(&self.store.read(position, chunk).await?);
+            offset += chunk;
+        }
+        Ok(data)
+    }
+
+    async fn write_block(&mut self, index: usize, data: &[u8])
+        -> Result<(), Box<dyn Error + Send + Sync>>
+    {
+        if index > self.frames.len() {
+            return Err("Framed blocks must be written in order".into())
+        }
+        // a rewritten block invalidates the frames after it
+        self.frames.truncate(index);
+        let block = Block::from_bytes(data)?;
+        let offset = self.frames.last().map_or(0, Frame::data_end);
+        if block.offset() != offset {
+            return Err("Framed block data must be contiguous".into())
+        }
+
+        let signature = block.signature();
+        let mut header = Vec::with_capacity(FRAME_HEADER as usize);
+        header.extend_from_slice(&block.length().to_le_bytes());
+        header.extend_from_slice(&signature.data().to_bytes());
+        header.extend_from_slice(&signature.tree().to_bytes());
+        let position = self.end();
+        self.store.write(position, &header).await?;
+        self.frames.push(Frame { position, offset, length: block.length() });
+        Ok(())
+    }
+
+    async fn read_block(&mut self, index: usize)
+        -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>
+    {
+        let frame = match self.frames.get(index) {
+            Some(frame) => *frame,
+            None => return Err("Framed block out of bounds".into()),
+        };
+        let signatures = self.store.read(
+            frame.position + size_of::<u32>() as u64,
+            2 * SIGNATURE_LENGTH as u64).await?;
+        let signature = BlockSignature::new(
+            Signature::from_bytes(&signatures[..SIGNATURE_LENGTH])?,
+            Signature::from_bytes(&signatures[SIGNATURE_LENGTH..])?);
+        Ok(Block::new(frame.offset, frame.length, signature).to_bytes()?)
+    }
+}
+
+/// Check that `length` bytes at `offset` of the blocks view
+/// are whole blocks, and return the index of the first one.
+fn block_index(offset: u64, length: u64)
+    -> Result<usize, Box<dyn Error + Send + Sync>>
+{
+    let block_length = BLOCK_LENGTH as u64;
+    let offset = offset.checked_sub(HEADER_LENGTH)
+        .ok_or("Framed blocks header is read only")?;
+    if !offset.is_multiple_of(block_length)
+        || !length.is_multiple_of(block_length)
+    {
+        return Err("Framed blocks must be accessed whole".into())
+    }
+    Ok((offset / block_length) as usize)
+}
+
+/// Map `length` bytes at the `offset` of the state view
+/// to their position in the store.
+fn state_position(offset: u64, length: u64)
+    -> Result<u64, Box<dyn Error + Send + Sync>>
+{
+    match offset.checked_add(length) {
+        Some(end) if end <= STATE_SIZE => Ok(HEADER_SIZE + offset),
+        _ => Err("State region exceeded".into()),
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> RandomAccess for Framed<T>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn write(
+        &mut self,
+        offset: u64,
+        data: &[u8],
+        ) -> Result<(), Self::Error>
+    {
+        let mut frames = self.frames.lock().await;
+        match self.view {
+            View::Data => {
+                if data.is_empty() {
+                    return Ok(())
+                }
+                let position = frames.data_position(offset)?;
+                frames.store.write(position, data).await
+            },
+            View::Blocks => {
+                let index = block_index(offset, data.len() as u64)?;
+                for (i, block) in data.chunks(BLOCK_LENGTH).enumerate() {
+                    frames.write_block(index + i, block).await?;
+                }
+                Ok(())
+            },
+            View::State => {
+                let position = state_position(offset, data.len() as u64)?;
+                frames.store.write(position, data).await
+            },
+        }
+    }
+
+    async fn read(
+        &mut self,
+        offset: u64,
+        length: u64,
+        ) -> Result<Vec<u8>, Self::Error>
+    {
+        let mut frames = self.frames.lock().await;
+        match self.view {
+            View::Data => frames.read_data(offset, length).await,
+            View::Blocks if offset == 0 && length == HEADER_LENGTH => {
+                Ok(store_header::new_header(BLOCKS_MAGIC))
+            },
+            View::Blocks => {
+                let index = block_index(offset, length)?;
+                let count = (length / BLOCK_LENGTH as u64) as usize;
+                let mut data = Vec::with_capacity(length as usize);
+                for i in index..index + count {
+                    data.extend_from_slice(&frames.read_block(i).await?);
+                }
+                Ok(data)
+            },
+            View::State => {
+                let position = state_position(offset, length)?;
+                frames.store.read(position, length).await
+            },
+        }
+    }
+
+    /// Frames are never rewritten in place, only truncated:
+    /// deleting blocks through the end drops their frames, data included,
+    /// and deleting data through the end leaves it to the blocks view.
+    async fn delete(
+        &mut self,
+        offset: u64,
+        length: u64,
+        ) -> Result<(), Self::Error>
+    {
+        let mut frames = self.frames.lock().await;
+        let end = offset.saturating_add(length);
+        match self.view {
+            View::Data => {
+                let data_end = frames.frames.last().map_or(0, Frame::data_end);
+                if end < data_end {
+                    return Err("Framed data can only be deleted at the end".into())
+                }
+                Ok(())
+            },
+            View::Blocks => {
+                let index = block_index(offset, 0)?;
+                let blocks_end = HEADER_LENGTH
+                    + (frames.frames.len() * BLOCK_LENGTH) as u64;
+                if end < blocks_end {
+                    return Err("Framed blocks can only be deleted at the end".into())
+                }
+                if index >= frames.frames.len() {
+                    return Ok(())
+                }
+                let position = frames.frames[index].position;
+                frames.frames.truncate(index);
+                let length = frames.store.len().await?;
+                frames.store.delete(position, length - position).await
+            },
+            View::State => {
+                let position = state_position(offset, length)?;
+                frames.store.delete(position, length).await
+            },
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.frames.lock().await.store.flush().await
+    }
+
+    async fn len(&mut self) -> Result<u64, Self::Error> {
+        let mut frames = self.frames.lock().await;
+        let length = frames.store.len().await?;
+        match self.view {
+            View::Data => {
+                // data written ahead of the header of the next frame
+                let ahead = length.saturating_sub(frames.end() + FRAME_HEADER);
+                let data_end = frames.frames.last().map_or(0, Frame::data_end);
+                Ok(data_end + ahead)
+            },
+            View::Blocks => Ok(HEADER_LENGTH
+                + (frames.frames.len() * BLOCK_LENGTH) as u64),
+            View::State => Ok(length
+                .saturating_sub(HEADER_SIZE)
+                .min(STATE_SIZE)),
+        }
+    }
+}
+
+fn new_header() -> Vec<u8> {
+    let mut header = vec![0u8; HEADER_SIZE as usize];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    header[MAGIC.len()] = VERSION;
+    header
+}
+
+fn check_header(header: &[u8]) -> Result<()> {
+    if header[..MAGIC.len()] != MAGIC[..] {
+        return Err(anyhow!("Not a framed store, magic mismatch."))
+    }
+    if header[MAGIC.len()] != VERSION {
+        return Err(anyhow!(
+                "Unknown framed store version {}.", header[MAGIC.len()]))
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use anyhow::{anyhow, ensure, Result};
+use std::error::Error;
+use std::fmt::Debug;
+use std::mem::size_of;
+
+use random_access_storage::RandomAccess;
+
+/// Magic bytes of the blocks store header.
+pub(crate) const BLOCKS_MAGIC: &[u8; 4] = b"DCBK";
+/// Magic bytes of the state store header.
+pub(crate) const STATE_MAGIC: &[u8; 4] = b"DCST";
+/// Version of the blocks and state store layouts.
+pub(crate) const STORE_VERSION: u32 = 1;
+/// Length of a store header, the magic and the version.
+pub(crate) const HEADER_LENGTH: u64 = 8;
+
+/// Header at offset 0 of the blocks and state stores:
+/// the magic, then the version as `u32` little endian.
+pub(crate) fn new_header(magic: &[u8; 4]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LENGTH as usize);
+    header.extend_from_slice(magic);
+    header.extend_from_slice(&STORE_VERSION.to_le_bytes());
+    header
+}
+
+/// Check the header of `store`, writing one if `store` is empty,
+/// and return the offset of its first record.
+///
+/// Stores written before the header was introduced start with their
+/// first record at offset `0`, and never with a magic: the first block
+/// has offset `0`, and a state holds less than 256 roots, so the bytes
+/// of a magic are zeros there.
+pub(crate) async fn open<T>(store: &mut T, magic: &[u8; 4]) -> Result<u64>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    if store.is_empty().await.map_err(|e| anyhow!(e))? {
+        store.write(0, &new_header(magic)).await.map_err(|e| anyhow!(e))?;
+        return Ok(HEADER_LENGTH)
+    }
+    let header = match store.read(0, HEADER_LENGTH).await {
+        Ok(header) if header[..magic.len()] == magic[..] => header,
+        // no header => legacy store
+        _ => return Ok(0),
+    };
+    let mut version = [0u8; size_of::<u32>()];
+    version.copy_from_slice(&header[magic.len()..]);
+    let version = u32::from_le_bytes(version);
+    ensure!(version == STORE_VERSION,
+        "Unsupported store format version {}.", version);
+    Ok(HEADER_LENGTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::test;
+    use random_access_memory::RandomAccessMemory;
+    use super::*;
+
+    fn ram() -> RandomAccessMemory {
+        let page_size = 1024;
+        RandomAccessMemory::new(page_size)
+    }
+
+    #[test]
+    pub async fn open_empty_writes_header() -> Result<()> {
+        let mut store = ram();
+        assert_eq!(open(&mut store, BLOCKS_MAGIC).await?, HEADER_LENGTH);
+        let header = store.read(0, HEADER_LENGTH).await
+            .map_err(|e| anyhow!(e))?;
+        assert_eq!(hex::encode(header), "4443424b01000000");
+        assert_eq!(open(&mut store, BLOCKS_MAGIC).await?, HEADER_LENGTH);
+        Ok(())
+    }
+
+    #[test]
+    pub async fn open_legacy() -> Result<()> {
+        let mut store = ram();
+        store.write(0, &[0u8; 140]).await.map_err(|e| anyhow!(e))?;
+        assert_eq!(open(&mut store, BLOCKS_MAGIC).await?, 0);
+
+        let mut store = ram();
+        store.write(0, &[1, 0, 0, 0]).await.map_err(|e| anyhow!(e))?;
+        assert_eq!(open(&mut store, STATE_MAGIC).await?, 0);
+        Ok(())
+    }
+
+    #[test]
+    pub async fn open_fails_on_unknown_version() -> Result<()> {
+        let mut store = ram();
+        let mut header = new_header(STATE_MAGIC);
+        header[STATE_MAGIC.len()] = 2;
+        store.write(0, &header).await.map_err(|e| anyhow!(e))?;
+        let error = open(&mut store, STATE_MAGIC).await.unwrap_err();
+        assert!(error.to_string().contains("version 2"));
+        Ok(())
+    }
+}
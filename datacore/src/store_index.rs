@@ -0,0 +1,312 @@
+use anyhow::{anyhow, ensure, Result};
+use std::mem::size_of;
+use std::error::Error;
+use std::fmt::Debug;
+use std::io::Cursor;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use random_access_storage::RandomAccess;
+use crate::hash::{Hash, HASH_SIZE};
+
+const HEADER_SIZE: usize = size_of::<u64>() + size_of::<u32>();
+/// Slot is a hash and `index + 1`, `0` marks an empty slot.
+const SLOT_SIZE: usize = HASH_SIZE + size_of::<u32>();
+const INITIAL_CAPACITY: u64 = 1024;
+
+/// Save a leaf [Hash] to block index map to a desired storage backend.
+///
+/// Stored as an open addressing hash table, which doubles in capacity
+/// when half full.
+#[derive(Debug)]
+pub struct StoreIndex<T>
+where
+    T: Debug,
+{
+    store: T,
+    capacity: u64,
+    count: u64,
+    length: u32,
+}
+impl<T> StoreIndex<T>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    /// Open a [StoreIndex] from [RandomAccess] interface,
+    /// initializing an empty one if needed.
+    pub async fn open(store: T) -> Result<Self> {
+        let mut this = Self {
+            store,
+            capacity: 0,
+            count: 0,
+            length: 0,
+        };
+        match this.store.read(0, HEADER_SIZE as u64).await {
+            Err(_) => {
+                let table = vec![0u8; INITIAL_CAPACITY as usize * SLOT_SIZE];
+                this.write_table(INITIAL_CAPACITY, 0, 0, &table).await?;
+            },
+            Ok(header) => {
+                let mut rdr = Cursor::new(header);
+                this.capacity = rdr.read_u64::<LittleEndian>()?;
+                this.length = rdr.read_u32::<LittleEndian>()?;
+                ensure!(this.capacity.is_power_of_two(),
+                    "Invalid index capacity {}.", this.capacity);
+                let table = this.read_table().await?;
+                this.count = table.chunks(SLOT_SIZE)
+                    .filter(|slot| !is_empty(slot))
+                    .count() as u64;
+            },
+        };
+        Ok(this)
+    }
+
+    /// Number of blocks indexed.
+    #[inline]
+    pub fn len(&self) -> u32 {
+        self.length
+    }
+
+    /// Find the index of the first block with `hash`.
+    pub async fn get(&mut self, hash: &Hash) -> Result<Option<u32>> {
+        let mut slot = self.slot_of(hash);
+        for _ in 0..self.capacity {
+            let data = self.read_slot(slot).await?;
+            if is_empty(&data) {
+                return Ok(None)
+            }
+            if &data[..HASH_SIZE] == hash.as_bytes() {
+                return Ok(Some(slot_index(&data)?))
+            }
+            slot = (slot + 1) & (self.capacity - 1);
+        }
+        Ok(None)
+    }
+
+    /// Insert a block `hash` at `index`, keeping an earlier index for the
+    /// same hash. Indices are expected in append order, so an existing entry
+    /// at `index` or later is stale and gets replaced.
+    pub async fn insert(&mut self, hash: &Hash, index: u32) -> Result<()> {
+        ensure!(index < u32::MAX);
+        if (self.count + 1) * 2 > self.capacity {
+            self.grow().await?;
+        }
+
+        let mut slot = self.slot_of(hash);
+        loop {
+            let data = self.read_slot(slot).await?;
+            if is_empty(&data) {
+                self.count += 1;
+                break
+            }
+            if &data[..HASH_SIZE] == hash.as_bytes() {
+                if slot_index(&data)? < index {
+                    return self.write_length(index + 1).await
+                }
+                break
+            }
+            slot = (slot + 1) & (self.capacity - 1);
+        }
+
+        let data = encode_slot(hash.as_bytes(), index)?;
+        self.store
+            .write(slot_offset(slot), &data)
+            .await.map_err(|e| anyhow!(e))?;
+        self.write_length(index + 1).await
+    }
+
+    /// Flush written data to the store, see [RandomAccess::flush].
+    #[inline]
+    pub async fn flush(&mut self) -> Result<()> {
+        self.store.flush().await.map_err(|e| anyhow!(e))
+    }
+
+    /// Remove the blocks at `length` and past it, e.g. after truncating
+    /// the [Core](crate::Core).
+    pub async fn truncate(&mut self, length: u32) -> Result<()> {
+        if length >= self.length {
+            return Ok(())
+        }
+        let old = self.read_table().await?;
+        let mut kept = vec![];
+        for data in old.chunks(SLOT_SIZE).filter(|slot| !is_empty(slot)) {
+            if slot_index(data)? < length {
+                kept.push(data);
+            }
+        }
+        self.rebuild(self.capacity, &kept, length).await
+    }
+
+    async fn grow(&mut self) -> Result<()> {
+        let old = self.read_table().await?;
+        let slots: Vec<&[u8]> = old.chunks(SLOT_SIZE)
+            .filter(|slot| !is_empty(slot))
+            .collect();
+        self.rebuild(self.capacity * 2, &slots, self.length).await
+    }
+
+    /// Write a new table of `capacity` holding `slots`.
+    async fn rebuild(
+        &mut self,
+        capacity: u64,
+        slots: &[&[u8]],
+        length: u32,
+        ) -> Result<()>
+    {
+        let mut table = vec![0u8; capacity as usize * SLOT_SIZE];
+        for data in slots {
+            let mut slot = slot_of(&data[..HASH_SIZE], capacity);
+            while !is_empty(&table[slot as usize * SLOT_SIZE..][..SLOT_SIZE]) {
+                slot = (slot + 1) & (capacity - 1);
+            }
+            table[slot as usize * SLOT_SIZE..][..SLOT_SIZE]
+                .copy_from_slice(data);
+        }
+        self.write_table(capacity, slots.len() as u64, length, &table).await
+    }
+
+    async fn read_table(&mut self) -> Result<Vec<u8>> {
+        self.store
+            .read(HEADER_SIZE as u64, self.capacity * SLOT_SIZE as u64)
+            .await.map_err(|e| anyhow!(e))
+    }
+    async fn write_table(
+        &mut self,
+        capacity: u64,
+        count: u64,
+        length: u32,
+        table: &[u8],
+        ) -> Result<()>
+    {
+        let mut data = Vec::with_capacity(HEADER_SIZE + table.len());
+        data.write_u64::<LittleEndian>(capacity)?;
+        data.write_u32::<LittleEndian>(length)?;
+        data.extend_from_slice(table);
+        self.store
+            .write(0, &data)
+            .await.map_err(|e| anyhow!(e))?;
+        self.capacity = capacity;
+        self.count = count;
+        self.length = length;
+        Ok(())
+    }
+    async fn write_length(&mut self, length: u32) -> Result<()> {
+        let mut data = Vec::with_capacity(size_of::<u32>());
+        data.write_u32::<LittleEndian>(length)?;
+        self.store
+            .write(size_of::<u64>() as u64, &data)
+            .await.map_err(|e| anyhow!(e))?;
+        self.length = length;
+        Ok(())
+    }
+    async fn read_slot(&mut self, slot: u64) -> Result<Vec<u8>> {
+        self.store
+            .read(slot_offset(slot), SLOT_SIZE as u64)
+            .await.map_err(|e| anyhow!(e))
+    }
+
+    #[inline]
+    fn slot_of(&self, hash: &Hash) -> u64 {
+        slot_of(hash.as_bytes(), self.capacity)
+    }
+}
+
+#[inline]
+fn slot_of(hash: &[u8], capacity: u64) -> u64 {
+    let mut rdr = Cursor::new(hash);
+    // Hashes are uniform, any 8 bytes will do.
+    let key = rdr.read_u64::<LittleEndian>().unwrap_or(0);
+    key & (capacity - 1)
+}
+#[inline]
+fn slot_offset(slot: u64) -> u64 {
+    HEADER_SIZE as u64 + slot * SLOT_SIZE as u64
+}
+#[inline]
+fn is_empty(slot: &[u8]) -> bool {
+    slot[HASH_SIZE..].iter().all(|&b| b == 0)
+}
+#[inline]
+fn slot_index(slot: &[u8]) -> Result<u32> {
+    let mut rdr = Cursor::new(&slot[HASH_SIZE..]);
+    Ok(rdr.read_u32::<LittleEndian>()? - 1)
+}
+#[inline]
+fn encode_slot(hash: &[u8], index: u32) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(SLOT_SIZE);
+    data.extend_from_slice(hash);
+    data.write_u32::<LittleEndian>(index + 1)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::test;
+    use random_access_memory::RandomAccessMemory;
+    use super::*;
+
+    fn ram() -> RandomAccessMemory {
+        let page_size = 1024;
+        RandomAccessMemory::new(page_size)
+    }
+
+    #[test]
+    pub async fn insert_get() -> Result<()> {
+        let mut store = StoreIndex::open(ram()).await?;
+        let hello = Hash::from_leaf(b"hello");
+        let world = Hash::from_leaf(b"world");
+        store.insert(&hello, 0).await?;
+        store.insert(&world, 1).await?;
+        store.insert(&hello, 2).await?;
+        assert_eq!(store.get(&hello).await?, Some(0));
+        assert_eq!(store.get(&world).await?, Some(1));
+        assert_eq!(store.get(&Hash::from_leaf(b"other")).await?, None);
+        assert_eq!(store.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    pub async fn grow() -> Result<()> {
+        let mut store = StoreIndex::open(ram()).await?;
+        let count = INITIAL_CAPACITY as u32 * 2;
+        for i in 0..count {
+            store.insert(&Hash::from_leaf(&i.to_be_bytes()), i).await?;
+        }
+        assert!(store.capacity > INITIAL_CAPACITY * 2);
+        for i in 0..count {
+            let hash = Hash::from_leaf(&i.to_be_bytes());
+            assert_eq!(store.get(&hash).await?, Some(i));
+        }
+        Ok(())
+    }
+
+    #[test]
+    pub async fn replace_stale() -> Result<()> {
+        let mut store = StoreIndex::open(ram()).await?;
+        let hello = Hash::from_leaf(b"hello");
+        store.insert(&hello, 5).await?;
+        store.insert(&hello, 3).await?;
+        assert_eq!(store.get(&hello).await?, Some(3));
+        Ok(())
+    }
+
+    #[test]
+    pub async fn truncate() -> Result<()> {
+        let mut store = StoreIndex::open(ram()).await?;
+        let hashes: Vec<Hash> = (0..10u32)
+            .map(|i| Hash::from_leaf(&i.to_be_bytes()))
+            .collect();
+        for (i, hash) in hashes.iter().enumerate() {
+            store.insert(hash, i as u32).await?;
+        }
+        store.truncate(4).await?;
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.count, 4);
+        for (i, hash) in hashes.iter().enumerate() {
+            let expected = if i < 4 { Some(i as u32) } else { None };
+            assert_eq!(store.get(hash).await?, expected);
+        }
+        store.insert(&hashes[9], 4).await?;
+        assert_eq!(store.get(&hashes[9]).await?, Some(4));
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::{anyhow, Result};
+use std::error::Error;
+use std::fmt::Debug;
+use std::sync::Arc;
+use async_lock::Mutex;
+
+use random_access_storage::RandomAccess;
+
+/// Magic bytes at the start of a single store, see [Partition].
+const MAGIC: &[u8; 8] = b"DATACORE";
+/// Version of the single store layout.
+const VERSION: u8 = 1;
+/// Bytes reserved for the header.
+pub(crate) const HEADER_SIZE: u64 = 64;
+/// Bytes reserved for the state.
+/// Fits the roots of a [Merkle] with [MAX_CORE_LENGTH] blocks.
+///
+/// [Merkle]: crate::Merkle
+/// [MAX_CORE_LENGTH]: crate::MAX_CORE_LENGTH
+pub(crate) const STATE_SIZE: u64 = 4096;
+/// Size of the pages the data and blocks regions are interleaved in.
+pub const PAGE_SIZE: u64 = 64 * 1024;
+
+/// Logical region of a single [RandomAccess] store,
+/// used by [Core::new_single] for all three of its stores.
+///
+/// The store is laid out as:
+/// - `[0, 64)`: header, the `DATACORE` magic, a version byte
+///   and the page size as `u32` little endian.
+/// - `[64, 4160)`: the state region.
+/// - `[4160, ..)`: the data and blocks regions, interleaved in
+///   [PAGE_SIZE] pages, data first.
+///
+/// Both regions grow independently, so the store contains holes
+/// where one of them is shorter than the other.
+///
+/// [Core::new_single]: crate::Core::new_single
+#[derive(Debug)]
+pub struct Partition<T>
+where
+    T: Debug,
+{
+    store: Arc<Mutex<T>>,
+    region: Region,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Region {
+    State,
+    Paged(u64),
+}
+
+impl<T> Partition<T>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    /// Split `store` into the data, blocks and state [Partition]s,
+    /// writing a header if `store` is empty.
+    pub(crate) async fn split(mut store: T) -> Result<(Self, Self, Self)> {
+        match store.read(0, HEADER_SIZE).await {
+            Ok(header) => check_header(&header)?,
+            Err(_) => {
+                store.write(0, &new_header()).await.map_err(|e| anyhow!(e))?;
+            },
+        }
+
+        let store = Arc::new(Mutex::new(store));
+        let partition = |region| Self { store: Arc::clone(&store), region };
+        Ok((
+            partition(Region::Paged(0)),
+            partition(Region::Paged(1)),
+            partition(Region::State),
+        ))
+    }
+
+    /// Map `length` bytes at the logical `offset`
+    /// to `(offset, length)` physical chunks.
+    fn chunks(&self, offset: u64, length: u64)
+        -> Result<Vec<(u64, u64)>, Box<dyn Error + Send + Sync>>
+    {
+        let end = offset.checked_add(length)
+            .ok_or("Partition offset overflow")?;
+        match self.region {
+            Region::State => {
+                if end > STATE_SIZE {
+                    return Err("State region exceeded".into())
+                }
+                Ok(vec![(HEADER_SIZE + offset, length)])
+            },
+            Region::Paged(lane) => {
+                let mut chunks = vec![];
+                let mut offset = offset;
+                while offset < end {
+                    let page = offset / PAGE_SIZE;
+                    let in_page = offset % PAGE_SIZE;
+                    let chunk = std::cmp::min(PAGE_SIZE - in_page, end - offset);
+                    let physical = page.checked_mul(2)
+                        .and_then(|page| page.checked_add(lane))
+                        .and_then(|page| page.checked_mul(PAGE_SIZE))
+                        .and_then(|start| start.checked_add(
+                                HEADER_SIZE + STATE_SIZE + in_page))
+                        .ok_or("Partition offset overflow")?;
+                    chunks.push((physical, chunk));
+                    offset += chunk;
+                }
+                Ok(chunks)
+            },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> RandomAccess for Partition<T>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+{
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn write(
+        &mut self,
+        offset: u64,
+        data: &[u8],
+        ) -> Result<(), Self::Error>
+    {
+        let chunks = self.chunks(offset, data.len() as u64)?;
+        let mut store = self.store.lock().await;
+        let mut written = 0;
+        for (offset, length) in chunks {
+            let length = length as usize;
+            store.write(offset, &data[written..written + length]).await?;
+            written += length;
+        }
+        Ok(())
+    }
+
+    async fn read(
+        &mut self,
+        offset: u64,
+        length: u64,
+        ) -> Result<Vec<u8>, Self::Error>
+    {
+        let chunks = self.chunks(offset, length)?;
+        let mut store = self.store.lock().await;
+        let mut data = Vec::with_capacity(length as usize);
+        for (offset, length) in chunks {
+            data.extend_from_slice(&store.read(offset, length).await?);
+        }
+        Ok(data)
+    }
+
+    async fn delete(
+        &mut self,
+        offset: u64,
+        length: u64,
+        ) -> Result<(), Self::Error>
+    {
+        let chunks = self.chunks(offset, length)?;
+        let mut store = self.store.lock().await;
+        for (offset, length) in chunks {
+            store.delete(offset, length).await?;
+        }
+        Ok(())
+    }
+
+    /// Flush the whole store, shared by all the regions.
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.store.lock().await.flush().await
+    }
+
+    /// Length of the region as far as the store extends.
+    ///
+    /// The regions share the store, so the length of the one ending
+    /// earlier is an upper bound, counting the holes up to the other.
+    async fn len(&mut self) -> Result<u64, Self::Error> {
+        let length = self.store.lock().await.len().await?;
+        match self.region {
+            Region::State => Ok(length
+                .saturating_sub(HEADER_SIZE)
+                .min(STATE_SIZE)),
+            Region::Paged(lane) => {
+                let paged = length.saturating_sub(HEADER_SIZE + STATE_SIZE);
+                let pages = paged / (2 * PAGE_SIZE);
+                let rest = (paged % (2 * PAGE_SIZE))
+                    .saturating_sub(lane * PAGE_SIZE)
+                    .min(PAGE_SIZE);
+                Ok(pages * PAGE_SIZE + rest)
+            },
+        }
+    }
+}
+
+fn new_header() -> Vec<u8> {
+    let mut header = vec![0u8; HEADER_SIZE as usize];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    header[MAGIC.len()] = VERSION;
+    header[MAGIC.len() + 1..MAGIC.len() + 5]
+        .copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+    header
+}
+
+fn check_header(header: &[u8]) -> Result<()> {
+    if header[..MAGIC.len()] != MAGIC[..] {
+        return Err(anyhow!("Not a single store, magic mismatch."))
+    }
+    if header[MAGIC.len()] != VERSION {
+        return Err(anyhow!(
+                "Unknown single store version {}.", header[MAGIC.len()]))
+    }
+    if header[..] != new_header()[..] {
+        return Err(anyhow!("Single store page size mismatch."))
+    }
+    Ok(())
+}
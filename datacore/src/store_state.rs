@@ -7,6 +7,7 @@ use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use random_access_storage::RandomAccess;
 use crate::merkle::{Merkle, Node, NODE_SIZE};
+use crate::store_header::{self, STATE_MAGIC};
 
 /// Save data to a desired storage backend.
 #[derive(Debug)]
@@ -15,18 +16,24 @@ where
     T: Debug,
 {
     store: T,
+    /// Offset of the roots, `0` in a legacy store without header.
+    start: u64,
 }
 impl<T> StoreState<T>
 where
     T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
 {
-    /// Create a new [StoreState] from [RandomAccess] interface.
-    #[inline]
-    pub fn new(store: T) -> Self {
-        Self { store }
+    /// Open a [StoreState] from [RandomAccess] interface,
+    /// writing the header if the store is empty.
+    pub async fn open(mut store: T) -> Result<Self> {
+        let start = store_header::open(&mut store, STATE_MAGIC).await?;
+        Ok(Self { store, start })
     }
 
     /// Write `Merkle` roots.
+    ///
+    /// Stored after a header, see [store_header::open], as the count
+    /// of roots as `u32` little endian, followed by the serialized `Node`s.
     #[inline]
     pub async fn write(
         &mut self,
@@ -44,10 +51,16 @@ where
         }
 
         self.store
-            .write(0, &data)
+            .write(self.start, &data)
             .await.map_err(|e| anyhow!(e))
     }
 
+    /// Flush written data to the store, see [RandomAccess::flush].
+    #[inline]
+    pub async fn flush(&mut self) -> Result<()> {
+        self.store.flush().await.map_err(|e| anyhow!(e))
+    }
+
     /// Read roots and reconstruct `Merkle`.
     #[inline]
     pub async fn read(
@@ -56,7 +69,7 @@ where
     {
         // try reading length
         let read_header = self.store
-            .read(0, size_of::<u32>() as u64)
+            .read(self.start, size_of::<u32>() as u64)
             .await.map_err(|e| anyhow!(e));
 
         // init [Merkle] from roots
@@ -71,7 +84,7 @@ where
                     length as usize * size_of::<Node>());
                 let data = self.store
                     .read(
-                        size_of::<u32>() as u64,
+                        self.start + size_of::<u32>() as u64,
                         length as u64 * NODE_SIZE as u64)
                     .await.map_err(|e| anyhow!(e))?;
 
@@ -93,7 +106,9 @@ where
 mod tests {
     use async_std::test;
     use random_access_memory::RandomAccessMemory;
-    use crate::hash::Hash;
+    use crate::hash::{Hash, HASH_SIZE};
+    use crate::merkle::NodeTrait;
+    use crate::store_header::HEADER_LENGTH;
     use super::*;
 
     fn ram() -> RandomAccessMemory {
@@ -103,13 +118,13 @@ mod tests {
 
     #[test]
     pub async fn init() -> Result<()> {
-        StoreState::new(ram());
+        StoreState::open(ram()).await?;
         Ok(())
     }
 
     #[test]
     pub async fn write_read() -> Result<()> {
-        let mut store = StoreState::new(ram());
+        let mut store = StoreState::open(ram()).await?;
         let mut merkle = Merkle::new();
         merkle.next(Hash::from_leaf(b"a"), 1);
         merkle.next(Hash::from_leaf(b"b"), 1);
@@ -119,4 +134,41 @@ mod tests {
         assert_eq!(merkle.roots(), merkle2.roots());
         Ok(())
     }
+
+    #[test]
+    pub async fn write_golden() -> Result<()> {
+        let mut store = StoreState::open(ram()).await?;
+        let hash = Hash::from_bytes(&[9u8; HASH_SIZE])?;
+        let merkle = Merkle::from_roots(vec![Node::new(0, hash, 3)]);
+        store.write(&merkle).await?;
+        let bytes = store.store
+            .read(0, HEADER_LENGTH + (size_of::<u32>() + NODE_SIZE) as u64)
+            .await.map_err(|e| anyhow!(e))?;
+        let expected = format!("{}{}{}{}{}",
+            "4443535401000000",
+            "01000000",
+            "0000000000000000", "0300000000000000",
+            "09".repeat(HASH_SIZE));
+        assert_eq!(hex::encode(bytes), expected);
+        Ok(())
+    }
+
+    #[test]
+    pub async fn open_legacy() -> Result<()> {
+        let hash = Hash::from_bytes(&[9u8; HASH_SIZE])?;
+        let node = Node::new(0, hash, 3);
+        let mut legacy = ram();
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&node.to_bytes()?);
+        legacy.write(0, &data).await.map_err(|e| anyhow!(e))?;
+
+        let mut store = StoreState::open(legacy).await?;
+        assert_eq!(store.read().await?.roots(), &vec![node.clone()]);
+        let merkle = Merkle::from_roots(vec![node.clone(), node]);
+        store.write(&merkle).await?;
+        assert_eq!(store.read().await?.roots(), merkle.roots());
+        assert_eq!(store.store.len().await.map_err(|e| anyhow!(e))?,
+            (size_of::<u32>() + 2 * NODE_SIZE) as u64);
+        Ok(())
+    }
 }
@@ -0,0 +1,134 @@
+use anyhow::{Result, ensure};
+use std::mem::size_of;
+use std::io::{Cursor, Read};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::merkle::{Merkle, Node, NODE_SIZE};
+
+/// [VerifyCursor] is the progress of an incremental verification
+/// of a `Core`, see [Core::verify_from].
+///
+/// Persist it with [VerifyCursor::to_bytes] to resume verifying
+/// after a restart. The cursor is trusted: it carries the merkle state
+/// of the blocks verified so far, so only resume from a cursor
+/// this `Core` returned.
+///
+/// [Core::verify_from]: crate::Core::verify_from
+#[derive(Debug, Clone)]
+pub struct VerifyCursor {
+    index: u32,
+    byte_length: u64,
+    merkle: Merkle,
+}
+
+impl Default for VerifyCursor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VerifyCursor {
+    /// Create a [VerifyCursor] at the first block.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            index: 0,
+            byte_length: 0,
+            merkle: Merkle::new(),
+        }
+    }
+
+    /// Index of the next block to verify,
+    /// every block before it was verified.
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    #[inline]
+    pub(crate) fn byte_length(&self) -> u64 {
+        self.byte_length
+    }
+
+    #[inline]
+    pub(crate) fn merkle(&self) -> &Merkle {
+        &self.merkle
+    }
+
+    /// Advance past a verified block of `length` bytes in the data store,
+    /// already added to the merkle state.
+    #[inline]
+    pub(crate) fn advance(&mut self, length: u32) {
+        self.index += 1;
+        self.byte_length += length as u64;
+    }
+
+    #[inline]
+    pub(crate) fn merkle_mut(&mut self) -> &mut Merkle {
+        &mut self.merkle
+    }
+
+    /// Serialize [VerifyCursor].
+    #[inline]
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let roots = self.merkle.roots();
+        let mut data = Vec::with_capacity(
+            size_of::<u32>() + size_of::<u64>() + size_of::<u32>()
+            + roots.len() * NODE_SIZE);
+
+        data.write_u32::<LittleEndian>(self.index)?;
+        data.write_u64::<LittleEndian>(self.byte_length)?;
+        data.write_u32::<LittleEndian>(roots.len() as u32)?;
+        for node in roots {
+            data.extend_from_slice(&node.to_bytes()?);
+        }
+
+        Ok(data)
+    }
+    /// Deserialize [VerifyCursor].
+    #[inline]
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut rdr = Cursor::new(data);
+        let index = rdr.read_u32::<LittleEndian>()?;
+        let byte_length = rdr.read_u64::<LittleEndian>()?;
+        let length = rdr.read_u32::<LittleEndian>()?;
+
+        let mut roots = vec![];
+        for _ in 0..length {
+            let mut node = [0u8; NODE_SIZE];
+            rdr.read_exact(&mut node)?;
+            roots.push(Node::from_bytes(&node)?);
+        }
+        let merkle = Merkle::from_roots(roots);
+        ensure!(merkle.blocks() == index as u64,
+            "VerifyCursor index does not match its merkle state.");
+
+        Ok(Self {
+            index,
+            byte_length,
+            merkle,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::Hash;
+
+    #[test]
+    pub fn to_bytes_from_bytes() -> Result<()> {
+        let mut cursor = VerifyCursor::new();
+        for data in [&b"hello"[..], b"world", b"!"] {
+            cursor.merkle_mut().next(Hash::from_leaf(data), data.len() as u64);
+            cursor.advance(data.len() as u32);
+        }
+        let bytes = cursor.to_bytes()?;
+        let cursor2 = VerifyCursor::from_bytes(&bytes)?;
+        assert_eq!(cursor2.index(), 3);
+        assert_eq!(cursor2.byte_length(), 11);
+        assert_eq!(cursor2.merkle().roots(), cursor.merkle().roots());
+        assert!(VerifyCursor::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        Ok(())
+    }
+}
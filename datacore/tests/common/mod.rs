@@ -1,10 +1,13 @@
 #![cfg_attr(test, allow(dead_code))]
 
 use std::path::PathBuf;
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use random_access_memory::RandomAccessMemory;
 use random_access_disk::RandomAccessDisk;
-use datacore::Keypair;
+use datacore::{Keypair, RandomAccess};
 
 pub fn random_access_memory() -> RandomAccessMemory {
     RandomAccessMemory::new(1024)
@@ -16,3 +19,101 @@ pub async fn random_access_disk(dir: PathBuf) -> RandomAccessDisk {
 pub fn copy_keypair(keypair: &Keypair) -> Keypair {
     Keypair::from_bytes(&keypair.to_bytes()).unwrap()
 }
+
+/// [RandomAccessMemory] counting its writes and flushes.
+#[derive(Debug)]
+pub struct Counting {
+    store: RandomAccessMemory,
+    pub writes: Arc<AtomicUsize>,
+    pub flushes: Arc<AtomicUsize>,
+}
+impl Counting {
+    pub fn new() -> Self {
+        Self {
+            store: random_access_memory(),
+            writes: Arc::new(AtomicUsize::new(0)),
+            flushes: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+#[async_trait::async_trait]
+impl RandomAccess for Counting {
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn write(&mut self, offset: u64, data: &[u8])
+        -> Result<(), Self::Error>
+    {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        self.store.write(offset, data).await
+    }
+    async fn read(&mut self, offset: u64, length: u64)
+        -> Result<Vec<u8>, Self::Error>
+    {
+        self.store.read(offset, length).await
+    }
+    async fn delete(&mut self, offset: u64, length: u64)
+        -> Result<(), Self::Error>
+    {
+        self.store.delete(offset, length).await
+    }
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flushes.fetch_add(1, Ordering::SeqCst);
+        self.store.flush().await
+    }
+    async fn len(&mut self) -> Result<u64, Self::Error> {
+        self.store.len().await
+    }
+}
+
+/// [RandomAccess] failing its reads while `fail_reads` is set,
+/// and its writes while `fail_writes` is set.
+#[derive(Debug)]
+pub struct Failing<T> {
+    store: T,
+    pub fail_reads: Arc<AtomicBool>,
+    pub fail_writes: Arc<AtomicBool>,
+}
+impl<T> Failing<T> {
+    pub fn new(store: T) -> Self {
+        Self {
+            store,
+            fail_reads: Arc::new(AtomicBool::new(false)),
+            fail_writes: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+#[async_trait::async_trait]
+impl<T> RandomAccess for Failing<T>
+where
+    T: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send,
+{
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn write(&mut self, offset: u64, data: &[u8])
+        -> Result<(), Self::Error>
+    {
+        if self.fail_writes.load(Ordering::SeqCst) {
+            return Err("write failed".into())
+        }
+        self.store.write(offset, data).await
+    }
+    async fn read(&mut self, offset: u64, length: u64)
+        -> Result<Vec<u8>, Self::Error>
+    {
+        if self.fail_reads.load(Ordering::SeqCst) {
+            return Err("read failed".into())
+        }
+        self.store.read(offset, length).await
+    }
+    async fn delete(&mut self, offset: u64, length: u64)
+        -> Result<(), Self::Error>
+    {
+        self.store.delete(offset, length).await
+    }
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.store.flush().await
+    }
+    async fn len(&mut self) -> Result<u64, Self::Error> {
+        self.store.len().await
+    }
+}
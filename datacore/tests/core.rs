@@ -1,14 +1,138 @@
 mod common;
-use common::{random_access_memory, random_access_disk, copy_keypair};
+use common::{
+    random_access_memory, random_access_disk, copy_keypair, Counting, Failing,
+};
 
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
 use async_std::test;
 use tempfile;
 
+use random_access_memory::RandomAccessMemory;
+
 use datacore::{
-    Merkle, NodeTrait, Hash, BlockSignature, Core,
-    generate_keypair, sign,
+    Merkle, Hash, BlockSignature, Core, CoreBuilder, CoreOptions, TryGet,
+    RandomAccess, PAGE_SIZE, SIGNATURE_LENGTH, generate_keypair, sign, verify,
+    verify_checkpoint, verify_proof, VerifyCursor,
 };
 
+#[test]
+pub async fn core_on_append() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    core.append(b"before", None).await.unwrap();
+    let appended = Arc::new(Mutex::new(vec![]));
+    let log = Arc::clone(&appended);
+    core.on_append(Box::new(move |index, data| {
+        log.lock().unwrap().push((index, data.to_vec()));
+    }));
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    let other = generate_keypair();
+    let bad = BlockSignature::new(
+        sign(&other.public, &other.secret, &Hash::from_leaf(b"bad")),
+        sign(&other.public, &other.secret, &Hash::from_leaf(b"bad")));
+    assert!(core.append(b"bad", Some(bad)).await.is_err());
+
+    assert_eq!(*appended.lock().unwrap(), vec![
+        (1, b"hello".to_vec()),
+        (2, b"world".to_vec()),
+    ]);
+}
+
+#[test]
+pub async fn core_appended() {
+    let keypair = generate_keypair();
+    let core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    let core = Arc::new(async_std::sync::Mutex::new(core));
+
+    let appended = core.lock().await.appended();
+    let writer = Arc::clone(&core);
+    async_std::task::spawn(async move {
+        writer.lock().await.append(b"hello", None).await.unwrap();
+    });
+    appended.await;
+    assert_eq!(core.lock().await.len(), 1);
+}
+
+#[test]
+pub async fn core_max_block_size() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap()
+        .with_max_block_size(4);
+
+    core.append(b"four", None).await.unwrap();
+    assert!(core.append(b"fives", None).await.is_err());
+    assert_eq!(core.len(), 1);
+}
+
+#[test]
+pub async fn core_try_get() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let keypair2 = copy_keypair(&keypair);
+    let options = CoreOptions {
+        cache_size: 2,
+        ..CoreOptions::default()
+    };
+    let mut core = Core::new_with_options(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(keypair.secret),
+        options.clone())
+        .await.unwrap();
+    for data in [b"a", b"b", b"c"] {
+        core.append(data, None).await.unwrap();
+    }
+    assert_eq!(core.try_get(0), TryGet::Miss);
+    let hit = core.get(2).await.unwrap().unwrap();
+    assert_eq!(core.try_get(2), TryGet::Hit(hit));
+    assert_eq!(core.try_get(3), TryGet::OutOfRange);
+    drop(core);
+
+    let mut core = Core::new_with_options(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair2.public, Some(keypair2.secret),
+        options)
+        .await.unwrap();
+    assert_eq!(core.try_get(0), TryGet::Miss);
+    let block = core.get(0).await.unwrap().unwrap();
+    assert_eq!(core.try_get(0), TryGet::Hit(block));
+}
+
+#[test]
+pub async fn core_try_get_disabled() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.get(0).await.unwrap();
+    assert_eq!(core.try_get(0), TryGet::Miss);
+}
+
 #[test]
 pub async fn core_init() {
     let keypair = generate_keypair();
@@ -48,6 +172,58 @@ pub async fn core_append() {
         Some(br#"{"hello":"welt"}"#.to_vec()));
 }
 
+#[test]
+pub async fn core_append_flushes() {
+    let keypair = generate_keypair();
+    let data = Counting::new();
+    let (writes, flushes) = (Arc::clone(&data.writes), Arc::clone(&data.flushes));
+    let mut core = Core::new(
+        data,
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    for data in [&b"hello"[..], b"world"] {
+        core.append(data, None).await.unwrap();
+    }
+    assert_eq!(writes.load(Ordering::SeqCst), 2);
+    assert_eq!(flushes.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+pub async fn core_append_with_hash() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        copy_keypair(&keypair).public, Some(keypair.secret))
+        .await.unwrap();
+    let mut core2 = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, None)
+        .await.unwrap();
+
+    core.append(b"hello", None).await.unwrap();
+    core.append_with_hash(b"world", Hash::from_leaf(b"world"), None)
+        .await.unwrap();
+    let (data, signature) = core.get(0).await.unwrap().unwrap();
+    core2.append_with_hash(&data, Hash::from_leaf(&data), Some(signature))
+        .await.unwrap();
+    let (data, signature) = core.get(1).await.unwrap().unwrap();
+    assert!(core2.append_with_hash(
+            &data, Hash::from_leaf(b"wrong"), Some(signature.clone()))
+        .await.is_err());
+    core2.append(&data, Some(signature)).await.unwrap();
+
+    assert_eq!(core2.len(), 2);
+    assert_eq!(core2.get(0).await.unwrap(), core.get(0).await.unwrap());
+    assert_eq!(core2.get(1).await.unwrap(), core.get(1).await.unwrap());
+}
+
 #[test]
 pub async fn core_signatures() {
     let keypair = generate_keypair();
@@ -69,11 +245,11 @@ pub async fn core_signatures() {
     merkle.next(Hash::from_leaf(data1), data1.len() as u64);
     let signature1 = BlockSignature::new(
         sign(&keypair2.public, &keypair2.secret, &Hash::from_leaf(data1)),
-        sign(&keypair2.public, &keypair2.secret, &hash_tree(&merkle)));
+        sign(&keypair2.public, &keypair2.secret, &merkle.root_hash()));
     merkle.next(Hash::from_leaf(data2), data2.len() as u64);
     let signature2 = BlockSignature::new(
         sign(&keypair2.public, &keypair2.secret, &Hash::from_leaf(data2)),
-        sign(&keypair2.public, &keypair2.secret, &hash_tree(&merkle)));
+        sign(&keypair2.public, &keypair2.secret, &merkle.root_hash()));
 
     assert_eq!(core.len(), 2);
     assert_eq!(
@@ -113,6 +289,164 @@ pub async fn core_get_head() {
         Some(br#"{"hello":"welt"}"#.to_vec()));
 }
 
+#[test]
+pub async fn core_signatures_range() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    for i in 0..10u32 {
+        core.append(&i.to_be_bytes(), None).await.unwrap();
+    }
+
+    let signatures = core.signatures(3, 5).await.unwrap();
+    assert_eq!(signatures.len(), 5);
+    for (i, signature) in signatures.into_iter().enumerate() {
+        let expected = core.get(3 + i as u32).await.unwrap().unwrap().1;
+        assert_eq!(signature, expected);
+    }
+
+    assert_eq!(core.signatures(10, 0).await.unwrap(), vec![]);
+    assert!(core.signatures(8, 3).await.is_err());
+    assert!(core.signatures(u32::MAX, 2).await.is_err());
+}
+
+#[test]
+pub async fn core_get_range() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap()
+        .with_verify_on_read(true);
+
+    for i in 0..10u32 {
+        core.append(&i.to_be_bytes(), None).await.unwrap();
+    }
+
+    let blocks = core.get_range(3, 8).await.unwrap();
+    assert_eq!(blocks.len(), 5);
+    for (i, block) in blocks.into_iter().enumerate() {
+        assert_eq!(Some(block), core.get(3 + i as u32).await.unwrap());
+    }
+
+    assert_eq!(core.get_range(10, 10).await.unwrap(), vec![]);
+    assert!(core.get_range(8, 11).await.is_err());
+    assert!(core.get_range(5, 4).await.is_err());
+}
+
+#[test]
+pub async fn core_framed_get_range() {
+    let keypair = generate_keypair();
+    let mut core = Core::new_framed(
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    for data in [&b"hello"[..], b"", b"world"] {
+        core.append(data, None).await.unwrap();
+    }
+    let data: Vec<_> = core.get_range(0, 3).await.unwrap()
+        .into_iter().map(first).collect();
+    assert_eq!(data, vec![b"hello".to_vec(), vec![], b"world".to_vec()]);
+}
+
+#[test]
+pub async fn core_checkpoint() {
+    let keypair = generate_keypair();
+    let public = keypair.public;
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    assert!(core.checkpoint().await.is_err());
+
+    core.append(b"hello", None).await.unwrap();
+    let first_checkpoint = core.checkpoint().await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    let checkpoint = core.checkpoint().await.unwrap();
+
+    assert_eq!(checkpoint.length, 2);
+    assert_eq!(checkpoint.byte_length, 10);
+    assert_eq!(
+        checkpoint.tree_signature,
+        core.head().await.unwrap().unwrap().1.tree());
+    assert!(verify_checkpoint(&public, &first_checkpoint).is_ok());
+    assert!(verify_checkpoint(&public, &checkpoint).is_ok());
+
+    let mut tampered = checkpoint.clone();
+    tampered.root_hash = first_checkpoint.root_hash;
+    assert!(verify_checkpoint(&public, &tampered).is_err());
+    let other = generate_keypair();
+    assert!(verify_checkpoint(&other.public, &checkpoint).is_err());
+}
+
+#[test]
+pub async fn core_proof() {
+    let keypair = generate_keypair();
+    let public = keypair.public;
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    let data: [&[u8]; 5] = [b"a", b"bb", b"ccc", b"dddd", b"eeeee"];
+    for block in data {
+        core.append(block, None).await.unwrap();
+    }
+    assert!(core.proof(5).await.unwrap().is_none());
+
+    for (i, block) in data.iter().enumerate() {
+        let proof = core.proof(i as u32).await.unwrap().unwrap();
+        assert_eq!(proof.roots.len(), 2);
+        assert!(verify_proof(&public, i as u32, block, &proof).unwrap());
+        assert!(!verify_proof(&public, i as u32, b"oops", &proof).unwrap());
+    }
+
+    let proof = core.proof(2).await.unwrap().unwrap();
+    assert_eq!(proof.nodes.len(), 2);
+    assert!(verify_proof(&public, 3, b"ccc", &proof).is_err());
+    let other = generate_keypair();
+    assert!(!verify_proof(&other.public, 2, b"ccc", &proof).unwrap());
+}
+
+#[test]
+pub async fn core_proof_many_roots() {
+    let keypair = generate_keypair();
+    let public = keypair.public;
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    // roots over 32, 4 and 1 blocks
+    for i in 0..37u32 {
+        core.append(&i.to_be_bytes(), None).await.unwrap();
+    }
+
+    for i in 0..37u32 {
+        let proof = core.proof(i).await.unwrap().unwrap();
+        assert_eq!(proof.roots.len(), 3);
+        let depth = match i {
+            0..=31 => 5,
+            32..=35 => 2,
+            _ => 0,
+        };
+        assert_eq!(proof.nodes.len(), depth);
+        assert!(verify_proof(&public, i, &i.to_be_bytes(), &proof).unwrap());
+    }
+}
+
 #[test]
 pub async fn core_append_no_secret_key() {
     let keypair = generate_keypair();
@@ -150,6 +484,49 @@ pub async fn core_disk_append() {
         Some(b"this is datacore".to_vec()));
 }
 
+#[test]
+pub async fn core_append_empty() {
+    for compress in [false, true] {
+        let dir = tempfile::tempdir().unwrap().into_path();
+        let keypair = generate_keypair();
+        let keypair2 = copy_keypair(&keypair);
+        let options = CoreOptions {
+            compress,
+            ..CoreOptions::default()
+        };
+        let mut core = Core::new_with_options(
+            random_access_disk(dir.to_path_buf().join("d")).await,
+            random_access_disk(dir.to_path_buf().join("b")).await,
+            random_access_disk(dir.to_path_buf().join("s")).await,
+            keypair.public, Some(keypair.secret),
+            options.clone())
+            .await.unwrap();
+
+        core.append(b"", None).await.unwrap();
+        core.append(b"hello", None).await.unwrap();
+        core.append(b"", None).await.unwrap();
+        assert_eq!(core.len(), 3);
+        assert_eq!(core.get(0).await.unwrap().map(first), Some(vec![]));
+        assert_eq!(
+            core.get(1).await.unwrap().map(first),
+            Some(b"hello".to_vec()));
+        assert_eq!(core.get(2).await.unwrap().map(first), Some(vec![]));
+        core.verify().await.unwrap();
+        drop(core);
+
+        let mut core = Core::new_with_options(
+            random_access_disk(dir.to_path_buf().join("d")).await,
+            random_access_disk(dir.to_path_buf().join("b")).await,
+            random_access_disk(dir.to_path_buf().join("s")).await,
+            keypair2.public, Some(keypair2.secret),
+            options)
+            .await.unwrap();
+        assert_eq!(core.len(), 3);
+        assert_eq!(core.get(2).await.unwrap().map(first), Some(vec![]));
+        core.verify().await.unwrap();
+    }
+}
+
 #[test]
 pub async fn core_disk_persists() {
     let dir = tempfile::tempdir().unwrap().into_path();
@@ -181,16 +558,861 @@ pub async fn core_disk_persists() {
         Some(b"this is datacore".to_vec()));
 }
 
-fn first<A, B>(t: (A, B)) -> A {
-    t.0
+#[test]
+pub async fn core_builder() {
+    let keypair = generate_keypair();
+    let mut core = CoreBuilder::new()
+        .data(random_access_memory())
+        .blocks(random_access_memory())
+        .state(random_access_memory())
+        .public_key(keypair.public)
+        .secret_key(keypair.secret)
+        .options(CoreOptions {
+            compress: true,
+            ..CoreOptions::default()
+        })
+        .build()
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    assert_eq!(
+        core.get(0).await.unwrap().map(first),
+        Some(b"hello".to_vec()));
+
+    // no blocks store
+    let keypair = generate_keypair();
+    let result = CoreBuilder::<_, RandomAccessMemory, _>::new()
+        .data(random_access_memory())
+        .state(random_access_memory())
+        .public_key(keypair.public)
+        .build()
+        .await;
+    assert!(result.is_err());
+}
+
+#[test]
+pub async fn core_export_import() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    for data in [&b"hello"[..], b"", b"world"] {
+        core.append(data, None).await.unwrap();
+    }
+    let blob = core.export().await.unwrap();
+
+    let mut imported = Core::import(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        &blob)
+        .await.unwrap();
+    assert_eq!(imported.public_key(), core.public_key());
+    assert_eq!(imported.len(), core.len());
+    for index in 0..core.len() {
+        assert_eq!(
+            imported.get(index).await.unwrap(),
+            core.get(index).await.unwrap());
+    }
+    imported.verify().await.unwrap();
+    assert_eq!(imported.export().await.unwrap(), blob);
+
+    // tampered data
+    let mut tampered = blob.clone();
+    let last = tampered.len() - 2 * SIGNATURE_LENGTH - 1;
+    tampered[last] ^= 1;
+    assert!(Core::import(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        &tampered)
+        .await.is_err());
+    // truncated
+    assert!(Core::import(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        &blob[..blob.len() - 1])
+        .await.is_err());
+}
+
+#[test]
+pub async fn core_export_to_import_from_pipe() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    let big = vec![7u8; 64 * 1024];
+    core.append(b"hello", None).await.unwrap();
+    core.append(&big, None).await.unwrap();
+    for i in 0..100u32 {
+        core.append(&i.to_le_bytes(), None).await.unwrap();
+    }
+
+    let (reader, writer) = sluice::pipe::pipe();
+    let export = async {
+        core.export_to(writer).await.unwrap();
+    };
+    let import = Core::import_from(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        reader);
+    let ((), imported) = futures_lite::future::zip(export, import).await;
+    let mut imported = imported.unwrap();
+
+    assert_eq!(imported.len(), core.len());
+    assert_eq!(imported.get(1).await.unwrap().map(first), Some(big));
+    imported.verify().await.unwrap();
+    assert_eq!(imported.export().await.unwrap(), core.export().await.unwrap());
+}
+
+#[test]
+pub async fn core_byte_len() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    assert_eq!(core.byte_len(), 0);
+
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world!", None).await.unwrap();
+    assert_eq!(core.byte_len(), 11);
+    assert_eq!(core.block_offset(0).await.unwrap(), Some(0));
+    assert_eq!(core.block_offset(1).await.unwrap(), Some(5));
+    assert_eq!(core.block_offset(2).await.unwrap(), None);
+
+    core.truncate(1).await.unwrap();
+    assert_eq!(core.byte_len(), 5);
+}
+
+#[test]
+pub async fn core_truncate() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        copy_keypair(&keypair).public, Some(keypair.secret))
+        .await.unwrap();
+    for data in [&b"hello"[..], b"world", b"!"] {
+        core.append(data, None).await.unwrap();
+    }
+
+    assert!(core.truncate(4).await.is_err());
+    core.truncate(1).await.unwrap();
+    assert_eq!(core.len(), 1);
+    assert_eq!(core.get(1).await.unwrap(), None);
+    core.verify().await.unwrap();
+
+    core.append(b"there", None).await.unwrap();
+    assert_eq!(
+        core.get(1).await.unwrap().map(first),
+        Some(b"there".to_vec()));
+    core.verify().await.unwrap();
+}
+
+#[test]
+pub async fn core_framed_truncate() {
+    let keypair = generate_keypair();
+    let mut core = Core::new_framed(
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    for data in [&b"hello"[..], b"world", b"!"] {
+        core.append(data, None).await.unwrap();
+    }
+
+    core.truncate(1).await.unwrap();
+    core.append(b"there", None).await.unwrap();
+    assert_eq!(core.len(), 2);
+    assert_eq!(
+        core.get(1).await.unwrap().map(first),
+        Some(b"there".to_vec()));
+    core.verify().await.unwrap();
 }
-fn hash_tree(merkle: &Merkle) -> Hash {
-    let roots = merkle.roots();
-    let hashes = roots.iter()
-        .map(|root| root.hash())
-        .collect::<Vec<&Hash>>();
-    let lengths = roots.iter()
-        .map(|root| root.len())
-        .collect::<Vec<u64>>();
-    Hash::from_roots(&hashes, &lengths)
+
+#[test]
+pub async fn core_single_store_persists() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let path = dir.to_path_buf().join("core");
+    let keypair = generate_keypair();
+    let keypair2 = copy_keypair(&keypair);
+    let mut core = Core::new_single(
+        random_access_disk(path.clone()).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    // larger than a page, spans pages of both regions
+    let big = vec![7u8; PAGE_SIZE as usize + 42];
+    core.append(b"hello", None).await.unwrap();
+    core.append(&big, None).await.unwrap();
+    for i in 0..1000u32 {
+        core.append(&i.to_le_bytes(), None).await.unwrap();
+    }
+    drop(core);
+
+    let mut core = Core::new_single(
+        random_access_disk(path.clone()).await,
+        keypair2.public, Some(keypair2.secret))
+        .await.unwrap();
+    assert_eq!(core.len(), 1002);
+    assert_eq!(
+        core.get(0).await.unwrap().map(first),
+        Some(b"hello".to_vec()));
+    assert_eq!(core.get(1).await.unwrap().map(first), Some(big));
+    assert_eq!(
+        core.get(1001).await.unwrap().map(first),
+        Some(999u32.to_le_bytes().to_vec()));
+    core.verify().await.unwrap();
+
+    core.append(b"world", None).await.unwrap();
+    assert_eq!(
+        core.get(1002).await.unwrap().map(first),
+        Some(b"world".to_vec()));
+    core.verify().await.unwrap();
+}
+
+#[test]
+pub async fn core_single_store_rejects_foreign() {
+    let keypair = generate_keypair();
+    let mut store = random_access_memory();
+    store.write(0, &[1u8; 128]).await.unwrap();
+    assert!(Core::new_single(store, keypair.public, Some(keypair.secret))
+            .await.is_err());
+}
+
+#[test]
+pub async fn core_framed_store_persists() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let path = dir.to_path_buf().join("core");
+    let keypair = generate_keypair();
+    let keypair2 = copy_keypair(&keypair);
+    let mut core = Core::new_framed(
+        random_access_disk(path.clone()).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"", None).await.unwrap();
+    for i in 0..100u32 {
+        core.append(&i.to_le_bytes(), None).await.unwrap();
+    }
+    drop(core);
+
+    let mut core = Core::new_framed(
+        random_access_disk(path.clone()).await,
+        keypair2.public, Some(keypair2.secret))
+        .await.unwrap();
+    assert_eq!(core.len(), 102);
+    assert_eq!(
+        core.get(0).await.unwrap().map(first),
+        Some(b"hello".to_vec()));
+    assert_eq!(core.get(1).await.unwrap().map(first), Some(vec![]));
+    assert_eq!(
+        core.get(101).await.unwrap().map(first),
+        Some(99u32.to_le_bytes().to_vec()));
+    assert_eq!(core.signatures(0, 102).await.unwrap().len(), 102);
+    core.verify().await.unwrap();
+
+    core.append(b"world", None).await.unwrap();
+    assert_eq!(
+        core.get(102).await.unwrap().map(first),
+        Some(b"world".to_vec()));
+    core.verify().await.unwrap();
+
+    // not a framed store
+    assert!(Core::new_single(
+        random_access_disk(path.clone()).await,
+        keypair.public, None)
+        .await.is_err());
+}
+
+#[test]
+pub async fn core_disk_lazy_state() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let options = CoreOptions {
+        lazy_state: true,
+        ..CoreOptions::default()
+    };
+
+    // flush
+    let mut core = Core::new_with_options(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(copy_keypair(&keypair).secret),
+        options.clone())
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    core.flush().await.unwrap();
+
+    // drop without flush, deferred state is lost
+    core.append(b"lost", None).await.unwrap();
+    drop(core);
+    let mut core = Core::new_with_options(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(copy_keypair(&keypair).secret),
+        options.clone())
+        .await.unwrap();
+    assert_eq!(core.len(), 2);
+
+    // flush before drop
+    core.append(b"again", None).await.unwrap();
+    core.flush().await.unwrap();
+    drop(core);
+    let mut core = Core::new_with_options(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(keypair.secret),
+        options)
+        .await.unwrap();
+    assert_eq!(core.len(), 3);
+    assert_eq!(
+        core.get(2).await.unwrap().map(first),
+        Some(b"again".to_vec()));
+    assert_eq!(core.len(), 3);
+}
+
+#[test]
+pub async fn core_rebuild_state() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(copy_keypair(&keypair).secret))
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    core.append(b"again", None).await.unwrap();
+    core.verify().await.unwrap();
+    drop(core);
+
+    // lose the state store
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s2")).await,
+        keypair.public, Some(copy_keypair(&keypair).secret))
+        .await.unwrap();
+    assert_eq!(core.len(), 0);
+    core.rebuild_state().await.unwrap();
+    assert_eq!(core.len(), 3);
+    core.verify().await.unwrap();
+    core.append(b"more", None).await.unwrap();
+    drop(core);
+
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s2")).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    assert_eq!(core.len(), 4);
+    assert_eq!(
+        core.get(3).await.unwrap().map(first),
+        Some(b"more".to_vec()));
+    core.verify().await.unwrap();
+}
+
+#[test]
+pub async fn core_verify_on_read() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    drop(core);
+
+    // corrupt the second block's data
+    let mut data = random_access_disk(dir.to_path_buf().join("d")).await;
+    data.write(5, b"W").await.unwrap();
+    drop(data);
+
+    let open = || async {
+        Core::new(
+            random_access_disk(dir.to_path_buf().join("d")).await,
+            random_access_disk(dir.to_path_buf().join("b")).await,
+            random_access_disk(dir.to_path_buf().join("s")).await,
+            keypair.public, None)
+            .await.unwrap()
+    };
+    let mut core = open().await;
+    assert_eq!(
+        core.get(1).await.unwrap().map(first),
+        Some(b"World".to_vec()));
+
+    let mut core = open().await.with_verify_on_read(true);
+    assert_eq!(
+        core.get(0).await.unwrap().map(first),
+        Some(b"hello".to_vec()));
+    assert!(core.get(1).await.is_err());
+}
+
+#[test]
+pub async fn core_get_tampered_block() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    drop(core);
+
+    // point the first block, after the store header, past the end of the data
+    let mut blocks = random_access_disk(dir.to_path_buf().join("b")).await;
+    blocks.write(8, &100u64.to_le_bytes()).await.unwrap();
+    drop(blocks);
+
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, None)
+        .await.unwrap();
+    let err = core.get(0).await.unwrap_err();
+    assert!(err.to_string().contains("beyond store bounds"));
+    assert_eq!(
+        core.get(1).await.unwrap().map(first),
+        Some(b"world".to_vec()));
+}
+
+#[test]
+pub async fn core_rejects_key_mismatch() {
+    let keypair = generate_keypair();
+    let other = generate_keypair();
+    let result = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(other.secret))
+        .await;
+    assert_eq!(result.unwrap_err().to_string(),
+        "SecretKey does not match the PublicKey.");
+}
+
+#[test]
+pub async fn core_signed_messages() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    for data in [&b"a"[..], b"bb", b"ccc"] {
+        core.append(data, None).await.unwrap();
+    }
+
+    for index in 0..3 {
+        let (data_hash, root_hash) =
+            core.signed_messages(index).await.unwrap().unwrap();
+        let (data, signature) = core.get(index).await.unwrap().unwrap();
+        assert_eq!(data_hash, Hash::from_leaf(&data));
+        verify(&keypair.public, &data_hash, &signature.data()).unwrap();
+        verify(&keypair.public, &root_hash, &signature.tree()).unwrap();
+    }
+    assert!(core.signed_messages(3).await.unwrap().is_none());
+}
+
+#[test]
+pub async fn core_rebuild_state_stops_at_corruption() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(copy_keypair(&keypair).secret))
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    core.append(b"again", None).await.unwrap();
+    drop(core);
+
+    // corrupt the second block's data
+    let mut data = random_access_disk(dir.to_path_buf().join("d")).await;
+    data.write(5, b"W").await.unwrap();
+    drop(data);
+
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    assert_eq!(core.len(), 3);
+    assert!(core.verify().await.is_err());
+
+    core.rebuild_state().await.unwrap();
+    assert_eq!(core.len(), 1);
+    core.verify().await.unwrap();
+}
+
+#[test]
+pub async fn core_rebuild_state_read_error() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let data = Failing::new(random_access_disk(dir.join("d")).await);
+    let fail_reads = Arc::clone(&data.fail_reads);
+    let mut core = Core::new(
+        data,
+        random_access_disk(dir.join("b")).await,
+        random_access_disk(dir.join("s")).await,
+        keypair.public, Some(copy_keypair(&keypair).secret))
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    core.append(b"again", None).await.unwrap();
+
+    // a failing read is not mistaken for the end of the core
+    fail_reads.store(true, Ordering::SeqCst);
+    assert!(core.rebuild_state().await.is_err());
+    fail_reads.store(false, Ordering::SeqCst);
+    assert_eq!(core.len(), 3);
+    core.verify().await.unwrap();
+    drop(core);
+
+    let mut core = Core::new(
+        random_access_disk(dir.join("d")).await,
+        random_access_disk(dir.join("b")).await,
+        random_access_disk(dir.join("s")).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    assert_eq!(core.len(), 3);
+    core.verify().await.unwrap();
+}
+
+#[test]
+pub async fn core_verify_from() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(copy_keypair(&keypair).secret))
+        .await.unwrap();
+    for i in 0..10u32 {
+        core.append(&i.to_le_bytes(), None).await.unwrap();
+    }
+
+    let cursor = core.verify_from(VerifyCursor::new(), 4).await.unwrap();
+    assert_eq!(cursor.index(), 4);
+    let cursor = VerifyCursor::from_bytes(&cursor.to_bytes().unwrap()).unwrap();
+    let cursor = core.verify_from(cursor, 100).await.unwrap();
+    assert_eq!(cursor.index(), core.len());
+    core.verify().await.unwrap();
+    drop(core);
+
+    // corrupt a block in the second slice
+    let mut data = random_access_disk(dir.to_path_buf().join("d")).await;
+    data.write(4 * 7, b"X").await.unwrap();
+    drop(data);
+
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    let cursor = core.verify_from(VerifyCursor::new(), 4).await.unwrap();
+    let cursor = VerifyCursor::from_bytes(&cursor.to_bytes().unwrap()).unwrap();
+    assert!(core.verify_from(cursor, 100).await.is_err());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+pub async fn core_verify_parallel() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    let blocks = datacore::VERIFY_BATCH + 10;
+    for i in 0..blocks {
+        core.append(&i.to_le_bytes(), None).await.unwrap();
+    }
+    core.verify_parallel().await.unwrap();
+    drop(core);
+
+    // corrupt a block of the second batch
+    let mut data = random_access_disk(dir.to_path_buf().join("d")).await;
+    data.write(4 * (datacore::VERIFY_BATCH as u64 + 5), b"X").await.unwrap();
+    drop(data);
+
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("d")).await,
+        random_access_disk(dir.to_path_buf().join("b")).await,
+        random_access_disk(dir.to_path_buf().join("s")).await,
+        keypair.public, None)
+        .await.unwrap();
+    let err = core.verify_parallel().await.unwrap_err();
+    assert_eq!(err.to_string(), format!(
+        "Block {} has invalid data signature.", datacore::VERIFY_BATCH + 5));
+}
+
+#[test]
+pub async fn core_disk_compressed() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let records = (0..100)
+        .map(|i| {
+            let items = (0..10)
+                .map(|j| format!(
+                    r#"{{"sku":"item-{}-{}","quantity":{},"status":"shipped"}}"#,
+                    i, j, j))
+                .collect::<Vec<String>>();
+            format!(r#"{{"order":{},"customer":"user {}","items":[{}]}}"#,
+                i, i, items.join(","))
+        })
+        .collect::<Vec<String>>();
+
+    let mut sizes = vec![];
+    for compress in [false, true] {
+        let path = dir.join(format!("{}", compress));
+        let keypair = generate_keypair();
+        let options = CoreOptions {
+            compress,
+            ..CoreOptions::default()
+        };
+        let mut core = Core::new_with_options(
+            random_access_disk(path.join("d")).await,
+            random_access_disk(path.join("b")).await,
+            random_access_disk(path.join("s")).await,
+            keypair.public, Some(copy_keypair(&keypair).secret),
+            options.clone())
+            .await.unwrap();
+        for record in records.iter() {
+            core.append(record.as_bytes(), None).await.unwrap();
+        }
+        drop(core);
+
+        let mut core = Core::new_with_options(
+            random_access_disk(path.join("d")).await,
+            random_access_disk(path.join("b")).await,
+            random_access_disk(path.join("s")).await,
+            keypair.public, Some(keypair.secret),
+            options)
+            .await.unwrap();
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(
+                core.get(i as u32).await.unwrap().map(first),
+                Some(record.as_bytes().to_vec()));
+        }
+        core.verify().await.unwrap();
+        sizes.push(std::fs::metadata(path.join("d")).unwrap().len());
+    }
+    assert!(sizes[1] < sizes[0]);
+}
+
+fn first<A, B>(t: (A, B)) -> A {
+    t.0
+}
+
+#[test]
+pub async fn core_index_of() {
+    let keypair = generate_keypair();
+    let mut core = Core::new_with_index(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret),
+        CoreOptions::default())
+        .await.unwrap();
+
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+
+    assert_eq!(
+        core.index_of(&Hash::from_leaf(b"hello")).await.unwrap(),
+        Some(0));
+    assert_eq!(
+        core.index_of(&Hash::from_leaf(b"world")).await.unwrap(),
+        Some(1));
+    assert_eq!(
+        core.index_of(&Hash::from_leaf(b"mundo")).await.unwrap(),
+        None);
+}
+
+#[test]
+pub async fn core_index_of_no_index() {
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    core.append(b"hello", None).await.unwrap();
+    assert!(core.index_of(&Hash::from_leaf(b"hello")).await.is_err());
+}
+
+#[test]
+pub async fn core_index_of_truncated() {
+    let keypair = generate_keypair();
+    let mut core = Core::new_with_index(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret),
+        CoreOptions::default())
+        .await.unwrap();
+
+    core.append(b"a", None).await.unwrap();
+    core.append(b"b", None).await.unwrap();
+    core.truncate(1).await.unwrap();
+    core.append(b"c", None).await.unwrap();
+
+    assert_eq!(
+        core.index_of(&Hash::from_leaf(b"b")).await.unwrap(),
+        None);
+    assert_eq!(
+        core.index_of(&Hash::from_leaf(b"c")).await.unwrap(),
+        Some(1));
+    core.append(b"b", None).await.unwrap();
+    assert_eq!(
+        core.index_of(&Hash::from_leaf(b"b")).await.unwrap(),
+        Some(2));
+}
+
+#[test]
+pub async fn core_disk_index_built() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_disk(dir.join("d")).await,
+        random_access_disk(dir.join("b")).await,
+        random_access_disk(dir.join("s")).await,
+        keypair.public, Some(copy_keypair(&keypair).secret))
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"world", None).await.unwrap();
+    drop(core);
+
+    let mut core = Core::new_with_index(
+        random_access_disk(dir.join("d")).await,
+        random_access_disk(dir.join("b")).await,
+        random_access_disk(dir.join("s")).await,
+        random_access_disk(dir.join("i")).await,
+        keypair.public, Some(keypair.secret),
+        CoreOptions::default())
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+    core.append(b"mundo", None).await.unwrap();
+
+    assert_eq!(
+        core.index_of(&Hash::from_leaf(b"world")).await.unwrap(),
+        Some(1));
+    assert_eq!(
+        core.index_of(&Hash::from_leaf(b"hello")).await.unwrap(),
+        Some(0));
+    assert_eq!(
+        core.index_of(&Hash::from_leaf(b"mundo")).await.unwrap(),
+        Some(3));
+}
+
+#[cfg(feature = "unsigned")]
+#[test]
+pub async fn core_append_unsigned_write_error() {
+    let keypair = generate_keypair();
+    let data = Failing::new(random_access_memory());
+    let fail_writes = Arc::clone(&data.fail_writes);
+    let mut core = Core::new(
+        data,
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    core.append(b"hello", None).await.unwrap();
+
+    fail_writes.store(true, Ordering::SeqCst);
+    assert!(core.append_unsigned(b"world").await.is_err());
+    fail_writes.store(false, Ordering::SeqCst);
+    assert!(!core.is_unsigned());
+    assert_eq!(core.len(), 1);
+
+    core.append(b"world", None).await.unwrap();
+    core.verify().await.unwrap();
+}
+
+#[cfg(feature = "unsigned")]
+#[test]
+pub async fn core_disk_append_unsigned() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_disk(dir.join("d")).await,
+        random_access_disk(dir.join("b")).await,
+        random_access_disk(dir.join("s")).await,
+        keypair.public, Some(copy_keypair(&keypair).secret))
+        .await.unwrap();
+
+    core.append(b"hello", None).await.unwrap();
+    assert!(!core.is_unsigned());
+    core.append_unsigned(b"world").await.unwrap();
+    assert!(core.is_unsigned());
+    drop(core);
+
+    let mut core = Core::new(
+        random_access_disk(dir.join("d")).await,
+        random_access_disk(dir.join("b")).await,
+        random_access_disk(dir.join("s")).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    assert!(core.is_unsigned());
+    assert_eq!(core.len(), 2);
+    assert_eq!(core.get_data(0).await.unwrap(), Some(b"hello".to_vec()));
+    assert_eq!(core.get_data(1).await.unwrap(), Some(b"world".to_vec()));
+    assert_eq!(core.get_data(2).await.unwrap(), None);
+
+    assert!(core.get(1).await.is_err());
+    assert!(core.signatures(0, 1).await.is_err());
+    assert!(core.verify().await.is_err());
+    assert!(core.rebuild_state().await.is_err());
+    assert!(core.append(b"mundo", None).await.is_err());
+    core.append_unsigned(b"mundo").await.unwrap();
+    assert_eq!(core.len(), 3);
+    drop(core);
+
+    // state lost, rebuild does not truncate at the first unsigned block
+    let mut core = Core::new(
+        random_access_disk(dir.join("d")).await,
+        random_access_disk(dir.join("b")).await,
+        random_access_disk(dir.join("s2")).await,
+        keypair.public, None)
+        .await.unwrap();
+    assert!(core.rebuild_state().await.is_err());
+    assert_eq!(core.len(), 0);
 }
@@ -7,9 +7,11 @@ use std::io::Read;
 use async_std::test;
 use tempfile;
 
+use random_access_memory::RandomAccessMemory;
+
 use datacore::{
-    Core, Merkle, Signature, BlockSignature, Hash, NodeTrait,
-    generate_keypair, sign, verify, SIGNATURE_LENGTH,
+    Core, CoreOptions, Merkle, Signature, BlockSignature, Hash,
+    generate_keypair, sign, verify, append_verified, SIGNATURE_LENGTH,
 };
 
 fn read_bytes(dir: &Path, s: &str) -> Vec<u8> {
@@ -19,17 +21,6 @@ fn read_bytes(dir: &Path, s: &str) -> Vec<u8> {
     bytes
 }
 
-fn hash_tree(merkle: &Merkle) -> Hash {
-    let roots = merkle.roots();
-    let hashes = roots.iter()
-        .map(|root| root.hash())
-        .collect::<Vec<&Hash>>();
-    let lengths = roots.iter()
-        .map(|root| root.len())
-        .collect::<Vec<u64>>();
-    Hash::from_roots(&hashes, &lengths)
-}
-
 #[test]
 pub async fn replicate_manual() {
     let dir = tempfile::tempdir().unwrap().into_path();
@@ -63,7 +54,7 @@ pub async fn replicate_manual() {
     let data_sign = sign(&keypair3.public, &keypair3.secret, &data_hash);
     merkle.next(data_hash.clone(), data1.len() as u64);
     verify(&keypair3.public, &data_hash, &data_sign).unwrap();
-    let tree_hash = hash_tree(&merkle);
+    let tree_hash = merkle.root_hash();
     let tree_sign = sign(&keypair3.public, &keypair3.secret, &tree_hash);
     verify(&keypair3.public, &tree_hash, &tree_sign).unwrap();
     let signature = BlockSignature::new(data_sign, tree_sign);
@@ -72,7 +63,7 @@ pub async fn replicate_manual() {
     merkle.next(data_hash.clone(), data2.len() as u64);
     let signature = BlockSignature::new(
         sign(&keypair3.public, &keypair3.secret, &data_hash),
-        sign(&keypair3.public, &keypair3.secret, &hash_tree(&merkle)));
+        sign(&keypair3.public, &keypair3.secret, &merkle.root_hash()));
     replica.append(data2, Some(signature)).await.unwrap();
     assert_eq!(replica.len(), 2);
 
@@ -84,6 +75,91 @@ pub async fn replicate_manual() {
         read_bytes(&dir2, "merkle"), read_bytes(&dir, "merkle"));
 }
 
+#[test]
+pub async fn replicate_append_verified() {
+    let keypair = generate_keypair();
+    let data = b"hello world";
+    let data_hash = Hash::from_leaf(data);
+    let mut merkle = Merkle::new();
+    let root_hash = merkle.clone()
+        .append_and_root(data_hash.clone(), data.len() as u64);
+    let signature = BlockSignature::new(
+        sign(&keypair.public, &keypair.secret, &data_hash),
+        sign(&keypair.public, &keypair.secret, &root_hash));
+
+    let wrong_length = append_verified(&keypair.public, &mut merkle.clone(),
+        data_hash.clone(), data.len() as u64 + 1, &signature);
+    assert!(wrong_length.is_err());
+    let wrong_data = append_verified(&keypair.public, &mut merkle.clone(),
+        Hash::from_leaf(b"other"), data.len() as u64, &signature);
+    assert!(wrong_data.is_err());
+
+    append_verified(&keypair.public, &mut merkle,
+        data_hash, data.len() as u64, &signature).unwrap();
+    assert_eq!(merkle.root_hash(), root_hash);
+}
+
+#[test]
+pub async fn replicate_fork_detected() {
+    let dir = tempfile::tempdir().unwrap().into_path();
+    let keypair = generate_keypair();
+    let keypair2 = copy_keypair(&keypair);
+    let memory = || RandomAccessMemory::new(1024);
+
+    // two writers sharing a key, diverging at block 1
+    let mut core = Core::new(memory(), memory(), memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    let mut fork = Core::new(memory(), memory(), memory(),
+        keypair2.public, Some(keypair2.secret))
+        .await.unwrap();
+    for data in [&b"hello"[..], b"world", b"!"] {
+        core.append(data, None).await.unwrap();
+    }
+    for data in [&b"hello"[..], b"mundo", b"!"] {
+        fork.append(data, None).await.unwrap();
+    }
+
+    let options = CoreOptions {
+        lazy_state: true,
+        ..CoreOptions::default()
+    };
+    let open = || async {
+        Core::new_with_options(
+            random_access_disk(dir.join("d")).await,
+            random_access_disk(dir.join("b")).await,
+            random_access_disk(dir.join("s")).await,
+            keypair.public, None,
+            options.clone())
+            .await.unwrap()
+    };
+    let mut replica = open().await;
+    let (data, signature) = core.get(0).await.unwrap().unwrap();
+    replica.append(&data, Some(signature)).await.unwrap();
+    replica.flush().await.unwrap();
+    let (data, signature) = core.get(1).await.unwrap().unwrap();
+    replica.append(&data, Some(signature)).await.unwrap();
+
+    // does not extend the merkle tree
+    let (data, signature) = fork.get(2).await.unwrap().unwrap();
+    let error = replica.append(&data, Some(signature)).await.unwrap_err();
+    assert!(error.to_string().contains("Fork detected"));
+    assert_eq!(replica.len(), 2);
+
+    // conflicts with the block left over from lost state
+    drop(replica);
+    let mut replica = open().await;
+    assert_eq!(replica.len(), 1);
+    let (data, signature) = fork.get(1).await.unwrap().unwrap();
+    let error = replica.append(&data, Some(signature)).await.unwrap_err();
+    assert!(error.to_string().contains("Fork detected"));
+    assert_eq!(replica.len(), 1);
+
+    let (data, signature) = core.get(1).await.unwrap().unwrap();
+    replica.append(&data, Some(signature)).await.unwrap();
+    assert_eq!(replica.get(1).await.unwrap().unwrap().0, b"world");
+}
+
 #[test]
 pub async fn replicate_manual_no_secret_key() {
     let dir = tempfile::tempdir().unwrap().into_path();
@@ -117,13 +193,13 @@ pub async fn replicate_manual_no_secret_key() {
     merkle.next(data_hash.clone(), data1.len() as u64);
     let signature = BlockSignature::new(
         sign(&keypair3.public, &keypair3.secret, &data_hash),
-        sign(&keypair3.public, &keypair3.secret, &hash_tree(&merkle)));
+        sign(&keypair3.public, &keypair3.secret, &merkle.root_hash()));
     replica.append(data1, Some(signature)).await.unwrap();
     let data_hash = Hash::from_leaf(data2);
     merkle.next(data_hash.clone(), data2.len() as u64);
     let signature = BlockSignature::new(
         sign(&keypair3.public, &keypair3.secret, &data_hash),
-        sign(&keypair3.public, &keypair3.secret, &hash_tree(&merkle)));
+        sign(&keypair3.public, &keypair3.secret, &merkle.root_hash()));
     replica.append(data2, Some(signature)).await.unwrap();
     assert_eq!(replica.len(), 2);
 
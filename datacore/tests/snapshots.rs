@@ -46,3 +46,45 @@ pub async fn snapshots_append() {
     insta::assert_debug_snapshot!(read_bytes(&dir, "blocks"));
     insta::assert_debug_snapshot!(read_bytes(&dir, "merkle"));
 }
+
+#[test]
+pub async fn snapshots_open_legacy() {
+    // stores of `snapshots_append` before the store headers were introduced
+    let dir = tempfile::tempdir().unwrap().into_path();
+    std::fs::write(dir.join("data"),
+        include_bytes!("fixtures/legacy/data")).unwrap();
+    std::fs::write(dir.join("blocks"),
+        include_bytes!("fixtures/legacy/blocks")).unwrap();
+    std::fs::write(dir.join("merkle"),
+        include_bytes!("fixtures/legacy/merkle")).unwrap();
+
+    let keypair = Keypair::from_bytes(&KEYPAIR_BYTES).unwrap();
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("data")).await,
+        random_access_disk(dir.to_path_buf().join("blocks")).await,
+        random_access_disk(dir.to_path_buf().join("merkle")).await,
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    assert_eq!(core.len(), 6);
+    core.verify().await.unwrap();
+    for (i, &b) in b"abcdef".iter().enumerate() {
+        assert_eq!(core.get(i as u32).await.unwrap().unwrap().0, [b]);
+    }
+
+    // appending keeps the legacy layout
+    core.append(b"g", None).await.unwrap();
+    drop(core);
+    let blocks = read_bytes(&dir, "blocks");
+    assert_eq!(blocks.len(), 7 * 140);
+    assert_eq!(blocks[..840], include_bytes!("fixtures/legacy/blocks")[..]);
+
+    let mut core = Core::new(
+        random_access_disk(dir.to_path_buf().join("data")).await,
+        random_access_disk(dir.to_path_buf().join("blocks")).await,
+        random_access_disk(dir.to_path_buf().join("merkle")).await,
+        keypair.public, None)
+        .await.unwrap();
+    assert_eq!(core.len(), 7);
+    core.verify().await.unwrap();
+    assert_eq!(core.get(6).await.unwrap().unwrap().0, b"g");
+}
@@ -82,8 +82,12 @@ impl CoreWasm {
 
     /// Returns [DiscoveryKeyWasm].
     pub fn discovery_key(&self) -> DiscoveryKeyWasm {
-        let discovery_key = discovery_key(self.public_key.as_bytes());
-        DiscoveryKeyWasm::new(discovery_key)
+        DiscoveryKeyWasm::new(self.discovery_key_inner())
+    }
+
+    /// Returns the [DiscoveryKey] as a hex [String].
+    pub fn discovery_key_hex(&self) -> String {
+        hex::encode(self.discovery_key_inner())
     }
 
     /// Returns [PublicKeyWasm].
@@ -135,6 +139,25 @@ impl CoreWasm {
 
         Ok(self)
     }
+    /// Verify every block of the core against its signatures,
+    /// to audit a core restored from untrusted storage.
+    ///
+    /// Because of the requirement for 'static lifetime for async wasm methods,
+    /// the [CoreWasm] is threaded through.
+    /// The error names the first block that failed.
+    pub async fn verify(
+        self,
+        ) -> Result<CoreWasm, JsError>
+    {
+        {
+            let mut core = self.core.lock().await;
+            core.verify().await
+                .map_err(|err| JsError::new(
+                        &format!("Core verification failed: {}", err)))?;
+        }
+        Ok(self)
+    }
+
     /// Retrieve the last value got.
     pub fn read_last(
         &mut self,
@@ -151,7 +174,7 @@ impl CoreWasm {
         let iter = CoreIterator::new(Arc::clone(&self.core), 0);
         CoreIteratorWasm {
             iter,
-            discovery_key: discovery_key(self.public_key.as_bytes()),
+            discovery_key: self.discovery_key_inner(),
         }
     }
 }
@@ -161,6 +184,11 @@ impl CoreWasm {
         &self.public_key
     }
 
+    /// Get the [DiscoveryKey].
+    pub fn discovery_key_inner(&self) -> DiscoveryKey {
+        discovery_key(self.public_key.as_bytes())
+    }
+
     /// Unwrap into [Arc<Mutex<Core>>].
     pub fn take(self) -> AMC<RandomAccessWasm> {
         self.core
@@ -1,9 +1,13 @@
-//! Re-export [DiscoveryKey] and [PublicKey] in an opinionated wrapper.
+//! Re-export [DiscoveryKey], [PublicKey] and [Signature]
+//! in an opinionated wrapper.
 
 use wasm_bindgen::prelude::*;
 use hex;
 
-use libdata::{DiscoveryKey, PublicKey, discovery_key};
+use libdata::{
+    DiscoveryKey, PublicKey, Signature, Hash, SIGNATURE_LENGTH,
+    discovery_key, verify,
+};
 
 /// WASM wrapper for [PublicKey].
 #[wasm_bindgen]
@@ -61,3 +65,45 @@ impl DiscoveryKeyWasm {
         self.key
     }
 }
+
+/// WASM wrapper for [Signature].
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct SignatureWasm {
+    signature: Signature,
+}
+#[wasm_bindgen]
+impl SignatureWasm {
+    /// Create from a hex [String].
+    ///
+    /// Fails on a malformed signature instead of panicking,
+    /// for signatures received from a peer.
+    pub fn from_hex(hex: String) -> Result<SignatureWasm, JsError> {
+        let bytes = hex::decode(&hex)?;
+        if bytes.len() != SIGNATURE_LENGTH {
+            return Err(JsError::new("Wrong length for Signature."))
+        }
+        let signature = Signature::from_bytes(&bytes)
+            .map_err(|_| JsError::new("Malformed Signature."))?;
+        Ok(SignatureWasm { signature })
+    }
+    /// Returns a hex [String].
+    pub fn as_hex(&self) -> String {
+        hex::encode(self.signature.to_bytes())
+    }
+    /// Check if this is a valid data signature of `data` by `public`.
+    pub fn verify_data(&self, public: &PublicKeyWasm, data: String) -> bool {
+        let hash = Hash::from_leaf(data.as_bytes());
+        verify(&public.key, &hash, &self.signature).is_ok()
+    }
+}
+impl SignatureWasm {
+    /// Wrap.
+    pub fn new(signature: Signature) -> Self {
+        Self { signature }
+    }
+    /// Unwrap.
+    pub fn take(self) -> Signature {
+        self.signature
+    }
+}
@@ -61,9 +61,13 @@ impl MultiCoreWasm {
     pub fn contains(&self, hex: &str) -> bool {
         let bytes = hex::decode(hex).unwrap();
         let public_key = PublicKey::from_bytes(&bytes).unwrap();
+        self.contains_public_key(&public_key)
+    }
 
-        *self.local.public_key_inner() == public_key
-            || self.cores.get_by_public(&public_key).is_some()
+    /// Returns `true` if contains a [Core] with the public key of `core`,
+    /// like [MultiCoreWasm::contains] without hex encoding it.
+    pub fn contains_key(&self, core: &CoreWasm) -> bool {
+        self.contains_public_key(core.public_key_inner())
     }
 
     /// Returns [MultiCoreIteratorsWasm].
@@ -103,3 +107,83 @@ impl MultiCoreWasm {
         ReplicasWasm::new(replicas)
     }
 }
+impl MultiCoreWasm {
+    fn contains_public_key(&self, public_key: &PublicKey) -> bool {
+        self.local.public_key_inner() == public_key
+            || self.cores.get_by_public(public_key).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    use libdata::{generate_keypair, public_key_to_hex};
+    use crate::storage::RandomAccessJs;
+    use super::*;
+
+    #[wasm_bindgen(inline_js = "
+        export function memory_storage() {
+            let buffer = new Uint8Array(0);
+            return {
+                write_js: async (offset, data) => {
+                    const end = Number(offset) + data.length;
+                    if (end > buffer.length) {
+                        const grown = new Uint8Array(end);
+                        grown.set(buffer);
+                        buffer = grown;
+                    }
+                    buffer.set(data, Number(offset));
+                },
+                read_js: async (offset, length) => {
+                    const end = Number(offset) + Number(length);
+                    if (end > buffer.length) {
+                        throw new Error('Read bounds exceeded.');
+                    }
+                    return buffer.slice(Number(offset), end);
+                },
+                delete_js: async (offset, length) => {
+                    const start = Number(offset);
+                    const end = Math.min(start + Number(length), buffer.length);
+                    if (end === buffer.length) {
+                        buffer = buffer.slice(0, Math.min(start, end));
+                    } else if (start < end) {
+                        buffer.fill(0, start, end);
+                    }
+                },
+                len_js: async () => buffer.length,
+            };
+        }
+    ")]
+    extern "C" {
+        fn memory_storage() -> RandomAccessJs;
+    }
+
+    async fn new_core() -> CoreWasm {
+        let keypair = generate_keypair();
+        CoreWasm::new(
+            public_key_to_hex(&keypair.public),
+            Some(hex::encode(keypair.secret.as_bytes())),
+            memory_storage(), memory_storage(), memory_storage())
+            .await.ok().expect("Could not create CoreWasm.")
+    }
+
+    #[wasm_bindgen_test]
+    async fn contains_key() {
+        let local = new_core().await;
+        let remote = new_core().await;
+        let other = new_core().await;
+
+        let mut multicore = MultiCoreWasm::new(local.clone());
+        multicore.insert(remote.clone());
+        assert!(multicore.contains_key(&local));
+        assert!(multicore.contains_key(&remote));
+        assert!(!multicore.contains_key(&other));
+
+        assert_eq!(remote.discovery_key_hex(), remote.discovery_key().as_hex());
+        assert_eq!(
+            remote.discovery_key_hex(),
+            hex::encode(discovery_key(remote.public_key_inner().as_bytes())));
+    }
+}
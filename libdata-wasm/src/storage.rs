@@ -10,7 +10,7 @@ use async_channel;
 use random_access_storage::RandomAccess;
 
 /// [RandomAccessWasm] creates a [RandomAccess] interface from
-/// a JS object with `read_js` and `write_js` methods.
+/// a JS object with `read_js`, `write_js`, `delete_js` and `len_js` methods.
 #[derive(Debug)]
 pub struct RandomAccessWasm (Arc<Mutex<RandomAccessJs>>);
 impl RandomAccessWasm {
@@ -65,6 +65,48 @@ impl RandomAccess for RandomAccessWasm {
 
         rx.recv().await?
     }
+
+    /// Delete a sequence of bytes at an offset from the backend.
+    async fn delete(
+        &mut self,
+        offset: u64,
+        length: u64,
+        ) -> Result<(), Self::Error>
+    {
+        let this = Arc::clone(&self.0);
+        let (tx, rx) = async_channel::bounded(1);
+
+        spawn_local(async move {
+            let ram = this.lock().await;
+            let result = ram.delete_js(offset, length).await
+                .map_err(|_| anyhow!("Error calling delete_js.").into());
+            tx.send(result).await.unwrap();
+        });
+
+        rx.recv().await?
+    }
+
+    /// Nothing to flush, `write_js` is expected to persist the data.
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Length of the backend in bytes.
+    async fn len(&mut self) -> Result<u64, Self::Error> {
+        let this = Arc::clone(&self.0);
+        let (tx, rx) = async_channel::bounded(1);
+
+        spawn_local(async move {
+            let ram = this.lock().await;
+            let result = ram.len_js().await.ok()
+                .and_then(|js| js.as_f64())
+                .map(|len| len as u64)
+                .ok_or_else(|| anyhow!("Error calling len_js.").into());
+            tx.send(result).await.unwrap();
+        });
+
+        rx.recv().await?
+    }
 }
 
 #[wasm_bindgen]
@@ -81,6 +123,15 @@ extern "C" {
     #[wasm_bindgen(structural, method, catch)]
     async fn write_js(this: &RandomAccessJs, offset: u64, data: JsValue)
         -> Result<(), JsValue>;
+
+    #[allow(unsafe_code)]
+    #[wasm_bindgen(structural, method, catch)]
+    async fn delete_js(this: &RandomAccessJs, offset: u64, length: u64)
+        -> Result<(), JsValue>;
+
+    #[allow(unsafe_code)]
+    #[wasm_bindgen(structural, method, catch)]
+    async fn len_js(this: &RandomAccessJs) -> Result<JsValue, JsValue>;
 }
 #[allow(unsafe_code)]
 unsafe impl Send for RandomAccessJs {}
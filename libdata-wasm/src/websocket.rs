@@ -8,7 +8,6 @@ use ws_stream_wasm::{WsMeta, WsEvent, WsStreamIo};
 use futures_lite::future::race;
 use futures_lite::stream::StreamExt;
 use futures_lite::io::{AsyncRead, AsyncWrite};
-use fluvio_wasm_timer::Delay;
 use async_io_stream::IoStream;
 use pharos::{self, Observable};
 
@@ -112,20 +111,15 @@ impl ReplicationWasm {
         let stream = ws.into_io();
 
         // Handshake
-        let t = async move {
-            Delay::new(Duration::from_secs(5)).await.unwrap();
-            Err(JsError::new("Handshake timed out."))
+        let options = Options {
+            is_initiator: true,
+            keepalive_ms: None,
+            handshake_timeout: Some(Duration::from_secs(5)),
+            ..Options::default()
         };
-        let replication = async move {
-            let options = Options {
-                is_initiator: true,
-                keepalive_ms: None,
-                ..Options::default()
-            };
-            Replication::with_options(stream, options)
-                .await.map_err(|_| JsError::new("Handshake error."))
-        };
-        let (replication, handle) = race(t, replication).await?;
+        let (replication, handle) = Replication::with_options(stream, options)
+            .await
+            .map_err(|e| JsError::new(&format!("Handshake error: {}", e)))?;
 
         Ok(Self {
             replication,
@@ -172,8 +166,9 @@ impl ReplicationWasm {
         // Run replication
         let replication = self.replication;
         let replication = async move {
-            replication.run()
-                .await.map_err(|_| JsError::new("Replication error."))
+            replication.run().await
+                .map(|_| ())
+                .map_err(|_| JsError::new("Replication error."))
         };
 
         race(replication, events).await?;
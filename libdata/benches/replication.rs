@@ -0,0 +1,100 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::async_executor::AsyncStdExecutor;
+use std::time::{Duration, Instant};
+use futures_lite::future::zip;
+use async_std::task;
+use async_std::sync::{Arc, Mutex};
+
+use random_access_memory::RandomAccessMemory;
+use protocol::test_util::create_laggy_duplex_pair;
+use libdata::{generate_keypair, Core, PublicKey};
+use libdata::replication::{CoreReplica, Replication, Options};
+
+type MemoryCore = Core<RandomAccessMemory, RandomAccessMemory, RandomAccessMemory>;
+
+const BLOCKS: u32 = 500;
+/// One way latency, for a 50ms round trip.
+const LATENCY: Duration = Duration::from_millis(25);
+
+fn random_access_memory() -> RandomAccessMemory {
+    RandomAccessMemory::new(1024)
+}
+
+async fn init() -> (MemoryCore, MemoryCore) {
+    let keypair = generate_keypair();
+    let mut a = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    for i in 0..BLOCKS {
+        a.append(&i.to_be_bytes(), None).await.unwrap();
+    }
+    let b = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, None)
+        .await.unwrap();
+    (a, b)
+}
+
+async fn sync(a: MemoryCore, b: MemoryCore, window: u32) -> Duration {
+    let public: PublicKey = *a.public_key();
+    let a = Arc::new(Mutex::new(a));
+    let b = Arc::new(Mutex::new(b));
+    let a_replica = Box::new(CoreReplica::with_window(a, window));
+    let b_replica = Box::new(CoreReplica::with_window(Arc::clone(&b), window));
+
+    let (a_stream, b_stream) = create_laggy_duplex_pair(LATENCY);
+    let (a_result, b_result) = zip(
+        Replication::with_options(a_stream, Options {
+            is_initiator: false,
+            ..Options::default()
+        }),
+        Replication::with_options(b_stream, Options {
+            is_initiator: true,
+            ..Options::default()
+        }),
+    ).await;
+    let (a_replication, mut a_handle) = a_result.unwrap();
+    let (b_replication, mut b_handle) = b_result.unwrap();
+    let a_task = task::spawn(a_replication.run());
+    let b_task = task::spawn(b_replication.run());
+
+    let start = Instant::now();
+    a_handle.open(&public, a_replica).await.unwrap();
+    b_handle.open(&public, b_replica).await.unwrap();
+    while b.lock().await.len() < BLOCKS {
+        task::sleep(Duration::from_millis(1)).await;
+    }
+    let elapsed = start.elapsed();
+
+    a_handle.quit().await.unwrap();
+    b_handle.quit().await.unwrap();
+    a_task.await.unwrap();
+    b_task.await.unwrap();
+    elapsed
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("replicate 500 blocks, 50ms rtt");
+    group.sample_size(10);
+    for window in [1, 16] {
+        group.bench_function(format!("window {}", window), |b| {
+            b.to_async(AsyncStdExecutor).iter_custom(|iters| async move {
+                let mut total = Duration::ZERO;
+                for _ in 0..iters {
+                    let (a, b) = init().await;
+                    total += sync(a, b, window).await;
+                }
+                total
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
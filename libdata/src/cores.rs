@@ -1,7 +1,8 @@
 use std::fmt::Debug;
 use std::error::Error;
 use std::collections::HashMap;
-use async_std::sync::{Arc, Mutex, Weak};
+use std::sync::{Arc, Weak};
+use async_lock::Mutex;
 
 use crate::{
     RandomAccess, Core,
@@ -93,6 +94,19 @@ where
             .collect()
     }
 
+    /// Get the [PublicKey]s of all stored [Core]s sorted by their bytes.
+    ///
+    /// The order only depends on the stored keys, not on insertion order.
+    #[inline]
+    pub fn public_keys_sorted(&self) -> Vec<PublicKey>
+    {
+        let mut keys: Vec<&PublicKeyBytes> = self.by_public.keys().collect();
+        keys.sort_unstable();
+        keys.into_iter()
+            .map(|bytes| PublicKey::from_bytes(bytes).unwrap())
+            .collect()
+    }
+
     /// Get the [DiscoveryKey]s of all stored [Core]s in an arbitrary order.
     #[inline]
     pub fn discovery_keys(&self) -> Vec<DiscoveryKey>
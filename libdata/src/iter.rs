@@ -6,11 +6,15 @@ use std::task::{Poll, Context};
 use std::future::Future;
 use futures_lite::stream::Stream;
 use futures_lite::future::FutureExt;
-use async_std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use async_lock::Mutex;
 
 use crate::{RandomAccess, Core, BlockSignature};
 
 /// Async [Stream] iterator over [Core].
+///
+/// A failed read is yielded as an `Err`, and the next poll retries
+/// the same block. The iterator only ends past the last block.
 pub struct CoreIterator<D, B, M>
 where
     D: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
@@ -18,7 +22,8 @@ where
     M: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
 {
     core: Arc<Mutex<Core<D, B, M>>>,
-    task: Pin<Box<dyn Future<Output=(u32, Option<Vec<u8>>)>>>,
+    live: bool,
+    task: Pin<Box<dyn Future<Output=(u32, Option<Result<Vec<u8>>>)>>>,
 }
 impl<D: 'static, B: 'static, M: 'static> CoreIterator<D, B, M>
 where
@@ -28,9 +33,22 @@ where
 {
     /// Create a new [CoreIterator].
     pub fn new(core: Arc<Mutex<Core<D, B, M>>>, index: u32) -> Self {
-        let task = Self::create_read_task(Arc::clone(&core), index);
+        Self::with_live(core, index, false)
+    }
+
+    /// Create a new live [CoreIterator],
+    /// which waits for new blocks at the end of the [Core] instead of ending.
+    pub fn new_live(core: Arc<Mutex<Core<D, B, M>>>, index: u32) -> Self {
+        Self::with_live(core, index, true)
+    }
+
+    fn with_live(
+        core: Arc<Mutex<Core<D, B, M>>>, index: u32, live: bool) -> Self
+    {
+        let task = Self::create_read_task(Arc::clone(&core), index, live);
         Self {
             core,
+            live,
             task,
         }
     }
@@ -39,19 +57,25 @@ where
     fn create_read_task(
         core: Arc<Mutex<Core<D, B, M>>>,
         index: u32,
-        ) -> Pin<Box<dyn Future<Output=(u32, Option<Vec<u8>>)>>>
+        live: bool,
+        ) -> Pin<Box<dyn Future<Output=(u32, Option<Result<Vec<u8>>>)>>>
     {
         async move {
-            let result: Result<Option<(Vec<u8>, BlockSignature)>>;
-            {
-                let mut core = core.lock().await;
-                result = core.get(index).await;
-            }
-            if let Ok(Some(data)) = result {
-                (index, Some(data.0))
-            }
-            else {
-                (index, None)
+            loop {
+                let result: Result<Option<(Vec<u8>, BlockSignature)>>;
+                let appended;
+                {
+                    let mut core = core.lock().await;
+                    result = core.get(index).await;
+                    // Listen before unlocking, not to miss an append.
+                    appended = core.appended();
+                }
+                match result {
+                    Ok(Some(data)) => return (index, Some(Ok(data.0))),
+                    Ok(None) if live => appended.await,
+                    Ok(None) => return (index, None),
+                    Err(err) => return (index, Some(Err(err))),
+                }
             }
         }.boxed()
     }
@@ -62,7 +86,7 @@ where
     B: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
     M: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
 {
-    type Item = (u32, Vec<u8>);
+    type Item = Result<(u32, Vec<u8>)>;
 
     fn poll_next(
         self: Pin<&mut Self>,
@@ -71,9 +95,14 @@ where
     {
         let this = self.get_mut();
         if let Poll::Ready((index, data)) = Pin::new(&mut this.task).poll(cx) {
+            // retry a failed read
+            let next = match data {
+                Some(Err(_)) => index,
+                _ => index + 1,
+            };
             this.task = Self::create_read_task(
-                Arc::clone(&this.core), index + 1);
-            return Poll::Ready(data.map(|data| (index, data)))
+                Arc::clone(&this.core), next, this.live);
+            return Poll::Ready(data.map(|data| data.map(|data| (index, data))))
         }
         Poll::Pending
     }
@@ -90,3 +119,91 @@ where
         write!(fmt, "CoreIterator")
     }
 }
+
+/// Async [Stream] over the blocks of [Core] with their signatures.
+///
+/// Unlike [CoreIterator], a failed read is yielded as an `Err`
+/// and ends the stream, instead of being retried.
+/// Each block is read when polled, so a slow consumer holds back the reads.
+pub struct CoreStream<D, B, M>
+where
+    D: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+    B: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+    M: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+{
+    core: Arc<Mutex<Core<D, B, M>>>,
+    task: Option<Pin<Box<dyn Future<Output=(u32, StreamRead)>>>>,
+}
+type StreamRead = Result<Option<(Vec<u8>, BlockSignature)>>;
+impl<D: 'static, B: 'static, M: 'static> CoreStream<D, B, M>
+where
+    D: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+    B: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+    M: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+{
+    /// Create a new [CoreStream], starting at block `index`.
+    pub fn new(core: Arc<Mutex<Core<D, B, M>>>, index: u32) -> Self {
+        let task = Some(Self::create_read_task(Arc::clone(&core), index));
+        Self {
+            core,
+            task,
+        }
+    }
+
+    #[inline]
+    fn create_read_task(
+        core: Arc<Mutex<Core<D, B, M>>>,
+        index: u32,
+        ) -> Pin<Box<dyn Future<Output=(u32, StreamRead)>>>
+    {
+        async move {
+            let result = core.lock().await.get(index).await;
+            (index, result)
+        }.boxed()
+    }
+}
+impl<D: 'static, B: 'static, M: 'static> Stream for CoreStream<D, B, M>
+where
+    D: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+    B: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+    M: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+{
+    type Item = Result<(u32, Vec<u8>, BlockSignature)>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        ) -> Poll<Option<Self::Item>>
+    {
+        let this = self.get_mut();
+        let task = match this.task.as_mut() {
+            Some(task) => task,
+            None => return Poll::Ready(None),
+        };
+        if let Poll::Ready((index, result)) = Pin::new(task).poll(cx) {
+            this.task = None;
+            return Poll::Ready(match result {
+                Ok(Some((data, signature))) => {
+                    this.task = Some(Self::create_read_task(
+                        Arc::clone(&this.core), index + 1));
+                    Some(Ok((index, data, signature)))
+                },
+                Ok(None) => None,
+                Err(err) => Some(Err(err)),
+            })
+        }
+        Poll::Pending
+    }
+}
+impl<D: 'static, B: 'static, M: 'static> Debug for CoreStream<D, B, M>
+where
+    D: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+    B: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+    M: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
+{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>)
+        -> Result<(), std::fmt::Error>
+    {
+        write!(fmt, "CoreStream")
+    }
+}
@@ -1,3 +1,4 @@
+use anyhow::{Result, anyhow};
 use rand_chacha::ChaCha20Rng;
 use rand_chacha::rand_core::{SeedableRng, RngCore};
 use rand;
@@ -30,7 +31,9 @@ impl rand::RngCore for CSPRNG {
         self.0.fill_bytes(bytes)
     }
     #[inline]
-    fn try_fill_bytes(&mut self, bytes: &mut [u8]) -> Result<(), rand::Error> {
+    fn try_fill_bytes(&mut self, bytes: &mut [u8])
+        -> std::result::Result<(), rand::Error>
+    {
         match self.0.try_fill_bytes(bytes) {
             Err(err) => Err(rand::Error::from(err.code().unwrap())),
             Ok(()) => Ok(()),
@@ -47,3 +50,30 @@ pub fn derive_keypair(key: &SecretKey, name: &str) -> Keypair {
     let mut rng = CSPRNG::from_seed(seed);
     Keypair::generate(&mut rng)
 }
+
+/// Encode a [PublicKey] as 64 lowercase hex characters.
+pub fn public_key_to_hex(key: &PublicKey) -> String {
+    hex::encode(key.to_bytes())
+}
+/// Decode a [PublicKey] from 64 hex characters.
+pub fn public_key_from_hex(hex: &str) -> Result<PublicKey> {
+    let bytes = key_from_hex(hex)?;
+    PublicKey::from_bytes(&bytes)
+        .map_err(|_| anyhow!("Invalid public key."))
+}
+
+/// Encode a [DiscoveryKey] as 64 lowercase hex characters.
+pub fn discovery_key_to_hex(key: &DiscoveryKey) -> String {
+    hex::encode(key)
+}
+/// Decode a [DiscoveryKey] from 64 hex characters.
+pub fn discovery_key_from_hex(hex: &str) -> Result<DiscoveryKey> {
+    key_from_hex(hex)
+}
+
+fn key_from_hex(hex: &str) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    hex::decode_to_slice(hex, &mut key)
+        .map_err(|err| anyhow!("Invalid hex key: {}.", err))?;
+    Ok(key)
+}
@@ -10,8 +10,13 @@
 //! defines interface for managing collection of [Cores],
 //! and specifies [replication] over [protocol].
 
+#[macro_use]
+mod logging;
+
 pub use datacore::{
-    Core, RandomAccess, BlockSignature, Signature,
+    Core, CoreBuilder, CoreOptions, TryGet, AppendHook, RandomAccess, BlockSignature,
+    Signature, SIGNATURE_LENGTH, verify, Signer, Verifier,
+    Checkpoint, verify_checkpoint, Proof, verify_proof, VerifyCursor, Hash,
     MAX_CORE_LENGTH,
 };
 
@@ -19,10 +24,12 @@ mod key;
 pub use key::{
     Keypair, PublicKey, SecretKey, DiscoveryKey,
     generate_keypair, derive_keypair, discovery_key,
+    public_key_to_hex, public_key_from_hex,
+    discovery_key_to_hex, discovery_key_from_hex,
 };
 
 mod iter;
-pub use iter::CoreIterator;
+pub use iter::{CoreIterator, CoreStream};
 
 mod cores;
 pub use cores::Cores;
@@ -1,15 +1,25 @@
 use anyhow::{Result, anyhow};
 use std::error::Error;
 use std::fmt::Debug;
+use std::collections::{BTreeMap, BTreeSet};
 use async_trait::async_trait;
-use async_std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use async_lock::Mutex;
 
-use crate::{RandomAccess, Core, BlockSignature, Signature, MAX_CORE_LENGTH};
-use crate::replication::{ReplicaTrait, Request, Data, DataOrRequest};
+use crate::{
+    RandomAccess, Core, BlockSignature, Signature, Hash, MAX_CORE_LENGTH,
+};
+use crate::replication::{
+    ReplicaTrait, Request, RequestByHash, Data, DataOrRequest, ProgressEvent,
+};
 
 /// CoreReplica describes eager, full, and sequential synchronization logic
 /// for [Core] over [Replication].
 ///
+/// Keeps up to a window of [Request]s in flight, see
+/// [CoreReplica::with_window]. Downloads the whole [Core]
+/// unless bounded with [CoreReplica::with_end].
+///
 /// [Replication]: super::Replication
 #[derive(Debug)]
 pub struct CoreReplica<D, B, M>
@@ -20,6 +30,15 @@ where
 {
     core: Arc<Mutex<Core<D, B, M>>>,
     remote_index: Option<u32>,
+    window: u32,
+    in_flight: BTreeSet<u32>,
+    buffered: BTreeMap<u32, Data>,
+    synced_at: Option<u32>,
+    progress: Option<async_channel::Sender<ProgressEvent>>,
+    max_repairs: u32,
+    repairs: u32,
+    receive_only: bool,
+    end: u32,
 }
 
 impl<D, B, M> CoreReplica<D, B, M>
@@ -28,12 +47,93 @@ where
     B: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
     M: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
 {
-    /// Create a new [CoreReplica].
+    /// Create a new [CoreReplica], requesting one block at a time.
     pub fn new(core: Arc<Mutex<Core<D, B, M>>>) -> Self {
+        Self::with_window(core, 1)
+    }
+
+    /// Create a new [CoreReplica] keeping up to `window` [Request]s
+    /// in flight.
+    ///
+    /// Blocks past the next missing one are only requested once the remote
+    /// is known to have them. [Data] arriving out of order is buffered until
+    /// it can be appended.
+    pub fn with_window(core: Arc<Mutex<Core<D, B, M>>>, window: u32) -> Self {
         Self {
             core,
             remote_index: None,
+            window: window.max(1),
+            in_flight: BTreeSet::new(),
+            buffered: BTreeMap::new(),
+            synced_at: None,
+            progress: None,
+            max_repairs: 0,
+            repairs: 0,
+            receive_only: false,
+            end: u32::MAX,
+        }
+    }
+
+    /// Create a new [CoreReplica] downloading only the blocks before `end`,
+    /// requesting one block at a time.
+    ///
+    /// Replication stops requesting at `end`, and the [CoreReplica] is
+    /// synced once it has `end` blocks, even if the remote has more.
+    /// [Core] storage is sequential, so it still downloads every block
+    /// from its length on. To download a range of blocks without the ones
+    /// before it, use [SparseReplica::with_range] instead.
+    ///
+    /// [SparseReplica::with_range]: super::SparseReplica::with_range
+    pub fn with_end(core: Arc<Mutex<Core<D, B, M>>>, end: u32) -> Self {
+        let mut replica = Self::new(core);
+        replica.end = end;
+        replica
+    }
+
+    /// Only download, never serve blocks to the remote,
+    /// see [ReplicaTrait::receive_only].
+    pub fn with_receive_only(mut self) -> Self {
+        self.receive_only = true;
+        self
+    }
+
+    /// Discard [Data] failing [Core::append] and request it again,
+    /// up to `max_repairs` times over the life of the replica,
+    /// instead of failing the replication on the first bad block.
+    ///
+    /// A forked block is rejected by [Core::append] like any other
+    /// invalid block, so it is re-requested too: the fork is never applied,
+    /// and a remote that keeps sending it fails the replication
+    /// once the repairs run out.
+    /// Storage errors count against the same limit.
+    pub fn with_read_repair(mut self, max_repairs: u32) -> Self {
+        self.max_repairs = max_repairs;
+        self
+    }
+
+    /// Subscribe to [ProgressEvent]s.
+    ///
+    /// Emits [ProgressEvent::Completed] each time the [Core] catches up
+    /// with the length the remote is known to have.
+    /// Replaces any earlier subscription.
+    pub fn progress(&mut self) -> async_channel::Receiver<ProgressEvent> {
+        let (tx, rx) = async_channel::unbounded();
+        self.progress = Some(tx);
+        rx
+    }
+
+    /// Call [ReplicaTrait::on_synced] once per length,
+    /// if `len` caught up with the remote.
+    async fn check_synced(&mut self, len: u32) -> Result<()> {
+        let remote_index = match self.remote_index {
+            Some(remote_index) => remote_index.min(self.end),
+            None => return Ok(()),
+        };
+        if len < remote_index || self.synced_at == Some(len) {
+            return Ok(())
         }
+        self.synced_at = Some(len);
+        self.on_synced().await
     }
 
     fn update_remote_index(&mut self, index: u32) {
@@ -44,6 +144,41 @@ where
         }
         self.remote_index = Some(index);
     }
+
+    /// Fill the window with [Request]s starting at `len`.
+    /// If `probe`, always (re)request `len`, as the remote might have
+    /// dropped an earlier request for it.
+    fn fill_window(&mut self, len: u32, probe: bool) -> Vec<Request> {
+        let mut requests = vec![];
+        if len as usize >= MAX_CORE_LENGTH || len >= self.end {
+            return requests
+        }
+
+        // Forget requests already answered.
+        self.in_flight = self.in_flight.split_off(&len);
+
+        if probe {
+            self.in_flight.insert(len);
+            requests.push(Request { index: len, sparse: None, end: None });
+        }
+
+        let limit = std::cmp::min(
+            std::cmp::max(self.remote_index.unwrap_or(0), len + 1)
+                .min(self.end) as usize,
+            MAX_CORE_LENGTH);
+        let mut index = len as usize;
+        while index < limit
+            && self.in_flight.len() + self.buffered.len() < self.window as usize
+        {
+            let i = index as u32;
+            if !self.in_flight.contains(&i) && !self.buffered.contains_key(&i) {
+                self.in_flight.insert(i);
+                requests.push(Request { index: i, sparse: None, end: None });
+            }
+            index += 1;
+        }
+        requests
+    }
 }
 
 #[async_trait]
@@ -53,78 +188,182 @@ where
     B: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
     M: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Send + Debug,
 {
-    async fn on_open(&mut self) -> Result<Option<Request>> {
+    async fn on_open(&mut self) -> Result<Vec<Request>> {
         let core = self.core.lock().await;
-        let request = Request {
-            index: core.len(),
+        if core.is_unsigned() {
+            return Err(anyhow!("Core is unsigned, cannot replicate."))
+        }
+        drop(core);
+        let requests = match self.resume_from().await {
+            Some(len) => self.fill_window(len, true),
+            None => vec![],
         };
-        Ok(Some(request))
+        Ok(requests)
+    }
+    async fn on_remote_length(&mut self, length: u32) -> Result<()> {
+        self.update_remote_index(length);
+        Ok(())
     }
     async fn on_request(&mut self, request: Request)
         -> Result<Option<DataOrRequest>>
     {
-        self.update_remote_index(request.index);
+        // A sparse Request is not a length probe.
+        let sparse = request.sparse();
+        if !sparse {
+            self.update_remote_index(request.index);
+        }
 
-        let mut core = self.core.lock().await;
-        let data = core.get(request.index).await?;
+        let core = Arc::clone(&self.core);
+        let mut core = core.lock().await;
+        let data = match self.receive_only {
+            // only learn the remote length
+            true => None,
+            false => core.get(request.index).await?,
+        };
         Ok(match data {
             Some((data, signature)) => {
-                let response = Data {
-                    index: request.index,
-                    data,
-                    data_signature: signature.data().to_bytes().to_vec(),
-                    tree_signature: signature.tree().to_bytes().to_vec(),
-                };
+                let response = data_response(request.index, data, signature);
                 Some(DataOrRequest::Data(response))
             },
             None => {
                 let index = core.len();
                 let remote_index = self.remote_index.unwrap_or(0);
-                if index as usize >= MAX_CORE_LENGTH || remote_index <= index {
+                if sparse
+                    || index as usize >= MAX_CORE_LENGTH
+                    || index >= self.end
+                    || remote_index <= index
+                {
+                    drop(core);
+                    self.check_synced(index).await?;
                     None
                 }
                 else {
-                    let response = Request { index };
+                    self.in_flight.insert(index);
+                    let response = Request { index, sparse: None, end: None };
                     Some(DataOrRequest::Request(response))
                 }
             },
         })
     }
-    async fn on_data(&mut self, data: Data)
-        -> Result<Option<Request>>
+    async fn on_request_by_hash(&mut self, request: RequestByHash)
+        -> Result<Option<Data>>
     {
+        if self.receive_only {
+            return Ok(None)
+        }
+        let hash = match Hash::from_bytes(&request.hash) {
+            Ok(hash) => hash,
+            // not a hash, no block can match
+            Err(_) => return Ok(None),
+        };
+
         let mut core = self.core.lock().await;
+        // without an index finding the block means scanning the whole core,
+        // leave it to the remote to ask by index
+        if !core.has_index() {
+            return Ok(None)
+        }
+        let index = match core.index_of(&hash).await? {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        Ok(core.get(index).await?
+            .map(|(data, signature)| data_response(index, data, signature)))
+    }
+    async fn on_data(&mut self, data: Data)
+        -> Result<Vec<Request>>
+    {
+        // Only keep Data we asked for, unsolicited Data could grow
+        // the buffer without bound.
+        let requested = self.in_flight.remove(&data.index);
+
+        let core = Arc::clone(&self.core);
+        let mut core = core.lock().await;
         let len = core.len();
-        if data.index == len {
-            let signature = BlockSignature::new(
-                Signature::from_bytes(&data.data_signature).unwrap(),
-                Signature::from_bytes(&data.tree_signature).unwrap());
-            core.append(&data.data, Some(signature)).await?;
-
-            if core.len() as usize >= MAX_CORE_LENGTH {
-                Ok(None)
+        if data.index > len {
+            if requested {
+                self.buffered.insert(data.index, data);
             }
-            else {
-                Ok(Some(Request {
-                    index: data.index + 1,
-                }))
+        }
+        else if data.index == len {
+            let mut next = Some(data);
+            while let Some(data) = next {
+                let appended = match block_signature(&data) {
+                    Ok(signature) =>
+                        core.append(&data.data, Some(signature)).await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = appended {
+                    if self.repairs >= self.max_repairs {
+                        return Err(err)
+                    }
+                    self.repairs += 1;
+                    let len = core.len();
+                    drop(core);
+                    self.in_flight.insert(len);
+                    let mut requests =
+                        vec![Request { index: len, sparse: None, end: None }];
+                    requests.extend(self.fill_window(len, false));
+                    return Ok(requests)
+                }
+                next = self.buffered.remove(&core.len());
             }
         }
-        else {
-            Ok(Some(Request {
-                index: len,
-            }))
+
+        let len = core.len();
+        drop(core);
+        self.buffered = self.buffered.split_off(&len);
+        self.check_synced(len).await?;
+        Ok(self.fill_window(len, false))
+    }
+    async fn on_cancel(&mut self, index: u32) -> Result<()> {
+        self.in_flight.remove(&index);
+        Ok(())
+    }
+    async fn on_synced(&mut self) -> Result<()> {
+        if let Some(progress) = &self.progress {
+            let length = self.synced_at.unwrap_or(0);
+            // The subscriber may be gone, progress is best effort.
+            let _ = progress.send(ProgressEvent::Completed { length }).await;
         }
+        Ok(())
     }
     async fn on_close(&mut self) -> Result<()> {
         if let Some(index) = self.remote_index {
             let core = self.core.lock().await;
             let len = core.len();
 
-            if len < index {
+            if len < index.min(self.end) {
                 return Err(anyhow!("Not synced; remote has more data."))
             }
         }
         Ok(())
     }
+    fn receive_only(&self) -> bool {
+        self.receive_only
+    }
+    async fn length(&mut self) -> Option<u32> {
+        Some(self.core.lock().await.len())
+    }
+    async fn resume_from(&self) -> Option<u32> {
+        let core = self.core.lock().await;
+        Some(core.len())
+    }
+}
+
+fn block_signature(data: &Data) -> Result<BlockSignature> {
+    Ok(BlockSignature::new(
+        Signature::from_bytes(&data.data_signature)?,
+        Signature::from_bytes(&data.tree_signature)?))
+}
+
+fn data_response(index: u32, data: Vec<u8>, signature: BlockSignature)
+    -> Data
+{
+    Data {
+        index,
+        data,
+        data_signature: signature.data().to_bytes().to_vec(),
+        tree_signature: signature.tree().to_bytes().to_vec(),
+    }
 }
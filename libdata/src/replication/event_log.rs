@@ -0,0 +1,39 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Ring buffer of the last events of a [Replication],
+/// see [Options::event_log_size].
+///
+/// [Replication]: crate::replication::Replication
+/// [Options::event_log_size]: crate::replication::Options::event_log_size
+#[derive(Debug)]
+pub(crate) struct EventLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<String>>,
+}
+
+impl EventLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Record an entry, formatted only if the log is enabled.
+    pub(crate) fn record(&self, entry: impl FnOnce() -> String) {
+        if self.capacity == 0 {
+            return
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry());
+    }
+
+    /// Get the entries, oldest first.
+    pub(crate) fn entries(&self) -> Vec<String> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
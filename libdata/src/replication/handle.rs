@@ -1,18 +1,29 @@
 use anyhow::{Result, anyhow};
 use std::fmt::Debug;
+use std::error::Error;
 use async_channel;
+use std::sync::Arc;
+use async_lock::Mutex;
 
-use crate::{DiscoveryKey, PublicKey, discovery_key};
-use crate::replication::ReplicaTrait;
+use crate::{
+    RandomAccess, Core, Cores, DiscoveryKey, PublicKey, Hash, discovery_key,
+};
+use crate::replication::{ReplicaTrait, RequestByHash, ReplicationEvent};
+use crate::replication::event_log::EventLog;
+use crate::replication::progress::Subscription;
 
 /// [Replication] command.
 pub enum Command {
     /// Open a new replica.
     Open(PublicKey, Box<dyn ReplicaTrait + Send>),
-    /// Re-open a replica.
+    /// Re-open a replica, verifying the remote capability again.
     ReOpen(DiscoveryKey),
     /// Close a replica.
     Close(DiscoveryKey),
+    /// Request a block by hash on a replica's channel.
+    RequestByHash(DiscoveryKey, RequestByHash),
+    /// Cancel the request for a block on a replica's channel.
+    Cancel(DiscoveryKey, u32),
     /// End the [Replication].
     Quit(),
 }
@@ -27,6 +38,11 @@ impl Debug for Command {
                 write!(fmt, "Command::ReOpen({:?})", key),
             Self::Close(key) =>
                 write!(fmt, "Command::Close({:?})", key),
+            Self::RequestByHash(key, request) =>
+                write!(fmt, "Command::RequestByHash({:?}, {:?})",
+                       key, request),
+            Self::Cancel(key, index) =>
+                write!(fmt, "Command::Cancel({:?}, {})", key, index),
             Self::Quit() =>
                 write!(fmt, "Command::Quit()"),
         }
@@ -37,8 +53,33 @@ impl Debug for Command {
 #[derive(Debug, Clone)]
 pub struct ReplicationHandle {
     pub(crate) tx: async_channel::Sender<Command>,
+    pub(crate) event_log: Arc<EventLog>,
+    pub(crate) events: Arc<Subscription>,
 }
 impl ReplicationHandle {
+    /// Get the last events of the [Replication], oldest first,
+    /// see [Options::event_log_size].
+    ///
+    /// Still available after the replication stopped,
+    /// to diagnose why it failed.
+    ///
+    /// [Replication]: crate::replication::Replication
+    /// [Options::event_log_size]: crate::replication::Options::event_log_size
+    pub fn event_log(&self) -> Vec<String> {
+        self.event_log.entries()
+    }
+
+    /// Subscribe to [ReplicationEvent]s, blocks downloaded and uploaded
+    /// on every channel.
+    ///
+    /// Replaces any earlier subscription, from this or another handle
+    /// of the same [Replication].
+    ///
+    /// [Replication]: crate::replication::Replication
+    pub fn events(&self) -> async_channel::Receiver<ReplicationEvent> {
+        self.events.subscribe()
+    }
+
     /// Open a new channel with [ReplicaTrait].
     pub async fn open(
         &mut self,
@@ -51,7 +92,29 @@ impl ReplicationHandle {
             .await.map_err(|_| anyhow!("Error sending command."))
     }
 
-    /// Reopen a replica.
+    /// Open a channel for every [Core] in `cores`,
+    /// with the [ReplicaTrait] returned by `make_replica`.
+    pub async fn open_all<D, B, M>(
+        &mut self,
+        cores: &Cores<D, B, M>,
+        make_replica: impl Fn(Arc<Mutex<Core<D, B, M>>>)
+            -> Box<dyn ReplicaTrait + Send>,
+        ) -> Result<()>
+    where
+        D: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+        B: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+        M: RandomAccess<Error = Box<dyn Error + Send + Sync>> + Debug + Send,
+    {
+        for (public, core) in cores.entries() {
+            self.open(&public, make_replica(core)).await?;
+        }
+        Ok(())
+    }
+
+    /// Reopen a replica to request more data.
+    ///
+    /// Verifies the remote capability of the channel again,
+    /// the replication fails if it no longer validates.
     pub async fn reopen(&mut self, key: &PublicKey) -> Result<()> {
         let cmd = Command::ReOpen(discovery_key(key.as_bytes()));
         self.tx.send(cmd)
@@ -65,6 +128,43 @@ impl ReplicationHandle {
             .await.map_err(|_| anyhow!("Error sending command."))
     }
 
+    /// Ask the remote for the block with data hashing to the leaf `hash`,
+    /// see [Hash::from_leaf], on the channel of `key`.
+    ///
+    /// The block arrives at [ReplicaTrait::on_data]
+    /// or the remote replies with [ReplicaTrait::on_not_found].
+    /// The remote looks up the hash in its [Core::index_of],
+    /// a remote `Core` opened without an index always replies not found.
+    ///
+    /// [Core::index_of]: crate::Core::index_of
+    pub async fn request_by_hash(&mut self, key: &PublicKey, hash: &Hash)
+        -> Result<()>
+    {
+        let request = RequestByHash { hash: hash.as_bytes().to_vec() };
+        let cmd = Command::RequestByHash(
+            discovery_key(key.as_bytes()), request);
+        self.tx.send(cmd)
+            .await.map_err(|_| anyhow!("Error sending command."))
+    }
+
+    /// Cancel the request for the block at `index` on the channel of `key`,
+    /// without closing the channel.
+    ///
+    /// The replica stops waiting for the block, see
+    /// [ReplicaTrait::on_cancel], and the remote stops sending it if it
+    /// has not written it yet. [Data] already on the way still arrives
+    /// at [ReplicaTrait::on_data].
+    /// Nothing is sent before the channel is open on both ends.
+    ///
+    /// [Data]: crate::replication::Data
+    pub async fn cancel(&mut self, key: &PublicKey, index: u32)
+        -> Result<()>
+    {
+        let cmd = Command::Cancel(discovery_key(key.as_bytes()), index);
+        self.tx.send(cmd)
+            .await.map_err(|_| anyhow!("Error sending command."))
+    }
+
     /// End the [Replication].
     pub async fn quit(&mut self) -> Result<()> {
         let cmd = Command::Quit();
@@ -1,15 +1,30 @@
 //! Replication protocol for safely synchronizing logs.
 
-pub use protocol::{Options, Duplex};
+pub use protocol::{
+    Options, Duplex, MeteredStream, Meter,
+    Transport, MessageIo, ChannelMessage,
+};
 
 mod replication;
-pub use replication::Replication;
+pub use replication::{
+    Replication, StopReason, StepOutcome, MAX_RANGE_LENGTH,
+};
+
+mod event_log;
 
 mod handle;
 pub use handle::{Command, ReplicationHandle};
 
 mod replica_trait;
-pub use replica_trait::{ReplicaTrait, Data, Request, DataOrRequest};
+pub use replica_trait::{
+    ReplicaTrait, Data, Request, RequestByHash, NotFound, DataOrRequest,
+};
+
+mod progress;
+pub use progress::{ProgressEvent, ReplicationEvent};
 
 mod core_replica;
 pub use core_replica::CoreReplica;
+
+mod sparse_replica;
+pub use sparse_replica::{SparseReplica, SparseBlocks, UnverifiedBlock};
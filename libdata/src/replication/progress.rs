@@ -0,0 +1,70 @@
+use std::sync::Mutex;
+
+use crate::DiscoveryKey;
+
+/// Progress of a replica, see [CoreReplica::progress].
+///
+/// [CoreReplica::progress]: super::CoreReplica::progress
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// Caught up with the remote, having `length` blocks.
+    Completed {
+        /// Length of the local core.
+        length: u32,
+    },
+}
+
+/// Transfer of a block over a [Replication],
+/// see [ReplicationHandle::events].
+///
+/// [Replication]: super::Replication
+/// [ReplicationHandle::events]: super::ReplicationHandle::events
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationEvent {
+    /// Received [Data] from the remote, applied by the replica,
+    /// see [ReplicaTrait::on_data]. Not emitted if applying it failed.
+    ///
+    /// [Data]: super::Data
+    /// [ReplicaTrait::on_data]: super::ReplicaTrait::on_data
+    Downloaded {
+        /// [DiscoveryKey] of the channel.
+        discovery_key: DiscoveryKey,
+        /// Index of the block.
+        index: u32,
+    },
+    /// Sent [Data] to the remote.
+    ///
+    /// [Data]: super::Data
+    Uploaded {
+        /// [DiscoveryKey] of the channel.
+        discovery_key: DiscoveryKey,
+        /// Index of the block.
+        index: u32,
+    },
+}
+
+/// Subscription to [ReplicationEvent]s,
+/// shared by a [Replication] and its handles.
+///
+/// [Replication]: super::Replication
+#[derive(Debug, Default)]
+pub(crate) struct Subscription {
+    tx: Mutex<Option<async_channel::Sender<ReplicationEvent>>>,
+}
+
+impl Subscription {
+    /// Subscribe, replacing any earlier subscription.
+    pub(crate) fn subscribe(&self) -> async_channel::Receiver<ReplicationEvent> {
+        let (tx, rx) = async_channel::unbounded();
+        *self.tx.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    /// Send an event, if subscribed.
+    pub(crate) fn send(&self, event: ReplicationEvent) {
+        if let Some(tx) = &*self.tx.lock().unwrap() {
+            // The subscriber may be gone, events are best effort.
+            let _ = tx.try_send(event);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
-pub use protocol::schema::{Data, Request};
+pub use protocol::schema::{Data, Request, RequestByHash, NotFound};
 
 /// Either [Data] or [Request].
 #[derive(Debug)]
@@ -14,26 +14,115 @@ pub enum DataOrRequest {
 
 /// ReplicaTrait describes the behavior of [Replication].
 ///
+/// [Replication] holds replicas as `Box<dyn ReplicaTrait>`,
+/// so replicas of [Core]s with different storage backends,
+/// for example in memory and on disk, share one [Replication].
+/// Replicas are `Sync`, so read-only methods take `&self`.
+///
 /// [Replication]: super::Replication
+/// [Core]: crate::Core
 #[async_trait]
-pub trait ReplicaTrait {
+pub trait ReplicaTrait: Sync {
     /// Called on connection opened.
-    /// Optionally return a [Request].
+    /// Return [Request]s to send, possibly none.
     async fn on_open(&mut self)
-        -> Result<Option<Request>>;
+        -> Result<Vec<Request>>;
+
+    /// Called before [ReplicaTrait::on_open] with the length
+    /// the remote advertised for its feed, if it did,
+    /// see [ReplicaTrait::length].
+    async fn on_remote_length(&mut self, _length: u32)
+        -> Result<()>
+    {
+        Ok(())
+    }
 
     /// Called on new [Request] received.
     /// Optionally return [DataOrRequest] to send back.
+    ///
+    /// A ranged [Request] arrives as a sparse [Request] for each block,
+    /// see [MAX_RANGE_LENGTH](super::MAX_RANGE_LENGTH).
     async fn on_request(&mut self, request: Request)
         -> Result<Option<DataOrRequest>>;
 
     /// Called on new [Data] received.
-    /// Optionally return a new [Request].
+    /// Return new [Request]s to send, possibly none.
+    ///
+    /// Runs while [Replication] keeps serving the other replicas,
+    /// events for this replica wait until it returns.
+    ///
+    /// [Replication]: super::Replication
     async fn on_data(&mut self, data: Data)
-        -> Result<Option<Request>>;
+        -> Result<Vec<Request>>;
+
+    /// Called on new [RequestByHash] received.
+    /// Optionally return the [Data] of the block, otherwise
+    /// [Replication] replies with [NotFound].
+    ///
+    /// [Replication]: super::Replication
+    async fn on_request_by_hash(&mut self, _request: RequestByHash)
+        -> Result<Option<Data>>
+    {
+        Ok(None)
+    }
+
+    /// Called on [NotFound] received,
+    /// the remote has no block for a [RequestByHash].
+    async fn on_not_found(&mut self, _msg: NotFound)
+        -> Result<()>
+    {
+        Ok(())
+    }
+
+    /// Called when the [Request] for the block at `index` is cancelled
+    /// with [ReplicationHandle::cancel], to stop waiting for it.
+    /// [Data] already on the way may still arrive at [ReplicaTrait::on_data].
+    ///
+    /// [ReplicationHandle::cancel]: super::ReplicationHandle::cancel
+    async fn on_cancel(&mut self, _index: u32)
+        -> Result<()>
+    {
+        Ok(())
+    }
+
+    /// Called by the replica itself once it has every block
+    /// the remote is known to have, see [CoreReplica::progress].
+    ///
+    /// [CoreReplica::progress]: super::CoreReplica::progress
+    async fn on_synced(&mut self)
+        -> Result<()>
+    {
+        Ok(())
+    }
 
     /// Called on connection close (possibly abnormal).
     /// Return `Ok` if this replica was synced correctly.
     async fn on_close(&mut self)
         -> Result<()>;
+
+    /// Open the channel receive-only: the remote is told this replica
+    /// does not serve data and sends it no [Request]s.
+    fn receive_only(&self) -> bool {
+        false
+    }
+
+    /// Length of the local feed, advertised to the remote
+    /// when the channel is opened.
+    async fn length(&mut self)
+        -> Option<u32>
+    {
+        None
+    }
+
+    /// Index to resume requesting from when a channel is (re)opened.
+    /// If `Some` and [ReplicaTrait::on_open] returns no [Request]s,
+    /// [Replication] requests it directly, so a reconnected session picks up
+    /// where the previous one left off.
+    ///
+    /// [Replication]: super::Replication
+    async fn resume_from(&self)
+        -> Option<u32>
+    {
+        None
+    }
 }
@@ -1,43 +1,136 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, ensure};
 use std::fmt::Debug;
+use std::error::Error as StdError;
+use std::io::{Error, ErrorKind};
 use std::task::{Context, Poll};
 use std::pin::Pin;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::future::Future;
-use futures_lite::io::{AsyncRead, AsyncWrite};
 use futures_lite::stream::{Stream, StreamExt};
+use futures_lite::future::FutureExt;
 use async_channel;
+use std::sync::Arc;
+use async_lock::Mutex;
+use futures_timer::Delay;
 
-use protocol::{new_protocol, Protocol, Message};
+use protocol::{new_protocol, Protocol, Message, MessageIo, Transport};
 use protocol::main::{Stage, Event as ProtocolEvent};
-use crate::{DiscoveryKey, discovery_key};
+use protocol::schema::Cancel;
+use crate::{DiscoveryKey, discovery_key, RandomAccess, Core, Cores};
 use crate::replication::{
-    Options, ReplicaTrait, Request, Data, DataOrRequest,
-    Command, ReplicationHandle,
+    Options, ReplicaTrait, Request, RequestByHash, NotFound, Data,
+    DataOrRequest, Command, ReplicationHandle, CoreReplica, ReplicationEvent,
 };
+use crate::replication::event_log::EventLog;
+use crate::replication::progress::Subscription;
 
 /// [Replication] event.
 #[derive(Debug)]
+// `Event::Event` wraps a [ProtocolEvent].
+#[allow(clippy::enum_variant_names)]
 pub enum Event {
     Command(Command),
     Event(Result<ProtocolEvent>),
+    /// Signaled by [Replication::with_cancel].
+    Cancelled,
+    /// A replica finished applying [Data].
+    Applied(DiscoveryKey, Applying),
+}
+
+type Replica = Box<dyn ReplicaTrait + Send>;
+/// A replica back from [ReplicaTrait::on_data], with its result.
+type Applied = (Replica, Result<Vec<Request>>);
+type Apply = Pin<Box<dyn Future<Output = Applied> + Send>>;
+
+/// Maximum number of events queued for a replica applying [Data],
+/// the remote is not read while a queue is full.
+const MAX_QUEUED_EVENTS: usize = 64;
+
+/// Maximum number of blocks served for a ranged [Request],
+/// a sparse [Request] with an `end`. Ask for longer ranges in parts.
+pub const MAX_RANGE_LENGTH: u32 = 256;
+
+/// A replica applying [Data] off the replication loop,
+/// see [Replication::replica_on_data].
+pub struct Applying {
+    apply: Apply,
+    /// Index of the [Data] applied.
+    index: u32,
+    output: Option<Applied>,
+    /// Events for the replica received meanwhile, handled in order after.
+    /// The remote is not read while [MAX_QUEUED_EVENTS] are queued.
+    queued: VecDeque<ProtocolEvent>,
+    /// Indices cancelled meanwhile, see [ReplicaTrait::on_cancel].
+    cancelled: Vec<u32>,
+    /// The replica was closed or replaced meanwhile, drop it after,
+    /// see [Replication::detach].
+    detached: bool,
+}
+impl Debug for Applying {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>)
+        -> Result<(), std::fmt::Error>
+    {
+        write!(fmt, "Applying(queued: {})", self.queued.len())
+    }
+}
+
+/// Why [Replication::run] stopped.
+///
+/// Only returned if every replica was synced,
+/// otherwise [Replication::run] fails.
+#[derive(Debug)]
+pub enum StopReason {
+    /// Stopped by [ReplicationHandle::quit].
+    Quit,
+    /// Stopped by the signal of [Replication::with_cancel].
+    Cancelled,
+    /// The remote closed the connection.
+    RemoteClosed,
+    /// Stopped on a transport error.
+    Error(anyhow::Error),
+}
+
+/// Outcome of a [Replication::step].
+#[derive(Debug)]
+pub enum StepOutcome {
+    /// Keep stepping.
+    Continue,
+    /// The replication stopped, do not step again.
+    Stopped(StopReason),
 }
 
 /// Replication protocol main abstraction:
 /// handle handshake, multiplexing, failures.
 ///
 /// Concrete behavior is specified in [ReplicaTrait].
+///
+/// [ReplicaTrait::on_data] runs off the loop: while one replica applies
+/// [Data], for example appending to a [Core] on a slow disk, the other
+/// replicas keep replicating. Events for the busy replica are queued
+/// and handled in order once it is done. The queue is bounded:
+/// [Data] is held back by the credit of [Options::data_credit],
+/// and the remote is not read while the queue is full.
+///
+/// [Core]: crate::Core
 pub struct Replication<T: 'static>
 where
-    T: AsyncWrite + AsyncRead + Send + Unpin,
+    T: Transport,
 {
     protocol: Protocol<T, Stage>,
     command_rx: async_channel::Receiver<Command>,
-    replicas: HashMap<DiscoveryKey, Box<dyn ReplicaTrait + Send>>,
+    handle: ReplicationHandle,
+    replicas: HashMap<DiscoveryKey, Replica>,
+    applying: HashMap<DiscoveryKey, Applying>,
+    /// Closed or replaced replicas still applying [Data].
+    detached: Vec<(DiscoveryKey, Applying)>,
+    max_open_channels: Option<usize>,
+    cancel: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    event_log: Arc<EventLog>,
+    events: Arc<Subscription>,
 }
 impl<T: 'static> Debug for Replication<T>
 where
-    T: AsyncWrite + AsyncRead + Send + Unpin,
+    T: Transport,
 {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>)
         -> Result<(), std::fmt::Error>
@@ -47,96 +140,297 @@ where
 }
 impl<T: 'static> Replication<T>
 where
-    T: AsyncWrite + AsyncRead + Send + Unpin,
+    T: Transport,
 {
     /// Create `Replication` and wait for protocol handshake.
     pub async fn new(stream: T, is_initiator: bool)
         -> Result<(Self, ReplicationHandle)>
     {
-        Self::with_options(stream, Options {
-            is_initiator,
-            ..Options::default()
-        }).await
+        Self::with_options(stream, Options::new(is_initiator)).await
     }
 
     /// Create `Replication` with [Options] and wait for protocol handshake.
+    ///
+    /// Fails with [ErrorKind::TimedOut] if the handshake does not complete
+    /// within [Options::handshake_timeout].
     pub async fn with_options(stream: T, options: Options)
         -> Result<(Self, ReplicationHandle)>
     {
         let (tx, rx) = async_channel::unbounded();
-        let handle = ReplicationHandle { tx };
+        let event_log = Arc::new(EventLog::new(options.event_log_size));
+        let events = Arc::new(Subscription::default());
+        let handle = ReplicationHandle {
+            tx,
+            event_log: Arc::clone(&event_log),
+            events: Arc::clone(&events),
+        };
 
-        let handshake = new_protocol(stream, options);
-        let protocol = handshake.handshake().await?;
+        let handshake_timeout = options.handshake_timeout;
+        let max_open_channels = options.max_open_channels;
+        let handshake = new_protocol(stream, options).handshake();
+        let protocol = match handshake_timeout {
+            None => handshake.await?,
+            Some(duration) => handshake.or(async {
+                Delay::new(duration).await;
+                Err(anyhow!(Error::new(
+                    ErrorKind::TimedOut, "Handshake timed out")))
+            }).await?,
+        };
 
         let replication = Self {
             protocol,
             command_rx: rx,
+            handle: handle.clone(),
             replicas: HashMap::new(),
+            applying: HashMap::new(),
+            detached: vec![],
+            max_open_channels,
+            cancel: None,
+            event_log,
+            events,
         };
 
         Ok((replication, handle))
     }
 
+    /// Stop the replication once `cancel` completes,
+    /// for example on a shutdown signal.
+    ///
+    /// Works without the [ReplicationHandle], which may be stuck
+    /// or gone. Checked between [Event]s: replicas are closed
+    /// as on [ReplicationHandle::quit], and the replication stops with
+    /// [StopReason::Cancelled], or fails if a replica was not synced.
+    pub fn with_cancel<C>(mut self, cancel: C) -> Self
+    where
+        C: Future<Output = ()> + Send + 'static,
+    {
+        self.cancel = Some(Box::pin(cancel));
+        self
+    }
+
     /// Run the replication loop to completion.
-    pub async fn run(self) -> Result<()> {
+    ///
+    /// With [Options::send_keepalive], the default, an idle remote keeps
+    /// the replication running until [ReplicationHandle::quit],
+    /// [Replication::with_cancel] or the remote closes the connection.
+    /// Without it, the replication also stops once the remote
+    /// goes quiet for [Options::keepalive_ms].
+    pub async fn run(self) -> Result<StopReason> {
         let on_discovery = |_| async move { Ok(()) };
         self.run_with_discovery_hook(on_discovery).await
     }
+    /// Run the replication loop to completion,
+    /// replicating the [Cores] the remote asks for.
+    ///
+    /// On [ProtocolEvent::DiscoveryKey], opens a [CoreReplica]
+    /// for the [Core] of that key, if present in `cores`.
+    /// Unknown keys are ignored.
+    ///
+    /// [Core]: crate::Core
+    pub async fn run_with_cores<D, B, M>(
+        self,
+        cores: Arc<Mutex<Cores<D, B, M>>>,
+        ) -> Result<StopReason>
+    where
+        D: RandomAccess<Error = Box<dyn StdError + Send + Sync>>
+            + Debug + Send + 'static,
+        B: RandomAccess<Error = Box<dyn StdError + Send + Sync>>
+            + Debug + Send + 'static,
+        M: RandomAccess<Error = Box<dyn StdError + Send + Sync>>
+            + Debug + Send + 'static,
+    {
+        let factory = |_| async move { Ok(None) };
+        self.run_with_factory(cores, factory).await
+    }
+    /// Run the replication loop to completion,
+    /// replicating the [Cores] the remote asks for
+    /// and creating missing ones with `factory`.
+    ///
+    /// On [ProtocolEvent::DiscoveryKey] missing from `cores`,
+    /// `factory` is called with the [DiscoveryKey].
+    /// If it returns a [Core], the [Core] is inserted into `cores`
+    /// and replicated with a [CoreReplica], same as a [Core] already
+    /// present; otherwise the key is ignored.
+    ///
+    /// Storage is only allocated for feeds a remote actually offers.
+    /// A [DiscoveryKey] does not reveal its [PublicKey],
+    /// so `factory` can only create [Core]s for keys it already knows,
+    /// for example from a list of feeds a relay is willing to cache
+    /// (compare with [discovery_key]).
+    /// Created [Core]s stay in `cores` after the replication ends.
+    ///
+    /// [Core]: crate::Core
+    /// [PublicKey]: crate::PublicKey
+    pub async fn run_with_factory<D, B, M, F, Fut>(
+        self,
+        cores: Arc<Mutex<Cores<D, B, M>>>,
+        factory: F,
+        ) -> Result<StopReason>
+    where
+        D: RandomAccess<Error = Box<dyn StdError + Send + Sync>>
+            + Debug + Send + 'static,
+        B: RandomAccess<Error = Box<dyn StdError + Send + Sync>>
+            + Debug + Send + 'static,
+        M: RandomAccess<Error = Box<dyn StdError + Send + Sync>>
+            + Debug + Send + 'static,
+        F: Fn(DiscoveryKey) -> Fut,
+        Fut: Future<Output = Result<Option<Core<D, B, M>>>>,
+    {
+        let handle = self.handle.clone();
+        let on_discovery = |discovery: DiscoveryKey| {
+            let cores = Arc::clone(&cores);
+            let mut handle = handle.clone();
+            let factory = &factory;
+            async move {
+                let core = cores.lock().await.get_by_discovery(&discovery);
+                let core = match core {
+                    Some(core) => Some(core),
+                    None => match factory(discovery).await? {
+                        Some(core) => {
+                            let public = *core.public_key();
+                            ensure!(
+                                discovery_key(public.as_bytes()) == discovery,
+                                "Factory created a Core for another key.");
+                            let core = Arc::new(Mutex::new(core));
+                            cores.lock().await.put(&public, Arc::clone(&core));
+                            Some(core)
+                        },
+                        None => None,
+                    },
+                };
+                if let Some(core) = core {
+                    let public = *core.lock().await.public_key();
+                    let replica = Box::new(CoreReplica::new(core));
+                    handle.open(&public, replica).await?;
+                }
+                Ok(())
+            }
+        };
+        self.run_with_discovery_hook(on_discovery).await
+    }
     /// Run the replication loop to completion
     /// with an `on_discovery` hook: handle [ProtocolEvent::DiscoveryKey].
     pub async fn run_with_discovery_hook<F>(
         mut self,
         on_discovery: impl Fn(DiscoveryKey) -> F,
-        ) -> Result<()>
+        ) -> Result<StopReason>
     where
         F: Future<Output=Result<()>>,
     {
         loop {
-            match self.next().await.unwrap() {
-                Event::Command(cmd) => {
-                    if !self.handle_command(cmd).await? {
-                        return Ok(())
-                    }
-                },
-                Event::Event(event) => {
-                    let on_discovery = |discovery| on_discovery(discovery);
-                    if !self.handle_event(event, on_discovery).await? {
-                        return Ok(())
-                    }
-                },
-            };
+            let on_discovery = |discovery| on_discovery(discovery);
+            if let StepOutcome::Stopped(reason) =
+                self.step_with_discovery_hook(on_discovery).await?
+            {
+                return Ok(reason)
+            }
         }
     }
-    async fn handle_command(&mut self, command: Command) -> Result<bool> {
-        #[cfg(test)] println!("handle_command {:?}", command);
+    /// Wait for the next [Event] and apply it,
+    /// the building block of [Replication::run].
+    ///
+    /// Lets the caller drive the replication on its own schedule,
+    /// interleave it with other work, or stop between steps.
+    /// Keep calling it until it returns [StepOutcome::Stopped].
+    pub async fn step(&mut self) -> Result<StepOutcome> {
+        let on_discovery = |_| async move { Ok(()) };
+        self.step_with_discovery_hook(on_discovery).await
+    }
+    /// [Replication::step] with an `on_discovery` hook:
+    /// handle [ProtocolEvent::DiscoveryKey].
+    pub async fn step_with_discovery_hook<F>(
+        &mut self,
+        on_discovery: impl FnOnce(DiscoveryKey) -> F,
+        ) -> Result<StepOutcome>
+    where
+        F: Future<Output=Result<()>>,
+    {
+        // Replication never ends the stream.
+        let stop = match self.next().await.unwrap() {
+            Event::Command(cmd) => self.handle_command(cmd).await,
+            Event::Event(event) =>
+                self.handle_event(event, on_discovery).await,
+            Event::Cancelled => match self.close_replicas().await {
+                true => Err(
+                    anyhow!("Cancelled before replication finished.")),
+                false => Ok(Some(StopReason::Cancelled)),
+            },
+            Event::Applied(key, applying) =>
+                self.handle_applied(key, applying).await,
+        };
+        let stop = stop.inspect_err(|err| {
+            log_warn!("replication failed: {:#}", err);
+            self.event_log.record(|| format!("error {:#}", err));
+        })?;
+        Ok(match stop {
+            None => StepOutcome::Continue,
+            Some(reason) => StepOutcome::Stopped(reason),
+        })
+    }
+    async fn handle_command(&mut self, command: Command)
+        -> Result<Option<StopReason>>
+    {
+        log_debug!("command {:?}", command);
+        self.event_log.record(|| format!("{:?}", command));
 
         match command {
-            Command::Open(key, replica) => {
+            Command::Open(key, mut replica) => {
                 let discovery = discovery_key(&key.to_bytes());
+                let open = self.replicas.len() + self.applying.len();
+                let limited = self.max_open_channels
+                    .is_some_and(|max| open >= max);
+                let replacing = self.replicas.contains_key(&discovery)
+                    || self.applying.contains_key(&discovery);
+                if limited && !replacing {
+                    return Err(anyhow!("Too many open channels."))
+                }
+                self.detach(&discovery);
+                let receive_only = replica.receive_only();
+                let length = replica.length().await;
                 self.replicas.insert(discovery, replica);
-                self.protocol.open(key.to_bytes()).await?;
-                Ok(true)
+                match (length, receive_only) {
+                    (Some(length), _) => self.protocol
+                        .open_with_length(key.to_bytes(), length, receive_only)
+                        .await?,
+                    (None, true) => self.protocol
+                        .open_receive_only(key.to_bytes()).await?,
+                    (None, false) => self.protocol.open(key.to_bytes()).await?,
+                };
+                Ok(None)
             },
             Command::ReOpen(key) => {
-                self.replica_on_open(&key).await?;
-                Ok(true)
+                self.protocol.verify_channel(&key)?;
+                match self.applying.get_mut(&key) {
+                    Some(applying) =>
+                        applying.queued.push_back(ProtocolEvent::Open(key)),
+                    None => self.replica_on_open(&key).await?,
+                }
+                Ok(None)
             },
             Command::Close(key) => {
                 self.protocol
                     .close(key)
                     .await?;
                 self.replicas.remove(&key);
-                Ok(true)
+                self.detach(&key);
+                Ok(None)
             },
-            Command::Quit() => {
-                let mut is_error = false;
-                for (_, replica) in self.replicas.iter_mut() {
-                    is_error |= replica.on_close().await.is_err();
+            Command::RequestByHash(key, request) => {
+                self.protocol.request_by_hash(&key, request).await?;
+                Ok(None)
+            },
+            Command::Cancel(key, index) => {
+                self.protocol.cancel(&key, Cancel { index }).await?;
+                match self.applying.get_mut(&key) {
+                    Some(applying) => applying.cancelled.push(index),
+                    None => self.replica_on_cancel(&key, index).await?,
                 }
-                return match is_error {
+                Ok(None)
+            },
+            Command::Quit() => {
+                return match self.close_replicas().await {
                     true => Err(anyhow!("Quit before replication finished.")),
-                    false => Ok(false),
+                    false => Ok(Some(StopReason::Quit)),
                 }
             },
         }
@@ -145,58 +439,193 @@ where
         &mut self,
         event: Result<ProtocolEvent>,
         on_discovery: impl FnOnce(DiscoveryKey) -> F,
-        ) -> Result<bool>
+        ) -> Result<Option<StopReason>>
     where
         F: Future<Output=Result<()>>,
     {
-        #[cfg(test)] println!("handle_event {:?}", event);
-
         let msg = match event {
             Ok(msg) => msg,
             Err(err) => {
-                let mut is_error = false;
-                for (_, replica) in self.replicas.iter_mut() {
-                    is_error |= replica.on_close().await.is_err();
-                }
-                return match is_error {
+                log_warn!("protocol failed: {:#}", err);
+                self.event_log.record(|| format!("protocol error {:#}", err));
+                return match self.close_replicas().await {
                     true => Err(err),
-                    false => Ok(false),
+                    false => Ok(Some(stop_reason(err))),
                 }
             },
         };
 
         match msg {
             ProtocolEvent::DiscoveryKey(discovery) => {
+                self.event_log.record(|| format!(
+                    "discovery key {}", hex::encode(discovery)));
                 on_discovery(discovery).await?;
             },
+            ProtocolEvent::Writable => {},
+            event => self.handle_feed_event(event).await?,
+        };
+        Ok(None)
+    }
+
+    /// Handle an event for a single replica,
+    /// or queue it while the replica is applying [Data].
+    async fn handle_feed_event(&mut self, event: ProtocolEvent)
+        -> Result<()>
+    {
+        let key = match &event {
+            ProtocolEvent::Open(discovery)
+            | ProtocolEvent::Close(discovery)
+            | ProtocolEvent::Message(discovery, _) => *discovery,
+            _ => return Ok(()),
+        };
+        if let Some(applying) = self.applying.get_mut(&key) {
+            applying.queued.push_back(event);
+            return Ok(())
+        }
+
+        match event {
             ProtocolEvent::Open(discovery) => {
+                self.event_log.record(|| format!(
+                    "open {}", hex::encode(discovery)));
                 self.replica_on_open(&discovery).await?;
             },
             ProtocolEvent::Close(discovery) => {
+                self.event_log.record(|| format!(
+                    "close {}", hex::encode(discovery)));
                 self.replica_on_close(&discovery).await?;
             },
             ProtocolEvent::Message(discovery, msg) => match msg {
                 Message::Request(request) => {
+                    log_trace!("request discovery={} index={}",
+                        hex::encode(discovery), request.index);
+                    self.event_log.record(|| format!("request {} index={}",
+                        hex::encode(discovery), request.index));
                     self.replica_on_request(&discovery, request).await?;
                 },
                 Message::Data(data) => {
+                    log_trace!("data discovery={} index={} length={}",
+                        hex::encode(discovery), data.index, data.data.len());
+                    self.event_log.record(|| format!(
+                        "data {} index={} length={}",
+                        hex::encode(discovery), data.index, data.data.len()));
                     self.replica_on_data(&discovery, data).await?;
                 },
+                Message::RequestByHash(request) => {
+                    log_trace!("request by hash discovery={} hash={}",
+                        hex::encode(discovery), hex::encode(&request.hash));
+                    self.event_log.record(|| format!(
+                        "request by hash {} hash={}",
+                        hex::encode(discovery), hex::encode(&request.hash)));
+                    self.replica_on_request_by_hash(&discovery, request)
+                        .await?;
+                },
+                Message::NotFound(msg) => {
+                    log_trace!("not found discovery={}",
+                        hex::encode(discovery));
+                    self.event_log.record(|| format!(
+                        "not found {}", hex::encode(discovery)));
+                    self.replica_on_not_found(&discovery, msg).await?;
+                },
+                Message::Cancel(msg) => {
+                    log_trace!("cancel discovery={} index={}",
+                        hex::encode(discovery), msg.index);
+                    self.event_log.record(|| format!("cancel {} index={}",
+                        hex::encode(discovery), msg.index));
+                },
                 _ => {},
             },
+            _ => {},
+        };
+        Ok(())
+    }
+
+    /// Return a replica done applying [Data], send its [Request]s
+    /// and handle the events queued meanwhile.
+    async fn handle_applied(&mut self, key: DiscoveryKey, applying: Applying)
+        -> Result<Option<StopReason>>
+    {
+        let (replica, result) = applying.output
+            .expect("Applying is done.");
+        self.protocol.data_applied(&key);
+        if result.is_ok() {
+            self.downloaded(&key, applying.index);
+        }
+        if applying.detached {
+            return Ok(None)
+        }
+        self.replicas.insert(key, replica);
+        for request in result? {
+            self.request(&key, request).await?;
+        }
+        for index in applying.cancelled {
+            self.replica_on_cancel(&key, index).await?;
+        }
+        for event in applying.queued {
+            self.handle_feed_event(event).await?;
+        }
+        Ok(None)
+    }
+
+    /// Set aside the replica applying [Data] for `key`,
+    /// closed or replaced meanwhile, until it is done.
+    ///
+    /// Later events for `key` go to its replacement, if any.
+    /// The events queued for it are dropped, handing the remote
+    /// the credit for its [Data] back.
+    fn detach(&mut self, key: &DiscoveryKey) {
+        let mut applying = match self.applying.remove(key) {
+            Some(applying) => applying,
+            None => return,
         };
-        Ok(true)
+        for event in applying.queued.drain(..) {
+            if let ProtocolEvent::Message(_, Message::Data(_)) = event {
+                self.protocol.data_applied(key);
+            }
+        }
+        applying.detached = true;
+        self.detached.push((*key, applying));
+    }
+
+    /// Call [ReplicaTrait::on_close] on every replica,
+    /// returns if any of them failed.
+    ///
+    /// Waits for replicas applying [Data] first.
+    async fn close_replicas(&mut self) -> bool {
+        let mut is_error = false;
+        let detached = std::mem::take(&mut self.detached);
+        let applying = std::mem::take(&mut self.applying);
+        for (key, applying) in detached.into_iter().chain(applying) {
+            let (replica, result) = applying.apply.await;
+            match result {
+                Ok(_) => self.downloaded(&key, applying.index),
+                Err(_) => is_error = true,
+            }
+            if !applying.detached {
+                self.replicas.insert(key, replica);
+            }
+        }
+        for (_, replica) in self.replicas.iter_mut() {
+            is_error |= replica.on_close().await.is_err();
+        }
+        is_error
     }
 
     async fn replica_on_open(
         &mut self, key: &DiscoveryKey) -> Result<()>
     {
+        let length = self.protocol.remote_length(key);
         if let Some(replica) = self.replicas.get_mut(key) {
-            let request = replica.on_open().await?;
-            if let Some(request) = request {
-                self.protocol
-                    .request(key, request)
-                    .await?;
+            if let Some(length) = length {
+                replica.on_remote_length(length).await?;
+            }
+            let mut requests = replica.on_open().await?;
+            if requests.is_empty() {
+                if let Some(index) = replica.resume_from().await {
+                    requests.push(Request { index, sparse: None, end: None });
+                }
+            }
+            for request in requests {
+                self.request(key, request).await?;
             }
         }
         Ok(())
@@ -212,39 +641,179 @@ where
         Ok(())
     }
 
+    /// Serve a ranged [Request] as sparse [Request]s for each block,
+    /// up to the first block the replica does not have,
+    /// and at most [MAX_RANGE_LENGTH] blocks.
     async fn replica_on_request(
         &mut self, key: &DiscoveryKey, request: Request) -> Result<()>
+    {
+        let end = match (request.sparse(), request.end) {
+            (true, Some(end)) =>
+                end.min(request.index.saturating_add(MAX_RANGE_LENGTH)),
+            _ => {
+                self.replica_on_single_request(key, request).await?;
+                return Ok(())
+            },
+        };
+        for index in request.index..end {
+            let request = Request { index, sparse: Some(true), end: None };
+            if !self.replica_on_single_request(key, request).await? {
+                break
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns if the replica replied with [Data].
+    async fn replica_on_single_request(
+        &mut self, key: &DiscoveryKey, request: Request) -> Result<bool>
+    {
+        let replica = match self.replicas.get_mut(key) {
+            Some(replica) => replica,
+            None => return Ok(false),
+        };
+        match replica.on_request(request).await? {
+            Some(DataOrRequest::Data(data)) => {
+                self.data(key, data).await?;
+                Ok(true)
+            },
+            Some(DataOrRequest::Request(request)) => {
+                self.request(key, request).await?;
+                Ok(false)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn replica_on_request_by_hash(
+        &mut self, key: &DiscoveryKey, request: RequestByHash) -> Result<()>
     {
         if let Some(replica) = self.replicas.get_mut(key) {
-            let msg = replica.on_request(request).await?;
-            match msg {
-                Some(DataOrRequest::Data(data)) =>
-                    self.protocol.data(key, data).await?,
-                Some(DataOrRequest::Request(request)) =>
-                    self.protocol.request(key, request).await?,
-                None => {},
+            let hash = request.hash.clone();
+            match replica.on_request_by_hash(request).await? {
+                Some(data) => self.data(key, data).await?,
+                None => self.protocol.not_found(key, NotFound { hash }).await?,
             };
         }
         Ok(())
     }
 
-    async fn replica_on_data(
-        &mut self, key: &DiscoveryKey, data: Data) -> Result<()>
+    async fn replica_on_cancel(
+        &mut self, key: &DiscoveryKey, index: u32) -> Result<()>
     {
         if let Some(replica) = self.replicas.get_mut(key) {
-            let request = replica.on_data(data).await?;
-            if let Some(request) = request {
-                self.protocol
-                    .request(key, request)
-                    .await?;
-            }
+            replica.on_cancel(index).await?;
         }
         Ok(())
     }
+
+    async fn replica_on_not_found(
+        &mut self, key: &DiscoveryKey, msg: NotFound) -> Result<()>
+    {
+        if let Some(replica) = self.replicas.get_mut(key) {
+            replica.on_not_found(msg).await?;
+        }
+        Ok(())
+    }
+
+    /// Send a [Request], unless the remote opened the channel
+    /// receive-only and will not serve it.
+    async fn request(&mut self, key: &DiscoveryKey, request: Request)
+        -> Result<()>
+    {
+        if self.protocol.is_remote_receive_only(key) {
+            return Ok(())
+        }
+        self.protocol.request(key, request).await
+    }
+
+    /// Send [Data] and emit [ReplicationEvent::Uploaded].
+    async fn data(&mut self, key: &DiscoveryKey, data: Data) -> Result<()> {
+        let index = data.index;
+        self.protocol.data(key, data).await?;
+        self.events.send(ReplicationEvent::Uploaded {
+            discovery_key: *key,
+            index,
+        });
+        Ok(())
+    }
+
+    /// Emit [ReplicationEvent::Downloaded] once [Data] is applied.
+    fn downloaded(&self, key: &DiscoveryKey, index: u32) {
+        self.events.send(ReplicationEvent::Downloaded {
+            discovery_key: *key,
+            index,
+        });
+    }
+
+    /// Move the replica off the loop to apply `data`,
+    /// see [Replication::handle_applied].
+    ///
+    /// The remote gets the credit for `data` back once it is applied,
+    /// so a slow replica holds back the remote instead of queueing.
+    async fn replica_on_data(
+        &mut self, key: &DiscoveryKey, data: Data) -> Result<()>
+    {
+        let mut replica = match self.replicas.remove(key) {
+            Some(replica) => replica,
+            None => {
+                self.protocol.data_applied(key);
+                return Ok(())
+            },
+        };
+        let index = data.index;
+        let apply = async move {
+            let result = replica.on_data(data).await;
+            (replica, result)
+        };
+        self.applying.insert(*key, Applying {
+            apply: Box::pin(apply),
+            index,
+            output: None,
+            queued: VecDeque::new(),
+            cancelled: vec![],
+            detached: false,
+        });
+        Ok(())
+    }
+}
+fn stop_reason(err: anyhow::Error) -> StopReason {
+    let closed = err.downcast_ref::<Error>()
+        .is_some_and(|err| matches!(err.kind(),
+            ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionReset
+            | ErrorKind::BrokenPipe));
+    match closed {
+        true => StopReason::RemoteClosed,
+        false => StopReason::Error(err),
+    }
+}
+impl<Si, St> Replication<MessageIo<Si, St>>
+where
+    MessageIo<Si, St>: Transport,
+{
+    /// Create `Replication` over a `Sink` and a `Stream` of already framed
+    /// [ChannelMessage]s, see [MessageIo].
+    ///
+    /// **This forgoes the noise handshake and transport encryption.**
+    /// Channel capabilities are not verified either,
+    /// so this relies entirely on the security of the transport.
+    ///
+    /// [ChannelMessage]: protocol::ChannelMessage
+    pub async fn from_message_io(sink: Si, stream: St)
+        -> Result<(Self, ReplicationHandle)>
+    {
+        Self::with_options(MessageIo::new(sink, stream), Options {
+            noise: false,
+            encrypted: false,
+            ..Options::default().no_keepalive()
+        }).await
+    }
 }
 impl<T: 'static> Stream for Replication<T>
 where
-    T: AsyncWrite + AsyncRead + Send + Unpin,
+    T: Transport,
 {
     type Item = Event;
 
@@ -255,9 +824,51 @@ where
     {
         let this = self.get_mut();
 
+        if let Some(cancel) = &mut this.cancel {
+            if cancel.as_mut().poll(cx).is_ready() {
+                this.cancel = None;
+                return Poll::Ready(Some(Event::Cancelled));
+            }
+        }
+        let applied = this.applying.iter_mut()
+            .find_map(|(key, applying)| {
+                match applying.apply.as_mut().poll(cx) {
+                    Poll::Ready(output) => {
+                        applying.output = Some(output);
+                        Some(*key)
+                    },
+                    Poll::Pending => None,
+                }
+            });
+        if let Some(key) = applied {
+            let applying = this.applying.remove(&key)
+                .expect("Applied replica is applying.");
+            return Poll::Ready(Some(Event::Applied(key, applying)));
+        }
+        let detached = this.detached.iter_mut()
+            .position(|(_, applying)| {
+                match applying.apply.as_mut().poll(cx) {
+                    Poll::Ready(output) => {
+                        applying.output = Some(output);
+                        true
+                    },
+                    Poll::Pending => false,
+                }
+            });
+        if let Some(position) = detached {
+            let (key, applying) = this.detached.swap_remove(position);
+            return Poll::Ready(Some(Event::Applied(key, applying)));
+        }
         if let Poll::Ready(Some(t)) = this.command_rx.poll_next(cx) {
             return Poll::Ready(Some(Event::Command(t)));
         }
+        // a full queue holds back the remote until its replica is done,
+        // woken by the apply polled above
+        let backlogged = this.applying.values()
+            .any(|applying| applying.queued.len() >= MAX_QUEUED_EVENTS);
+        if backlogged {
+            return Poll::Pending;
+        }
         if let Poll::Ready(Some(t)) = this.protocol.poll_next(cx) {
             return Poll::Ready(Some(Event::Event(t)));
         }
@@ -0,0 +1,160 @@
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use async_trait::async_trait;
+use std::sync::Arc;
+use async_lock::Mutex;
+use datacore::{Hash, verify};
+
+use crate::{PublicKey, BlockSignature, Signature};
+use crate::replication::{
+    ReplicaTrait, Request, Data, DataOrRequest, MAX_RANGE_LENGTH,
+};
+
+/// Block downloaded by a [SparseReplica].
+///
+/// **The index of the block is not verified.**
+/// The data signature proves the writer signed `data`,
+/// but without merkle proofs nothing proves it was signed at this index:
+/// a remote can send a genuine block labelled with another index.
+#[derive(Debug, Clone)]
+pub struct UnverifiedBlock {
+    /// Block data.
+    pub data: Vec<u8>,
+    /// Block signatures, only the data signature is verified.
+    pub signature: BlockSignature,
+}
+
+/// [UnverifiedBlock]s downloaded by a [SparseReplica], by claimed index.
+pub type SparseBlocks = Arc<Mutex<BTreeMap<u32, UnverifiedBlock>>>;
+
+/// SparseReplica downloads only selected blocks of a [Core]
+/// over [Replication].
+///
+/// [Core] storage is sequential and has no sparse mode, so the blocks are
+/// not stored in a [Core], but kept as [UnverifiedBlock]s in [SparseBlocks].
+/// Do not treat them as verified [Core] blocks.
+///
+/// [Core]: crate::Core
+/// [Replication]: super::Replication
+#[derive(Debug)]
+pub struct SparseReplica {
+    public_key: PublicKey,
+    wanted: BTreeSet<u32>,
+    blocks: SparseBlocks,
+    /// End of the range of [SparseReplica::with_range].
+    end: Option<u32>,
+    /// End of the part of the range requested so far.
+    requested: u32,
+}
+
+impl SparseReplica {
+    /// Create a new [SparseReplica] downloading blocks at `indices`.
+    pub fn new(
+        public_key: PublicKey,
+        indices: impl IntoIterator<Item = u32>,
+        ) -> Self
+    {
+        Self {
+            public_key,
+            wanted: indices.into_iter().collect(),
+            blocks: Arc::new(Mutex::new(BTreeMap::new())),
+            end: None,
+            requested: 0,
+        }
+    }
+
+    /// Create a new [SparseReplica] downloading the blocks in `range`,
+    /// asked for with [Request]s carrying an `end`.
+    ///
+    /// The range is requested in parts of [MAX_RANGE_LENGTH] blocks,
+    /// the next part once the last block of the previous one arrives.
+    pub fn with_range(public_key: PublicKey, range: Range<u32>) -> Self {
+        let mut replica = Self::new(public_key, range.clone());
+        replica.end = Some(range.end);
+        replica
+    }
+
+    /// Access the downloaded [SparseBlocks], see [UnverifiedBlock].
+    pub fn unverified_blocks(&self) -> SparseBlocks {
+        Arc::clone(&self.blocks)
+    }
+
+    /// Request the next part of the range from `start`.
+    fn request_range(&mut self, start: u32) -> Vec<Request> {
+        let end = match self.end {
+            Some(end) if start < end =>
+                end.min(start.saturating_add(MAX_RANGE_LENGTH)),
+            _ => return vec![],
+        };
+        self.requested = end;
+        vec![Request { index: start, sparse: Some(true), end: Some(end) }]
+    }
+}
+
+#[async_trait]
+impl ReplicaTrait for SparseReplica {
+    async fn on_open(&mut self) -> Result<Vec<Request>> {
+        let blocks = self.blocks.lock().await;
+        if self.end.is_some() {
+            let start = self.wanted.iter()
+                .find(|index| !blocks.contains_key(index))
+                .copied();
+            drop(blocks);
+            return Ok(match start {
+                Some(start) => self.request_range(start),
+                None => vec![],
+            })
+        }
+        let requests = self.wanted.iter()
+            .filter(|index| !blocks.contains_key(index))
+            .map(|&index| Request { index, sparse: Some(true), end: None })
+            .collect();
+        Ok(requests)
+    }
+    async fn on_request(&mut self, _request: Request)
+        -> Result<Option<DataOrRequest>>
+    {
+        Ok(None)
+    }
+    async fn on_data(&mut self, data: Data)
+        -> Result<Vec<Request>>
+    {
+        if !self.wanted.contains(&data.index) {
+            return Ok(vec![])
+        }
+
+        let signature = BlockSignature::new(
+            Signature::from_bytes(&data.data_signature)?,
+            Signature::from_bytes(&data.tree_signature)?);
+        let data_hash = Hash::from_leaf(&data.data);
+        verify(&self.public_key, &data_hash, &signature.data())
+            .map_err(|_| anyhow!(
+                "Block {} has invalid data signature.", data.index))?;
+
+        let mut blocks = self.blocks.lock().await;
+        blocks.insert(data.index, UnverifiedBlock {
+            data: data.data,
+            signature,
+        });
+        drop(blocks);
+        if self.end.is_some() && data.index + 1 == self.requested {
+            return Ok(self.request_range(self.requested))
+        }
+        Ok(vec![])
+    }
+    async fn on_cancel(&mut self, index: u32) -> Result<()> {
+        self.wanted.remove(&index);
+        Ok(())
+    }
+    async fn on_close(&mut self) -> Result<()> {
+        let blocks = self.blocks.lock().await;
+        let missing = self.wanted.iter()
+            .filter(|index| !blocks.contains_key(index))
+            .count();
+        if missing > 0 {
+            return Err(anyhow!("Not synced; missing {} blocks.", missing))
+        }
+        Ok(())
+    }
+}
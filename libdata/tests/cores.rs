@@ -1,5 +1,6 @@
 use anyhow::Result;
 use async_std::test;
+use async_std::sync::{Arc, Mutex};
 
 use random_access_memory::RandomAccessMemory;
 use libdata::{Core, Cores, generate_keypair, discovery_key};
@@ -68,3 +69,31 @@ async fn cores_insert_2() -> Result<()>
 
     Ok(())
 }
+
+#[test]
+async fn cores_public_keys_sorted() -> Result<()>
+{
+    let mut entries = vec![];
+    for _ in 0..5 {
+        let core = new_core().await?;
+        let public = *core.public_key();
+        entries.push((public, Arc::new(Mutex::new(core))));
+    }
+
+    let mut forward = Cores::new();
+    for (public, core) in entries.iter() {
+        forward.put(public, Arc::clone(core));
+    }
+    let mut backward = Cores::new();
+    for (public, core) in entries.iter().rev() {
+        backward.put(public, Arc::clone(core));
+    }
+
+    let sorted = forward.public_keys_sorted();
+    assert_eq!(sorted, backward.public_keys_sorted());
+    let bytes: Vec<_> = sorted.iter().map(|key| key.to_bytes()).collect();
+    let mut expected = bytes.clone();
+    expected.sort();
+    assert_eq!(bytes, expected);
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use std::sync::Arc;
+use async_lock::Mutex;
+use futures_lite::future::{block_on, zip};
+use sluice::pipe::pipe;
+
+use random_access_memory::RandomAccessMemory;
+use libdata::{generate_keypair, Core, Cores};
+use libdata::replication::{CoreReplica, Duplex, Options, Replication};
+
+// Driven by a plain `block_on`, without the async-std runtime.
+#[test]
+fn replication_without_runtime() -> Result<()> {
+    block_on(async {
+        let memory = || RandomAccessMemory::new(1024);
+        let keypair = generate_keypair();
+        let public = keypair.public;
+        let mut a = Core::new(memory(), memory(), memory(),
+            keypair.public, Some(keypair.secret))
+            .await?;
+        a.append(b"hello", None).await?;
+        a.append(b"world", None).await?;
+        let b = Core::new(memory(), memory(), memory(), public, None).await?;
+
+        let mut cores = Cores::new();
+        cores.put(&public, Arc::new(Mutex::new(b)));
+        let b = cores.get_by_public(&public).unwrap();
+
+        let (ar, bw) = pipe();
+        let (br, aw) = pipe();
+        let options = |is_initiator| Options {
+            is_initiator,
+            keepalive_ms: Some(500),
+            send_keepalive: false,
+            ..Options::default()
+        };
+        let (a_replication, b_replication) = zip(
+            Replication::with_options(Duplex::new(ar, aw), options(false)),
+            Replication::with_options(Duplex::new(br, bw), options(true)))
+            .await;
+        let (a_replication, mut a_handle) = a_replication?;
+        let (b_replication, mut b_handle) = b_replication?;
+
+        a_handle.open(&public,
+            Box::new(CoreReplica::new(Arc::new(Mutex::new(a))))).await?;
+        b_handle.open(&public,
+            Box::new(CoreReplica::new(Arc::clone(&b)))).await?;
+        let (a_result, b_result) =
+            zip(a_replication.run(), b_replication.run()).await;
+        a_result?;
+        b_result?;
+
+        let mut b = b.lock().await;
+        assert_eq!(b.len(), 2);
+        assert_eq!(b.get(1).await?.unwrap().0, b"world");
+        Ok(())
+    })
+}
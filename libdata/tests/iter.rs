@@ -1,15 +1,58 @@
 use anyhow::Result;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
 use futures_lite::stream::StreamExt;
 use async_std::sync::{Arc, Mutex};
-use async_std::test;
+use async_std::{test, task};
 
 use random_access_memory::RandomAccessMemory;
-use libdata::{generate_keypair, Core, CoreIterator};
+use libdata::{generate_keypair, Core, CoreIterator, CoreStream, RandomAccess};
 
 fn random_access_memory() -> RandomAccessMemory {
     RandomAccessMemory::new(1024)
 }
 
+/// [RandomAccessMemory] failing every read once `fail` is set.
+#[derive(Debug)]
+struct Faulty {
+    store: RandomAccessMemory,
+    fail: Arc<AtomicBool>,
+}
+
+#[async_trait::async_trait]
+impl RandomAccess for Faulty {
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn write(&mut self, offset: u64, data: &[u8])
+        -> Result<(), Self::Error>
+    {
+        self.store.write(offset, data).await
+    }
+
+    async fn read(&mut self, offset: u64, length: u64)
+        -> Result<Vec<u8>, Self::Error>
+    {
+        if self.fail.load(Ordering::SeqCst) {
+            return Err("Faulty read".into())
+        }
+        self.store.read(offset, length).await
+    }
+
+    async fn delete(&mut self, offset: u64, length: u64)
+        -> Result<(), Self::Error>
+    {
+        self.store.delete(offset, length).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.store.flush().await
+    }
+
+    async fn len(&mut self) -> Result<u64, Self::Error> {
+        self.store.len().await
+    }
+}
+
 #[test]
 async fn iter_simple() -> Result<()>
 {
@@ -27,10 +70,10 @@ async fn iter_simple() -> Result<()>
     }
 
     let mut iter = CoreIterator::new(Arc::new(Mutex::new(core)), 0);
-    assert_eq!(iter.next().await.unwrap(), (0, vec![1]));
-    assert_eq!(iter.next().await.unwrap(), (1, vec![2]));
-    assert_eq!(iter.next().await.unwrap(), (2, vec![3]));
-    assert_eq!(iter.next().await, None);
+    assert_eq!(iter.next().await.unwrap()?, (0, vec![1]));
+    assert_eq!(iter.next().await.unwrap()?, (1, vec![2]));
+    assert_eq!(iter.next().await.unwrap()?, (2, vec![3]));
+    assert!(iter.next().await.is_none());
     Ok(())
 }
 
@@ -51,9 +94,9 @@ async fn iter_offset() -> Result<()>
     }
 
     let mut iter = CoreIterator::new(Arc::new(Mutex::new(core)), 1);
-    assert_eq!(iter.next().await.unwrap(), (1, vec![2]));
-    assert_eq!(iter.next().await.unwrap(), (2, vec![3]));
-    assert_eq!(iter.next().await, None);
+    assert_eq!(iter.next().await.unwrap()?, (1, vec![2]));
+    assert_eq!(iter.next().await.unwrap()?, (2, vec![3]));
+    assert!(iter.next().await.is_none());
     Ok(())
 }
 
@@ -74,6 +117,115 @@ async fn iter_out_of_bounds() -> Result<()>
     }
 
     let mut iter = CoreIterator::new(Arc::new(Mutex::new(core)), 100);
-    assert_eq!(iter.next().await, None);
+    assert!(iter.next().await.is_none());
+    Ok(())
+}
+
+#[test]
+async fn iter_live() -> Result<()>
+{
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+    core.append(&[1], None).await.unwrap();
+    let core = Arc::new(Mutex::new(core));
+
+    let mut iter = CoreIterator::new_live(Arc::clone(&core), 0);
+    assert_eq!(iter.next().await.unwrap()?, (0, vec![1]));
+
+    let writer = task::spawn(async move {
+        for d in [2, 3] {
+            core.lock().await.append(&[d], None).await.unwrap();
+        }
+    });
+    assert_eq!(iter.next().await.unwrap()?, (1, vec![2]));
+    assert_eq!(iter.next().await.unwrap()?, (2, vec![3]));
+    writer.await;
+    Ok(())
+}
+
+#[test]
+async fn stream_simple() -> Result<()>
+{
+    let keypair = generate_keypair();
+    let mut core = Core::new(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    for d in [1, 2] {
+        core.append(&[d], None).await.unwrap();
+    }
+    let signature = core.get(1).await?.unwrap().1;
+
+    let mut stream = CoreStream::new(Arc::new(Mutex::new(core)), 0);
+    let (index, data, _) = stream.next().await.unwrap()?;
+    assert_eq!((index, data), (0, vec![1]));
+    assert_eq!(stream.next().await.unwrap()?, (1, vec![2], signature));
+    assert!(stream.next().await.is_none());
+    Ok(())
+}
+
+#[test]
+async fn stream_read_error() -> Result<()>
+{
+    let keypair = generate_keypair();
+    let fail = Arc::new(AtomicBool::new(false));
+    let data = Faulty {
+        store: random_access_memory(),
+        fail: Arc::clone(&fail),
+    };
+    let mut core = Core::new(
+        data,
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    for d in [1, 2] {
+        core.append(&[d], None).await.unwrap();
+    }
+
+    let mut stream = CoreStream::new(Arc::new(Mutex::new(core)), 0);
+    assert_eq!(stream.next().await.unwrap()?.1, vec![1]);
+    fail.store(true, Ordering::SeqCst);
+    assert!(stream.next().await.unwrap().is_err());
+    assert!(stream.next().await.is_none());
+    Ok(())
+}
+
+#[test]
+async fn iter_read_error() -> Result<()>
+{
+    let keypair = generate_keypair();
+    let fail = Arc::new(AtomicBool::new(false));
+    let data = Faulty {
+        store: random_access_memory(),
+        fail: Arc::clone(&fail),
+    };
+    let mut core = Core::new(
+        data,
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret))
+        .await.unwrap();
+
+    for d in [1, 2] {
+        core.append(&[d], None).await.unwrap();
+    }
+
+    let mut iter = CoreIterator::new(Arc::new(Mutex::new(core)), 0);
+    assert_eq!(iter.next().await.unwrap()?, (0, vec![1]));
+    fail.store(true, Ordering::SeqCst);
+    assert!(iter.next().await.unwrap().is_err());
+    fail.store(false, Ordering::SeqCst);
+    assert_eq!(iter.next().await.unwrap()?, (1, vec![2]));
+    assert!(iter.next().await.is_none());
     Ok(())
 }
@@ -1,7 +1,11 @@
 use quickcheck::{quickcheck, TestResult};
 use insta;
 
-use libdata::{generate_keypair, derive_keypair, SecretKey};
+use libdata::{
+    generate_keypair, derive_keypair, SecretKey, discovery_key,
+    public_key_to_hex, public_key_from_hex,
+    discovery_key_to_hex, discovery_key_from_hex,
+};
 
 #[test]
 fn key_can_generate() {
@@ -14,6 +18,24 @@ fn key_can_derive() {
     derive_keypair(&keypair.secret, "hello");
 }
 
+#[test]
+fn key_hex_roundtrip() {
+    let public = generate_keypair().public;
+    let hex = public_key_to_hex(&public);
+    assert_eq!(hex.len(), 64);
+    assert_eq!(hex, hex.to_lowercase());
+    assert_eq!(public_key_from_hex(&hex).unwrap(), public);
+    assert_eq!(public_key_from_hex(&hex.to_uppercase()).unwrap(), public);
+
+    let discovery = discovery_key(&public.to_bytes());
+    let hex = discovery_key_to_hex(&discovery);
+    assert_eq!(hex.len(), 64);
+    assert_eq!(discovery_key_from_hex(&hex).unwrap(), discovery);
+
+    assert!(discovery_key_from_hex(&hex[2..]).is_err());
+    assert!(discovery_key_from_hex(&"zz".repeat(32)).is_err());
+}
+
 quickcheck! {
     fn key_same_key_different_names(a: String, b: String) -> TestResult {
         if a == b {
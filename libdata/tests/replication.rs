@@ -1,14 +1,27 @@
 use anyhow::Result;
+use async_trait::async_trait;
 use std::time::Duration;
+use std::collections::BTreeSet;
 use futures_lite::future::zip;
+use futures_lite::io::{AsyncRead, AsyncWrite};
 use async_std::{test, task};
 use async_std::sync::{Arc, Mutex};
 use sluice::pipe::{PipeReader, PipeWriter, pipe};
 
 use random_access_memory::RandomAccessMemory;
-use libdata::{generate_keypair, PublicKey, Core};
+use random_access_disk::RandomAccessDisk;
+use protocol::test_util::{
+    LaggyDuplex, create_laggy_duplex_pair, create_message_channel_pair,
+};
+use libdata::{
+    generate_keypair, discovery_key, PublicKey, Core, Cores, CoreOptions, Hash,
+    SIGNATURE_LENGTH,
+};
 use libdata::replication::{
     CoreReplica, Duplex, Replication, Options, ReplicationHandle,
+    ReplicaTrait, SparseReplica, Data, ProgressEvent, StopReason,
+    StepOutcome, Request, DataOrRequest, NotFound, ReplicationEvent,
+    MeteredStream, MAX_RANGE_LENGTH,
 };
 
 fn random_access_memory() -> RandomAccessMemory {
@@ -48,28 +61,220 @@ fn create_duplex_pair_memory()
 }
 async fn create_replication_pair_memory()
     -> (ReplicationMemory, ReplicationMemory)
+{
+    let (a_stream, b_stream) = create_duplex_pair_memory();
+    create_replication_pair(a_stream, b_stream).await
+}
+async fn create_replication_pair_laggy(latency: Duration)
+    -> ((Replication<LaggyDuplex>, ReplicationHandle),
+        (Replication<LaggyDuplex>, ReplicationHandle))
+{
+    let (a_stream, b_stream) = create_laggy_duplex_pair(latency);
+    create_replication_pair(a_stream, b_stream).await
+}
+async fn create_replication_pair<T>(a_stream: T, b_stream: T)
+    -> ((Replication<T>, ReplicationHandle),
+        (Replication<T>, ReplicationHandle))
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
 {
     const KEEPALIVE_MS: u64 = 500;
 
-    let (a_stream, b_stream) = create_duplex_pair_memory();
     zip(
         task::spawn(async move {
             Replication::with_options(a_stream, Options {
-                is_initiator: false,
                 keepalive_ms: Some(KEEPALIVE_MS),
-                ..Options::default()
+                send_keepalive: false,
+                ..Options::responder()
             }).await.unwrap()
         }),
         task::spawn(async move {
             Replication::with_options(b_stream, Options {
-                is_initiator: true,
                 keepalive_ms: Some(KEEPALIVE_MS),
-                ..Options::default()
+                send_keepalive: false,
+                ..Options::initiator()
             }).await.unwrap()
         })
     ).await
 }
 
+#[test]
+async fn replication_handshake_timeout() -> Result<()>
+{
+    // the remote end never reads nor writes
+    let (stream, _remote) = create_duplex_pair_memory();
+    let result = Replication::with_options(stream, Options {
+        handshake_timeout: Some(Duration::from_millis(100)),
+        ..Options::initiator()
+    }).await;
+
+    let error = result.unwrap_err();
+    let error = error.downcast_ref::<std::io::Error>().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    Ok(())
+}
+
+#[test]
+async fn replication_max_open_channels() -> Result<()>
+{
+    let a = Arc::new(Mutex::new(new_core().await?));
+    let b = Arc::new(Mutex::new(new_core().await?));
+    let a_public = *a.lock().await.public_key();
+    let b_public = *b.lock().await.public_key();
+
+    let (a_stream, b_stream) = create_duplex_pair_memory();
+    let (a_result, b_result) = zip(
+        Replication::with_options(a_stream, Options {
+            max_open_channels: Some(1),
+            ..Options::responder()
+        }),
+        Replication::with_options(b_stream, Options {
+            keepalive_ms: Some(500),
+            send_keepalive: false,
+            ..Options::initiator()
+        }))
+        .await;
+    let ((a_replication, mut a_handle), (b_replication, _)) =
+        (a_result?, b_result?);
+
+    // replacing an open replica does not count
+    a_handle.open(&a_public, Box::new(CoreReplica::new(Arc::clone(&a))))
+        .await?;
+    a_handle.open(&a_public, Box::new(CoreReplica::new(Arc::clone(&a))))
+        .await?;
+    a_handle.open(&b_public, Box::new(CoreReplica::new(Arc::clone(&b))))
+        .await?;
+    let (a_result, _) = zip(
+        task::spawn(a_replication.run()),
+        task::spawn(b_replication.run()))
+        .await;
+    let error = a_result.unwrap_err();
+    assert!(error.to_string().contains("Too many open channels"));
+    Ok(())
+}
+
+#[test]
+async fn replication_event_log() -> Result<()>
+{
+    let a = Arc::new(Mutex::new(new_core().await?));
+    let b = Arc::new(Mutex::new(new_core().await?));
+    let a_public = *a.lock().await.public_key();
+    let b_public = *b.lock().await.public_key();
+
+    let (a_stream, b_stream) = create_duplex_pair_memory();
+    let (a_result, b_result) = zip(
+        Replication::with_options(a_stream, Options {
+            max_open_channels: Some(1),
+            event_log_size: 2,
+            ..Options::responder()
+        }),
+        Replication::with_options(b_stream, Options {
+            keepalive_ms: Some(500),
+            send_keepalive: false,
+            ..Options::initiator()
+        }))
+        .await;
+    let ((a_replication, mut a_handle), (b_replication, _)) =
+        (a_result?, b_result?);
+
+    a_handle.open(&a_public, Box::new(CoreReplica::new(Arc::clone(&a))))
+        .await?;
+    a_handle.open(&b_public, Box::new(CoreReplica::new(Arc::clone(&b))))
+        .await?;
+    let (a_result, _) = zip(
+        task::spawn(a_replication.run()),
+        task::spawn(b_replication.run()))
+        .await;
+    assert!(a_result.is_err());
+
+    // the log outlives the replication, keeping only the last events
+    let log = a_handle.event_log();
+    assert_eq!(log.len(), 2);
+    assert!(log[0].starts_with("Command::Open"));
+    assert_eq!(log[1], "error Too many open channels.");
+    Ok(())
+}
+
+#[test]
+async fn replication_cancel_request() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    for i in 0..3u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
+    let a = Arc::new(Mutex::new(a));
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
+
+    let (a_stream, b_stream) = create_duplex_pair_memory();
+    let (a_result, b_result) = zip(
+        Replication::with_options(a_stream, Options {
+            keepalive_ms: Some(500),
+            send_keepalive: false,
+            event_log_size: 32,
+            ..Options::responder()
+        }),
+        Replication::with_options(b_stream, Options {
+            keepalive_ms: Some(500),
+            send_keepalive: false,
+            ..Options::initiator()
+        }))
+        .await;
+    let ((a_replication, mut a_handle), (b_replication, mut b_handle)) =
+        (a_result?, b_result?);
+    let b_events = b_handle.events();
+
+    a_handle.open(&public, Box::new(CoreReplica::new(Arc::clone(&a))))
+        .await?;
+    let cancelled = Arc::new(Mutex::new(vec![]));
+    b_handle.open(&public, Box::new(CancelReplica {
+        replica: CoreReplica::new(Arc::clone(&b)),
+        cancelled: Arc::clone(&cancelled),
+    })).await?;
+    let a_task = task::spawn(a_replication.run());
+    let b_task = task::spawn(b_replication.run());
+    // Cancel once the channel is open, nothing is sent before.
+    b_events.recv().await?;
+    b_handle.cancel(&public, 7).await?;
+    let (a_result, b_result) = zip(a_task, b_task).await;
+    a_result?;
+    b_result?;
+
+    let discovery = hex::encode(discovery_key(public.as_bytes()));
+    let cancel = format!("cancel {} index=7", discovery);
+    assert!(a_handle.event_log().contains(&cancel));
+    assert_eq!(*cancelled.lock().await, vec![7]);
+    assert_eq!(b.lock().await.len(), 3);
+    Ok(())
+}
+/// Replica recording the indices cancelled on `replica`.
+#[derive(Debug)]
+struct CancelReplica<R> {
+    replica: R,
+    cancelled: Arc<Mutex<Vec<u32>>>,
+}
+#[async_trait]
+impl<R: ReplicaTrait + Send> ReplicaTrait for CancelReplica<R> {
+    async fn on_open(&mut self) -> Result<Vec<Request>> {
+        self.replica.on_open().await
+    }
+    async fn on_request(&mut self, request: Request)
+        -> Result<Option<DataOrRequest>>
+    {
+        self.replica.on_request(request).await
+    }
+    async fn on_data(&mut self, data: Data) -> Result<Vec<Request>> {
+        self.replica.on_data(data).await
+    }
+    async fn on_cancel(&mut self, index: u32) -> Result<()> {
+        self.cancelled.lock().await.push(index);
+        self.replica.on_cancel(index).await
+    }
+    async fn on_close(&mut self) -> Result<()> {
+        self.replica.on_close().await
+    }
+}
+
 #[test]
 async fn replication_core_replica() -> Result<()>
 {
@@ -103,14 +308,56 @@ async fn replication_core_replica() -> Result<()>
     Ok(())
 }
 #[test]
-async fn replication_core_replica_async_open() -> Result<()>
+async fn replication_open_all() -> Result<()>
+{
+    let mut a_cores = Cores::new();
+    let mut b_cores = Cores::new();
+    let mut replicas = vec![];
+    for data in [b"hello", b"world"] {
+        let mut a = new_core().await?;
+        a.append(data, None).await?;
+        let b = Arc::new(Mutex::new(new_replica(*a.public_key()).await?));
+        b_cores.put(a.public_key(), Arc::clone(&b));
+        replicas.push((b, data));
+        a_cores.insert(a);
+    }
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    zip(
+        task::spawn(async move {
+            a_handle.open_all(&a_cores, |core|
+                Box::new(CoreReplica::new(core))).await.unwrap();
+            a_replication.run().await.unwrap();
+        }),
+        task::spawn(async move {
+            b_handle.open_all(&b_cores, |core|
+                Box::new(CoreReplica::new(core))).await.unwrap();
+            b_replication.run().await.unwrap();
+        })
+    ).await;
+
+    for (b, data) in replicas {
+        let mut b = b.lock().await;
+        assert_eq!(b.get(0).await?.unwrap().0, data);
+    }
+    Ok(())
+}
+#[test]
+async fn replication_core_replica_memory_to_disk() -> Result<()>
 {
     let mut a = new_core().await?;
-    let public = a.public_key().clone();
-    let b = new_replica(public.clone()).await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+    a.append(b"world", None).await?;
 
-    let data = b"hello world";
-    a.append(data, None).await?;
+    let dir = tempfile::tempdir()?;
+    let disk = |name: &str| RandomAccessDisk::open(dir.path().join(name));
+    let b = Core::new(
+        disk("data").await?, disk("blocks").await?, disk("state").await?,
+        public, None)
+        .await?;
 
     let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
     let b = Arc::new(Mutex::new(b));
@@ -120,47 +367,41 @@ async fn replication_core_replica_async_open() -> Result<()>
          (b_replication, mut b_handle)) =
         create_replication_pair_memory().await;
     zip(
-        zip(
-            task::spawn(async move {
-                a_replication.run().await.unwrap();
-            }),
-            task::spawn(async move {
-                b_replication.run().await.unwrap();
-            })
-        ),
-        zip(
-            task::spawn(async move {
-                a_handle.open(&public, a_replica).await.unwrap();
-            }),
-            task::spawn(async move {
-                b_handle.open(&public, b_replica).await.unwrap();
-            })
-        ),
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await.unwrap();
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await.unwrap();
+        })
     ).await;
+    drop(b);
 
-    let mut b = b.lock().await;
-    assert_eq!(b.get(0).await?.unwrap().0, data);
+    let mut b = Core::new(
+        disk("data").await?, disk("blocks").await?, disk("state").await?,
+        public, None)
+        .await?;
+    assert_eq!(b.len(), 2);
+    assert_eq!(b.get(1).await?.unwrap().0, b"world");
     Ok(())
 }
-
 #[test]
-async fn replication_core_replica_multiple_blocks() -> Result<()>
+async fn replication_step() -> Result<()>
 {
     let mut a = new_core().await?;
-    let public = a.public_key().clone();
-    let b = new_replica(public.clone()).await?;
+    let public = *a.public_key();
+    let b = new_replica(public).await?;
 
-    let data = b"hello world";
-    for &d in data.into_iter() {
-        a.append(&[d], None).await?;
-    }
+    a.append(b"hello", None).await?;
+    a.append(b"world", None).await?;
 
     let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
     let b = Arc::new(Mutex::new(b));
     let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
 
     let ((a_replication, mut a_handle),
-         (b_replication, mut b_handle)) =
+         (mut b_replication, mut b_handle)) =
         create_replication_pair_memory().await;
     let (a_result, b_result) = zip(
         task::spawn(async move {
@@ -169,115 +410,1481 @@ async fn replication_core_replica_multiple_blocks() -> Result<()>
         }),
         task::spawn(async move {
             b_handle.open(&public, b_replica).await.unwrap();
-            b_replication.run().await
+            let mut steps = 0;
+            loop {
+                steps += 1;
+                if let StepOutcome::Stopped(reason) =
+                    b_replication.step().await?
+                {
+                    return Ok::<_, anyhow::Error>((reason, steps))
+                }
+            }
         })
     ).await;
     a_result?;
-    b_result?;
+    let (_, steps) = b_result?;
+    assert!(steps > 1);
 
     let mut b = b.lock().await;
-    for (i, &d) in data.into_iter().enumerate() {
-        assert_eq!(b.get(i as u32).await?.unwrap().0[0], d);
-    }
+    assert_eq!(b.get(1).await?.unwrap().0, b"world");
     Ok(())
 }
-
 #[test]
-async fn replication_core_replica_multiple_blocks_live() -> Result<()>
+async fn replication_core_replica_message_io() -> Result<()>
 {
-    let a = new_core().await?;
-    let public = a.public_key().clone();
-    let b = new_replica(public.clone()).await?;
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    let b = new_replica(public).await?;
 
-    let data = b"hello world";
+    a.append(b"hello", None).await?;
+    a.append(b"world", None).await?;
 
-    let a = Arc::new(Mutex::new(a));
-    let a_replica = Box::new(CoreReplica::new(Arc::clone(&a)));
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
     let b = Arc::new(Mutex::new(b));
     let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
 
+    let ((a_sink, a_stream), (b_sink, b_stream)) =
+        create_message_channel_pair();
+    let (a_result, b_result) = zip(
+        Replication::from_message_io(a_sink, a_stream),
+        Replication::from_message_io(b_sink, b_stream))
+        .await;
     let ((a_replication, mut a_handle),
-         (b_replication, mut b_handle)) =
-        create_replication_pair_memory().await;
-    zip(
-        zip(
-            task::spawn(async move {
-                a_replication.run().await.unwrap();
-            }),
-            task::spawn(async move {
-                b_replication.run().await.unwrap();
-            })
-        ),
-        zip(
-            task::spawn(async move {
-                a_handle.open(&public, a_replica).await.unwrap();
-                for &d in data.into_iter() {
-                    let mut a = a.lock().await;
-                    a.append(&[d], None).await.unwrap();
-                    a_handle.reopen(&public).await.unwrap();
-                    task::sleep(Duration::from_millis(10)).await;
-                }
-            }),
-            task::spawn(async move {
-                b_handle.open(&public, b_replica).await.unwrap();
-            })
-        ),
-    ).await;
+         (b_replication, mut b_handle)) = (a_result?, b_result?);
+    let mut b_quit = b_handle.clone();
+    let a_task = task::spawn(async move {
+        a_handle.open(&public, a_replica).await.unwrap();
+        a_replication.run().await
+    });
+    let b_task = task::spawn(async move {
+        b_handle.open(&public, b_replica).await.unwrap();
+        b_replication.run().await
+    });
 
-    let mut b = b.lock().await;
-    for (i, &d) in data.into_iter().enumerate() {
-        assert_eq!(b.get(i as u32).await?.unwrap().0[0], d);
+    // Nothing times out a message transport,
+    // quitting drops b and ends the stream a receives from.
+    while b.lock().await.len() < 2 {
+        task::sleep(Duration::from_millis(10)).await;
     }
+    b_quit.quit().await?;
+    let (a_result, b_result) = zip(a_task, b_task).await;
+    assert!(matches!(a_result?, StopReason::RemoteClosed));
+    assert!(matches!(b_result?, StopReason::Quit));
+
+    let mut b = b.lock().await;
+    assert_eq!(b.get(1).await?.unwrap().0, b"world");
     Ok(())
 }
+#[test]
+async fn replication_cancel() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    let b = new_replica(public).await?;
+    for i in 0..100u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_laggy(Duration::from_millis(10)).await;
+    let (cancel_tx, cancel_rx) = async_channel::bounded::<()>(1);
+    let b_replication = b_replication.with_cancel(async move {
+        let _ = cancel_rx.recv().await;
+    });
+    a_handle.open(&public, a_replica).await?;
+    b_handle.open(&public, b_replica).await?;
+    let a_task = task::spawn(a_replication.run());
+    let b_task = task::spawn(b_replication.run());
 
+    while b.lock().await.is_empty() {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+    cancel_tx.send(()).await?;
+    // the replica is closed before it synced
+    let error = b_task.await.unwrap_err();
+    assert!(error.to_string().contains("Cancelled"));
+    assert!(b.lock().await.len() < 100);
+    drop(a_task.await);
+
+    // nothing to sync
+    let ((a_replication, _a_handle), (b_replication, _b_handle)) =
+        create_replication_pair_memory().await;
+    let b_task = task::spawn(b_replication.with_cancel(async {}).run());
+    assert!(matches!(b_task.await?, StopReason::Cancelled));
+    drop(a_replication);
+    Ok(())
+}
 #[test]
-async fn replication_core_replica_of_replica() -> Result<()>
+async fn replication_core_replica_progress_completed() -> Result<()>
 {
     let mut a = new_core().await?;
-    let public = a.public_key().clone();
-    let b = new_replica(public.clone()).await?;
-    let c = new_replica(public.clone()).await?;
+    let public = *a.public_key();
+    let b = new_replica(public).await?;
 
-    let data = b"hello world";
-    a.append(data, None).await?;
+    for i in 0..3u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
 
     let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
     let b = Arc::new(Mutex::new(b));
+    let mut b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+    let progress = b_replica.progress();
+
+    let ((a_sink, a_stream), (b_sink, b_stream)) =
+        create_message_channel_pair();
+    let (a_result, b_result) = zip(
+        Replication::from_message_io(a_sink, a_stream),
+        Replication::from_message_io(b_sink, b_stream))
+        .await;
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) = (a_result?, b_result?);
+    let mut b_quit = b_handle.clone();
+    let a_task = task::spawn(async move {
+        a_handle.open(&public, a_replica).await.unwrap();
+        a_replication.run().await
+    });
+    let b_task = task::spawn(async move {
+        b_handle.open(&public, b_replica).await.unwrap();
+        b_replication.run().await
+    });
+
+    assert_eq!(progress.recv().await?, ProgressEvent::Completed { length: 3 });
+    assert_eq!(b.lock().await.len(), 3);
+    b_quit.quit().await?;
+    let (a_result, b_result) = zip(a_task, b_task).await;
+    a_result?;
+    b_result?;
+    Ok(())
+}
+#[test]
+async fn replication_events() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    let discovery = discovery_key(public.as_bytes());
+    for i in 0..3u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
     let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
-    let b2_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
-    let c = Arc::new(Mutex::new(c));
-    let c_replica = Box::new(CoreReplica::new(Arc::clone(&c)));
 
     let ((a_replication, mut a_handle),
          (b_replication, mut b_handle)) =
         create_replication_pair_memory().await;
-    zip(
+    let a_events = a_handle.events();
+    let b_events = b_handle.events();
+    let (a_result, b_result) = zip(
         task::spawn(async move {
             a_handle.open(&public, a_replica).await.unwrap();
-            a_replication.run().await.unwrap();
+            a_replication.run().await
         }),
         task::spawn(async move {
             b_handle.open(&public, b_replica).await.unwrap();
-            b_replication.run().await.unwrap();
+            b_replication.run().await
         })
     ).await;
+    a_result?;
+    b_result?;
+    assert_eq!(b.lock().await.len(), 3);
 
-    let ((b2_replication, mut b2_handle),
-         (c_replication, mut c_handle)) =
+    // A block may be transferred more than once.
+    let mut uploaded = BTreeSet::new();
+    while let Ok(event) = a_events.try_recv() {
+        match event {
+            ReplicationEvent::Uploaded { discovery_key, index } => {
+                assert_eq!(discovery_key, discovery);
+                uploaded.insert(index);
+            },
+            event => panic!("Unexpected {:?}", event),
+        }
+    }
+    let mut downloaded = BTreeSet::new();
+    while let Ok(event) = b_events.try_recv() {
+        match event {
+            ReplicationEvent::Downloaded { discovery_key, index } => {
+                assert_eq!(discovery_key, discovery);
+                downloaded.insert(index);
+            },
+            event => panic!("Unexpected {:?}", event),
+        }
+    }
+    assert_eq!(uploaded, BTreeSet::from([0, 1, 2]));
+    assert_eq!(downloaded, uploaded);
+    Ok(())
+}
+
+#[test]
+async fn replication_events_not_downloaded_on_failed_apply() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    // a Core of another key rejects the blocks
+    let other = new_core().await?;
+    let b = Arc::new(Mutex::new(new_replica(*other.public_key()).await?));
+    let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
         create_replication_pair_memory().await;
-    zip(
+    let b_events = b_handle.events();
+    let (_, b_result) = zip(
         task::spawn(async move {
-            b2_handle.open(&public, b2_replica).await.unwrap();
-            b2_replication.run().await.unwrap();
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await
         }),
         task::spawn(async move {
-            c_handle.open(&public, c_replica).await.unwrap();
-            c_replication.run().await.unwrap();
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await
         })
     ).await;
+    assert!(b_result.is_err());
+    assert_eq!(b.lock().await.len(), 0);
+    assert!(b_events.try_recv().is_err());
+    Ok(())
+}
 
-    let mut c = c.lock().await;
-    assert_eq!(c.get(0).await?.unwrap().0, data);
+#[test]
+async fn replication_run_with_cores() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+    a.append(b"world", None).await?;
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
+    let mut cores = Cores::new();
+    cores.put(&public, Arc::clone(&b));
+    cores.insert(new_core().await?);
+    let cores = Arc::new(Mutex::new(cores));
+
+    let ((a_sink, a_stream), (b_sink, b_stream)) =
+        create_message_channel_pair();
+    let (a_result, b_result) = zip(
+        Replication::from_message_io(a_sink, a_stream),
+        Replication::from_message_io(b_sink, b_stream))
+        .await;
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) = (a_result?, b_result?);
+    let unknown = *new_core().await?.public_key();
+    let unknown_replica = Box::new(CoreReplica::new(
+        Arc::new(Mutex::new(new_core().await?))));
+    let a_task = task::spawn(async move {
+        a_handle.open(&unknown, unknown_replica).await.unwrap();
+        a_handle.open(&public, a_replica).await.unwrap();
+        a_replication.run().await
+    });
+    let b_task = task::spawn(b_replication.run_with_cores(cores));
+
+    while b.lock().await.len() < 2 {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+    b_handle.quit().await?;
+    let (a_result, b_result) = zip(a_task, b_task).await;
+    a_result?;
+    b_result?;
+
+    let mut b = b.lock().await;
+    assert_eq!(b.get(1).await?.unwrap().0, b"world");
+    Ok(())
+}
+/// [CoreReplica] holding its first [Data] until `gate` opens.
+#[derive(Debug)]
+struct SlowReplica<R> {
+    replica: R,
+    gate: Option<async_channel::Receiver<()>>,
+}
+#[async_trait]
+impl<R: ReplicaTrait + Send> ReplicaTrait for SlowReplica<R> {
+    async fn on_open(&mut self) -> Result<Vec<Request>> {
+        self.replica.on_open().await
+    }
+    async fn on_request(&mut self, request: Request)
+        -> Result<Option<DataOrRequest>>
+    {
+        self.replica.on_request(request).await
+    }
+    async fn on_data(&mut self, data: Data) -> Result<Vec<Request>> {
+        if let Some(gate) = self.gate.take() {
+            gate.recv().await?;
+        }
+        self.replica.on_data(data).await
+    }
+    async fn on_close(&mut self) -> Result<()> {
+        self.replica.on_close().await
+    }
+}
+#[test]
+async fn replication_slow_replica_does_not_block() -> Result<()>
+{
+    let mut a_slow = new_core().await?;
+    let mut a_fast = new_core().await?;
+    let slow_public = *a_slow.public_key();
+    let fast_public = *a_fast.public_key();
+    for i in 0..10u32 {
+        a_slow.append(&i.to_be_bytes(), None).await?;
+        a_fast.append(&i.to_be_bytes(), None).await?;
+    }
+    let b_slow = Arc::new(Mutex::new(new_replica(slow_public).await?));
+    let b_fast = Arc::new(Mutex::new(new_replica(fast_public).await?));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    a_handle.open(&slow_public,
+        Box::new(CoreReplica::new(Arc::new(Mutex::new(a_slow))))).await?;
+    a_handle.open(&fast_public,
+        Box::new(CoreReplica::new(Arc::new(Mutex::new(a_fast))))).await?;
+    let (gate_tx, gate_rx) = async_channel::bounded(1);
+    b_handle.open(&slow_public, Box::new(SlowReplica {
+        replica: CoreReplica::new(Arc::clone(&b_slow)),
+        gate: Some(gate_rx),
+    })).await?;
+    b_handle.open(&fast_public,
+        Box::new(CoreReplica::new(Arc::clone(&b_fast)))).await?;
+    let a_task = task::spawn(a_replication.run());
+    let b_task = task::spawn(b_replication.run());
+
+    // the fast feed syncs while the slow one is stuck on its first block
+    async_std::future::timeout(Duration::from_secs(5), async {
+        while b_fast.lock().await.len() < 10 {
+            task::sleep(Duration::from_millis(10)).await;
+        }
+    }).await?;
+    assert_eq!(b_slow.lock().await.len(), 0);
+
+    gate_tx.send(()).await?;
+    while b_slow.lock().await.len() < 10 {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+    b_handle.quit().await?;
+    let (a_result, b_result) = zip(a_task, b_task).await;
+    a_result?;
+    b_result?;
+
+    let mut b_slow = b_slow.lock().await;
+    for i in 0..10u32 {
+        assert_eq!(b_slow.get(i).await?.unwrap().0, i.to_be_bytes());
+    }
+    Ok(())
+}
+#[test]
+async fn replication_reopen_while_applying() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    for i in 0..10u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
+    let a = Arc::new(Mutex::new(a));
+    let b_slow = Arc::new(Mutex::new(new_replica(public).await?));
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
+    let discovery = discovery_key(public.as_bytes());
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    a_handle.open(&public, Box::new(CoreReplica::new(Arc::clone(&a))))
+        .await?;
+    let (gate_tx, gate_rx) = async_channel::bounded(1);
+    b_handle.open(&public, Box::new(SlowReplica {
+        replica: CoreReplica::new(Arc::clone(&b_slow)),
+        gate: Some(gate_rx),
+    })).await?;
+    let a_task = task::spawn(a_replication.run());
+    let b_task = task::spawn(b_replication.run());
+    let logged = |handle: &ReplicationHandle, prefix: &str| handle
+        .event_log().iter().any(|entry| entry.starts_with(prefix));
+
+    // replace the replica while it is stuck on its first block
+    while !logged(&b_handle, "data ") {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+    b_handle.close(discovery).await?;
+    while !logged(&a_handle, "close ") {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+    b_handle.open(&public, Box::new(CoreReplica::new(Arc::clone(&b))))
+        .await?;
+    while !logged(&a_handle, "discovery key ") {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+    a_handle.open(&public, Box::new(CoreReplica::new(Arc::clone(&a))))
+        .await?;
+    // let b receive the new channel before the old replica is done
+    task::sleep(Duration::from_millis(100)).await;
+    gate_tx.send(()).await?;
+
+    async_std::future::timeout(Duration::from_secs(5), async {
+        while b.lock().await.len() < 10 {
+            task::sleep(Duration::from_millis(10)).await;
+        }
+    }).await?;
+    a_handle.quit().await?;
+    b_handle.quit().await?;
+    let (a_result, b_result) = zip(a_task, b_task).await;
+    a_result?;
+    b_result?;
+    Ok(())
+}
+/// Replica serving `replica`, which asks for `flood` blocks by hash
+/// along the first [Data] it serves, and counts the replies.
+#[derive(Debug)]
+struct FloodReplica<R> {
+    replica: R,
+    public: PublicKey,
+    handle: ReplicationHandle,
+    flood: u32,
+    not_found: Arc<Mutex<u32>>,
+}
+#[async_trait]
+impl<R: ReplicaTrait + Send> ReplicaTrait for FloodReplica<R> {
+    async fn on_open(&mut self) -> Result<Vec<Request>> {
+        self.replica.on_open().await
+    }
+    async fn on_request(&mut self, request: Request)
+        -> Result<Option<DataOrRequest>>
+    {
+        for i in 0..std::mem::take(&mut self.flood) {
+            let hash = Hash::from_leaf(&i.to_be_bytes());
+            self.handle.request_by_hash(&self.public, &hash).await?;
+        }
+        self.replica.on_request(request).await
+    }
+    async fn on_data(&mut self, data: Data) -> Result<Vec<Request>> {
+        self.replica.on_data(data).await
+    }
+    async fn on_not_found(&mut self, _msg: NotFound) -> Result<()> {
+        *self.not_found.lock().await += 1;
+        Ok(())
+    }
+    async fn on_close(&mut self) -> Result<()> {
+        self.replica.on_close().await
+    }
+}
+#[test]
+async fn replication_slow_replica_bounds_queue() -> Result<()>
+{
+    const FLOOD: u32 = 10_000;
+
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+
+    let (a_stream, b_stream) = create_duplex_pair_memory();
+    let b_stream = MeteredStream::new(b_stream);
+    let b_meter = b_stream.meter();
+    let ((a_replication, mut a_handle), (b_replication, mut b_handle)) = zip(
+        task::spawn(async move {
+            Replication::with_options(a_stream, Options::responder())
+                .await.unwrap()
+        }),
+        task::spawn(async move {
+            Replication::with_options(b_stream, Options {
+                read_buf_size: 1024,
+                ..Options::initiator()
+            }).await.unwrap()
+        })
+    ).await;
+
+    let not_found = Arc::new(Mutex::new(0));
+    a_handle.open(&public, Box::new(FloodReplica {
+        replica: CoreReplica::new(Arc::new(Mutex::new(a))),
+        public,
+        handle: a_handle.clone(),
+        flood: FLOOD,
+        not_found: Arc::clone(&not_found),
+    })).await?;
+    let (gate_tx, gate_rx) = async_channel::bounded(1);
+    b_handle.open(&public, Box::new(SlowReplica {
+        replica: SparseReplica::new(public, [0]),
+        gate: Some(gate_rx),
+    })).await?;
+    let a_task = task::spawn(a_replication.run());
+    let b_task = task::spawn(b_replication.run());
+
+    // the requests by hash pile up behind the stuck Data,
+    // only a few of them are read
+    task::sleep(Duration::from_millis(500)).await;
+    assert!(b_meter.bytes_read() < 32 * 1024);
+
+    gate_tx.send(()).await?;
+    async_std::future::timeout(Duration::from_secs(10), async {
+        while *not_found.lock().await < FLOOD {
+            task::sleep(Duration::from_millis(10)).await;
+        }
+    }).await?;
+    assert!(b_meter.bytes_read() > FLOOD as u64 * 32);
+
+    a_handle.quit().await?;
+    b_handle.quit().await?;
+    let (a_result, b_result) = zip(a_task, b_task).await;
+    a_result?;
+    b_result?;
+    Ok(())
+}
+#[test]
+async fn replication_run_with_factory() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+    a.append(b"world", None).await?;
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let unknown = new_core().await?;
+    let unknown_public = *unknown.public_key();
+    let unknown_replica = Box::new(CoreReplica::new(
+        Arc::new(Mutex::new(unknown))));
+
+    // b caches `public` only, without any storage for it yet.
+    let cores = Arc::new(Mutex::new(Cores::new()));
+    let created = Arc::new(Mutex::new(vec![]));
+    let factory = {
+        let created = Arc::clone(&created);
+        move |discovery| {
+            let created = Arc::clone(&created);
+            async move {
+                if discovery != discovery_key(public.as_bytes()) {
+                    return Ok(None)
+                }
+                created.lock().await.push(discovery);
+                Ok(Some(new_replica(public).await?))
+            }
+        }
+    };
+
+    let ((a_sink, a_stream), (b_sink, b_stream)) =
+        create_message_channel_pair();
+    let (a_result, b_result) = zip(
+        Replication::from_message_io(a_sink, a_stream),
+        Replication::from_message_io(b_sink, b_stream))
+        .await;
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) = (a_result?, b_result?);
+    let a_task = task::spawn(async move {
+        a_handle.open(&unknown_public, unknown_replica).await.unwrap();
+        a_handle.open(&public, a_replica).await.unwrap();
+        a_replication.run().await
+    });
+    let b_task = task::spawn(
+        b_replication.run_with_factory(Arc::clone(&cores), factory));
+
+    let b = loop {
+        if let Some(b) = cores.lock().await.get_by_public(&public) {
+            if b.lock().await.len() == 2 {
+                break b
+            }
+        }
+        task::sleep(Duration::from_millis(10)).await;
+    };
+    b_handle.quit().await?;
+    let (a_result, b_result) = zip(a_task, b_task).await;
+    a_result?;
+    b_result?;
+
+    assert_eq!(cores.lock().await.len(), 1);
+    assert!(cores.lock().await.get_by_public(&unknown_public).is_none());
+    assert_eq!(created.lock().await.len(), 1);
+    let mut b = b.lock().await;
+    assert_eq!(b.get(1).await?.unwrap().0, b"world");
+    Ok(())
+}
+#[test]
+async fn replication_core_replica_async_open() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = a.public_key().clone();
+    let b = new_replica(public.clone()).await?;
+
+    let data = b"hello world";
+    a.append(data, None).await?;
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    zip(
+        zip(
+            task::spawn(async move {
+                a_replication.run().await.unwrap();
+            }),
+            task::spawn(async move {
+                b_replication.run().await.unwrap();
+            })
+        ),
+        zip(
+            task::spawn(async move {
+                a_handle.open(&public, a_replica).await.unwrap();
+            }),
+            task::spawn(async move {
+                b_handle.open(&public, b_replica).await.unwrap();
+            })
+        ),
+    ).await;
+
+    let mut b = b.lock().await;
+    assert_eq!(b.get(0).await?.unwrap().0, data);
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_multiple_blocks() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = a.public_key().clone();
+    let b = new_replica(public.clone()).await?;
+
+    let data = b"hello world";
+    for &d in data.into_iter() {
+        a.append(&[d], None).await?;
+    }
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    let (a_result, b_result) = zip(
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await
+        })
+    ).await;
+    a_result?;
+    b_result?;
+
+    let mut b = b.lock().await;
+    for (i, &d) in data.into_iter().enumerate() {
+        assert_eq!(b.get(i as u32).await?.unwrap().0[0], d);
+    }
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_receive_only() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = a.public_key().clone();
+    let b = new_replica(public.clone()).await?;
+
+    let data = b"hello world";
+    for &d in data.into_iter() {
+        a.append(&[d], None).await?;
+    }
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(
+        CoreReplica::with_window(Arc::clone(&b), 4).with_receive_only());
+
+    let (a_stream, b_stream) = create_duplex_pair_memory();
+    let (a_result, b_result) = zip(
+        Replication::with_options(a_stream, Options {
+            keepalive_ms: Some(500),
+            send_keepalive: false,
+            ..Options::responder()
+        }),
+        Replication::with_options(b_stream, Options {
+            keepalive_ms: Some(500),
+            send_keepalive: false,
+            event_log_size: 1000,
+            ..Options::initiator()
+        }))
+        .await;
+    let ((a_replication, mut a_handle), (b_replication, mut b_handle)) =
+        (a_result?, b_result?);
+    a_handle.open(&public, a_replica).await?;
+    b_handle.open(&public, b_replica).await?;
+    let (a_result, b_result) = zip(
+        task::spawn(a_replication.run()),
+        task::spawn(b_replication.run()))
+        .await;
+    a_result?;
+    b_result?;
+
+    let mut b = b.lock().await;
+    assert_eq!(b.len(), data.len() as u32);
+    for (i, &d) in data.into_iter().enumerate() {
+        assert_eq!(b.get(i as u32).await?.unwrap().0[0], d);
+    }
+    let log = b_handle.event_log();
+    assert!(log.iter().any(|entry| entry.starts_with("data ")));
+    assert!(!log.iter().any(|entry| entry.starts_with("request ")));
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_multiple_blocks_live() -> Result<()>
+{
+    let a = new_core().await?;
+    let public = a.public_key().clone();
+    let b = new_replica(public.clone()).await?;
+
+    let data = b"hello world";
+
+    let a = Arc::new(Mutex::new(a));
+    let a_replica = Box::new(CoreReplica::new(Arc::clone(&a)));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    zip(
+        zip(
+            task::spawn(async move {
+                a_replication.run().await.unwrap();
+            }),
+            task::spawn(async move {
+                b_replication.run().await.unwrap();
+            })
+        ),
+        zip(
+            task::spawn(async move {
+                a_handle.open(&public, a_replica).await.unwrap();
+                for &d in data.into_iter() {
+                    let mut a = a.lock().await;
+                    a.append(&[d], None).await.unwrap();
+                    a_handle.reopen(&public).await.unwrap();
+                    task::sleep(Duration::from_millis(10)).await;
+                }
+            }),
+            task::spawn(async move {
+                b_handle.open(&public, b_replica).await.unwrap();
+            })
+        ),
+    ).await;
+
+    let mut b = b.lock().await;
+    for (i, &d) in data.into_iter().enumerate() {
+        assert_eq!(b.get(i as u32).await?.unwrap().0[0], d);
+    }
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_of_replica() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = a.public_key().clone();
+    let b = new_replica(public.clone()).await?;
+    let c = new_replica(public.clone()).await?;
+
+    let data = b"hello world";
+    a.append(data, None).await?;
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+    let b2_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+    let c = Arc::new(Mutex::new(c));
+    let c_replica = Box::new(CoreReplica::new(Arc::clone(&c)));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    zip(
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await.unwrap();
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await.unwrap();
+        })
+    ).await;
+
+    let ((b2_replication, mut b2_handle),
+         (c_replication, mut c_handle)) =
+        create_replication_pair_memory().await;
+    zip(
+        task::spawn(async move {
+            b2_handle.open(&public, b2_replica).await.unwrap();
+            b2_replication.run().await.unwrap();
+        }),
+        task::spawn(async move {
+            c_handle.open(&public, c_replica).await.unwrap();
+            c_replication.run().await.unwrap();
+        })
+    ).await;
+
+    let mut c = c.lock().await;
+    assert_eq!(c.get(0).await?.unwrap().0, data);
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_laggy() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    let b = new_replica(public).await?;
+
+    let data = b"hello world";
+    for &d in data.iter() {
+        a.append(&[d], None).await?;
+    }
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_laggy(Duration::from_millis(5)).await;
+    let (a_result, b_result) = zip(
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await
+        })
+    ).await;
+    a_result?;
+    b_result?;
+
+    let mut b = b.lock().await;
+    assert_eq!(b.len() as usize, data.len());
+    for (i, &d) in data.iter().enumerate() {
+        assert_eq!(b.get(i as u32).await?.unwrap().0[0], d);
+    }
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_resume() -> Result<()>
+{
+    let a = new_core().await?;
+    let public = *a.public_key();
+    let b = new_replica(public).await?;
+
+    let a = Arc::new(Mutex::new(a));
+    let b = Arc::new(Mutex::new(b));
+    a.lock().await.append(b"hello", None).await?;
+
+    for data in [b"world", b"again"] {
+        let a_replica = Box::new(CoreReplica::new(Arc::clone(&a)));
+        let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+        let b_len = b.lock().await.len();
+        assert_eq!(b_replica.resume_from().await, Some(b_len));
+
+        let ((a_replication, mut a_handle),
+             (b_replication, mut b_handle)) =
+            create_replication_pair_memory().await;
+        zip(
+            task::spawn(async move {
+                a_handle.open(&public, a_replica).await.unwrap();
+                a_replication.run().await.unwrap();
+            }),
+            task::spawn(async move {
+                b_handle.open(&public, b_replica).await.unwrap();
+                b_replication.run().await.unwrap();
+            })
+        ).await;
+
+        assert_eq!(b.lock().await.len(), a.lock().await.len());
+        a.lock().await.append(data, None).await?;
+    }
+
+    let mut b = b.lock().await;
+    assert_eq!(b.get(0).await?.unwrap().0, b"hello");
+    assert_eq!(b.get(1).await?.unwrap().0, b"world");
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_window() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    let b = new_replica(public).await?;
+
+    for i in 0..100u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
+
+    let a_replica = Box::new(CoreReplica::with_window(
+            Arc::new(Mutex::new(a)), 16));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(CoreReplica::with_window(Arc::clone(&b), 16));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_laggy(Duration::from_millis(5)).await;
+    let (a_result, b_result) = zip(
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await
+        })
+    ).await;
+    a_result?;
+    b_result?;
+
+    let mut b = b.lock().await;
+    assert_eq!(b.len(), 100);
+    for i in 0..100u32 {
+        assert_eq!(b.get(i).await?.unwrap().0, i.to_be_bytes());
+    }
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_end() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    for i in 0..20u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
+    let a = Arc::new(Mutex::new(a));
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
+
+    for end in [5, 10] {
+        let a_replica = Box::new(CoreReplica::new(Arc::clone(&a)));
+        let b_replica = Box::new(CoreReplica::with_end(Arc::clone(&b), end));
+        let ((a_replication, mut a_handle),
+             (b_replication, mut b_handle)) =
+            create_replication_pair_memory().await;
+        let (a_result, b_result) = zip(
+            task::spawn(async move {
+                a_handle.open(&public, a_replica).await.unwrap();
+                a_replication.run().await
+            }),
+            task::spawn(async move {
+                b_handle.open(&public, b_replica).await.unwrap();
+                b_replication.run().await
+            })
+        ).await;
+        a_result?;
+        b_result?;
+        assert_eq!(b.lock().await.len(), end);
+    }
+
+    let mut b = b.lock().await;
+    for i in 0..10u32 {
+        assert_eq!(b.get(i).await?.unwrap().0, i.to_be_bytes());
+    }
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_remote_length() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    for i in 0..20u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
+    let mut a_replica = CoreReplica::new(Arc::new(Mutex::new(a)));
+    assert_eq!(a_replica.length().await, Some(20));
+
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
+    let mut replica = CoreReplica::with_window(Arc::clone(&b), 8);
+    replica.on_remote_length(20).await?;
+    // The window fills up front, without probing the remote length.
+    let requests = replica.on_open().await?;
+    let indices = requests.iter()
+        .map(|request| request.index)
+        .collect::<Vec<u32>>();
+    assert_eq!(indices, (0..8).collect::<Vec<u32>>());
+    assert!(replica.on_close().await.is_err());
+
+    let mut replica = CoreReplica::with_window(Arc::clone(&b), 8);
+    replica.on_remote_length(0).await?;
+    assert_eq!(replica.on_open().await?.len(), 1);
+    replica.on_close().await?;
+    Ok(())
+}
+
+#[test]
+async fn replication_core_replica_drops_unsolicited_data() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+    a.append(b"world", None).await?;
+    let mut blocks = vec![];
+    for index in 0..2 {
+        let (data, signature) = a.get(index).await?.unwrap();
+        blocks.push(Data {
+            index,
+            data,
+            data_signature: signature.data().to_bytes().to_vec(),
+            tree_signature: signature.tree().to_bytes().to_vec(),
+        });
+    }
+
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
+    let mut replica = CoreReplica::with_window(Arc::clone(&b), 4);
+    replica.on_open().await?;
+    let block1 = blocks.pop().unwrap();
+    let block0 = blocks.pop().unwrap();
+    // Block 1 was never requested, so it is not buffered.
+    replica.on_data(block1).await?;
+    replica.on_data(block0).await?;
+    assert_eq!(b.lock().await.len(), 1);
+    Ok(())
+}
+#[test]
+async fn replication_core_replica_cancel() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    let mut blocks = vec![];
+    for index in 0..3u32 {
+        a.append(&index.to_be_bytes(), None).await?;
+        let (data, signature) = a.get(index).await?.unwrap();
+        blocks.push(Data {
+            index,
+            data,
+            data_signature: signature.data().to_bytes().to_vec(),
+            tree_signature: signature.tree().to_bytes().to_vec(),
+        });
+    }
+
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
+    let mut replica = CoreReplica::with_window(Arc::clone(&b), 4);
+    replica.on_remote_length(3).await?;
+    let requests = replica.on_open().await?;
+    assert_eq!(requests.len(), 3);
+    replica.on_cancel(2).await?;
+    // Block 2 is no longer in flight, so it is not buffered.
+    let block2 = blocks.pop().unwrap();
+    replica.on_data(block2).await?;
+    for block in blocks {
+        replica.on_data(block).await?;
+    }
+    assert_eq!(b.lock().await.len(), 2);
+    Ok(())
+}
+#[test]
+async fn replication_core_replica_malformed_signature() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+    let (data, signature) = a.get(0).await?.unwrap();
+    let block = Data {
+        index: 0,
+        data,
+        data_signature: signature.data().to_bytes().to_vec(),
+        tree_signature: signature.tree().to_bytes().to_vec(),
+    };
+
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
+    let mut replica = CoreReplica::new(Arc::clone(&b));
+    replica.on_open().await?;
+    let short = Data {
+        data_signature: vec![1u8; 12],
+        ..block.clone()
+    };
+    assert!(replica.on_data(short).await.is_err());
+    let garbage = Data {
+        tree_signature: vec![0xffu8; SIGNATURE_LENGTH],
+        ..block
+    };
+    assert!(replica.on_data(garbage).await.is_err());
+    assert_eq!(b.lock().await.len(), 0);
+    Ok(())
+}
+#[test]
+async fn replication_core_replica_read_repair() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+    let (data, signature) = a.get(0).await?.unwrap();
+    let block = Data {
+        index: 0,
+        data,
+        data_signature: signature.data().to_bytes().to_vec(),
+        tree_signature: signature.tree().to_bytes().to_vec(),
+    };
+    let bad = Data {
+        data: b"jello".to_vec(),
+        ..block.clone()
+    };
+
+    // fails without read repair
+    let b = Arc::new(Mutex::new(new_replica(public).await?));
+    let mut replica = CoreReplica::new(Arc::clone(&b));
+    replica.on_open().await?;
+    assert!(replica.on_data(bad.clone()).await.is_err());
+
+    let mut replica = CoreReplica::new(Arc::clone(&b)).with_read_repair(1);
+    replica.on_open().await?;
+    let requests = replica.on_data(bad.clone()).await?;
+    assert_eq!(requests, vec![Request { index: 0, sparse: None, end: None }]);
+    assert_eq!(b.lock().await.len(), 0);
+    // out of repairs
+    assert!(replica.on_data(bad).await.is_err());
+
+    let mut replica = CoreReplica::new(Arc::clone(&b)).with_read_repair(1);
+    replica.on_open().await?;
+    replica.on_data(block).await?;
+    assert_eq!(b.lock().await.get(0).await?.unwrap().0, b"hello");
+    Ok(())
+}
+#[test]
+async fn replication_core_replica_window_live() -> Result<()>
+{
+    let a = new_core().await?;
+    let public = *a.public_key();
+    let b = new_replica(public).await?;
+
+    let data = b"hello world";
+
+    let a = Arc::new(Mutex::new(a));
+    let a_replica = Box::new(CoreReplica::with_window(Arc::clone(&a), 4));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(CoreReplica::with_window(Arc::clone(&b), 4));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    zip(
+        zip(
+            task::spawn(async move {
+                a_replication.run().await.unwrap();
+            }),
+            task::spawn(async move {
+                b_replication.run().await.unwrap();
+            })
+        ),
+        zip(
+            task::spawn(async move {
+                a_handle.open(&public, a_replica).await.unwrap();
+                for &d in data.iter() {
+                    let mut a = a.lock().await;
+                    a.append(&[d], None).await.unwrap();
+                    a_handle.reopen(&public).await.unwrap();
+                    task::sleep(Duration::from_millis(10)).await;
+                }
+            }),
+            task::spawn(async move {
+                b_handle.open(&public, b_replica).await.unwrap();
+            })
+        ),
+    ).await;
+
+    let mut b = b.lock().await;
+    for (i, &d) in data.iter().enumerate() {
+        assert_eq!(b.get(i as u32).await?.unwrap().0[0], d);
+    }
+    Ok(())
+}
+
+#[test]
+async fn replication_sparse_replica() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+
+    for i in 0..20u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let wanted = vec![3, 7, 8, 9, 10];
+    let b_replica = Box::new(SparseReplica::new(public, wanted.clone()));
+    let blocks = b_replica.unverified_blocks();
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    let (a_result, b_result) = zip(
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await
+        })
+    ).await;
+    a_result?;
+    b_result?;
+
+    let blocks = blocks.lock().await;
+    assert_eq!(blocks.keys().copied().collect::<Vec<u32>>(), wanted);
+    for (index, block) in blocks.iter() {
+        assert_eq!(block.data, index.to_be_bytes());
+    }
+    Ok(())
+}
+
+#[test]
+async fn replication_sparse_replica_range() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    for i in 0..600u32 {
+        a.append(&i.to_be_bytes(), None).await?;
+    }
+
+    // one ranged Request per part of the range
+    let mut replica = SparseReplica::with_range(public, 100..600);
+    assert_eq!(replica.on_open().await?, vec![Request {
+        index: 100,
+        sparse: Some(true),
+        end: Some(100 + MAX_RANGE_LENGTH),
+    }]);
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let blocks = replica.unverified_blocks();
+    let b_replica = Box::new(replica);
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    let (a_result, b_result) = zip(
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await
+        })
+    ).await;
+    a_result?;
+    b_result?;
+
+    let blocks = blocks.lock().await;
+    assert_eq!(
+        blocks.keys().copied().collect::<Vec<u32>>(),
+        (100..600).collect::<Vec<u32>>());
+    for (index, block) in blocks.iter() {
+        assert_eq!(block.data, index.to_be_bytes());
+    }
+    Ok(())
+}
+
+#[test]
+async fn replication_sparse_replica_missing() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let b_replica = Box::new(SparseReplica::new(public, 0..3));
+    let blocks = b_replica.unverified_blocks();
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    let (a_result, b_result) = zip(
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await
+        })
+    ).await;
+    assert!(a_result.is_ok());
+    assert!(b_result.is_err());
+    assert_eq!(blocks.lock().await.len(), 1);
+    Ok(())
+}
+
+#[test]
+async fn replication_sparse_replica_cancel() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = *a.public_key();
+    a.append(b"hello", None).await?;
+    let (data, signature) = a.get(0).await?.unwrap();
+
+    let mut replica = SparseReplica::new(public, 0..3);
+    let blocks = replica.unverified_blocks();
+    replica.on_open().await?;
+    replica.on_data(Data {
+        index: 0,
+        data,
+        data_signature: signature.data().to_bytes().to_vec(),
+        tree_signature: signature.tree().to_bytes().to_vec(),
+    }).await?;
+    assert!(replica.on_close().await.is_err());
+    // Cancelled blocks are no longer wanted.
+    replica.on_cancel(1).await?;
+    replica.on_cancel(2).await?;
+    replica.on_close().await?;
+    assert_eq!(blocks.lock().await.len(), 1);
+    Ok(())
+}
+
+/// Requests `hashes` by hash on open, quits once all are answered.
+#[derive(Debug)]
+struct HashReplica {
+    public: PublicKey,
+    hashes: Vec<Hash>,
+    handle: ReplicationHandle,
+    found: Arc<Mutex<Vec<Data>>>,
+    not_found: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+impl HashReplica {
+    async fn answered(&mut self) -> Result<()> {
+        let answered = self.found.lock().await.len()
+            + self.not_found.lock().await.len();
+        if answered == self.hashes.len() {
+            self.handle.quit().await?;
+        }
+        Ok(())
+    }
+}
+#[async_trait]
+impl ReplicaTrait for HashReplica {
+    async fn on_open(&mut self) -> Result<Vec<Request>> {
+        for hash in self.hashes.clone() {
+            self.handle.request_by_hash(&self.public, &hash).await?;
+        }
+        Ok(vec![])
+    }
+    async fn on_request(&mut self, _request: Request)
+        -> Result<Option<DataOrRequest>>
+    {
+        Ok(None)
+    }
+    async fn on_data(&mut self, data: Data) -> Result<Vec<Request>> {
+        self.found.lock().await.push(data);
+        self.answered().await?;
+        Ok(vec![])
+    }
+    async fn on_not_found(&mut self, msg: NotFound) -> Result<()> {
+        self.not_found.lock().await.push(msg.hash);
+        self.answered().await
+    }
+    async fn on_close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+async fn replication_request_by_hash(
+    mut a: Core<RandomAccessMemory, RandomAccessMemory, RandomAccessMemory>)
+    -> Result<(Vec<Data>, Vec<Vec<u8>>)>
+{
+    let public = *a.public_key();
+    for data in [&b"hello"[..], b"world", b"mundo"] {
+        a.append(data, None).await?;
+    }
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    let found = Arc::new(Mutex::new(vec![]));
+    let not_found = Arc::new(Mutex::new(vec![]));
+    let b_replica = Box::new(HashReplica {
+        public,
+        hashes: vec![Hash::from_leaf(b"world"), Hash::from_leaf(b"other")],
+        handle: b_handle.clone(),
+        found: Arc::clone(&found),
+        not_found: Arc::clone(&not_found),
+    });
+    let (a_result, b_result) = zip(
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await
+        })
+    ).await;
+    a_result?;
+    assert!(matches!(b_result?, StopReason::Quit));
+
+    let found = found.lock().await.clone();
+    let not_found = not_found.lock().await.clone();
+    Ok((found, not_found))
+}
+
+#[test]
+async fn replication_request_by_hash_no_index() -> Result<()>
+{
+    let (found, not_found) =
+        replication_request_by_hash(new_core().await?).await?;
+    assert!(found.is_empty());
+    assert_eq!(not_found, vec![
+        Hash::from_leaf(b"world").as_bytes().to_vec(),
+        Hash::from_leaf(b"other").as_bytes().to_vec(),
+    ]);
+    Ok(())
+}
+
+#[test]
+async fn replication_request_by_hash_index() -> Result<()>
+{
+    let keypair = generate_keypair();
+    let a = Core::new_with_index(
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        random_access_memory(),
+        keypair.public, Some(keypair.secret),
+        CoreOptions::default())
+        .await?;
+    assert!(a.has_index());
+    let (found, not_found) = replication_request_by_hash(a).await?;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].index, 1);
+    assert_eq!(found[0].data, b"world");
+    assert_eq!(not_found, vec![Hash::from_leaf(b"other").as_bytes().to_vec()]);
+    Ok(())
+}
+
+#[cfg(feature = "unsigned")]
+#[test]
+async fn replication_core_replica_unsigned() -> Result<()>
+{
+    let mut a = new_core().await?;
+    let public = a.public_key().clone();
+    let b = new_replica(public.clone()).await?;
+    a.append_unsigned(b"hello world").await?;
+
+    let a_replica = Box::new(CoreReplica::new(Arc::new(Mutex::new(a))));
+    let b = Arc::new(Mutex::new(b));
+    let b_replica = Box::new(CoreReplica::new(Arc::clone(&b)));
+
+    let ((a_replication, mut a_handle),
+         (b_replication, mut b_handle)) =
+        create_replication_pair_memory().await;
+    let (a_result, _) = zip(
+        task::spawn(async move {
+            a_handle.open(&public, a_replica).await.unwrap();
+            a_replication.run().await
+        }),
+        task::spawn(async move {
+            b_handle.open(&public, b_replica).await.unwrap();
+            b_replication.run().await
+        })
+    ).await;
+    assert!(a_result.is_err());
+    assert_eq!(b.lock().await.len(), 0);
     Ok(())
 }
@@ -1,9 +1,16 @@
 use anyhow::{Result, anyhow};
 use std::io::{Error, ErrorKind};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use hex;
 
-use crate::{Key, DiscoveryKey, discovery_key};
+use crate::{Key, DiscoveryKey, Message, discovery_key};
+use crate::message::ChannelMessage;
+use crate::main::CHANNEL_CAP;
+
+/// Largest local channel id, ids are allocated from 1 up to it.
+/// Keeps channel ids far from overflowing the message header,
+/// which shifts them left by 4 bits for the message type.
+pub const MAX_LOCAL_ID: usize = CHANNEL_CAP;
 
 #[inline]
 fn error<T>(kind: ErrorKind, msg: &str) -> Result<T> {
@@ -20,6 +27,21 @@ struct LocalState {
 struct RemoteState {
     remote_id: usize,
     remote_capability: Option<Vec<u8>>,
+    receive_only: bool,
+    length: Option<u32>,
+}
+
+/// Credit based flow control state of a channel.
+#[derive(Clone, Debug, Default)]
+struct FlowState {
+    /// Data messages the remote allows us to send, `None` for no limit.
+    send_credit: Option<u32>,
+    /// Outbound messages waiting for credit, kept in order.
+    blocked: VecDeque<ChannelMessage>,
+    /// Data messages consumed since the remote was last granted credit.
+    consumed: u32,
+    /// Data messages the remote may still send us.
+    recv_credit: u32,
 }
 
 /// The handle for a channel that lives with the main Protocol.
@@ -28,6 +50,7 @@ pub struct ChannelHandle {
     discovery_key: DiscoveryKey,
     local_state: Option<LocalState>,
     remote_state: Option<RemoteState>,
+    flow: FlowState,
 }
 
 impl ChannelHandle {
@@ -37,6 +60,7 @@ impl ChannelHandle {
             discovery_key,
             local_state: None,
             remote_state: None,
+            flow: FlowState::default(),
         }
     }
     #[inline]
@@ -52,10 +76,12 @@ impl ChannelHandle {
         remote_id: usize,
         discovery_key: DiscoveryKey,
         remote_capability: Option<Vec<u8>>,
+        receive_only: bool,
+        length: Option<u32>,
         ) -> Self
     {
         let mut this = Self::new(discovery_key);
-        this.attach_remote(remote_id, remote_capability);
+        this.attach_remote(remote_id, remote_capability, receive_only, length);
         this
     }
 
@@ -66,11 +92,18 @@ impl ChannelHandle {
     }
     #[inline]
     pub fn attach_remote(
-        &mut self, remote_id: usize, remote_capability: Option<Vec<u8>>)
+        &mut self,
+        remote_id: usize,
+        remote_capability: Option<Vec<u8>>,
+        receive_only: bool,
+        length: Option<u32>,
+        )
     {
         let remote_state = RemoteState {
             remote_id,
             remote_capability,
+            receive_only,
+            length,
         };
         self.remote_state = Some(remote_state);
     }
@@ -88,6 +121,20 @@ impl ChannelHandle {
         self.remote_state.as_ref().map(|s| s.remote_id)
     }
 
+    /// Check if the remote opened the channel receive-only,
+    /// it does not serve data.
+    #[inline]
+    pub fn is_remote_receive_only(&self) -> bool {
+        self.remote_state.as_ref().is_some_and(|s| s.receive_only)
+    }
+
+    /// Length of the feed the remote advertised when opening the channel,
+    /// if it did.
+    #[inline]
+    pub fn remote_length(&self) -> Option<u32> {
+        self.remote_state.as_ref().and_then(|s| s.length)
+    }
+
     #[inline]
     pub fn is_connected(&self) -> bool {
         self.local_state.is_some() && self.remote_state.is_some()
@@ -105,6 +152,86 @@ impl ChannelHandle {
         let remote_state = self.remote_state.as_ref().unwrap();
         Ok((&local_state.key, remote_state.remote_capability.as_ref()))
     }
+
+    /// Pass an outbound message through flow control.
+    /// Returns the message if it may be sent now, otherwise keeps it
+    /// until [ChannelHandle::add_credit] allows it.
+    pub fn take_credit(&mut self, message: ChannelMessage)
+        -> Option<ChannelMessage>
+    {
+        if !self.flow.blocked.is_empty() {
+            self.flow.blocked.push_back(message);
+            return None
+        }
+        if let Message::Data(_) = message.message {
+            match self.flow.send_credit {
+                Some(0) => {
+                    self.flow.blocked.push_back(message);
+                    return None
+                },
+                Some(ref mut credit) => *credit -= 1,
+                None => {},
+            }
+        }
+        Some(message)
+    }
+    /// Add credit granted by the remote.
+    /// Returns the blocked messages which may be sent now.
+    pub fn add_credit(&mut self, credit: u32) -> Vec<ChannelMessage> {
+        let mut send_credit = self.flow.send_credit
+            .unwrap_or(0)
+            .saturating_add(credit);
+        let mut ready = vec![];
+        while let Some(message) = self.flow.blocked.front() {
+            if let Message::Data(_) = message.message {
+                if send_credit == 0 {
+                    break
+                }
+                send_credit -= 1;
+            }
+            ready.extend(self.flow.blocked.pop_front());
+        }
+        self.flow.send_credit = Some(send_credit);
+        ready
+    }
+    /// Drop blocked Data for the block at `index`,
+    /// the remote cancelled its request.
+    pub fn cancel_data(&mut self, index: u32) {
+        self.flow.blocked.retain(|message| !matches!(
+            &message.message, Message::Data(data) if data.index == index));
+    }
+    /// Number of outbound messages waiting for credit.
+    #[cfg(test)]
+    pub fn blocked_len(&self) -> usize {
+        self.flow.blocked.len()
+    }
+    /// Record credit granted to the remote.
+    pub fn grant_credit(&mut self, credit: u32) {
+        self.flow.recv_credit = self.flow.recv_credit.saturating_add(credit);
+    }
+    /// Record an inbound Data message.
+    /// Returns false if the remote had no credit left to send it.
+    pub fn use_credit(&mut self) -> bool {
+        match self.flow.recv_credit {
+            0 => false,
+            _ => {
+                self.flow.recv_credit -= 1;
+                true
+            },
+        }
+    }
+    /// Record a consumed Data message.
+    /// Returns credit to grant the remote, once at least `threshold`
+    /// messages were consumed.
+    pub fn consume_credit(&mut self, threshold: u32) -> Option<u32> {
+        self.flow.consumed += 1;
+        if self.flow.consumed < threshold {
+            return None
+        }
+        let credit = self.flow.consumed;
+        self.flow.consumed = 0;
+        Some(credit)
+    }
 }
 
 /// The ChannelMap maintains a list of open channels
@@ -127,10 +254,11 @@ impl ChannelMap {
         }
     }
 
-    pub fn attach_local(&mut self, key: Key) -> &ChannelHandle {
+    /// Fails if all local ids up to [MAX_LOCAL_ID] are in use.
+    pub fn attach_local(&mut self, key: Key) -> Result<&ChannelHandle> {
         let discovery_key = discovery_key(&key);
         let discovery_key_hex = hex::encode(&discovery_key);
-        let local_id = self.alloc_local();
+        let local_id = self.alloc_local()?;
 
         self.channels
             .entry(discovery_key_hex.clone())
@@ -139,7 +267,7 @@ impl ChannelMap {
                 || ChannelHandle::new_local(local_id, discovery_key, key));
 
         self.local_id[local_id] = Some(discovery_key_hex.clone());
-        self.channels.get(&discovery_key_hex).unwrap()
+        Ok(self.channels.get(&discovery_key_hex).unwrap())
     }
 
     pub fn attach_remote(
@@ -147,19 +275,30 @@ impl ChannelMap {
         discovery_key: DiscoveryKey,
         remote_id: usize,
         remote_capability: Option<Vec<u8>>,
+        receive_only: bool,
+        length: Option<u32>,
         ) -> &ChannelHandle
     {
         let discovery_key_hex = hex::encode(&discovery_key);
         self.alloc_remote(remote_id);
 
+        // Forget a previous remote id of the channel.
+        let previous_id = self.channels.get(&discovery_key_hex)
+            .and_then(|channel| channel.remote_id());
+        if let Some(previous_id) = previous_id {
+            self.remote_id[previous_id] = None;
+        }
+
         self.channels
             .entry(discovery_key_hex.clone())
             .and_modify(
                 |channel| channel.attach_remote(
-                    remote_id, remote_capability.clone()))
+                    remote_id, remote_capability.clone(), receive_only,
+                    length))
             .or_insert_with(
                 || ChannelHandle::new_remote(
-                    remote_id, discovery_key, remote_capability));
+                    remote_id, discovery_key, remote_capability,
+                    receive_only, length));
 
         self.remote_id[remote_id] = Some(discovery_key_hex.clone());
         self.channels.get(&discovery_key_hex).unwrap()
@@ -169,6 +308,12 @@ impl ChannelMap {
         let discovery_key_hex = hex::encode(&discovery_key);
         self.channels.get(&discovery_key_hex)
     }
+    pub fn get_mut(&mut self, discovery_key: &DiscoveryKey)
+        -> Option<&mut ChannelHandle>
+    {
+        let discovery_key_hex = hex::encode(discovery_key);
+        self.channels.get_mut(&discovery_key_hex)
+    }
     pub fn get_remote(&self, remote_id: usize) -> Option<&ChannelHandle> {
         if let Some(Some(discovery_key_hex)) =
             self.remote_id.get(remote_id).as_ref()
@@ -178,6 +323,17 @@ impl ChannelMap {
             None
         }
     }
+    pub fn get_remote_mut(&mut self, remote_id: usize)
+        -> Option<&mut ChannelHandle>
+    {
+        if let Some(Some(discovery_key_hex)) =
+            self.remote_id.get(remote_id).as_ref()
+        {
+            self.channels.get_mut(discovery_key_hex)
+        } else {
+            None
+        }
+    }
     pub fn get_local(&self, local_id: usize) -> Option<&ChannelHandle> {
         if let Some(Some(discovery_key_hex)) =
             self.local_id.get(local_id).as_ref()
@@ -187,6 +343,22 @@ impl ChannelMap {
             None
         }
     }
+    pub fn get_local_mut(&mut self, local_id: usize)
+        -> Option<&mut ChannelHandle>
+    {
+        if let Some(Some(discovery_key_hex)) =
+            self.local_id.get(local_id).as_ref()
+        {
+            self.channels.get_mut(discovery_key_hex)
+        } else {
+            None
+        }
+    }
+
+    /// Check if any channel has outbound messages waiting for credit.
+    pub fn has_blocked(&self) -> bool {
+        self.channels.values().any(|channel| !channel.flow.blocked.is_empty())
+    }
 
     pub fn remove(&mut self, discovery_key: &[u8]) {
         let discovery_key_hex = hex::encode(discovery_key);
@@ -212,14 +384,18 @@ impl ChannelMap {
         channel_handle.prepare_to_verify()
     }
 
-    fn alloc_local(&mut self) -> usize {
+    fn alloc_local(&mut self) -> Result<usize> {
         let empty_id = self.local_id
             .iter().skip(1).position(|x| x.is_none());
         match empty_id {
-            Some(empty_id) => empty_id,
+            // skipped the reserved id 0
+            Some(empty_id) => Ok(empty_id + 1),
+            None if self.local_id.len() > MAX_LOCAL_ID => error(
+                ErrorKind::Other,
+                "No free local channel id, too many channels open"),
             None => {
                 self.local_id.push(None);
-                self.local_id.len() - 1
+                Ok(self.local_id.len() - 1)
             }
         }
     }
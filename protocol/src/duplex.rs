@@ -1,4 +1,4 @@
-use futures_lite::{AsyncRead, AsyncWrite};
+use futures_lite::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use std::io;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -26,6 +26,16 @@ where
     pub fn new(reader: R, writer: W) -> Self {
         Self { reader, writer }
     }
+
+    /// Close the writer and drop the reader, tearing down both halves.
+    ///
+    /// The peer reads EOF once the written data is flushed.
+    /// `Protocol` and `Replication` own their stream and drop it when
+    /// they end, which does not flush the writer, so close a `Duplex`
+    /// you still hold, or one taken back after the protocol finished.
+    pub async fn close(mut self) -> io::Result<()> {
+        self.writer.close().await
+    }
 }
 
 impl<R, W> AsyncRead for Duplex<R, W>
@@ -63,3 +73,29 @@ where
         Pin::new(&mut self.writer).poll_close(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::io::AsyncReadExt;
+    use futures_lite::future::{block_on, zip};
+    use sluice::pipe::pipe;
+
+    #[test]
+    fn close_unblocks_peer_read() {
+        block_on(async {
+            let (ar, bw) = pipe();
+            let (br, aw) = pipe();
+            let a = Duplex::new(ar, aw);
+            let mut b = Duplex::new(br, bw);
+
+            let read = async {
+                let mut buf = [0u8; 1];
+                b.read(&mut buf).await.unwrap()
+            };
+            let (read, closed) = zip(read, a.close()).await;
+            closed.unwrap();
+            assert_eq!(read, 0);
+        })
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::{Error, ErrorKind, Result};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use futures_lite::{ready, Stream};
+use futures_sink::Sink;
+
+use crate::message::{ChannelMessage, Frame};
+use super::Transport;
+use super::reader::ReadState;
+use super::writer::WriteState;
+
+/// [Transport] over a [Sink] and a [Stream] of already framed
+/// [ChannelMessage]s, for message oriented transports
+/// (a WebRTC data channel, an in-process message bus, ...).
+///
+/// Messages bypass the byte framing and the transport encryption:
+/// there is no noise handshake, no encryption and no capability
+/// verification, so the transport must provide its own security.
+/// Keepalive timeouts are not enforced either;
+/// the end of the `stream` is reported as [ErrorKind::ConnectionAborted].
+/// The [Options] must have `noise` and `encrypted` disabled.
+///
+/// [Options]: crate::Options
+#[derive(Debug)]
+pub struct MessageIo<Si, St> {
+    sink: Si,
+    stream: St,
+    flushing: bool,
+}
+
+impl<Si, St> MessageIo<Si, St> {
+    /// Create a new [MessageIo] sending to `sink`
+    /// and receiving from `stream`.
+    pub fn new(sink: Si, stream: St) -> Self {
+        Self {
+            sink,
+            stream,
+            flushing: false,
+        }
+    }
+}
+
+impl<Si, St> Transport for MessageIo<Si, St>
+where
+    Si: Sink<ChannelMessage, Error = Error> + Send + Unpin + 'static,
+    St: Stream<Item = Result<ChannelMessage>> + Send + Unpin + 'static,
+{
+    fn poll_read_frame(
+        &mut self,
+        cx: &mut Context<'_>,
+        _read_state: &mut ReadState,
+        ) -> Poll<Result<Frame>>
+    {
+        match Pin::new(&mut self.stream).poll_next(cx) {
+            Poll::Ready(Some(Ok(message))) =>
+                Poll::Ready(Ok(Frame::Message(message))),
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Err(err)),
+            Poll::Ready(None) => Poll::Ready(Err(Error::new(
+                ErrorKind::ConnectionAborted, "Message stream closed"))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_write_frames(
+        &mut self,
+        cx: &mut Context<'_>,
+        write_state: &mut WriteState,
+        ) -> Poll<Result<()>>
+    {
+        while write_state.has_frame() {
+            ready!(Pin::new(&mut self.sink).poll_ready(cx))?;
+            match write_state.pop_frame() {
+                Some(Frame::Message(message)) => {
+                    Pin::new(&mut self.sink).start_send(message)?;
+                    self.flushing = true;
+                },
+                Some(Frame::Raw(_)) =>
+                    unreachable!("Raw frames are only sent with noise"),
+                None => {},
+            }
+        }
+        if self.flushing {
+            ready!(Pin::new(&mut self.sink).poll_flush(cx))?;
+            self.flushing = false;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn is_flushed(&self) -> bool {
+        !self.flushing
+    }
+
+    fn is_framed() -> bool {
+        true
+    }
+}
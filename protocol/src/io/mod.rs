@@ -1,7 +1,9 @@
 mod reader;
 mod writer;
+mod message_io;
 
 use anyhow::{Result, anyhow};
+use std::io;
 use std::task::{Context, Poll};
 use futures_lite::io::{AsyncRead, AsyncWrite};
 
@@ -10,6 +12,63 @@ use crate::message::{Frame, EncodeError};
 use self::reader::ReadState;
 use self::writer::WriteState;
 
+pub use self::message_io::MessageIo;
+pub use self::reader::{FrameStats, FRAME_SIZE_BUCKETS};
+
+/// Transport of a [Protocol]: any `AsyncRead + AsyncWrite` byte stream,
+/// or a [MessageIo] of already framed messages.
+///
+/// [Protocol]: crate::Protocol
+pub trait Transport: Send + Unpin + 'static {
+    /// Poll for the next inbound [Frame].
+    #[doc(hidden)]
+    fn poll_read_frame(
+        &mut self,
+        cx: &mut Context<'_>,
+        read_state: &mut ReadState,
+        ) -> Poll<io::Result<Frame>>;
+    /// Poll to write out the frames queued in `write_state`.
+    #[doc(hidden)]
+    fn poll_write_frames(
+        &mut self,
+        cx: &mut Context<'_>,
+        write_state: &mut WriteState,
+        ) -> Poll<io::Result<()>>;
+    /// Check if everything written is flushed.
+    #[doc(hidden)]
+    fn is_flushed(&self) -> bool {
+        true
+    }
+    /// Check if the transport carries frames itself,
+    /// bypassing the byte framing and encryption.
+    #[doc(hidden)]
+    fn is_framed() -> bool {
+        false
+    }
+}
+
+impl<T> Transport for T
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    fn poll_read_frame(
+        &mut self,
+        cx: &mut Context<'_>,
+        read_state: &mut ReadState,
+        ) -> Poll<io::Result<Frame>>
+    {
+        read_state.poll_reader(cx, self)
+    }
+    fn poll_write_frames(
+        &mut self,
+        cx: &mut Context<'_>,
+        write_state: &mut WriteState,
+        ) -> Poll<io::Result<()>>
+    {
+        write_state.poll_send(cx, self)
+    }
+}
+
 #[derive(Debug)]
 pub struct IO<T> {
     io: T,
@@ -20,25 +79,54 @@ pub struct IO<T> {
 
 impl<T> IO<T>
 where
-    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    T: Transport,
 {
     pub fn new(io: T, options: Options) -> Self {
-        let keepalive_ms = options.keepalive_ms;
+        let read_state = match T::is_framed() {
+            true => ReadState::unbuffered(),
+            false => ReadState::new(
+                options.keepalive_ms,
+                options.max_message_size,
+                options.read_buf_size),
+        };
         Self {
             io,
             options,
-            read_state: ReadState::new(keepalive_ms),
+            read_state,
             write_state: WriteState::new(),
         }
     }
 
+    /// Check the [Options] are supported by the transport.
+    pub fn check_options(&self) -> Result<()> {
+        if !cfg!(feature = "noise")
+            && (self.options.noise || self.options.encrypted)
+        {
+            return Err(anyhow!(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Compiled without the noise feature, \
+                 noise and encryption are not supported")))
+        }
+        if T::is_framed() && (self.options.noise || self.options.encrypted) {
+            return Err(anyhow!(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Framed transport does not support noise or encryption")))
+        }
+        if self.options.data_credit == Some(0) {
+            return Err(anyhow!(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Data credit must be at least 1")))
+        }
+        Ok(())
+    }
+
     /// Poll for inbound messages and process them.
     pub fn poll_inbound_read(
         &mut self,
         cx: &mut Context<'_>,
         ) -> Result<Option<Frame>>
     {
-        let msg = self.read_state.poll_reader(cx, &mut self.io);
+        let msg = self.io.poll_read_frame(cx, &mut self.read_state);
         return match msg {
             Poll::Ready(Ok(message)) => Ok(Some(message)),
             Poll::Ready(Err(e)) => Err(anyhow!(e)),
@@ -49,13 +137,18 @@ where
     /// Poll for outbound messages and write them.
     pub fn poll_outbound_write(&mut self, cx: &mut Context<'_>) -> Result<()>
     {
-        let poll = self.write_state.poll_send(cx, &mut self.io);
+        let poll = self.io.poll_write_frames(cx, &mut self.write_state);
         if let Poll::Ready(Err(e)) = poll {
             return Err(anyhow!(e));
         }
         return Ok(());
     }
 
+    /// Check if all queued frames are written and flushed.
+    pub fn is_idle(&self) -> bool {
+        self.write_state.is_idle() && self.io.is_flushed()
+    }
+
     pub fn queue_frame_direct(&mut self, body: Vec<u8>)
         -> std::result::Result<bool, EncodeError>
     {
@@ -10,7 +10,43 @@ use crate::message::{Frame, FrameType};
 use crate::noise::{Cipher, HandshakeResult};
 use crate::MAX_MESSAGE_SIZE;
 
-const READ_BUF_INITIAL_SIZE: usize = 1024 * 128;
+/// Longest varint header of a frame.
+const MAX_HEADER_LEN: usize = 10;
+
+/// Upper bounds (inclusive, in bytes) of the [FrameStats::sizes] buckets.
+/// The last bucket goes up to [MAX_MESSAGE_SIZE].
+pub const FRAME_SIZE_BUCKETS: [usize; 6] = [
+    64,
+    1024,
+    16 * 1024,
+    256 * 1024,
+    1024 * 1024,
+    MAX_MESSAGE_SIZE as usize,
+];
+
+/// Counts of inbound frames read from a byte stream,
+/// see [Protocol::frame_stats].
+///
+/// Frames delivered by a [MessageIo] are not counted.
+///
+/// [Protocol::frame_stats]: crate::Protocol::frame_stats
+/// [MessageIo]: crate::MessageIo
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Frames read, by body size, bucketed by [FRAME_SIZE_BUCKETS].
+    pub sizes: [u64; FRAME_SIZE_BUCKETS.len()],
+    /// Frames rejected for declaring a length above the max message size.
+    pub rejected: u64,
+}
+
+impl FrameStats {
+    fn record(&mut self, body_len: usize) {
+        let bucket = FRAME_SIZE_BUCKETS.iter()
+            .position(|&bound| body_len <= bound)
+            .unwrap_or(FRAME_SIZE_BUCKETS.len() - 1);
+        self.sizes[bucket] += 1;
+    }
+}
 
 #[derive(Debug)]
 pub struct ReadState {
@@ -30,13 +66,25 @@ pub struct ReadState {
     cipher: Option<Cipher>,
     /// The frame type to be passed to the decoder.
     frame_type: FrameType,
+    /// Maximum accepted message size.
+    max_message_size: usize,
+    /// Size to shrink the read buffer back to.
+    initial_size: usize,
+    /// Inbound frame counts.
+    stats: FrameStats,
 }
 
 impl ReadState {
-    pub fn new(timeout_ms: Option<u64>) -> Self {
+    pub fn new(
+        timeout_ms: Option<u64>,
+        max_message_size: u64,
+        read_buf_size: usize,
+        ) -> Self
+    {
         let timeout_duration = timeout_ms.map(Duration::from_millis);
+        let initial_size = read_buf_size.max(1);
         Self {
-            buf: vec![0u8; READ_BUF_INITIAL_SIZE as usize],
+            buf: vec![0u8; initial_size],
             start: 0,
             end: 0,
             step: Step::Header,
@@ -44,6 +92,27 @@ impl ReadState {
             timeout_duration,
             cipher: None,
             frame_type: FrameType::Raw,
+            max_message_size: max_message_size.min(MAX_MESSAGE_SIZE) as usize,
+            initial_size,
+            stats: FrameStats::default(),
+        }
+    }
+
+    /// Create without a read buffer,
+    /// for transports delivering frames directly.
+    pub fn unbuffered() -> Self {
+        Self {
+            buf: vec![],
+            start: 0,
+            end: 0,
+            step: Step::Header,
+            timeout: None,
+            timeout_duration: None,
+            cipher: None,
+            frame_type: FrameType::Raw,
+            max_message_size: MAX_MESSAGE_SIZE as usize,
+            initial_size: 0,
+            stats: FrameStats::default(),
         }
     }
 }
@@ -66,6 +135,10 @@ impl ReadState {
         self.frame_type = frame_type;
     }
 
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
     pub fn poll_reader<R>(
         &mut self,
         cx: &mut Context<'_>,
@@ -123,19 +196,45 @@ impl ReadState {
         }
     }
 
+    fn shrink_buf_if_needed(&mut self) {
+        // Release the memory of an oversized frame once it is processed.
+        let len = self.end - self.start;
+        if self.buf.len() > self.initial_size && len <= self.initial_size {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.buf.truncate(self.initial_size);
+            self.buf.shrink_to_fit();
+            self.end = len;
+            self.start = 0;
+        }
+    }
+
     fn process(&mut self) -> Option<Result<Frame>> {
         if self.start == self.end {
+            // Nothing to keep, read into the whole buffer again.
+            self.start = 0;
+            self.end = 0;
             return None;
         }
         loop {
             match self.step {
                 Step::Header => {
+                    // Wait for the rest of a header split across reads.
+                    let available = &self.buf[self.start..self.end];
+                    let complete = available.iter().any(|byte| byte & 0x80 == 0);
+                    if !complete && available.len() < MAX_HEADER_LEN {
+                        self.cycle_buf_if_needed();
+                        if self.end == self.buf.len() {
+                            self.buf.resize(MAX_HEADER_LEN, 0u8);
+                        }
+                        return None;
+                    }
                     let mut body_len = 0;
                     let header_len = varinteger::decode(
                         &self.buf[self.start..self.end], &mut body_len);
 
                     let body_len = body_len as usize;
-                    if body_len > MAX_MESSAGE_SIZE as usize {
+                    if body_len > self.max_message_size {
+                        self.stats.rejected += 1;
                         return Some(Err(Error::new(
                             ErrorKind::InvalidData,
                             "Message length above max allowed size",
@@ -157,11 +256,22 @@ impl ReadState {
                     if (self.end - self.start) < message_len {
                         self.cycle_buf_if_needed();
                         return None;
+                    } else if body_len == 0
+                        && matches!(self.frame_type, FrameType::Message)
+                    {
+                        // Keepalive frame, nothing to deliver.
+                        self.start += message_len;
+                        self.step = Step::Header;
+                        if self.start == self.end {
+                            return None;
+                        }
                     } else {
                         let range = self.start + header_len..self.start + message_len;
                         let frame = Frame::decode(&self.buf[range], &self.frame_type);
+                        self.stats.record(body_len);
                         self.start += message_len;
                         self.step = Step::Header;
+                        self.shrink_buf_if_needed();
                         return Some(frame);
                     }
                 }
@@ -169,3 +279,115 @@ impl ReadState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::future::poll_fn;
+    use futures_lite::io::Cursor;
+    use crate::message::{ChannelMessage, Encoder};
+    use crate::schema::Cancel;
+    use crate::Message;
+    use crate::options::DEFAULT_READ_BUF_SIZE;
+
+    fn encode(frame: &Frame) -> Vec<u8> {
+        let mut buf = vec![0u8; frame.encoded_len()];
+        frame.encode(&mut buf).unwrap();
+        buf
+    }
+
+    async fn read(state: &mut ReadState, reader: &mut Cursor<Vec<u8>>)
+        -> Result<Frame>
+    {
+        poll_fn(|cx| state.poll_reader(cx, reader)).await
+    }
+
+    #[async_std::test]
+    async fn shrink_after_large_frame() -> Result<()> {
+        let large = Frame::Raw(vec![1u8; DEFAULT_READ_BUF_SIZE * 4]);
+        let small = Frame::Raw(vec![2u8; 16]);
+        let mut bytes = encode(&large);
+        bytes.extend(encode(&small));
+        let mut reader = Cursor::new(bytes);
+
+        let mut state = ReadState::new(None, MAX_MESSAGE_SIZE, DEFAULT_READ_BUF_SIZE);
+        assert_eq!(read(&mut state, &mut reader).await?, large);
+        assert_eq!(state.buf.len(), DEFAULT_READ_BUF_SIZE);
+        assert_eq!(read(&mut state, &mut reader).await?, small);
+        assert_eq!(state.buf.len(), DEFAULT_READ_BUF_SIZE);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn grow_small_buffer() -> Result<()> {
+        let frames = vec![
+            Frame::Raw(vec![1u8; 1000]),
+            Frame::Raw(vec![2u8; 300]),
+            Frame::Raw(vec![3u8; 2]),
+        ];
+        let bytes = frames.iter().flat_map(encode).collect();
+        let mut reader = Cursor::new(bytes);
+
+        let mut state = ReadState::new(None, MAX_MESSAGE_SIZE, 1);
+        for frame in frames {
+            assert_eq!(read(&mut state, &mut reader).await?, frame);
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn skip_keepalive_frames() -> Result<()> {
+        let keepalive = encode(&Frame::Raw(vec![]));
+        let message = Frame::Message(ChannelMessage::new(
+            1, Message::Cancel(Cancel { index: 3 })));
+        let mut bytes = keepalive.clone();
+        bytes.extend(&keepalive);
+        bytes.extend(encode(&message));
+        let mut reader = Cursor::new(bytes);
+
+        let mut state = ReadState::new(None, MAX_MESSAGE_SIZE, DEFAULT_READ_BUF_SIZE);
+        state.set_frame_type(FrameType::Message);
+        assert_eq!(read(&mut state, &mut reader).await?, message);
+        assert_eq!(state.stats().sizes.iter().sum::<u64>(), 1);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reject_above_max_message_size() -> Result<()> {
+        let frame = Frame::Raw(vec![1u8; 1024]);
+        let mut reader = Cursor::new(encode(&frame));
+
+        let mut state = ReadState::new(None, 512, DEFAULT_READ_BUF_SIZE);
+        let error = read(&mut state, &mut reader).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(state.stats().rejected, 1);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn frame_stats() -> Result<()> {
+        let frames = vec![
+            Frame::Raw(vec![1u8; 64]),
+            Frame::Raw(vec![2u8; 65]),
+            Frame::Raw(vec![3u8; 2]),
+            Frame::Raw(vec![4u8; 300 * 1024]),
+        ];
+        let mut bytes: Vec<u8> = frames.iter().flat_map(encode).collect();
+        // declare a length above the max, without a body
+        let mut header = vec![0u8; MAX_HEADER_LEN];
+        let n = varinteger::encode(MAX_MESSAGE_SIZE + 1, &mut header);
+        bytes.extend(&header[..n]);
+        let mut reader = Cursor::new(bytes);
+
+        let mut state = ReadState::new(None, MAX_MESSAGE_SIZE, DEFAULT_READ_BUF_SIZE);
+        for frame in frames {
+            assert_eq!(read(&mut state, &mut reader).await?, frame);
+        }
+        assert!(read(&mut state, &mut reader).await.is_err());
+        assert_eq!(*state.stats(), FrameStats {
+            sizes: [2, 1, 0, 0, 1, 0],
+            rejected: 1,
+        });
+        Ok(())
+    }
+}
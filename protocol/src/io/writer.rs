@@ -90,6 +90,24 @@ impl WriteState {
         }
     }
 
+    /// Check if a frame is waiting to be written.
+    pub fn has_frame(&self) -> bool {
+        self.current_frame.is_some() || !self.queue.is_empty()
+    }
+
+    /// Take the next frame to be written, bypassing the write buffer.
+    pub fn pop_frame(&mut self) -> Option<Frame> {
+        self.current_frame.take().or_else(|| self.queue.pop_front())
+    }
+
+    /// Check if all queued frames are written and flushed.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.step, Step::Processing)
+            && self.queue.is_empty()
+            && self.current_frame.is_none()
+            && self.pending() == 0
+    }
+
     fn advance(&mut self, n: usize) {
         let end = self.end + n;
         if let Some(ref mut cipher) = self.cipher {
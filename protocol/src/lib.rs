@@ -7,20 +7,34 @@
 
 //! Replication protocol for hypercore feeds.
 
+#[macro_use]
+mod logging;
 mod options;
 mod channels;
 mod duplex;
+mod metered;
 mod message;
 mod io;
 mod util;
+#[cfg(feature = "noise")]
 mod noise;
+#[cfg(not(feature = "noise"))]
+mod noise_disabled;
+#[cfg(not(feature = "noise"))]
+use noise_disabled as noise;
 mod protocol;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 
 /// The wire messages used by the protocol.
+#[cfg(not(feature = "manual-schema"))]
 #[allow(missing_docs)]
 pub mod schema {
     include!(concat!(env!("OUT_DIR"), "/hypercore.schema.rs"));
 }
+/// The wire messages used by the protocol.
+#[cfg(feature = "manual-schema")]
+pub mod schema;
 
 /// Maximum size of a `Message`.
 // 4MB is the max wire message size (will be much smaller usually).
@@ -35,8 +49,10 @@ pub type DiscoveryKey = [u8; 32];
 
 pub use options::Options;
 pub use duplex::Duplex;
-pub use message::Message;
-pub use util::discovery_key;
+pub use metered::{MeteredStream, Meter};
+pub use message::{Message, ChannelMessage};
+pub use io::{Transport, MessageIo, FrameStats, FRAME_SIZE_BUCKETS};
+pub use util::{discovery_key, discovery_key_ns};
 pub use crate::protocol::{
     new_protocol, new_protocol_with_defaults,
     Protocol, handshake, main,
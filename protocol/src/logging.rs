@@ -0,0 +1,16 @@
+//! Records through the [log](https://docs.rs/log) crate,
+//! compiled out unless the `log` feature is enabled.
+//!
+//! Without the feature the arguments are still type checked,
+//! but never evaluated.
+
+macro_rules! log_debug {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::debug!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
@@ -1,7 +1,6 @@
 use super::schema::*;
 use super::MAX_MESSAGE_SIZE;
 
-use prost::Message as _;
 use std::fmt;
 use std::io;
 use hex;
@@ -23,7 +22,7 @@ impl fmt::Display for EncodeError {
 }
 
 impl EncodeError {
-    fn new(required: usize) -> Self {
+    pub(crate) fn new(required: usize) -> Self {
         Self { required }
     }
 }
@@ -40,10 +39,41 @@ impl From<EncodeError> for io::Error {
     }
 }
 
+/// Wire encoding of the [schema](crate::schema) messages.
+///
+/// Generated by `prost`, or written by hand with the `manual-schema` feature.
+pub(crate) trait SchemaMessage: Sized {
+    /// Calculates the length of the encoded message.
+    fn schema_len(&self) -> usize;
+
+    /// Encodes the message to a buffer.
+    fn schema_encode(&self, buf: &mut [u8]) -> Result<usize, EncodeError>;
+
+    /// Decodes a message from a buffer.
+    fn schema_decode(buf: &[u8]) -> io::Result<Self>;
+}
+
+#[cfg(not(feature = "manual-schema"))]
+impl<T: prost::Message + Default> SchemaMessage for T {
+    fn schema_len(&self) -> usize {
+        self.encoded_len()
+    }
+
+    fn schema_encode(&self, mut buf: &mut [u8]) -> Result<usize, EncodeError> {
+        let len = self.encoded_len();
+        self.encode(&mut buf)?;
+        Ok(len)
+    }
+
+    fn schema_decode(buf: &[u8]) -> io::Result<Self> {
+        Ok(Self::decode(buf)?)
+    }
+}
+
 /// Encode data into a buffer.
 ///
 /// This trait is implemented on data frames and their components
-/// (channel messages, messages, and individual message types through prost).
+/// (channel messages, messages, and individual message types).
 pub trait Encoder: Sized + fmt::Debug {
     /// Calculates the length that the encoded message needs.
     fn encoded_len(&self) -> usize;
@@ -156,16 +186,31 @@ pub enum Message {
     Request(Request),
     /// Send a Data block.
     Data(Data),
+    /// Grant credit for more Data blocks.
+    Credit(Credit),
+    /// Request a block by the leaf hash of its data.
+    RequestByHash(RequestByHash),
+    /// No block for a [Message::RequestByHash].
+    NotFound(NotFound),
+    /// Cancel a [Message::Request].
+    Cancel(Cancel),
+    /// User-defined message, see [Extension].
+    Extension(Extension),
 }
 
 impl Message {
     /// Decode a message from a buffer.
     pub fn decode(buf: &[u8], typ: u64) -> io::Result<Self> {
         match typ {
-            0 => Ok(Self::Open(Open::decode(buf)?)),
-            1 => Ok(Self::Close(Close::decode(buf)?)),
-            2 => Ok(Self::Request(Request::decode(buf)?)),
-            3 => Ok(Self::Data(Data::decode(buf)?)),
+            0 => Ok(Self::Open(Open::schema_decode(buf)?)),
+            1 => Ok(Self::Close(Close::schema_decode(buf)?)),
+            2 => Ok(Self::Request(Request::schema_decode(buf)?)),
+            3 => Ok(Self::Data(Data::schema_decode(buf)?)),
+            4 => Ok(Self::Credit(Credit::schema_decode(buf)?)),
+            5 => Ok(Self::RequestByHash(RequestByHash::schema_decode(buf)?)),
+            6 => Ok(Self::NotFound(NotFound::schema_decode(buf)?)),
+            7 => Ok(Self::Cancel(Cancel::schema_decode(buf)?)),
+            8 => Ok(Self::Extension(Extension::schema_decode(buf)?)),
             _ => Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Invalid message type",
@@ -179,6 +224,11 @@ impl Message {
             Self::Close(_) => 1,
             Self::Request(_) => 2,
             Self::Data(_) => 3,
+            Self::Credit(_) => 4,
+            Self::RequestByHash(_) => 5,
+            Self::NotFound(_) => 6,
+            Self::Cancel(_) => 7,
+            Self::Extension(_) => 8,
         }
     }
 }
@@ -186,32 +236,33 @@ impl Message {
 impl Encoder for Message {
     fn encoded_len(&self) -> usize {
         match self {
-            Self::Open(ref message) => message.encoded_len(),
-            Self::Close(ref message) => message.encoded_len(),
-            Self::Request(ref message) => message.encoded_len(),
-            Self::Data(ref message) => message.encoded_len(),
+            Self::Open(ref message) => message.schema_len(),
+            Self::Close(ref message) => message.schema_len(),
+            Self::Request(ref message) => message.schema_len(),
+            Self::Data(ref message) => message.schema_len(),
+            Self::Credit(ref message) => message.schema_len(),
+            Self::RequestByHash(ref message) => message.schema_len(),
+            Self::NotFound(ref message) => message.schema_len(),
+            Self::Cancel(ref message) => message.schema_len(),
+            Self::Extension(ref message) => message.schema_len(),
         }
     }
 
     fn encode(&self, buf: &mut [u8]) -> Result<usize, EncodeError> {
         match self {
-            Self::Open(ref message) => encode_prost_message(message, buf),
-            Self::Close(ref message) => encode_prost_message(message, buf),
-            Self::Request(ref message) => encode_prost_message(message, buf),
-            Self::Data(ref message) => encode_prost_message(message, buf),
+            Self::Open(ref message) => message.schema_encode(buf),
+            Self::Close(ref message) => message.schema_encode(buf),
+            Self::Request(ref message) => message.schema_encode(buf),
+            Self::Data(ref message) => message.schema_encode(buf),
+            Self::Credit(ref message) => message.schema_encode(buf),
+            Self::RequestByHash(ref message) => message.schema_encode(buf),
+            Self::NotFound(ref message) => message.schema_encode(buf),
+            Self::Cancel(ref message) => message.schema_encode(buf),
+            Self::Extension(ref message) => message.schema_encode(buf),
         }
     }
 }
 
-fn encode_prost_message(
-    msg: &impl prost::Message,
-    mut buf: &mut [u8],
-) -> Result<usize, EncodeError> {
-    let len = msg.encoded_len();
-    msg.encode(&mut buf)?;
-    Ok(len)
-}
-
 impl fmt::Display for Message {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -239,6 +290,32 @@ impl fmt::Display for Message {
                 msg.data_signature.len(),
                 msg.tree_signature.len(),
             ),
+            Self::Credit(msg) => write!(
+                f,
+                "Credit(credit: {})",
+                msg.credit,
+            ),
+            Self::RequestByHash(msg) => write!(
+                f,
+                "RequestByHash(hash: {})",
+                hex::encode(&msg.hash),
+            ),
+            Self::NotFound(msg) => write!(
+                f,
+                "NotFound(hash: {})",
+                hex::encode(&msg.hash),
+            ),
+            Self::Cancel(msg) => write!(
+                f,
+                "Cancel(index: {})",
+                msg.index,
+            ),
+            Self::Extension(msg) => write!(
+                f,
+                "Extension(name: {}, payload: <{}>)",
+                msg.name,
+                msg.payload.len(),
+            ),
         }
     }
 }
@@ -246,7 +323,9 @@ impl fmt::Display for Message {
 /// A message on a channel.
 #[derive(Clone, PartialEq)]
 pub struct ChannelMessage {
+    /// Channel id, `0` for stream level messages.
     pub channel: u64,
+    /// The [Message].
     pub message: Message,
 }
 
@@ -338,24 +417,118 @@ mod tests {
         }
     }
 
+    macro_rules! schema_golden {
+        ($( $msg:expr => $hex:expr ),*) => {
+            $(
+                let msg = $msg;
+                let mut buf = vec![0u8; msg.schema_len()];
+                let n = msg.schema_encode(&mut buf)
+                    .expect("Failed to encode message");
+                assert_eq!(hex::encode(&buf[..n]), $hex);
+                let decoded = SchemaMessage::schema_decode(&buf[..n])
+                    .expect("Failed to decode message");
+                assert_eq!(msg, decoded);
+            )*
+        }
+    }
+
+    /// Bytes produced by prost, the `manual-schema` encoding has to match.
+    #[test]
+    fn schema_golden_bytes() {
+        schema_golden! {
+            NoisePayload { nonce: vec![7u8; 2] } => "0a020707",
+            Open {
+                discovery_key: vec![1u8; 4],
+                capability: Some(vec![2u8; 3]),
+                receive_only: None,
+                length: None,
+            } => "0a04010101011203020202",
+            Open {
+                discovery_key: vec![1u8; 4],
+                capability: None,
+                receive_only: None,
+                length: None,
+            } => "0a0401010101",
+            Open {
+                discovery_key: vec![1u8; 4],
+                capability: None,
+                receive_only: Some(true),
+                length: None,
+            } => "0a04010101011801",
+            Open {
+                discovery_key: vec![1u8; 4],
+                capability: None,
+                receive_only: None,
+                length: Some(300),
+            } => "0a040101010120ac02",
+            Close { discovery_key: vec![3u8; 2] } => "0a020303",
+            Request { index: 300, sparse: Some(true), end: None }
+                => "08ac021001",
+            Request { index: 1, sparse: Some(false), end: None } => "08011000",
+            Request { index: 0, sparse: None, end: None } => "0800",
+            Request { index: 2, sparse: Some(true), end: Some(300) }
+                => "0802100118ac02",
+            Data {
+                index: 1,
+                data: vec![4u8; 2],
+                data_signature: vec![5u8; 1],
+                tree_signature: vec![6u8; 1],
+            } => "0801120204042201052a0106",
+            Data {
+                index: 0,
+                data: vec![],
+                data_signature: vec![],
+                tree_signature: vec![],
+            } => "0800120022002a00",
+            Credit { credit: u32::MAX } => "08ffffffff0f",
+            RequestByHash { hash: vec![8u8; 2] } => "0a020808",
+            NotFound { hash: vec![9u8; 2] } => "0a020909",
+            Cancel { index: 300 } => "08ac02",
+            Extension {
+                name: "ab".to_string(),
+                payload: vec![1, 2],
+            } => "0a02616212020102"
+        };
+    }
+
     #[test]
     fn encode_decode() {
         message_enc_dec! {
             Message::Open(Open {
                 discovery_key: vec![2u8; 20],
-                capability: None
+                capability: None,
+                receive_only: Some(true),
+                length: Some(7),
             }),
             Message::Close(Close {
                 discovery_key: vec![1u8; 10]
             }),
             Message::Request(Request {
                 index: 0,
+                sparse: Some(true),
+                end: Some(4),
             }),
             Message::Data(Data {
                 index: 1,
                 data: vec![0u8; 10],
                 data_signature: vec![1u8; 32],
                 tree_signature: vec![2u8; 32],
+            }),
+            Message::Credit(Credit {
+                credit: 16,
+            }),
+            Message::RequestByHash(RequestByHash {
+                hash: vec![3u8; 32],
+            }),
+            Message::NotFound(NotFound {
+                hash: vec![4u8; 32],
+            }),
+            Message::Cancel(Cancel {
+                index: 5,
+            }),
+            Message::Extension(Extension {
+                name: "want".to_string(),
+                payload: vec![5u8; 16],
             })
         };
     }
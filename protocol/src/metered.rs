@@ -0,0 +1,181 @@
+use futures_lite::{AsyncRead, AsyncWrite};
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// IO stream counting the bytes read and written through it.
+///
+/// Wrap a transport to measure its raw throughput,
+/// including framing, handshake and keepalive bytes.
+/// Counters are read through a [Meter] handle.
+#[derive(Debug)]
+pub struct MeteredStream<T>
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    inner: T,
+    meter: Meter,
+}
+
+impl<T> MeteredStream<T>
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    /// Wrap the `inner` stream, starting the [Meter] now.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            meter: Meter::new(),
+        }
+    }
+
+    /// Get a [Meter] handle for this stream.
+    pub fn meter(&self) -> Meter {
+        self.meter.clone()
+    }
+
+    /// Unwrap the inner stream.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> AsyncRead for MeteredStream<T>
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.meter.state.read.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl<T> AsyncWrite for MeteredStream<T>
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.meter.state.written.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[derive(Debug)]
+struct MeterState {
+    read: AtomicU64,
+    written: AtomicU64,
+    started: Instant,
+}
+
+/// Handle to the counters of a [MeteredStream].
+///
+/// Displays as a one line summary of bytes and rates, ready for logging.
+#[derive(Debug, Clone)]
+pub struct Meter {
+    state: Arc<MeterState>,
+}
+
+impl Meter {
+    fn new() -> Self {
+        Self {
+            state: Arc::new(MeterState {
+                read: AtomicU64::new(0),
+                written: AtomicU64::new(0),
+                started: Instant::now(),
+            }),
+        }
+    }
+
+    /// Total bytes read from the stream.
+    pub fn bytes_read(&self) -> u64 {
+        self.state.read.load(Ordering::Relaxed)
+    }
+    /// Total bytes written to the stream.
+    pub fn bytes_written(&self) -> u64 {
+        self.state.written.load(Ordering::Relaxed)
+    }
+    /// Time since the stream was wrapped.
+    pub fn elapsed(&self) -> Duration {
+        self.state.started.elapsed()
+    }
+    /// Average read rate in bytes per second.
+    pub fn read_rate(&self) -> f64 {
+        rate(self.bytes_read(), self.elapsed())
+    }
+    /// Average write rate in bytes per second.
+    pub fn write_rate(&self) -> f64 {
+        rate(self.bytes_written(), self.elapsed())
+    }
+}
+
+impl fmt::Display for Meter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "read {} B ({:.0} B/s), written {} B ({:.0} B/s)",
+            self.bytes_read(), self.read_rate(),
+            self.bytes_written(), self.write_rate())
+    }
+}
+
+#[inline]
+fn rate(bytes: u64, elapsed: Duration) -> f64 {
+    match elapsed.as_secs_f64() {
+        secs if secs > 0.0 => bytes as f64 / secs,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::io::{AsyncReadExt, AsyncWriteExt};
+    use futures_lite::future::block_on;
+    use crate::test_util::create_laggy_duplex_pair;
+
+    #[test]
+    fn counts_bytes() {
+        block_on(async {
+            let (a, b) = create_laggy_duplex_pair(Duration::ZERO);
+            let mut a = MeteredStream::new(a);
+            let mut b = MeteredStream::new(b);
+            let (a_meter, b_meter) = (a.meter(), b.meter());
+
+            a.write_all(b"hello").await.unwrap();
+            b.write_all(b"hello world").await.unwrap();
+            let mut buf = [0u8; 5];
+            b.read_exact(&mut buf).await.unwrap();
+            let mut buf = [0u8; 11];
+            a.read_exact(&mut buf).await.unwrap();
+
+            assert_eq!(a_meter.bytes_written(), 5);
+            assert_eq!(a_meter.bytes_read(), 11);
+            assert_eq!(b_meter.bytes_written(), 11);
+            assert_eq!(b_meter.bytes_read(), 5);
+        })
+    }
+}
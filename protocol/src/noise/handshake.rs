@@ -1,11 +1,12 @@
 use std::io::{Error, ErrorKind, Result};
-use prost::Message;
 use rand::Rng;
 use blake2_rfc::blake2b::Blake2b;
 use snow::{Builder, Error as SnowError, HandshakeState};
+use subtle::ConstantTimeEq;
 pub use snow::Keypair;
 
 use super::super::schema::NoisePayload;
+use super::super::message::SchemaMessage;
 use super::CAP_NS_BUF;
 
 const CIPHER_KEY_LENGTH: usize = 32;
@@ -45,7 +46,10 @@ impl HandshakeResult {
     pub fn verify_remote_capability(&self, capability: Option<Vec<u8>>, key: &[u8]) -> Result<()> {
         let expected_capability = self.remote_capability(key);
         match (capability, expected_capability) {
-            (Some(c1), Some(c2)) if c1 == c2 => Ok(()),
+            // Compare in constant time, so the time to reject a forged
+            // capability tells a network attacker nothing about
+            // how many of its leading bytes are right.
+            (Some(c1), Some(c2)) if bool::from(c1.ct_eq(&c2)) => Ok(()),
             (None, None) => Err(Error::new(
                 ErrorKind::PermissionDenied,
                 "Missing capabilities for verification",
@@ -198,13 +202,13 @@ fn generate_nonce() -> Vec<u8> {
 #[inline]
 fn encode_nonce(nonce: Vec<u8>) -> Vec<u8> {
     let nonce_msg = NoisePayload { nonce };
-    let mut buf = vec![0u8; 0];
-    nonce_msg.encode(&mut buf).unwrap();
+    let mut buf = vec![0u8; nonce_msg.schema_len()];
+    nonce_msg.schema_encode(&mut buf).unwrap();
     buf
 }
 
 #[inline]
 fn decode_nonce(msg: &[u8]) -> Result<Vec<u8>> {
-    let decoded = NoisePayload::decode(msg)?;
+    let decoded = NoisePayload::schema_decode(msg)?;
     Ok(decoded.nonce)
 }
@@ -0,0 +1,67 @@
+//! Stand-in for the `noise` module when compiled without
+//! the `noise` feature.
+//!
+//! The types are uninhabited: the handshake can never be started,
+//! so raw framing without capabilities is the only mode.
+
+use std::io::{Error, ErrorKind, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeResult {}
+
+impl HandshakeResult {
+    pub fn capability(&self, _key: &[u8]) -> Option<Vec<u8>> {
+        match *self {}
+    }
+
+    pub fn verify_remote_capability(
+        &self, _capability: Option<Vec<u8>>, _key: &[u8]) -> Result<()>
+    {
+        match *self {}
+    }
+}
+
+#[derive(Debug)]
+pub enum Handshake {}
+
+impl Handshake {
+    pub fn new(_is_initiator: bool) -> Result<Self> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Handshake requires the noise feature",
+        ))
+    }
+
+    pub fn start(&mut self) -> Result<Option<&'_ [u8]>> {
+        match *self {}
+    }
+
+    pub fn complete(&self) -> bool {
+        match *self {}
+    }
+
+    pub fn read(&mut self, _msg: &[u8]) -> Result<Option<&'_ [u8]>> {
+        match *self {}
+    }
+
+    pub fn into_result(self) -> Result<HandshakeResult> {
+        match self {}
+    }
+}
+
+#[derive(Debug)]
+pub enum Cipher {}
+
+impl Cipher {
+    pub fn from_handshake_rx(handshake: &HandshakeResult) -> Result<Self> {
+        match *handshake {}
+    }
+
+    pub fn from_handshake_tx(handshake: &HandshakeResult) -> Result<Self> {
+        match *handshake {}
+    }
+
+    pub fn apply(&mut self, _buffer: &mut [u8]) {
+        match *self {}
+    }
+}
@@ -1,5 +1,16 @@
+use std::time::Duration;
+
+use crate::main::CHANNEL_CAP;
+use crate::MAX_MESSAGE_SIZE;
+
 /// Default keepalive interval (in milliseconds)
 pub const DEFAULT_KEEPALIVE: u64 = 10_000;
+/// Default initial read buffer size (in bytes)
+pub const DEFAULT_READ_BUF_SIZE: usize = 1024 * 128;
+/// Default credit for Data messages per channel.
+pub const DEFAULT_DATA_CREDIT: u32 = CHANNEL_CAP as u32;
+/// Default number of events a `Replication` keeps for diagnostics.
+pub const DEFAULT_EVENT_LOG_SIZE: usize = 32;
 
 /// Options for a Protocol instance.
 #[derive(Debug)]
@@ -9,11 +20,54 @@ pub struct Options {
     /// Enable or disable the handshake.
     /// Disabling the handshake will also disable capability verification.
     /// Don't disable this if you're not 100% sure you want this.
+    /// Requires the `noise` feature, disabled by default without it.
     pub noise: bool,
     /// Enable or disable transport encryption.
+    /// Requires the `noise` feature, disabled by default without it.
     pub encrypted: bool,
     /// Keepalive time in milliseconds or `None` for no timeout.
     pub keepalive_ms: Option<u64>,
+    /// Send an empty keepalive frame when nothing was written for half of
+    /// [Options::keepalive_ms], so the remote does not time out
+    /// a connection that is idle but alive.
+    /// Framed transports have no keepalive.
+    ///
+    /// Enabled by default, so only a remote that is gone times out.
+    /// Disable it to end a `Replication` once its remote goes quiet,
+    /// for example after a one-off sync.
+    pub send_keepalive: bool,
+    /// Number of Data messages the remote may send on a channel
+    /// before waiting for us to consume them, or `None` for no limit.
+    /// Credit is replenished as the receiver applies the Data,
+    /// see `Protocol::data_applied`.
+    /// Data beyond the granted credit is a protocol error.
+    /// Must be at least 1.
+    pub data_credit: Option<u32>,
+    /// Time to wait for the handshake to complete or `None` for no timeout.
+    pub handshake_timeout: Option<Duration>,
+    /// Time after which an open channel without any Request or Data,
+    /// in either direction, is closed, or `None` to keep idle channels.
+    /// Unlike the keepalive, which only covers the whole connection,
+    /// this frees the channels of feeds a remote opened and abandoned.
+    pub channel_idle_timeout: Option<Duration>,
+    /// Maximum size of a message accepted from the remote,
+    /// capped at [MAX_MESSAGE_SIZE].
+    pub max_message_size: u64,
+    /// Initial size of the read buffer in bytes.
+    ///
+    /// The buffer grows on demand to fit larger messages, and shrinks back
+    /// after them. A larger buffer reads more per syscall, for throughput;
+    /// a smaller one saves memory on servers with many idle connections.
+    pub read_buf_size: usize,
+    /// Maximum number of channels a `Replication` keeps open at once,
+    /// or `None` for no limit.
+    /// Opening past the limit fails the replication, guarding against
+    /// a remote asking for unbounded feeds on one connection.
+    pub max_open_channels: Option<usize>,
+    /// Number of the last events a `Replication` keeps in memory,
+    /// its commands, messages and errors, or `0` to keep none.
+    /// Read them from `ReplicationHandle::event_log`.
+    pub event_log_size: usize,
 }
 
 impl Options {
@@ -24,15 +78,69 @@ impl Options {
             ..Self::default()
         }
     }
+
+    /// Create with default options, for the peer initiating the connection.
+    ///
+    /// ```
+    /// # use protocol::Options;
+    /// let options = Options::initiator();
+    /// assert!(options.is_initiator);
+    /// ```
+    pub fn initiator() -> Self {
+        Self::new(true)
+    }
+
+    /// Create with default options, for the peer accepting the connection.
+    ///
+    /// ```
+    /// # use protocol::Options;
+    /// let options = Options::responder();
+    /// assert!(!options.is_initiator);
+    /// ```
+    pub fn responder() -> Self {
+        Self::new(false)
+    }
+
+    /// Disable the keepalive, see [Options::keepalive_ms].
+    ///
+    /// ```
+    /// # use protocol::Options;
+    /// let options = Options::initiator().no_keepalive();
+    /// assert_eq!(options.keepalive_ms, None);
+    /// ```
+    pub fn no_keepalive(mut self) -> Self {
+        self.keepalive_ms = None;
+        self
+    }
+
+    /// Do not send keepalive frames, see [Options::send_keepalive].
+    ///
+    /// ```
+    /// # use protocol::Options;
+    /// let options = Options::initiator().no_send_keepalive();
+    /// assert!(!options.send_keepalive);
+    /// ```
+    pub fn no_send_keepalive(mut self) -> Self {
+        self.send_keepalive = false;
+        self
+    }
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             is_initiator: false,
-            noise: true,
-            encrypted: true,
+            noise: cfg!(feature = "noise"),
+            encrypted: cfg!(feature = "noise"),
             keepalive_ms: Some(DEFAULT_KEEPALIVE),
+            send_keepalive: true,
+            data_credit: Some(DEFAULT_DATA_CREDIT),
+            handshake_timeout: None,
+            channel_idle_timeout: None,
+            max_message_size: MAX_MESSAGE_SIZE,
+            read_buf_size: DEFAULT_READ_BUF_SIZE,
+            max_open_channels: None,
+            event_log_size: DEFAULT_EVENT_LOG_SIZE,
         }
     }
 }
@@ -1,5 +1,4 @@
 use anyhow::{Result, anyhow};
-use futures_lite::io::{AsyncRead, AsyncWrite};
 use futures_lite::stream::{Stream, StreamExt};
 use std::task::{Context, Poll};
 use std::pin::Pin;
@@ -7,7 +6,7 @@ use std::pin::Pin;
 use crate::Options;
 use crate::noise;
 use crate::message::{FrameType, Frame};
-use crate::io::IO;
+use crate::io::{IO, Transport};
 
 use super::{Protocol, ProtocolStage, main};
 
@@ -36,7 +35,7 @@ impl ProtocolStage for Stage {}
 
 impl<T> Protocol<T, Stage>
 where
-    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    T: Transport,
 {
     /// Create a new replication protocol in handshake stage.
     pub fn new(io: T, options: Options) -> Self {
@@ -54,6 +53,7 @@ where
     /// Wait for handshake and upgrade to [Protocol<IO>].
     pub async fn handshake(mut self) -> Result<Protocol<T, main::Stage>>
     {
+        self.io.check_options()?;
         if !self.io.options.noise {
             return self.establish(None)
         }
@@ -71,10 +71,13 @@ where
     fn establish(self, handshake_result: Option<noise::HandshakeResult>)
         -> Result<Protocol<T, main::Stage>>
     {
+        log_debug!("handshake complete noise={} initiator={}",
+            handshake_result.is_some(), self.io.options.is_initiator);
         Protocol::<T, main::Stage>::new(self.io, handshake_result)
     }
 
     fn init(&mut self) -> Result<()> {
+        self.io.check_options()?;
         if self.io.options.noise {
             let mut handshake =
                 noise::Handshake::new(self.io.options.is_initiator)?;
@@ -161,7 +164,7 @@ where
 
 impl<T> Stream for Protocol<T, Stage>
 where
-    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    T: Transport,
 {
     type Item = Result<Event>;
     fn poll_next(
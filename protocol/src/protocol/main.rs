@@ -1,18 +1,20 @@
 use anyhow::{Result, anyhow};
-use futures_lite::io::{AsyncRead, AsyncWrite};
 use futures_lite::stream::Stream;
 use std::task::{Context, Poll};
 use std::pin::Pin;
 use std::io::{self, Error, ErrorKind};
 use async_channel::{Receiver, Sender};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryInto;
+use std::future::Future;
+use std::time::Duration;
+use futures_timer::Delay;
 
 use crate::schema::*;
 use crate::message::{Frame, FrameType, ChannelMessage};
 use crate::channels::ChannelMap;
-use crate::io::IO;
-use crate::{noise, Key, DiscoveryKey, Message};
+use crate::io::{IO, Transport};
+use crate::{noise, Key, DiscoveryKey, Message, Options};
 
 use super::{Protocol, ProtocolStage};
 
@@ -31,6 +33,11 @@ fn map_channel_err<T>(err: async_channel::SendError<T>) -> Error {
     )
 }
 
+/// Interval of the keepalive frames, half of [Options::keepalive_ms].
+fn keepalive_interval(options: &Options) -> Option<Duration> {
+    options.keepalive_ms.map(|ms| Duration::from_millis((ms / 2).max(1)))
+}
+
 /// Concurrent channels cap.
 pub const CHANNEL_CAP: usize = 1000;
 
@@ -45,6 +52,12 @@ pub enum Event {
     Close(DiscoveryKey),
     /// A new [Message] received on a channel.
     Message(DiscoveryKey, Message),
+    /// A user-defined [Extension] message received on a channel,
+    /// see [Protocol::extension].
+    Extension(DiscoveryKey, Extension),
+    /// All messages sent through [Protocol::request], [Protocol::data]
+    /// and [Protocol::close] so far are written and flushed.
+    Writable,
 }
 
 /// Main stage of [Protocol], contains stage-specific fields.
@@ -54,13 +67,22 @@ pub struct Stage {
     channels: ChannelMap,
     outbound_rx: Receiver<ChannelMessage>,
     outbound_tx: Sender<ChannelMessage>,
+    /// Outbound messages released by flow control.
+    outbound_ready: VecDeque<ChannelMessage>,
+    /// Outbound messages were written since the last [Event::Writable].
+    outbound_flushing: bool,
     queued_events: VecDeque<Event>,
+    /// Idle timeouts of the open channels,
+    /// see [Options::channel_idle_timeout].
+    idle: HashMap<DiscoveryKey, Delay>,
+    /// Time until the next keepalive frame, see [Options::send_keepalive].
+    keepalive: Option<Delay>,
 }
 impl ProtocolStage for Stage {}
 
 impl<T> Protocol<T, Stage>
 where
-    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    T: Transport,
 {
     /// Create a new [Protocol] after completing the handshake.
     pub fn new(mut io: IO<T>, result: Option<noise::HandshakeResult>)
@@ -76,6 +98,10 @@ where
 
         // setup channels
         let (outbound_tx, outbound_rx) = async_channel::unbounded();
+        let keepalive = match T::is_framed() || !io.options.send_keepalive {
+            true => None,
+            false => keepalive_interval(&io.options).map(Delay::new),
+        };
 
         Ok(Self {
             io,
@@ -84,15 +110,113 @@ where
                 channels: ChannelMap::new(),
                 outbound_tx,
                 outbound_rx,
+                outbound_ready: VecDeque::new(),
+                outbound_flushing: false,
                 queued_events: VecDeque::new(),
+                idle: HashMap::new(),
+                keepalive,
             },
         })
     }
 
+    /// Create a new [Protocol] in the main stage directly over `io`,
+    /// skipping the handshake stage, for transports that authenticate
+    /// the peer themselves (a TLS tunnel, an authenticated message bus).
+    ///
+    /// With a `handshake_result` of an earlier handshake, see
+    /// [handshake::Event::Handshake], [Options::noise] and
+    /// [Options::encrypted] apply as after [handshake::Stage].
+    /// Without one, both must be disabled: nothing is encrypted and
+    /// channel capabilities are not verified, so this relies entirely on
+    /// the transport to authenticate the peer and protect the messages.
+    ///
+    /// [handshake::Stage]: super::handshake::Stage
+    /// [handshake::Event::Handshake]: super::handshake::Event::Handshake
+    pub fn new_established(
+        io: T,
+        options: Options,
+        handshake_result: Option<noise::HandshakeResult>,
+        ) -> Result<Self>
+    {
+        let io = IO::new(io, options);
+        io.check_options()?;
+        if handshake_result.is_none()
+            && (io.options.noise || io.options.encrypted)
+        {
+            return Err(anyhow!(Error::new(
+                ErrorKind::InvalidInput,
+                "Established protocol without a handshake result \
+                 requires noise and encryption disabled")))
+        }
+        log_debug!("established noise={} initiator={}",
+            handshake_result.is_some(), io.options.is_initiator);
+        Self::new(io, handshake_result)
+    }
+
     /// Open a new protocol channel.
     pub async fn open(&mut self, key: Key) -> Result<()> {
+        self.open_with_id(key).await?;
+        Ok(())
+    }
+
+    /// Open a new protocol channel.
+    /// Returns its [DiscoveryKey] and the allocated local channel id.
+    pub async fn open_with_id(&mut self, key: Key)
+        -> Result<(DiscoveryKey, u64)>
+    {
+        self.open_channel(key, false, None)
+    }
+
+    /// Open a new protocol channel, telling the remote we will not serve
+    /// data on it, so it should not send us [Request]s.
+    /// The remote capability is verified as with [Protocol::open].
+    ///
+    /// The remote sees it with [Protocol::is_remote_receive_only].
+    pub async fn open_receive_only(&mut self, key: Key) -> Result<()> {
+        self.open_channel(key, true, None)?;
+        Ok(())
+    }
+
+    /// Open a new protocol channel, advertising the `length` of our feed,
+    /// receive-only as with [Protocol::open_receive_only] if `receive_only`.
+    ///
+    /// The remote sees it with [Protocol::remote_length].
+    pub async fn open_with_length(
+        &mut self,
+        key: Key,
+        length: u32,
+        receive_only: bool,
+        ) -> Result<()>
+    {
+        self.open_channel(key, receive_only, Some(length))?;
+        Ok(())
+    }
+
+    /// Length of the feed the remote advertised when opening the channel
+    /// for `discovery_key`, see [Protocol::open_with_length].
+    pub fn remote_length(&self, discovery_key: &DiscoveryKey) -> Option<u32> {
+        self.state.channels.get(discovery_key)
+            .and_then(|channel| channel.remote_length())
+    }
+
+    /// Check if the remote opened the channel for `discovery_key`
+    /// receive-only, see [Protocol::open_receive_only].
+    pub fn is_remote_receive_only(&self, discovery_key: &DiscoveryKey)
+        -> bool
+    {
+        self.state.channels.get(discovery_key)
+            .is_some_and(|channel| channel.is_remote_receive_only())
+    }
+
+    fn open_channel(
+        &mut self,
+        key: Key,
+        receive_only: bool,
+        length: Option<u32>,
+        ) -> Result<(DiscoveryKey, u64)>
+    {
         // Create a new channel.
-        let channel_handle = self.state.channels.attach_local(key);
+        let channel_handle = self.state.channels.attach_local(key)?;
         // Safe because attach_local always puts Some(local_id)
         let local_id = channel_handle.local_id().unwrap();
         let discovery_key = *channel_handle.discovery_key();
@@ -111,10 +235,33 @@ where
         let message = Message::Open(Open {
             discovery_key: discovery_key.to_vec(),
             capability,
+            receive_only: receive_only.then_some(true),
+            length,
         });
         let channel_message = ChannelMessage::new(local_id as u64, message);
         self.io.write_state.queue_frame(Frame::Message(channel_message));
-        Ok(())
+
+        // Grant the remote initial credit for sending Data.
+        if let Some(credit) = self.io.options.data_credit {
+            self.queue_credit(local_id as u64, credit);
+        }
+        Ok((discovery_key, local_id as u64))
+    }
+
+    /// Verify the remote capability of the channel for `discovery_key`
+    /// again.
+    ///
+    /// Capabilities are verified when a channel is established,
+    /// this repeats the check for a channel kept open,
+    /// as defense in depth.
+    /// Passes for channels not established, nothing is sent on them.
+    pub fn verify_channel(&self, discovery_key: &DiscoveryKey) -> Result<()> {
+        let channel = match self.state.channels.get(discovery_key) {
+            Some(channel) if channel.is_connected() => channel,
+            _ => return Ok(()),
+        };
+        let (key, remote_capability) = channel.prepare_to_verify()?;
+        self.verify_remote_capability(remote_capability.cloned(), key)
     }
 
     /// Close a protocol channel.
@@ -154,6 +301,53 @@ where
     {
         self.send(&discovery_key, Message::Data(msg)).await
     }
+    /// Send a [Message::RequestByHash] on a channel.
+    pub async fn request_by_hash(
+        &mut self, discovery_key: &DiscoveryKey, msg: RequestByHash)
+        -> Result<()>
+    {
+        self.send(discovery_key, Message::RequestByHash(msg)).await
+    }
+    /// Send a [Message::NotFound] on a channel.
+    pub async fn not_found(
+        &mut self, discovery_key: &DiscoveryKey, msg: NotFound) -> Result<()>
+    {
+        self.send(discovery_key, Message::NotFound(msg)).await
+    }
+    /// Send a user-defined [Message::Extension] on a channel,
+    /// the remote receives it as [Event::Extension].
+    ///
+    /// The protocol does not interpret extensions, `name` tells apart
+    /// extensions sharing a channel.
+    pub async fn extension(
+        &mut self,
+        discovery_key: &DiscoveryKey,
+        name: &str,
+        payload: Vec<u8>,
+        ) -> Result<()>
+    {
+        let msg = Extension { name: name.to_string(), payload };
+        self.send(discovery_key, Message::Extension(msg)).await
+    }
+    /// Return the credit of a [Message::Data] received on a channel,
+    /// once the receiver applied it, so the remote may send more,
+    /// see [Options::data_credit].
+    ///
+    /// Call it once for every Data [Event::Message] taken.
+    pub fn data_applied(&mut self, discovery_key: &DiscoveryKey) {
+        if let Some(credit) = self.io.options.data_credit {
+            self.on_data_applied(discovery_key, credit);
+        }
+    }
+    /// Send a [Message::Cancel] on a channel.
+    ///
+    /// The remote drops the [Message::Data] for the block if it has not
+    /// written it yet, Data already sent arrives anyway.
+    pub async fn cancel(
+        &mut self, discovery_key: &DiscoveryKey, msg: Cancel) -> Result<()>
+    {
+        self.send(discovery_key, Message::Cancel(msg)).await
+    }
 
     fn poll_next(
         self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Event>>
@@ -161,18 +355,24 @@ where
         let this = self.get_mut();
 
         // Drain queued events first
-        if let Some(event) = this.state.queued_events.pop_front() {
+        if let Some(event) = this.pop_event() {
             return Poll::Ready(Ok(event));
         }
 
         // Read and process incoming messages
         return_error!(this.poll_inbound_read(cx));
 
+        // Close channels idle for too long
+        this.poll_idle(cx);
+
+        // Keep the connection alive while nothing is written
+        this.poll_keepalive(cx);
+
         // Write everything we can write
         return_error!(this.poll_outbound_write(cx));
 
         // Check if any events are enqueued
-        if let Some(event) = this.state.queued_events.pop_front() {
+        if let Some(event) = this.pop_event() {
             Poll::Ready(Ok(event))
         } else {
             Poll::Pending
@@ -196,7 +396,85 @@ where
         }
     }
 
+    fn poll_keepalive(&mut self, cx: &mut Context<'_>) {
+        let interval = match keepalive_interval(&self.io.options) {
+            Some(interval) => interval,
+            None => return,
+        };
+        let delay = match self.state.keepalive.as_mut() {
+            Some(delay) => delay,
+            None => return,
+        };
+        if Pin::new(&mut *delay).poll(cx).is_pending() {
+            return
+        }
+        delay.reset(interval);
+        // Poll again to register the waker for the next interval.
+        let _ = Pin::new(delay).poll(cx);
+        if self.io.is_idle() {
+            self.io.write_state.queue_frame(Frame::Raw(vec![]));
+        }
+    }
+
+    fn poll_idle(&mut self, cx: &mut Context<'_>) {
+        let expired: Vec<DiscoveryKey> = self.state.idle.iter_mut()
+            .filter_map(|(discovery_key, delay)| {
+                Pin::new(delay).poll(cx).is_ready().then_some(*discovery_key)
+            })
+            .collect();
+        for discovery_key in expired {
+            self.close_idle(discovery_key);
+        }
+    }
+
+    /// Close an idle channel, telling the remote.
+    fn close_idle(&mut self, discovery_key: DiscoveryKey) {
+        log_debug!("channel idle discovery={}", hex::encode(discovery_key));
+        let local_id = self.state.channels.get(&discovery_key)
+            .and_then(|channel| channel.local_id());
+        if let Some(local_id) = local_id {
+            let message = Message::Close(Close {
+                discovery_key: discovery_key.to_vec(),
+            });
+            let channel_message = ChannelMessage::new(local_id as u64, message);
+            self.io.write_state.queue_frame(Frame::Message(channel_message));
+        }
+        self.state.channels.remove(&discovery_key);
+        self.queue_event(Event::Close(discovery_key));
+    }
+
+    /// Restart the idle timeout of a channel on a Request or Data.
+    fn on_activity(&mut self, discovery_key: &DiscoveryKey, message: &Message) {
+        if !matches!(message, Message::Request(_) | Message::Data(_)) {
+            return
+        }
+        let timeout = match self.io.options.channel_idle_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        if let Some(delay) = self.state.idle.get_mut(discovery_key) {
+            delay.reset(timeout);
+        }
+    }
+
     fn poll_outbound_write(&mut self, cx: &mut Context<'_>) -> Result<()> {
+        self.poll_outbound_messages(cx)?;
+
+        if self.state.outbound_flushing && self.is_drained() {
+            self.state.outbound_flushing = false;
+            self.queue_event(Event::Writable);
+        }
+        Ok(())
+    }
+
+    fn is_drained(&self) -> bool {
+        self.io.is_idle()
+            && self.state.outbound_ready.is_empty()
+            && self.state.outbound_rx.is_empty()
+            && !self.state.channels.has_blocked()
+    }
+
+    fn poll_outbound_messages(&mut self, cx: &mut Context<'_>) -> Result<()> {
         loop {
             self.io.poll_outbound_write(cx)?;
 
@@ -204,15 +482,74 @@ where
                 return Ok(())
             }
 
-            match Pin::new(&mut self.state.outbound_rx).poll_next(cx) {
-                Poll::Ready(Some(message)) => {
-                    self.on_outbound_message(&message);
-                    let frame = Frame::Message(message);
-                    self.io.write_state.park_frame(frame);
-                }
-                Poll::Ready(None) => unreachable!("Channel closed before end"),
-                Poll::Pending => return Ok(())
-            }
+            let message = match self.state.outbound_ready.pop_front() {
+                Some(message) => message,
+                None => match Pin::new(&mut self.state.outbound_rx)
+                    .poll_next(cx)
+                {
+                    Poll::Ready(Some(message)) =>
+                        match self.take_credit(message) {
+                            Some(message) => message,
+                            None => continue,
+                        },
+                    Poll::Ready(None) =>
+                        unreachable!("Channel closed before end"),
+                    Poll::Pending => return Ok(())
+                },
+            };
+            self.on_outbound_message(&message);
+            let frame = Frame::Message(message);
+            self.io.write_state.park_frame(frame);
+            self.state.outbound_flushing = true;
+            self.reset_keepalive();
+        }
+    }
+
+    fn reset_keepalive(&mut self) {
+        let interval = keepalive_interval(&self.io.options);
+        if let (Some(delay), Some(interval)) =
+            (self.state.keepalive.as_mut(), interval)
+        {
+            delay.reset(interval);
+        }
+    }
+
+    /// Hold back Data on channels without credit.
+    fn take_credit(&mut self, message: ChannelMessage)
+        -> Option<ChannelMessage>
+    {
+        match self.state.channels.get_local_mut(message.channel as usize) {
+            Some(channel) => channel.take_credit(message),
+            None => Some(message),
+        }
+    }
+
+    fn queue_credit(&mut self, local_id: u64, credit: u32) {
+        if let Some(channel) = self.state.channels.get_local_mut(local_id as usize) {
+            channel.grant_credit(credit);
+        }
+        let message = Message::Credit(Credit { credit });
+        let channel_message = ChannelMessage::new(local_id, message);
+        self.io.write_state.queue_frame(Frame::Message(channel_message));
+    }
+
+    fn pop_event(&mut self) -> Option<Event> {
+        self.state.queued_events.pop_front()
+    }
+
+    fn on_data_applied(&mut self, discovery_key: &DiscoveryKey, credit: u32) {
+        // Grant credit in batches to avoid a Credit message per Data.
+        let threshold = std::cmp::max(credit / 2, 1);
+        let channel = match self.state.channels.get_mut(discovery_key) {
+            Some(channel) => channel,
+            None => return,
+        };
+        let local_id = match channel.local_id() {
+            Some(local_id) => local_id,
+            None => return,
+        };
+        if let Some(credit) = channel.consume_credit(threshold) {
+            self.queue_credit(local_id as u64, credit);
         }
     }
 
@@ -225,6 +562,12 @@ where
         {
             self.close_local(*channel);
         }
+        let discovery_key = self.state.channels
+            .get_local(message.channel as usize)
+            .map(|local| *local.discovery_key());
+        if let Some(discovery_key) = discovery_key {
+            self.on_activity(&discovery_key, &message.message);
+        }
     }
 
     fn on_inbound_message(
@@ -240,25 +583,67 @@ where
             _ => match message {
                 Message::Open(msg) => self.on_open(remote_id, msg)?,
                 Message::Close(msg) => self.on_close(remote_id, msg)?,
-                _ => {
-                    // Emit [Event::Message].
+                Message::Credit(msg) => self.on_credit(remote_id, msg),
+                Message::Cancel(msg) => {
+                    self.on_cancel(remote_id, &msg);
+                    self.queue_message_event(remote_id, Message::Cancel(msg));
+                },
+                Message::Extension(msg) => {
                     let discovery_key = self.state.channels
                         .get_remote(remote_id as usize)
-                        .map(|remote| remote.discovery_key().clone());
+                        .map(|remote| *remote.discovery_key());
                     if let Some(discovery_key) = discovery_key {
-                        self.queue_event(
-                            Event::Message(discovery_key.clone(), message));
+                        self.queue_event(Event::Extension(discovery_key, msg));
                     }
                 },
+                Message::Data(_) if self.io.options.data_credit.is_some() => {
+                    let has_credit = self.state.channels
+                        .get_remote_mut(remote_id as usize)
+                        .is_none_or(|channel| channel.use_credit());
+                    if !has_credit {
+                        return Err(protocol_error(
+                            "Remote sent Data without credit"))
+                    }
+                    self.queue_message_event(remote_id, message);
+                },
+                _ => self.queue_message_event(remote_id, message),
             },
         }
         Ok(())
     }
 
+    fn queue_message_event(&mut self, remote_id: u64, message: Message) {
+        // Emit [Event::Message].
+        let discovery_key = self.state.channels
+            .get_remote(remote_id as usize)
+            .map(|remote| *remote.discovery_key());
+        if let Some(discovery_key) = discovery_key {
+            self.on_activity(&discovery_key, &message);
+            self.queue_event(Event::Message(discovery_key, message));
+        }
+    }
+
     fn on_open(&mut self, ch: u64, msg: Open) -> Result<()> {
         let discovery_key: DiscoveryKey = parse_key(&msg.discovery_key)?;
+
+        // Reject reusing a remote id without closing it first.
+        let reused = self.state.channels.get_remote(ch as usize)
+            .is_some_and(|channel| *channel.discovery_key() != discovery_key);
+        if reused {
+            return Err(protocol_error("Remote channel id is already in use"))
+        }
+        // Reject re-attaching an established channel.
+        let connected = self.state.channels.get(&discovery_key)
+            .is_some_and(|channel| channel.is_connected());
+        if connected {
+            return Err(protocol_error("Channel is already open"))
+        }
+
+        let receive_only = msg.receive_only();
         let channel_handle = self.state.channels
-            .attach_remote(discovery_key, ch as usize, msg.capability);
+            .attach_remote(
+                discovery_key, ch as usize, msg.capability, receive_only,
+                msg.length);
 
         if channel_handle.is_connected() {
             let local_id = channel_handle.local_id().unwrap();
@@ -286,15 +671,85 @@ where
         let remote = self.state.channels.get_remote(remote_id as usize);
         if let Some(channel_handle) = remote {
             let discovery_key = *channel_handle.discovery_key();
-            if msg.discovery_key == discovery_key {
-                self.state.channels.remove(&discovery_key);
-                self.queue_event(Event::Close(discovery_key));
+            if msg.discovery_key != discovery_key {
+                return Err(protocol_error(
+                    "Close does not match the channel discovery key"))
             }
+            self.state.channels.remove(&discovery_key);
+            self.queue_event(Event::Close(discovery_key));
         }
         Ok(())
     }
 
+    fn on_credit(&mut self, remote_id: u64, msg: Credit) {
+        let remote = self.state.channels.get_remote_mut(remote_id as usize);
+        if let Some(channel_handle) = remote {
+            let ready = channel_handle.add_credit(msg.credit);
+            self.state.outbound_ready.extend(ready);
+        }
+    }
+
+    /// Drop the [Message::Data] for the block the remote cancelled,
+    /// unless it is already written: waiting for credit, ready to write,
+    /// or still queued by [Protocol::data].
+    fn on_cancel(&mut self, remote_id: u64, msg: &Cancel) {
+        let local_id = match self.state.channels
+            .get_remote_mut(remote_id as usize)
+        {
+            Some(channel_handle) => {
+                channel_handle.cancel_data(msg.index);
+                channel_handle.local_id()
+            },
+            None => None,
+        };
+        let local_id = match local_id {
+            Some(local_id) => local_id as u64,
+            None => return,
+        };
+        let is_cancelled = |message: &ChannelMessage| {
+            message.channel == local_id && matches!(&message.message,
+                Message::Data(data) if data.index == msg.index)
+        };
+
+        // Data ready to write took credit, give it back.
+        let ready = self.state.outbound_ready.len();
+        self.state.outbound_ready.retain(|message| !is_cancelled(message));
+        let refund = (ready - self.state.outbound_ready.len()) as u32;
+        if refund > 0 && self.io.options.data_credit.is_some() {
+            let channel = self.state.channels.get_local_mut(local_id as usize);
+            if let Some(channel_handle) = channel {
+                let ready = channel_handle.add_credit(refund);
+                self.state.outbound_ready.extend(ready);
+            }
+        }
+
+        // Take the queued messages in order, as when writing them.
+        while let Ok(message) = self.state.outbound_rx.try_recv() {
+            if is_cancelled(&message) {
+                continue
+            }
+            if let Some(message) = self.take_credit(message) {
+                self.state.outbound_ready.push_back(message);
+            }
+        }
+    }
+
     fn queue_event(&mut self, event: Event) {
+        match &event {
+            Event::Open(discovery) => {
+                log_debug!(
+                    "channel open discovery={}", hex::encode(discovery));
+                if let Some(timeout) = self.io.options.channel_idle_timeout {
+                    self.state.idle.insert(*discovery, Delay::new(timeout));
+                }
+            },
+            Event::Close(discovery) => {
+                log_debug!(
+                    "channel close discovery={}", hex::encode(discovery));
+                self.state.idle.remove(discovery);
+            },
+            _ => {},
+        }
         self.state.queued_events.push_back(event);
     }
 
@@ -311,6 +766,10 @@ where
         key: &[u8],
         ) -> Result<()>
     {
+        // Capability verification is disabled together with the handshake.
+        if !self.io.options.noise {
+            return Ok(())
+        }
         match self.state.handshake.as_ref() {
             Some(handshake) => handshake
                 .verify_remote_capability(capability, key)
@@ -325,7 +784,7 @@ where
 
 impl<T> Stream for Protocol<T, Stage>
 where
-    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    T: Transport,
 {
     type Item = Result<Event>;
     fn poll_next(
@@ -337,9 +796,642 @@ where
     }
 }
 
+fn protocol_error(msg: &str) -> anyhow::Error {
+    anyhow!(Error::new(ErrorKind::InvalidData, msg))
+}
+
 fn parse_key(key: &[u8]) -> io::Result<[u8; 32]> {
     key.try_into().map_err(
         |_| io::Error::new(
             io::ErrorKind::InvalidInput,
             "Key must be 32 bytes long"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use futures_lite::future::{race, zip};
+    use futures_lite::stream::StreamExt;
+    use async_std::future::timeout;
+
+    use crate::{new_protocol, discovery_key, Options};
+    use crate::channels::MAX_LOCAL_ID;
+    use crate::test_util::{
+        create_laggy_duplex_pair, create_message_io_pair, LaggyDuplex,
+    };
+
+    type TestProtocol = Protocol<LaggyDuplex, Stage>;
+
+    async fn create_pair(data_credit: Option<u32>)
+        -> (TestProtocol, TestProtocol)
+    {
+        let (a, b) = create_laggy_duplex_pair(Duration::ZERO);
+        let a = new_protocol(a, Options {
+            data_credit,
+            ..Options::initiator()
+        });
+        let b = new_protocol(b, Options {
+            data_credit,
+            ..Options::responder()
+        });
+        let (a, b) = zip(a.handshake(), b.handshake()).await;
+        (a.unwrap(), b.unwrap())
+    }
+
+    /// Poll until no new event arrives for a while.
+    async fn drain<T: Transport>(proto: &mut Protocol<T, Stage>)
+        -> Vec<Event>
+    {
+        let mut events = vec![];
+        let idle = Duration::from_millis(50);
+        while let Ok(Some(event)) = timeout(idle, proto.next()).await {
+            events.push(event.unwrap());
+        }
+        events
+    }
+
+    async fn open_pair(key: Key, a: &mut TestProtocol, b: &mut TestProtocol)
+        -> Result<()>
+    {
+        a.open(key).await?;
+        drain(a).await;
+        b.open(key).await?;
+        drain(b).await;
+        drain(a).await;
+        Ok(())
+    }
+
+    fn data(index: u32) -> Data {
+        Data {
+            index,
+            data: vec![index as u8; 8],
+            data_signature: vec![],
+            tree_signature: vec![],
+        }
+    }
+
+    /// Apply the Data in `events`, returning the credit to the remote.
+    fn apply_data(proto: &mut TestProtocol, events: Vec<Event>) -> Vec<u32> {
+        let indices = data_indices(events);
+        for _ in &indices {
+            proto.data_applied(&discovery_key(&[3u8; 32]));
+        }
+        indices
+    }
+
+    fn data_indices(events: Vec<Event>) -> Vec<u32> {
+        events.into_iter()
+            .filter_map(|event| match event {
+                Event::Message(_, Message::Data(data)) => Some(data.index),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[async_std::test]
+    async fn message_io() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (a, b) = create_message_io_pair();
+        let options = |is_initiator| Options {
+            is_initiator,
+            noise: false,
+            encrypted: false,
+            keepalive_ms: None,
+            ..Options::default()
+        };
+        let a = new_protocol(a, options(true));
+        let b = new_protocol(b, options(false));
+        let (a, b) = zip(a.handshake(), b.handshake()).await;
+        let (mut a, mut b) = (a?, b?);
+
+        a.open(key).await?;
+        drain(&mut a).await;
+        b.open(key).await?;
+        assert!(drain(&mut b).await.contains(&Event::Open(discovery)));
+        assert!(drain(&mut a).await.contains(&Event::Open(discovery)));
+
+        for index in 0..3 {
+            a.data(&discovery, data(index)).await?;
+        }
+        drain(&mut a).await;
+        assert_eq!(data_indices(drain(&mut b).await), vec![0, 1, 2]);
+
+        drop(a);
+        let error = b.next().await.unwrap().unwrap_err();
+        let error = error.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(error.kind(), ErrorKind::ConnectionAborted);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn keepalive() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (a, b) = create_laggy_duplex_pair(Duration::ZERO);
+        let options = |is_initiator| Options {
+            is_initiator,
+            keepalive_ms: Some(300),
+            ..Options::default()
+        };
+        let a = new_protocol(a, options(true));
+        let b = new_protocol(b, options(false));
+        let (a, b) = zip(a.handshake(), b.handshake()).await;
+        let (mut a, mut b) = (a?, b?);
+
+        // Idle for several keepalive periods, neither side times out.
+        let idle = timeout(
+            Duration::from_millis(1000), race(a.next(), b.next())).await;
+        assert!(idle.is_err());
+
+        open_pair(key, &mut a, &mut b).await?;
+        a.data(&discovery, data(0)).await?;
+        drain(&mut a).await;
+        assert_eq!(data_indices(drain(&mut b).await), vec![0]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn small_read_buffer() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (a, b) = create_laggy_duplex_pair(Duration::ZERO);
+        let options = |is_initiator| Options {
+            is_initiator,
+            read_buf_size: 16,
+            ..Options::default()
+        };
+        let a = new_protocol(a, options(true));
+        let b = new_protocol(b, options(false));
+        let (a, b) = zip(a.handshake(), b.handshake()).await;
+        let (mut a, mut b) = (a?, b?);
+        open_pair(key, &mut a, &mut b).await?;
+
+        let large = Data {
+            data: vec![7u8; 64 * 1024],
+            ..data(0)
+        };
+        a.data(&discovery, large.clone()).await?;
+        a.data(&discovery, data(1)).await?;
+        drain(&mut a).await;
+        let received: Vec<Data> = drain(&mut b).await.into_iter()
+            .filter_map(|event| match event {
+                Event::Message(_, Message::Data(data)) => Some(data),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(received, vec![large, data(1)]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn open_with_id() -> Result<()> {
+        let (mut a, _b) = create_pair(None).await;
+        let (discovery, id) = a.open_with_id([3u8; 32]).await?;
+        assert_eq!(discovery, discovery_key(&[3u8; 32]));
+        assert_eq!(id, 1);
+        let (_, id) = a.open_with_id([4u8; 32]).await?;
+        assert_eq!(id, 2);
+        Ok(())
+    }
+
+    #[cfg(feature = "noise")]
+    #[async_std::test]
+    async fn verify_channel() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(None).await;
+        // not established yet
+        a.verify_channel(&discovery)?;
+        open_pair(key, &mut a, &mut b).await?;
+
+        b.data(&discovery, data(0)).await?;
+        drain(&mut b).await;
+        assert_eq!(data_indices(drain(&mut a).await), vec![0]);
+        a.verify_channel(&discovery)?;
+
+        // the remote capability no longer matches the handshake
+        a.state.handshake.as_mut().unwrap().split_rx = [0u8; 32];
+        let error = a.verify_channel(&discovery).unwrap_err();
+        let error = error.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        Ok(())
+    }
+
+    #[cfg(feature = "noise")]
+    #[async_std::test]
+    async fn message_io_rejects_noise() -> Result<()> {
+        let (a, _b) = create_message_io_pair();
+        let a = new_protocol(a, Options::new(true));
+        let error = a.handshake().await.unwrap_err();
+        let error = error.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "noise"))]
+    #[async_std::test]
+    async fn rejects_noise_without_feature() -> Result<()> {
+        let (a, _b) = create_laggy_duplex_pair(Duration::ZERO);
+        let a = new_protocol(a, Options {
+            noise: true,
+            ..Options::new(true)
+        });
+        let error = a.handshake().await.unwrap_err();
+        let error = error.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn flow_control_blocks_sender() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(Some(4)).await;
+        open_pair(key, &mut a, &mut b).await?;
+
+        for index in 0..10 {
+            a.data(&discovery, data(index)).await?;
+        }
+        drain(&mut a).await;
+        let channel = a.state.channels.get(&discovery).unwrap();
+        assert_eq!(channel.blocked_len(), 6);
+
+        let mut received = vec![];
+        for _ in 0..10 {
+            let events = drain(&mut b).await;
+            received.extend(apply_data(&mut b, events));
+            drain(&mut b).await;
+            drain(&mut a).await;
+        }
+        assert_eq!(received, (0..10).collect::<Vec<u32>>());
+        let channel = a.state.channels.get(&discovery).unwrap();
+        assert_eq!(channel.blocked_len(), 0);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn flow_control_credit_after_applied() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(Some(2)).await;
+        open_pair(key, &mut a, &mut b).await?;
+
+        for index in 0..4 {
+            a.data(&discovery, data(index)).await?;
+        }
+        drain(&mut a).await;
+        // taking the Data events does not return credit
+        assert_eq!(data_indices(drain(&mut b).await), vec![0, 1]);
+        drain(&mut a).await;
+        assert_eq!(drain(&mut b).await, vec![]);
+        let channel = a.state.channels.get(&discovery).unwrap();
+        assert_eq!(channel.blocked_len(), 2);
+
+        b.data_applied(&discovery);
+        b.data_applied(&discovery);
+        drain(&mut b).await;
+        drain(&mut a).await;
+        assert_eq!(data_indices(drain(&mut b).await), vec![2, 3]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn extension() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(None).await;
+        // not sent before the channel is open
+        a.extension(&discovery, "want", vec![0]).await?;
+        open_pair(key, &mut a, &mut b).await?;
+
+        a.extension(&discovery, "want", vec![1, 2]).await?;
+        drain(&mut a).await;
+        let events = drain(&mut b).await;
+        assert_eq!(events, vec![Event::Extension(discovery, Extension {
+            name: "want".to_string(),
+            payload: vec![1, 2],
+        })]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn cancel_drops_blocked_data() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(Some(4)).await;
+        open_pair(key, &mut a, &mut b).await?;
+
+        for index in 0..10 {
+            a.data(&discovery, data(index)).await?;
+        }
+        drain(&mut a).await;
+        b.cancel(&discovery, Cancel { index: 7 }).await?;
+
+        let mut received = vec![];
+        let mut cancelled = vec![];
+        for _ in 0..10 {
+            let events = drain(&mut b).await;
+            received.extend(apply_data(&mut b, events));
+            drain(&mut b).await;
+            for event in drain(&mut a).await {
+                if let Event::Message(_, Message::Cancel(msg)) = event {
+                    cancelled.push(msg.index);
+                }
+            }
+        }
+        assert_eq!(cancelled, vec![7]);
+        assert_eq!(received, vec![0, 1, 2, 3, 4, 5, 6, 8, 9]);
+        let channel = a.state.channels.get(&discovery).unwrap();
+        assert_eq!(channel.blocked_len(), 0);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn cancel_drops_queued_data() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        for data_credit in [None, Some(4)] {
+            let (mut a, mut b) = create_pair(data_credit).await;
+            open_pair(key, &mut a, &mut b).await?;
+
+            // queued, not written until `a` is polled
+            for index in 0..10 {
+                a.data(&discovery, data(index)).await?;
+            }
+            b.cancel(&discovery, Cancel { index: 2 }).await?;
+            drain(&mut b).await;
+
+            let mut received = vec![];
+            for _ in 0..10 {
+                drain(&mut a).await;
+                let events = drain(&mut b).await;
+                received.extend(apply_data(&mut b, events));
+            }
+            assert_eq!(received, vec![0, 1, 3, 4, 5, 6, 7, 8, 9]);
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn flow_control_disabled() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(None).await;
+        open_pair(key, &mut a, &mut b).await?;
+
+        for index in 0..10 {
+            a.data(&discovery, data(index)).await?;
+        }
+        drain(&mut a).await;
+        let channel = a.state.channels.get(&discovery).unwrap();
+        assert_eq!(channel.blocked_len(), 0);
+
+        let received = data_indices(drain(&mut b).await);
+        assert_eq!(received, (0..10).collect::<Vec<u32>>());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn flow_control_rejects_excess_data() -> Result<()> {
+        let key = [3u8; 32];
+        let (mut a, mut b) = create_pair(Some(2)).await;
+        open_pair(key, &mut a, &mut b).await?;
+
+        // Bypass the sender side flow control.
+        for index in 0..2 {
+            let msg = ChannelMessage::new(1, Message::Data(data(index)));
+            b.on_inbound_message(msg)?;
+        }
+        let msg = ChannelMessage::new(1, Message::Data(data(2)));
+        let error = b.on_inbound_message(msg).unwrap_err();
+        let error = error.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn flow_control_rejects_zero_credit() -> Result<()> {
+        let (a, _b) = create_laggy_duplex_pair(Duration::ZERO);
+        let a = new_protocol(a, Options {
+            data_credit: Some(0),
+            ..Options::initiator()
+        });
+        let error = a.handshake().await.unwrap_err();
+        let error = error.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn open_receive_only() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(None).await;
+        a.open_receive_only(key).await?;
+        drain(&mut a).await;
+        b.open(key).await?;
+        drain(&mut b).await;
+        drain(&mut a).await;
+
+        assert!(b.is_remote_receive_only(&discovery));
+        assert!(!a.is_remote_receive_only(&discovery));
+        assert!(!b.is_remote_receive_only(&discovery_key(&[4u8; 32])));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn open_with_length() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(None).await;
+        a.open_with_length(key, 42, true).await?;
+        drain(&mut a).await;
+        b.open(key).await?;
+        drain(&mut b).await;
+        drain(&mut a).await;
+
+        assert_eq!(b.remote_length(&discovery), Some(42));
+        assert!(b.is_remote_receive_only(&discovery));
+        assert_eq!(a.remote_length(&discovery), None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn open_exhausts_local_ids() -> Result<()> {
+        let (mut a, _b) = create_pair(None).await;
+        let key = |i: usize| {
+            let mut key = [0u8; 32];
+            key[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            key
+        };
+        for i in 0..MAX_LOCAL_ID {
+            let (_, id) = a.open_with_id(key(i)).await?;
+            assert_eq!(id as usize, i + 1);
+        }
+        assert!(a.open(key(MAX_LOCAL_ID)).await.is_err());
+
+        // a freed id is reused, never the reserved id 0
+        a.state.channels.remove(&discovery_key(&key(4)));
+        let (_, id) = a.open_with_id(key(MAX_LOCAL_ID)).await?;
+        assert_eq!(id, 5);
+        Ok(())
+    }
+
+    fn open_message(key: &Key) -> Message {
+        Message::Open(Open {
+            discovery_key: discovery_key(key).to_vec(),
+            capability: None,
+            receive_only: None,
+            length: None,
+        })
+    }
+
+    #[async_std::test]
+    async fn reject_duplicate_open() -> Result<()> {
+        let key = [3u8; 32];
+        let (mut a, mut b) = create_pair(None).await;
+        open_pair(key, &mut a, &mut b).await?;
+
+        // same remote id
+        let msg = ChannelMessage::new(1, open_message(&key));
+        assert!(b.on_inbound_message(msg).is_err());
+        // same discovery key on a new remote id
+        let msg = ChannelMessage::new(2, open_message(&key));
+        assert!(b.on_inbound_message(msg).is_err());
+        // remote id reused for another discovery key
+        let msg = ChannelMessage::new(1, open_message(&[4u8; 32]));
+        assert!(b.on_inbound_message(msg).is_err());
+
+        let channel = b.state.channels.get(&discovery_key(&key)).unwrap();
+        assert!(channel.is_connected());
+        Ok(())
+    }
+
+    #[cfg(feature = "noise")]
+    #[async_std::test]
+    async fn reject_wrong_capability() -> Result<()> {
+        let key = [3u8; 32];
+        let (_a, mut b) = create_pair(None).await;
+        b.open(key).await?;
+
+        // the expected capability, one bit off
+        let mut capability = b.state.handshake.as_ref().unwrap()
+            .remote_capability(&key).unwrap();
+        capability[31] ^= 1;
+        let msg = ChannelMessage::new(1, Message::Open(Open {
+            discovery_key: discovery_key(&key).to_vec(),
+            capability: Some(capability),
+            receive_only: None,
+            length: None,
+        }));
+        let error = b.on_inbound_message(msg).unwrap_err();
+        let error = error.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reopen_before_connected() -> Result<()> {
+        let key = [3u8; 32];
+        let (_, mut b) = create_pair(None).await;
+
+        let msg = ChannelMessage::new(1, open_message(&key));
+        b.on_inbound_message(msg)?;
+        let msg = ChannelMessage::new(2, open_message(&key));
+        b.on_inbound_message(msg)?;
+
+        assert!(b.state.channels.get_remote(1).is_none());
+        assert!(b.state.channels.get_remote(2).is_some());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reject_mismatched_close() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(None).await;
+        open_pair(key, &mut a, &mut b).await?;
+
+        let msg = ChannelMessage::new(1, Message::Close(Close {
+            discovery_key: discovery_key(&[4u8; 32]).to_vec(),
+        }));
+        assert!(b.on_inbound_message(msg).is_err());
+        assert!(b.state.channels.get(&discovery).is_some());
+
+        // unknown remote id is ignored
+        let msg = ChannelMessage::new(7, Message::Close(Close {
+            discovery_key: discovery.to_vec(),
+        }));
+        assert!(b.on_inbound_message(msg).is_ok());
+        assert!(b.state.channels.get(&discovery).is_some());
+
+        let msg = ChannelMessage::new(1, Message::Close(Close {
+            discovery_key: discovery.to_vec(),
+        }));
+        assert!(b.on_inbound_message(msg).is_ok());
+        assert!(b.state.channels.get(&discovery).is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn close_idle_channel() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (a, b) = create_laggy_duplex_pair(Duration::ZERO);
+        let a = new_protocol(a, Options::new(true));
+        let b = new_protocol(b, Options {
+            channel_idle_timeout: Some(Duration::from_millis(300)),
+            ..Options::new(false)
+        });
+        let (a, b) = zip(a.handshake(), b.handshake()).await;
+        let (mut a, mut b) = (a?, b?);
+        open_pair(key, &mut a, &mut b).await?;
+
+        // activity keeps the channel open
+        for index in 0..3 {
+            a.data(&discovery, data(index)).await?;
+            drain(&mut a).await;
+            assert_eq!(data_indices(drain(&mut b).await), vec![index]);
+        }
+        assert!(b.state.channels.get(&discovery).is_some());
+
+        let events = timeout(Duration::from_secs(5), async {
+            let mut events = vec![];
+            while !events.contains(&Event::Close(discovery)) {
+                events.push(b.next().await.unwrap()?);
+            }
+            Ok::<_, anyhow::Error>(events)
+        }).await??;
+        assert!(events.contains(&Event::Close(discovery)));
+        assert!(b.state.channels.get(&discovery).is_none());
+        assert!(drain(&mut a).await.contains(&Event::Close(discovery)));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn writable_after_drain() -> Result<()> {
+        let key = [3u8; 32];
+        let discovery = discovery_key(&key);
+        let (mut a, mut b) = create_pair(Some(2)).await;
+        open_pair(key, &mut a, &mut b).await?;
+
+        a.data(&discovery, data(0)).await?;
+        a.data(&discovery, data(1)).await?;
+        let events = drain(&mut a).await;
+        assert_eq!(events, vec![Event::Writable]);
+
+        // Data waiting for credit is not drained.
+        for index in 2..4 {
+            a.data(&discovery, data(index)).await?;
+        }
+        assert_eq!(drain(&mut a).await, vec![]);
+
+        let events = drain(&mut b).await;
+        assert_eq!(apply_data(&mut b, events), vec![0, 1]);
+        drain(&mut b).await;
+        assert_eq!(drain(&mut a).await, vec![Event::Writable]);
+        Ok(())
+    }
+}
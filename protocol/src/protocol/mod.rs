@@ -1,7 +1,5 @@
-use futures_lite::io::{AsyncRead, AsyncWrite};
-
 use crate::Options;
-use crate::io::IO;
+use crate::io::{IO, Transport, FrameStats};
 
 /// Handshake stage of the [Protocol].
 pub mod handshake;
@@ -13,7 +11,7 @@ pub mod main;
 pub fn new_protocol<T>(io: T, options: Options)
     -> Protocol<T, handshake::Stage>
 where
-    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    T: Transport,
 {
     Protocol::<T, handshake::Stage>::new(io, options)
 }
@@ -23,7 +21,7 @@ where
 pub fn new_protocol_with_defaults<T>(io: T, is_initiator: bool)
     -> Protocol<T, handshake::Stage>
 where
-    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    T: Transport,
 {
     let options = Options::new(is_initiator);
     new_protocol(io, options)
@@ -38,3 +36,10 @@ pub struct Protocol<T, S: ProtocolStage> {
     io: IO<T>,
     state: S,
 }
+
+impl<T, S: ProtocolStage> Protocol<T, S> {
+    /// Get the [FrameStats] of the inbound frames so far.
+    pub fn frame_stats(&self) -> &FrameStats {
+        self.io.read_state.stats()
+    }
+}
@@ -0,0 +1,559 @@
+//! Hand-written encoding of the messages in `schema.proto`,
+//! enabled with the `manual-schema` feature.
+//!
+//! Encodes the same bytes as the code generated by `prost`
+//! (fields in tag order, required fields always written,
+//! optional fields only if set), without running `protoc`.
+//! Unknown fields are skipped when decoding.
+
+use std::io::{Error, ErrorKind, Result};
+
+use crate::message::{EncodeError, SchemaMessage};
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_BYTES: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+/// Max encoded length of a varint.
+const MAX_VARINT_LEN: usize = 10;
+
+/// Sent as part of the noise protocol.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct NoisePayload {
+    /// noise nonce
+    pub nonce: Vec<u8>,
+}
+/// type=0
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Open {
+    /// [crate::DiscoveryKey]
+    pub discovery_key: Vec<u8>,
+    /// used to verify the remote knows the public [crate::Key]
+    pub capability: Option<Vec<u8>>,
+    /// the sender will not serve data, do not send it requests
+    pub receive_only: Option<bool>,
+    /// length of the sender's feed as it opens the channel
+    pub length: Option<u32>,
+}
+/// type=1, explicitly close a channel
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Close {
+    /// [crate::DiscoveryKey]
+    pub discovery_key: Vec<u8>,
+}
+/// type=2, ask for data
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Request {
+    /// index
+    pub index: u32,
+    /// only this block is wanted, do not read as "have all blocks before index"
+    pub sparse: Option<bool>,
+    /// with sparse, every block from index up to end, exclusive, is wanted
+    pub end: Option<u32>,
+}
+/// type=3, send some data
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Data {
+    /// index
+    pub index: u32,
+    /// data
+    pub data: Vec<u8>,
+    /// data signature
+    pub data_signature: Vec<u8>,
+    /// tree signature
+    pub tree_signature: Vec<u8>,
+}
+/// type=4, allow the remote to send more data
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Credit {
+    /// number of additional Data messages the remote may send
+    pub credit: u32,
+}
+/// type=5, ask for a block by the leaf hash of its data
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct RequestByHash {
+    /// leaf hash of the block data
+    pub hash: Vec<u8>,
+}
+/// type=6, reply to RequestByHash when there is no such block
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct NotFound {
+    /// hash of the RequestByHash
+    pub hash: Vec<u8>,
+}
+/// type=7, the sender no longer wants a requested block
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Cancel {
+    /// index of the Request
+    pub index: u32,
+}
+/// type=8, user-defined message on a channel
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Extension {
+    /// name of the extension
+    pub name: String,
+    /// extension specific payload
+    pub payload: Vec<u8>,
+}
+
+impl Open {
+    /// Returns the value of `capability`, or the default value if unset.
+    pub fn capability(&self) -> &[u8] {
+        self.capability.as_deref().unwrap_or(&[])
+    }
+    /// Returns the value of `receive_only`, or the default value if unset.
+    pub fn receive_only(&self) -> bool {
+        self.receive_only.unwrap_or(false)
+    }
+    /// Returns the value of `length`, or the default value if unset.
+    pub fn length(&self) -> u32 {
+        self.length.unwrap_or(0)
+    }
+}
+impl Request {
+    /// Returns the value of `sparse`, or the default value if unset.
+    pub fn sparse(&self) -> bool {
+        self.sparse.unwrap_or(false)
+    }
+    /// Returns the value of `end`, or the default value if unset.
+    pub fn end(&self) -> u32 {
+        self.end.unwrap_or(0)
+    }
+}
+
+impl SchemaMessage for NoisePayload {
+    fn schema_len(&self) -> usize {
+        bytes_len(1, &self.nonce)
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.bytes(1, &self.nonce);
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.nonce = reader.bytes(wire_type)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl SchemaMessage for Open {
+    fn schema_len(&self) -> usize {
+        bytes_len(1, &self.discovery_key)
+            + self.capability.as_ref().map_or(0, |c| bytes_len(2, c))
+            + self.receive_only.map_or(0, |_| bool_len(3))
+            + self.length.map_or(0, |length| uint32_len(4, length))
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.bytes(1, &self.discovery_key);
+        if let Some(capability) = &self.capability {
+            writer.bytes(2, capability);
+        }
+        if let Some(receive_only) = self.receive_only {
+            writer.bool(3, receive_only);
+        }
+        if let Some(length) = self.length {
+            writer.uint32(4, length);
+        }
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.discovery_key = reader.bytes(wire_type)?,
+                2 => msg.capability = Some(reader.bytes(wire_type)?),
+                3 => msg.receive_only = Some(reader.bool(wire_type)?),
+                4 => msg.length = Some(reader.uint32(wire_type)?),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl SchemaMessage for Close {
+    fn schema_len(&self) -> usize {
+        bytes_len(1, &self.discovery_key)
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.bytes(1, &self.discovery_key);
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.discovery_key = reader.bytes(wire_type)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl SchemaMessage for Request {
+    fn schema_len(&self) -> usize {
+        uint32_len(1, self.index)
+            + self.sparse.map_or(0, |_| bool_len(2))
+            + self.end.map_or(0, |end| uint32_len(3, end))
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.uint32(1, self.index);
+        if let Some(sparse) = self.sparse {
+            writer.bool(2, sparse);
+        }
+        if let Some(end) = self.end {
+            writer.uint32(3, end);
+        }
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.index = reader.uint32(wire_type)?,
+                2 => msg.sparse = Some(reader.bool(wire_type)?),
+                3 => msg.end = Some(reader.uint32(wire_type)?),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl SchemaMessage for Data {
+    fn schema_len(&self) -> usize {
+        uint32_len(1, self.index)
+            + bytes_len(2, &self.data)
+            + bytes_len(4, &self.data_signature)
+            + bytes_len(5, &self.tree_signature)
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.uint32(1, self.index);
+        writer.bytes(2, &self.data);
+        writer.bytes(4, &self.data_signature);
+        writer.bytes(5, &self.tree_signature);
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.index = reader.uint32(wire_type)?,
+                2 => msg.data = reader.bytes(wire_type)?,
+                4 => msg.data_signature = reader.bytes(wire_type)?,
+                5 => msg.tree_signature = reader.bytes(wire_type)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl SchemaMessage for Credit {
+    fn schema_len(&self) -> usize {
+        uint32_len(1, self.credit)
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.uint32(1, self.credit);
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.credit = reader.uint32(wire_type)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl SchemaMessage for RequestByHash {
+    fn schema_len(&self) -> usize {
+        bytes_len(1, &self.hash)
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.bytes(1, &self.hash);
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.hash = reader.bytes(wire_type)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl SchemaMessage for NotFound {
+    fn schema_len(&self) -> usize {
+        bytes_len(1, &self.hash)
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.bytes(1, &self.hash);
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.hash = reader.bytes(wire_type)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl SchemaMessage for Cancel {
+    fn schema_len(&self) -> usize {
+        uint32_len(1, self.index)
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.uint32(1, self.index);
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.index = reader.uint32(wire_type)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+impl SchemaMessage for Extension {
+    fn schema_len(&self) -> usize {
+        bytes_len(1, self.name.as_bytes()) + bytes_len(2, &self.payload)
+    }
+    fn schema_encode(&self, buf: &mut [u8]) -> std::result::Result<usize, EncodeError> {
+        let mut writer = Writer::new(buf, self.schema_len())?;
+        writer.bytes(1, self.name.as_bytes());
+        writer.bytes(2, &self.payload);
+        Ok(writer.pos)
+    }
+    fn schema_decode(buf: &[u8]) -> Result<Self> {
+        let mut msg = Self::default();
+        let mut reader = Reader::new(buf);
+        while let Some((tag, wire_type)) = reader.key()? {
+            match tag {
+                1 => msg.name = reader.string(wire_type)?,
+                2 => msg.payload = reader.bytes(wire_type)?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(msg)
+    }
+}
+
+#[inline]
+fn key_len(tag: u64) -> usize {
+    varinteger::length(tag << 3)
+}
+#[inline]
+fn bytes_len(tag: u64, value: &[u8]) -> usize {
+    key_len(tag) + varinteger::length(value.len() as u64) + value.len()
+}
+#[inline]
+fn uint32_len(tag: u64, value: u32) -> usize {
+    key_len(tag) + varinteger::length(value as u64)
+}
+#[inline]
+fn bool_len(tag: u64) -> usize {
+    key_len(tag) + 1
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+impl<'a> Writer<'a> {
+    /// Wrap `buf`, failing if it cannot hold `len` bytes.
+    fn new(buf: &'a mut [u8], len: usize)
+        -> std::result::Result<Self, EncodeError>
+    {
+        if buf.len() < len {
+            return Err(EncodeError::new(len));
+        }
+        Ok(Self { buf, pos: 0 })
+    }
+    fn varint(&mut self, value: u64) {
+        self.pos += varinteger::encode(value, &mut self.buf[self.pos..]);
+    }
+    fn key(&mut self, tag: u64, wire_type: u64) {
+        self.varint(tag << 3 | wire_type);
+    }
+    fn bytes(&mut self, tag: u64, value: &[u8]) {
+        self.key(tag, WIRE_BYTES);
+        self.varint(value.len() as u64);
+        self.buf[self.pos..self.pos + value.len()].copy_from_slice(value);
+        self.pos += value.len();
+    }
+    fn uint32(&mut self, tag: u64, value: u32) {
+        self.key(tag, WIRE_VARINT);
+        self.varint(value as u64);
+    }
+    fn bool(&mut self, tag: u64, value: bool) {
+        self.key(tag, WIRE_VARINT);
+        self.varint(value as u64);
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+    fn varint(&mut self) -> Result<u64> {
+        let rest = &self.buf[self.pos..];
+        // varinteger panics on truncated or overlong input, find the end first.
+        let len = rest.iter()
+            .take(MAX_VARINT_LEN)
+            .position(|byte| byte & 0x80 == 0)
+            .map(|i| i + 1)
+            .ok_or_else(|| invalid("Invalid varint"))?;
+        if len == MAX_VARINT_LEN && rest[len - 1] > 1 {
+            return Err(invalid("Varint overflow"));
+        }
+        let mut value = 0;
+        self.pos += varinteger::decode(&rest[..len], &mut value);
+        Ok(value)
+    }
+    /// Read the next field key, `None` at the end of the message.
+    fn key(&mut self) -> Result<Option<(u64, u64)>> {
+        if self.pos == self.buf.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let tag = key >> 3;
+        if tag == 0 {
+            return Err(invalid("Invalid field tag"));
+        }
+        Ok(Some((tag, key & 0b111)))
+    }
+    fn expect(wire_type: u64, expected: u64) -> Result<()> {
+        match wire_type == expected {
+            true => Ok(()),
+            false => Err(invalid("Invalid wire type")),
+        }
+    }
+    fn slice(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() - self.pos < len {
+            return Err(invalid("Buffer underflow"));
+        }
+        let slice = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+    fn bytes(&mut self, wire_type: u64) -> Result<Vec<u8>> {
+        Self::expect(wire_type, WIRE_BYTES)?;
+        let len = self.varint()?;
+        let len = usize::try_from(len).map_err(|_| invalid("Buffer underflow"))?;
+        Ok(self.slice(len)?.to_vec())
+    }
+    fn string(&mut self, wire_type: u64) -> Result<String> {
+        String::from_utf8(self.bytes(wire_type)?)
+            .map_err(|_| invalid("Invalid UTF-8 string"))
+    }
+    fn uint32(&mut self, wire_type: u64) -> Result<u32> {
+        Self::expect(wire_type, WIRE_VARINT)?;
+        // Truncate like prost does.
+        Ok(self.varint()? as u32)
+    }
+    fn bool(&mut self, wire_type: u64) -> Result<bool> {
+        Self::expect(wire_type, WIRE_VARINT)?;
+        Ok(self.varint()? != 0)
+    }
+    fn skip(&mut self, wire_type: u64) -> Result<()> {
+        match wire_type {
+            WIRE_VARINT => { self.varint()?; },
+            WIRE_FIXED64 => { self.slice(8)?; },
+            WIRE_BYTES => {
+                let len = self.varint()?;
+                let len = usize::try_from(len)
+                    .map_err(|_| invalid("Buffer underflow"))?;
+                self.slice(len)?;
+            },
+            WIRE_FIXED32 => { self.slice(4)?; },
+            _ => return Err(invalid("Unsupported wire type")),
+        };
+        Ok(())
+    }
+}
+
+#[inline]
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_skips_unknown_fields() {
+        // index = 7, unknown varint 15 = 1, unknown bytes 14 = [9, 9]
+        let buf = [0x08, 0x07, 0x78, 0x01, 0x72, 0x02, 0x09, 0x09];
+        let request = Request::schema_decode(&buf).unwrap();
+        assert_eq!(request, Request { index: 7, sparse: None, end: None });
+    }
+
+    #[test]
+    fn decode_truncated() {
+        assert!(Data::schema_decode(&[0x08]).is_err());
+        assert!(Data::schema_decode(&[0x08, 0x80]).is_err());
+        assert!(Data::schema_decode(&[0x12, 0x05, 0x01]).is_err());
+        let mut overlong = vec![0x80u8; 12];
+        overlong[0] = 0x08;
+        assert!(Data::schema_decode(&overlong).is_err());
+        assert!(Data::schema_decode(&[0x08, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0x7f]).is_err());
+    }
+
+    #[test]
+    fn decode_invalid_utf8() {
+        // name = [0xff]
+        assert!(Extension::schema_decode(&[0x0a, 0x01, 0xff]).is_err());
+    }
+
+    #[test]
+    fn decode_wrong_wire_type() {
+        // index as bytes
+        assert!(Request::schema_decode(&[0x0a, 0x01, 0x07]).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! In-memory transports with injected latency, for testing timeouts
+//! and retries under realistic conditions,
+//! and in-memory message transports for [MessageIo].
+//!
+//! Enabled with the `test-util` feature.
+
+use futures_lite::io::{AsyncRead, AsyncWrite};
+use futures_lite::stream::Stream;
+use futures_sink::Sink;
+use futures_timer::Delay;
+use async_channel::{Receiver, Sender};
+use rand::Rng;
+use std::future::Future;
+use std::io::{self, Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::{Duplex, MessageIo, ChannelMessage};
+
+/// [Duplex] over a [laggy_pipe] in each direction.
+pub type LaggyDuplex = Duplex<LaggyReader, LaggyWriter>;
+
+/// Create a pair of connected [LaggyDuplex] streams,
+/// delaying every write by `latency` before it can be read.
+pub fn create_laggy_duplex_pair(latency: Duration)
+    -> (LaggyDuplex, LaggyDuplex)
+{
+    create_laggy_duplex_pair_with_jitter(latency, Duration::ZERO)
+}
+
+/// Create a pair of connected [LaggyDuplex] streams,
+/// delaying every write by `latency` plus a random `[0, jitter)`.
+pub fn create_laggy_duplex_pair_with_jitter(
+    latency: Duration,
+    jitter: Duration,
+    ) -> (LaggyDuplex, LaggyDuplex)
+{
+    let (ar, bw) = laggy_pipe(latency, jitter);
+    let (br, aw) = laggy_pipe(latency, jitter);
+    (Duplex::new(ar, aw), Duplex::new(br, bw))
+}
+
+/// Create a unidirectional in-memory pipe with injected latency.
+///
+/// Jitter varies the delay of each write, but never reorders them:
+/// a byte stream cannot survive reordering, so a write is never delivered
+/// before the one preceding it.
+pub fn laggy_pipe(latency: Duration, jitter: Duration)
+    -> (LaggyReader, LaggyWriter)
+{
+    let (tx, rx) = async_channel::unbounded();
+    let reader = LaggyReader {
+        rx,
+        delayed: None,
+        ready: None,
+    };
+    let writer = LaggyWriter {
+        tx,
+        latency,
+        jitter,
+        last_deliver_at: None,
+    };
+    (reader, writer)
+}
+
+type Chunk = (Instant, Vec<u8>);
+
+/// Reading half of a [laggy_pipe].
+#[derive(Debug)]
+pub struct LaggyReader {
+    rx: Receiver<Chunk>,
+    /// Chunk waiting for its delivery time.
+    delayed: Option<(Delay, Vec<u8>)>,
+    /// Delivered chunk and the read position in it.
+    ready: Option<(Vec<u8>, usize)>,
+}
+
+impl AsyncRead for LaggyReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            if let Some((chunk, start)) = self.ready.take() {
+                let n = std::cmp::min(buf.len(), chunk.len() - start);
+                buf[..n].copy_from_slice(&chunk[start..start + n]);
+                if start + n < chunk.len() {
+                    self.ready = Some((chunk, start + n));
+                }
+                return Poll::Ready(Ok(n));
+            }
+
+            if let Some((mut delay, chunk)) = self.delayed.take() {
+                match Pin::new(&mut delay).poll(cx) {
+                    Poll::Ready(_) => self.ready = Some((chunk, 0)),
+                    Poll::Pending => {
+                        self.delayed = Some((delay, chunk));
+                        return Poll::Pending;
+                    }
+                }
+                continue;
+            }
+
+            match Pin::new(&mut self.rx).poll_next(cx) {
+                Poll::Ready(Some((deliver_at, chunk))) => {
+                    let now = Instant::now();
+                    if deliver_at <= now {
+                        self.ready = Some((chunk, 0));
+                    } else {
+                        let delay = Delay::new(deliver_at - now);
+                        self.delayed = Some((delay, chunk));
+                    }
+                }
+                // Writer closed or dropped, signal EOF.
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Writing half of a [laggy_pipe].
+#[derive(Debug)]
+pub struct LaggyWriter {
+    tx: Sender<Chunk>,
+    latency: Duration,
+    jitter: Duration,
+    last_deliver_at: Option<Instant>,
+}
+
+impl LaggyWriter {
+    fn deliver_at(&mut self) -> Instant {
+        let mut delay = self.latency;
+        if !self.jitter.is_zero() {
+            let jitter_ns = self.jitter.as_nanos() as u64;
+            delay += Duration::from_nanos(
+                rand::thread_rng().gen_range(0, jitter_ns));
+        }
+        let mut deliver_at = Instant::now() + delay;
+        // Keep delivery in order.
+        if let Some(last) = self.last_deliver_at {
+            if deliver_at < last {
+                deliver_at = last;
+            }
+        }
+        self.last_deliver_at = Some(deliver_at);
+        deliver_at
+    }
+}
+
+impl AsyncWrite for LaggyWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let deliver_at = self.deliver_at();
+        match self.tx.try_send((deliver_at, buf.to_vec())) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err(_) => Poll::Ready(Err(
+                Error::new(ErrorKind::BrokenPipe, "Laggy pipe closed"))),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>)
+        -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>)
+        -> Poll<io::Result<()>>
+    {
+        self.tx.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// [MessageIo] over an in-memory channel in each direction.
+pub type ChannelMessageIo =
+    MessageIo<ChannelSink, Receiver<io::Result<ChannelMessage>>>;
+
+/// Sending and receiving half of an in-memory message transport.
+pub type MessageChannel = (ChannelSink, Receiver<io::Result<ChannelMessage>>);
+
+/// Create a pair of connected [ChannelMessageIo] transports.
+pub fn create_message_io_pair() -> (ChannelMessageIo, ChannelMessageIo) {
+    let ((a_sink, a_stream), (b_sink, b_stream)) =
+        create_message_channel_pair();
+    (MessageIo::new(a_sink, a_stream), MessageIo::new(b_sink, b_stream))
+}
+
+/// Create a pair of connected [MessageChannel]s.
+pub fn create_message_channel_pair() -> (MessageChannel, MessageChannel) {
+    let (a_tx, b_rx) = async_channel::unbounded();
+    let (b_tx, a_rx) = async_channel::unbounded();
+    ((ChannelSink { tx: a_tx }, a_rx), (ChannelSink { tx: b_tx }, b_rx))
+}
+
+/// [Sink] of [ChannelMessage]s into an unbounded channel.
+#[derive(Debug)]
+pub struct ChannelSink {
+    tx: Sender<io::Result<ChannelMessage>>,
+}
+
+impl Sink<ChannelMessage> for ChannelSink {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>)
+        -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: ChannelMessage)
+        -> io::Result<()>
+    {
+        self.tx.try_send(Ok(item)).map_err(
+            |_| Error::new(ErrorKind::BrokenPipe, "Message channel closed"))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>)
+        -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>)
+        -> Poll<io::Result<()>>
+    {
+        self.tx.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::io::{AsyncReadExt, AsyncWriteExt};
+    use futures_lite::future::block_on;
+
+    #[test]
+    fn delays_by_latency() {
+        block_on(async {
+            let latency = Duration::from_millis(50);
+            let (mut a, mut b) = create_laggy_duplex_pair(latency);
+
+            let start = Instant::now();
+            a.write_all(b"hello").await.unwrap();
+            let mut buf = [0u8; 5];
+            b.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+            assert!(start.elapsed() >= latency);
+        })
+    }
+
+    #[test]
+    fn jitter_keeps_order() {
+        block_on(async {
+            let (mut r, mut w) = laggy_pipe(
+                Duration::from_millis(1), Duration::from_millis(20));
+            for i in 0..20u8 {
+                w.write_all(&[i]).await.unwrap();
+            }
+            w.close().await.unwrap();
+
+            let mut buf = vec![];
+            r.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, (0..20u8).collect::<Vec<u8>>());
+        })
+    }
+}
@@ -7,7 +7,41 @@ const DISCOVERY_NS_BUF: &[u8] = b"hypercore";
 
 /// Calculate the discovery key of a key.
 ///
-/// The discovery key is a 32 byte namespaced hash of the key.
+/// The discovery key is a 32 byte namespaced hash of the key
+/// in the default `hypercore` namespace, see [discovery_key_ns].
 pub fn discovery_key(key: &[u8; 32]) -> DiscoveryKey {
-    *keyed_hash(key, &DISCOVERY_NS_BUF).as_bytes()
+    discovery_key_ns(key, DISCOVERY_NS_BUF)
+}
+
+/// Calculate the discovery key of a key in a custom `namespace`,
+/// to run a network isolated from the default [discovery_key].
+///
+/// Both ends of a connection must use the same namespace,
+/// otherwise their channels never match.
+pub fn discovery_key_ns(key: &[u8; 32], namespace: &[u8]) -> DiscoveryKey {
+    *keyed_hash(key, namespace).as_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discovery_key_vectors() {
+        assert_eq!(
+            hex::encode(discovery_key(&[0u8; 32])),
+            "e2fe8f59494f3af13efae55f9a7d2ed952e72747e1e3af226aafc6ddcd4b6423"
+        );
+        assert_eq!(
+            hex::encode(discovery_key(&[7u8; 32])),
+            "cac54516abe4f426af33404fb405f6c572a53e1bdfa9280db02aa038a22bb26e"
+        );
+    }
+
+    #[test]
+    fn discovery_key_namespaced() {
+        let key = [7u8; 32];
+        assert_eq!(discovery_key_ns(&key, b"hypercore"), discovery_key(&key));
+        assert_ne!(discovery_key_ns(&key, b"isolated"), discovery_key(&key));
+    }
 }
@@ -35,14 +35,14 @@ pub fn create_pair_memory_keepalive(keepalive_ms: Option<u64>)
 {
     let (a, b) = create_duplex_pair_memory();
     let b = new_protocol(b, Options {
-        is_initiator: false,
         keepalive_ms,
-        ..Options::default()
+        send_keepalive: false,
+        ..Options::responder()
     });
     let a = new_protocol(a, Options {
-        is_initiator: true,
         keepalive_ms,
-        ..Options::default()
+        send_keepalive: false,
+        ..Options::initiator()
     });
     Ok((a, b))
 }
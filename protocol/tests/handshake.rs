@@ -6,9 +6,13 @@ use common::{
 };
 
 use anyhow::Result;
+use futures_lite::stream::StreamExt;
 use async_std::{task, test};
 
-use protocol::{Options, new_protocol, new_protocol_with_defaults};
+use protocol::{
+    Options, Protocol, main, discovery_key,
+    new_protocol, new_protocol_with_defaults,
+};
 
 #[test]
 async fn test_handshake() -> Result<()> {
@@ -34,14 +38,12 @@ async fn test_handshake_disabled() -> Result<()> {
     let (proto_a, proto_b) = create_duplex_pair_memory();
 
     let b = new_protocol(proto_b, Options {
-        is_initiator: false,
         noise: false,
-        ..Options::default()
+        ..Options::responder()
     });
     let a = new_protocol(proto_a, Options {
-        is_initiator: true,
         noise: false,
-        ..Options::default()
+        ..Options::initiator()
     });
 
     let task_a = task::spawn(async move {
@@ -56,6 +58,41 @@ async fn test_handshake_disabled() -> Result<()> {
     Ok(())
 }
 
+#[test]
+async fn test_established() -> Result<()> {
+    let (proto_a, proto_b) = create_duplex_pair_memory();
+    let options = |is_initiator| Options {
+        is_initiator,
+        noise: false,
+        encrypted: false,
+        ..Options::default()
+    };
+
+    let mut a = Protocol::new_established(proto_a, options(true), None)?;
+    let mut b = Protocol::new_established(proto_b, options(false), None)?;
+    let key = [3u8; 32];
+    a.open(key).await?;
+    b.open(key).await?;
+
+    let task_a = task::spawn(async move { a.next().await.unwrap() });
+    let task_b = task::spawn(async move { b.next().await.unwrap() });
+    let open = main::Event::Open(discovery_key(&key));
+    assert_eq!(task_a.await?, open);
+    assert_eq!(task_b.await?, open);
+    Ok(())
+}
+
+#[cfg(feature = "noise")]
+#[test]
+async fn test_established_requires_handshake_result() -> Result<()> {
+    let (proto_a, _) = create_duplex_pair_memory();
+    let result = Protocol::new_established(proto_a, Options::initiator(), None);
+    let error = result.unwrap_err();
+    let error = error.downcast_ref::<std::io::Error>().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
 #[test]
 async fn test_handshake_test_helpers_memory() -> Result<()> {
     let (proto_a, proto_b) = create_pair_memory()?;
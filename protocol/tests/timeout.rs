@@ -1,3 +1,6 @@
+// The timeouts are exercised through the handshake.
+#![cfg(feature = "noise")]
+
 mod common;
 use common::{
     create_duplex_pair_memory,
@@ -25,9 +28,8 @@ async fn timeout_no_connection() -> Result<()>
 
     let (a, b) = create_duplex_pair_memory();
     let mut proto_a = new_protocol(a, Options {
-        is_initiator: true,
         keepalive_ms: Some(keepalive_ms),
-        ..Options::default()
+        ..Options::initiator()
     });
 
     assert!(matches!(proto_a.poll_next(&mut cx), Poll::Pending));
@@ -12,14 +12,23 @@ use async_std::fs::{self, OpenOptions};
 use async_std::io::prelude::{SeekExt, WriteExt};
 use async_std::io::{ReadExt, SeekFrom};
 use random_access_storage::RandomAccess;
+use std::cmp;
+use std::io;
 use std::ops::Drop;
 use std::path::PathBuf;
 
+/// Default limit of a single [RandomAccessDisk] read, 64MB.
+pub const DEFAULT_MAX_READ_SIZE: u64 = 64 * 1024 * 1024;
+/// Size of the zeroed buffer written by [RandomAccessDisk] deletes, 64KB.
+const DELETE_CHUNK_SIZE: u64 = 64 * 1024;
+
 /// Main constructor.
 #[derive(Debug)]
 pub struct RandomAccessDisk {
-    file: Option<fs::File>,
+    file: fs::File,
     length: u64,
+    max_read_size: u64,
+    read_only: bool,
 }
 
 impl RandomAccessDisk {
@@ -30,20 +39,82 @@ impl RandomAccessDisk {
         if let Some(dirname) = filename.parent() {
             mkdirp::mkdirp(&dirname)?;
         }
+        Self::open_file(filename).await
+    }
+
+    /// Create a new instance in a directory which must already exist.
+    ///
+    /// Skips creating the parent directories, for opening many files
+    /// in the same directory.
+    pub async fn open_existing_dir(filename: PathBuf)
+        -> Result<RandomAccessDisk, Error>
+    {
+        if let Some(dirname) = filename.parent() {
+            if !dirname.as_os_str().is_empty() && !dirname.is_dir() {
+                return Err(anyhow!(
+                    "Directory {} does not exist.", dirname.display()))
+            }
+        }
+        Self::open_file(filename).await
+    }
+
+    async fn open_file(filename: PathBuf) -> Result<RandomAccessDisk, Error>
+    {
         let file = OpenOptions::new()
             .create(true)
             .read(true)
             .write(true)
             .open(&filename)
             .await?;
-        file.sync_all().await?;
 
         let metadata = filename.metadata()?;
         Ok(RandomAccessDisk {
-            file: Some(file),
+            file,
+            length: metadata.len(),
+            max_read_size: DEFAULT_MAX_READ_SIZE,
+            read_only: false,
+        })
+    }
+
+    /// Open an existing file read-only, failing if it is missing.
+    ///
+    /// Writes fail with [io::ErrorKind::PermissionDenied].
+    pub async fn open_readonly(filename: PathBuf)
+        -> Result<RandomAccessDisk, Error>
+    {
+        let file = OpenOptions::new()
+            .read(true)
+            .open(&filename)
+            .await?;
+
+        let metadata = filename.metadata()?;
+        Ok(RandomAccessDisk {
+            file,
             length: metadata.len(),
+            max_read_size: DEFAULT_MAX_READ_SIZE,
+            read_only: true,
         })
     }
+
+    /// Limit the length of a single read,
+    /// [DEFAULT_MAX_READ_SIZE] by default.
+    ///
+    /// The read buffer is allocated up front, so the limit bounds the memory
+    /// a single read (for example serving a replication request) can take.
+    /// Larger reads fail, callers wanting more must read in chunks.
+    pub fn with_max_read_size(mut self, max_read_size: u64) -> Self {
+        self.max_read_size = max_read_size;
+        self
+    }
+
+    fn check_writable(&self) -> Result<(), io::Error> {
+        if self.read_only {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "RandomAccessDisk opened read-only"));
+        }
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
@@ -55,10 +126,10 @@ impl RandomAccess for RandomAccessDisk {
         offset: u64,
         data: &[u8],
         ) -> Result<(), Self::Error> {
-        let mut file = self.file.as_ref().expect("self.file was None.");
+        self.check_writable()?;
+        let mut file = &self.file;
         file.seek(SeekFrom::Start(offset)).await?;
         file.write_all(&data).await?;
-        file.sync_all().await?;
 
         // We've changed the length of our file.
         let new_len = offset + (data.len() as u64);
@@ -82,35 +153,93 @@ impl RandomAccess for RandomAccessDisk {
         offset: u64,
         length: u64,
         ) -> Result<Vec<u8>, Self::Error> {
-        if (offset + length) as u64 > self.length {
+        if length > self.max_read_size {
+            return Err(
+                anyhow!(
+                    "Read too large. {} > {}, read in chunks",
+                    length,
+                    self.max_read_size,
+                    )
+                .into(),
+                );
+        }
+        if offset.checked_add(length).is_none_or(|end| end > self.length) {
             return Err(
                 anyhow!(
                     "Read bounds exceeded. {} < {}..{}",
                     self.length,
                     offset,
-                    offset + length
+                    offset.saturating_add(length)
                     )
                 .into(),
                 );
         }
 
-        let mut file = self.file.as_ref().expect("self.file was None.");
+        let mut file = &self.file;
         let mut buffer = vec![0; length as usize];
         file.seek(SeekFrom::Start(offset)).await?;
         let _bytes_read = file.read(&mut buffer[..]).await?;
         Ok(buffer)
     }
+
+    /// Truncate the file if the deleted bytes reach its end,
+    /// otherwise overwrite them with zeros.
+    ///
+    /// Hole punching is platform specific and not used,
+    /// a delete inside the file does not free disk space.
+    async fn delete(
+        &mut self,
+        offset: u64,
+        length: u64,
+        ) -> Result<(), Self::Error> {
+        self.check_writable()?;
+        let end = cmp::min(offset.saturating_add(length), self.length);
+        if offset >= end {
+            return Ok(());
+        }
+        if end == self.length {
+            self.file.set_len(offset).await?;
+            self.length = offset;
+            return Ok(());
+        }
+
+        let zeros = vec![0; cmp::min(end - offset, DELETE_CHUNK_SIZE) as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut cursor = offset;
+        while cursor < end {
+            let chunk = cmp::min(end - cursor, DELETE_CHUNK_SIZE) as usize;
+            file.write_all(&zeros[..chunk]).await?;
+            cursor += chunk as u64;
+        }
+        Ok(())
+    }
+
+    /// Sync the file, its data and metadata, to disk.
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.file.sync_all().await?;
+        Ok(())
+    }
+
+    /// The tracked length, the file is not queried again.
+    async fn len(&mut self) -> Result<u64, Self::Error> {
+        Ok(self.length)
+    }
 }
 
 impl Drop for RandomAccessDisk {
     fn drop(&mut self) {
-        if let Some(file) = &self.file {
-            // We need to flush the file on drop. Unfortunately, that is not possible to do in a
-            // non-blocking fashion, but our only other option here is losing data remaining in the
-            // write cache. Good task schedulers should be resilient to occasional blocking hiccups in
-            // file destructors so we don't expect this to be a common problem in practice.
-            // (from async_std::fs::File::drop)
-            let _ = async_std::task::block_on(file.sync_all());
+        if self.read_only {
+            return;
         }
+        // We need to flush the file on drop. Unfortunately, that is not possible to do in a
+        // non-blocking fashion, but our only other option here is losing data remaining in the
+        // write cache. Good task schedulers should be resilient to occasional blocking hiccups in
+        // file destructors so we don't expect this to be a common problem in practice.
+        // (from async_std::fs::File::drop)
+        let _ = async_std::task::block_on(self.file.sync_all());
     }
 }
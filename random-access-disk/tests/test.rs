@@ -13,6 +13,25 @@ async fn can_call_new() {
     .unwrap();
 }
 
+#[async_std::test]
+async fn can_open_existing_dir() {
+  let dir = Builder::new()
+    .prefix("random-access-disk")
+    .tempdir()
+    .unwrap();
+  let mut file = rad::RandomAccessDisk::open_existing_dir(dir.path().join("1.db"))
+    .await
+    .unwrap();
+  file.write(0, b"hello").await.unwrap();
+
+  let missing = dir.path().join("missing").join("1.db");
+  let err = rad::RandomAccessDisk::open_existing_dir(missing)
+    .await
+    .unwrap_err();
+  assert!(err.to_string().contains("does not exist"));
+  assert!(!dir.path().join("missing").exists());
+}
+
 #[async_std::test]
 async fn can_open_buffer() {
   let dir = Builder::new()
@@ -52,3 +71,98 @@ async fn can_read() {
   let text = file.read(0, 11).await.unwrap();
   assert_eq!(String::from_utf8(text.to_vec()).unwrap(), "hello world");
 }
+
+#[async_std::test]
+async fn can_reject_huge_read() {
+  let dir = Builder::new()
+    .prefix("random-access-disk")
+    .tempdir()
+    .unwrap();
+  let mut file = rad::RandomAccessDisk::open(dir.path().join("5.db"))
+    .await
+    .unwrap()
+    .with_max_read_size(8);
+  file.write(0, b"hello world").await.unwrap();
+  assert!(file.read(0, u64::MAX).await.is_err());
+  assert!(file.read(0, 9).await.is_err());
+  assert!(file.read(u64::MAX, 1).await.is_err());
+  assert_eq!(file.read(3, 8).await.unwrap(), b"lo world");
+}
+
+#[async_std::test]
+async fn can_len() {
+  let dir = Builder::new()
+    .prefix("random-access-disk")
+    .tempdir()
+    .unwrap();
+  let mut file = rad::RandomAccessDisk::open(dir.path().join("6.db"))
+    .await
+    .unwrap();
+  assert!(file.is_empty().await.unwrap());
+  file.write(5, b" world").await.unwrap();
+  file.write(0, b"hello").await.unwrap();
+  assert_eq!(file.len().await.unwrap(), 11);
+  drop(file);
+
+  let mut file = rad::RandomAccessDisk::open(dir.path().join("6.db"))
+    .await
+    .unwrap();
+  assert_eq!(file.len().await.unwrap(), 11);
+}
+
+#[async_std::test]
+async fn can_flush() {
+  let dir = Builder::new()
+    .prefix("random-access-disk")
+    .tempdir()
+    .unwrap();
+  let path = dir.path().join("7.db");
+  let mut file = rad::RandomAccessDisk::open(path.clone()).await.unwrap();
+  file.write(0, b"hello").await.unwrap();
+  file.flush().await.unwrap();
+  assert_eq!(std::fs::read(path).unwrap(), b"hello");
+}
+
+#[async_std::test]
+async fn can_open_readonly() {
+  let dir = Builder::new()
+    .prefix("random-access-disk")
+    .tempdir()
+    .unwrap();
+  let path = dir.path().join("8.db");
+  assert!(rad::RandomAccessDisk::open_readonly(path.clone()).await.is_err());
+  assert!(!path.exists());
+
+  let mut file = rad::RandomAccessDisk::open(path.clone()).await.unwrap();
+  file.write(0, b"hello").await.unwrap();
+  drop(file);
+
+  let mut file = rad::RandomAccessDisk::open_readonly(path).await.unwrap();
+  assert_eq!(file.read(0, 5).await.unwrap(), b"hello");
+  let err = file.write(0, b"world").await.unwrap_err();
+  let err = err.downcast_ref::<std::io::Error>().unwrap();
+  assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+  assert_eq!(file.read(0, 5).await.unwrap(), b"hello");
+}
+
+#[async_std::test]
+async fn can_delete() {
+  let dir = Builder::new()
+    .prefix("random-access-disk")
+    .tempdir()
+    .unwrap();
+  let mut file = rad::RandomAccessDisk::open(dir.path().join("9.db"))
+    .await
+    .unwrap();
+  file.write(0, b"hello world!").await.unwrap();
+
+  file.delete(2, 7).await.unwrap();
+  assert_eq!(file.len().await.unwrap(), 12);
+  assert_eq!(file.read(0, 12).await.unwrap(), b"he\0\0\0\0\0\0\0ld!");
+
+  // the tail truncates
+  file.delete(10, 100).await.unwrap();
+  assert_eq!(file.len().await.unwrap(), 10);
+  file.write(11, b"?").await.unwrap();
+  assert_eq!(file.read(9, 3).await.unwrap(), b"l\0?");
+}
@@ -29,6 +29,7 @@
 use anyhow::anyhow;
 use random_access_storage::RandomAccess;
 use std::cmp;
+use std::collections::HashMap;
 
 /// Main constructor.
 #[derive(Debug)]
@@ -36,9 +37,9 @@ pub struct RandomAccessMemory {
   /// The length length of each buffer.
   page_size: usize,
 
-  /// The memory we read/write to.
-  // TODO: initialize as a sparse vector.
-  buffers: Vec<Vec<u8>>,
+  /// The memory we read/write to, by page number.
+  /// Only pages written to are allocated.
+  buffers: HashMap<usize, Vec<u8>>,
 
   /// Total length of the data.
   length: u64,
@@ -48,7 +49,7 @@ impl RandomAccessMemory {
   /// Create a new instance.
   pub fn new(page_size: usize) -> Self {
     RandomAccessMemory {
-      buffers: Vec::new(),
+      buffers: HashMap::new(),
       page_size,
       length: 0,
     }
@@ -58,7 +59,7 @@ impl RandomAccessMemory {
   // We cannot use the `Default` trait here because we aren't returning `Self`.
   pub fn default() -> Self {
     RandomAccessMemory {
-      buffers: Vec::new(),
+      buffers: HashMap::new(),
       page_size: 1024 * 1024,
       length: 0,
     }
@@ -68,7 +69,7 @@ impl RandomAccessMemory {
   pub fn with_buffers(page_size: usize, buffers: Vec<Vec<u8>>) -> Self {
     RandomAccessMemory {
       page_size,
-      buffers,
+      buffers: buffers.into_iter().enumerate().collect(),
       length: 0,
     }
   }
@@ -101,24 +102,16 @@ impl RandomAccess for RandomAccessMemory {
       let range = page_cursor..upper_bound;
       let range_len = (page_cursor as usize..upper_bound as usize).len();
 
-      // Allocate buffer if needed. Either append a new buffer to the end, or
-      // set a buffer in the center.
-      if self.buffers.get(page_num).is_none() {
-        let buf = vec![0; self.page_size as usize];
-        if self.buffers.len() < page_num + 1 {
-          self.buffers.resize(page_num + 1, buf);
-        } else {
-          self.buffers[page_num] = buf;
-        }
-      }
+      // Allocate the page if needed.
+      let page_size = self.page_size;
+      let buffer = self
+        .buffers
+        .entry(page_num)
+        .or_insert_with(|| vec![0; page_size]);
 
       // Copy data from the vec slice.
-      // TODO: use a batch operation such as `.copy_from_slice()` so it can be
-      // optimized.
-      let buffer = &mut self.buffers[page_num as usize];
-      for (index, buf_index) in range.enumerate() {
-        buffer[buf_index as usize] = data[data_cursor + index];
-      }
+      buffer[range]
+        .copy_from_slice(&data[data_cursor..data_cursor + range_len]);
 
       page_num += 1;
       page_cursor = 0;
@@ -162,17 +155,11 @@ impl RandomAccess for RandomAccessMemory {
 
       // Fill until either we're done reading the page, or we're done
       // filling the buffer. Whichever arrives sooner.
-      match self.buffers.get(page_num as usize) {
-        Some(buf) => {
-          for (index, buf_index) in range.enumerate() {
-            res_buf[res_cursor as usize + index] = buf[buf_index as usize];
-          }
-        }
-        None => {
-          for (index, _) in range.enumerate() {
-            res_buf[res_cursor as usize + index] = 0;
-          }
-        }
+      // Pages never written read as zeros, as `res_buf` is initialized.
+      if let Some(buf) = self.buffers.get(&page_num) {
+        let res_start = res_cursor as usize;
+        res_buf[res_start..res_start + range.len()]
+          .copy_from_slice(&buf[range]);
       }
 
       res_cursor += relative_bound;
@@ -182,4 +169,47 @@ impl RandomAccess for RandomAccessMemory {
 
     Ok(res_buf)
   }
+
+  async fn flush(&mut self) -> Result<(), Self::Error> {
+    Ok(())
+  }
+
+  async fn delete(
+    &mut self,
+    offset: u64,
+    length: u64,
+  ) -> Result<(), Self::Error> {
+    let end = cmp::min(offset.saturating_add(length), self.length);
+    if offset >= end {
+      return Ok(());
+    }
+    let tail = end == self.length;
+
+    // Drop the pages covered to their end, or to the end of the data,
+    // zero the rest.
+    let page_size = self.page_size as u64;
+    let mut cursor = offset;
+    while cursor < end {
+      let page_num = (cursor / page_size) as usize;
+      let page_start = page_num as u64 * page_size;
+      let page_end = cmp::min(page_start + page_size, end);
+      if cursor == page_start && (tail || page_end - page_start == page_size) {
+        self.buffers.remove(&page_num);
+      } else if let Some(buf) = self.buffers.get_mut(&page_num) {
+        let range =
+          (cursor - page_start) as usize..(page_end - page_start) as usize;
+        buf[range].fill(0);
+      }
+      cursor = page_end;
+    }
+
+    if tail {
+      self.length = offset;
+    }
+    Ok(())
+  }
+
+  async fn len(&mut self) -> Result<u64, Self::Error> {
+    Ok(self.length)
+  }
 }
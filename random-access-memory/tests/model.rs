@@ -59,6 +59,7 @@ quickcheck! {
           },
         }
       }
+      assert_eq!(implementation.len().await.unwrap(), model.len() as u64);
       true
     })
   }
@@ -28,3 +28,70 @@ async fn can_read() {
   let text = String::from_utf8(text.to_vec()).unwrap();
   assert_eq!(text, "hello world");
 }
+
+#[async_std::test]
+async fn can_len() {
+  let mut file = ram::RandomAccessMemory::default();
+  assert!(file.is_empty().await.unwrap());
+  file.write(5, b" world").await.unwrap();
+  file.write(0, b"hello").await.unwrap();
+  assert_eq!(file.len().await.unwrap(), 11);
+  assert!(!file.is_empty().await.unwrap());
+}
+
+#[async_std::test]
+async fn can_read_write_across_pages() {
+  let page_size = 1000;
+  let mut file = ram::RandomAccessMemory::new(page_size);
+  let data: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+  let offset = 3 * page_size as u64 - 7;
+  file.write(offset, &data).await.unwrap();
+
+  assert_eq!(file.read(offset, data.len() as u64).await.unwrap(), data);
+  assert_eq!(file.read(0, offset).await.unwrap(), vec![0; offset as usize]);
+  // a page edge, crossed by a short read
+  let edge = 5 * page_size as u64 - offset;
+  let read = file.read(offset + edge - 2, 4).await.unwrap();
+  assert_eq!(read, &data[edge as usize - 2..edge as usize + 2]);
+}
+
+#[async_std::test]
+async fn can_write_far_offset() {
+  let mut file = ram::RandomAccessMemory::default();
+  let offset = 10 * 1024 * 1024 * 1024;
+  file.write(offset, b"hello").await.unwrap();
+  assert_eq!(file.len().await.unwrap(), offset + 5);
+  assert_eq!(file.read(offset - 2, 7).await.unwrap(), b"\0\0hello");
+  assert_eq!(file.read(1024, 4).await.unwrap(), vec![0; 4]);
+}
+
+#[async_std::test]
+async fn can_open_with_buffers() {
+  let mut file = ram::RandomAccessMemory::with_buffers(4, vec![
+    b"abcd".to_vec(),
+    b"efgh".to_vec(),
+  ]);
+  file.write(8, b"ij").await.unwrap();
+  assert_eq!(file.read(2, 8).await.unwrap(), b"cdefghij");
+}
+
+#[async_std::test]
+async fn can_delete() {
+  let mut file = ram::RandomAccessMemory::new(4);
+  file.write(0, b"hello world!").await.unwrap();
+
+  // a whole page and partial edges
+  file.delete(2, 7).await.unwrap();
+  assert_eq!(file.len().await.unwrap(), 12);
+  assert_eq!(file.read(0, 12).await.unwrap(), b"he\0\0\0\0\0\0\0ld!");
+
+  // the tail truncates
+  file.delete(10, 100).await.unwrap();
+  assert_eq!(file.len().await.unwrap(), 10);
+  file.write(11, b"?").await.unwrap();
+  assert_eq!(file.read(9, 3).await.unwrap(), b"l\0?");
+
+  // past the end, nothing to delete
+  file.delete(20, 4).await.unwrap();
+  assert_eq!(file.len().await.unwrap(), 12);
+}
@@ -31,4 +31,28 @@ pub trait RandomAccess {
     offset: u64,
     length: u64,
   ) -> Result<Vec<u8>, Self::Error>;
+
+  /// Delete a sequence of bytes at an offset, reading back as zeros.
+  ///
+  /// The length of the backend is kept, unless the sequence reaches
+  /// its end: the backend is then truncated to `offset`.
+  async fn delete(
+    &mut self,
+    offset: u64,
+    length: u64,
+  ) -> Result<(), Self::Error>;
+
+  /// Persist the writes so far, `write` alone does not guarantee it.
+  ///
+  /// Batch writes and flush once, flushing can be expensive,
+  /// for example syncing a file to disk.
+  async fn flush(&mut self) -> Result<(), Self::Error>;
+
+  /// Length of the backend in bytes, the end of the furthest write.
+  async fn len(&mut self) -> Result<u64, Self::Error>;
+
+  /// Check if nothing was written to the backend.
+  async fn is_empty(&mut self) -> Result<bool, Self::Error> {
+    Ok(self.len().await? == 0)
+  }
 }